@@ -0,0 +1,12 @@
+CREATE TABLE favorites (
+  organization_id uuid NOT NULL REFERENCES organizations (id) ON DELETE CASCADE,
+  user_id uuid NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  object_type text NOT NULL,
+  object_id uuid NOT NULL,
+  created_at timestamptz NOT NULL DEFAULT now(),
+  PRIMARY KEY (organization_id, user_id, object_id)
+);
+
+CREATE INDEX favorites_user_object_type ON favorites (organization_id, user_id, object_type, created_at DESC);
+
+CREATE INDEX favorites_object_id ON favorites (organization_id, object_id);
@@ -0,0 +1 @@
+DROP FUNCTION jsonb_merge_patch(jsonb, jsonb);
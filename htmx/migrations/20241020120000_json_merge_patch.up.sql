@@ -0,0 +1,37 @@
+-- Apply an RFC 7396 JSON Merge Patch document to a value.
+CREATE OR REPLACE FUNCTION jsonb_merge_patch(target jsonb, patch jsonb)
+  RETURNS jsonb
+  LANGUAGE plpgsql
+  IMMUTABLE
+  AS $$
+DECLARE
+  result jsonb;
+  item record;
+BEGIN
+  IF patch IS NULL OR jsonb_typeof(patch) <> 'object' THEN
+    RETURN patch;
+  END IF;
+
+  IF target IS NULL OR jsonb_typeof(target) <> 'object' THEN
+    result := '{}'::jsonb;
+  ELSE
+    result := target;
+  END IF;
+
+  FOR item IN
+  SELECT
+    key,
+    value
+  FROM
+    jsonb_each(patch)
+    LOOP
+      IF jsonb_typeof(item.value) = 'null' THEN
+        result := result - item.key;
+      ELSE
+        result := jsonb_set(result, ARRAY[item.key], jsonb_merge_patch(result -> item.key, item.value));
+      END IF;
+    END LOOP;
+
+  RETURN result;
+END;
+$$;
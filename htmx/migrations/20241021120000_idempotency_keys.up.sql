@@ -0,0 +1,15 @@
+CREATE TABLE idempotency_keys (
+  organization_id uuid NOT NULL REFERENCES organizations (id) ON DELETE CASCADE,
+  user_id uuid NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  key text NOT NULL,
+  path text NOT NULL,
+  -- The response fields are NULL while the first request is still running.
+  status_code smallint,
+  content_type text,
+  response_body bytea,
+  created_at timestamptz NOT NULL DEFAULT now(),
+  expires_at timestamptz NOT NULL,
+  PRIMARY KEY (organization_id, user_id, key)
+);
+
+CREATE INDEX idempotency_keys_expires_at ON idempotency_keys (expires_at);
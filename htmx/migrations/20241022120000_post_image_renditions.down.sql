@@ -0,0 +1,4 @@
+ALTER TABLE post_images
+  DROP COLUMN renditions,
+  DROP COLUMN height,
+  DROP COLUMN width;
@@ -0,0 +1,4 @@
+ALTER TABLE post_images
+  ADD COLUMN width integer,
+  ADD COLUMN height integer,
+  ADD COLUMN renditions jsonb NOT NULL DEFAULT '[]'::jsonb;
@@ -0,0 +1,3 @@
+DROP INDEX post_images_post_id;
+
+CREATE UNIQUE INDEX post_images_post_id ON post_images (organization_id, post_id);
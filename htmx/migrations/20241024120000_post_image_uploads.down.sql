@@ -0,0 +1 @@
+DROP TABLE post_image_uploads;
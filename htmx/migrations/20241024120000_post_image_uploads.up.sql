@@ -0,0 +1,19 @@
+CREATE TABLE post_image_uploads (
+  -- The ID of the PostImage that is created when the upload finishes
+  id uuid NOT NULL PRIMARY KEY,
+  organization_id uuid NOT NULL REFERENCES organizations (id) ON DELETE CASCADE,
+  post_id uuid NOT NULL REFERENCES posts (id) ON DELETE CASCADE,
+  file_original_name text,
+  upload_length bigint NOT NULL,
+  upload_offset bigint NOT NULL DEFAULT 0,
+  -- The staged chunks in the image_uploads bucket, in order
+  chunk_keys text[] NOT NULL DEFAULT '{}',
+  completed_at timestamptz,
+  created_at timestamptz NOT NULL DEFAULT now(),
+  updated_at timestamptz NOT NULL DEFAULT now(),
+  expires_at timestamptz NOT NULL
+);
+
+CREATE INDEX post_image_uploads_post_id ON post_image_uploads (organization_id, post_id);
+
+CREATE INDEX post_image_uploads_expires_at ON post_image_uploads (expires_at);
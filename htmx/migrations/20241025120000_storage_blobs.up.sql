@@ -0,0 +1,15 @@
+-- Files stored under a key derived from their content, shared by every row with the same hash
+CREATE TABLE storage_blobs (
+  organization_id uuid NOT NULL REFERENCES organizations (id) ON DELETE CASCADE,
+  bucket text NOT NULL,
+  hash bytea NOT NULL,
+  storage_key text NOT NULL,
+  size bigint NOT NULL,
+  -- The number of rows that refer to this blob. The blob is deleted when this reaches zero.
+  ref_count bigint NOT NULL DEFAULT 0,
+  created_at timestamptz NOT NULL DEFAULT now(),
+  updated_at timestamptz NOT NULL DEFAULT now(),
+  PRIMARY KEY (organization_id, bucket, hash)
+);
+
+CREATE UNIQUE INDEX storage_blobs_storage_key ON storage_blobs (bucket, storage_key);
@@ -0,0 +1,11 @@
+-- Per-organization overrides of the default storage quotas
+CREATE TABLE storage_quotas (
+  organization_id uuid NOT NULL PRIMARY KEY REFERENCES organizations (id) ON DELETE CASCADE,
+  -- The limits in bytes. NULL uses the default limit.
+  soft_limit bigint,
+  hard_limit bigint,
+  -- When the organization's administrators were last warned about the soft limit
+  soft_limit_warned_at timestamptz,
+  created_at timestamptz NOT NULL DEFAULT now(),
+  updated_at timestamptz NOT NULL DEFAULT now()
+);
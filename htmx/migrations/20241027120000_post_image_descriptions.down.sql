@@ -0,0 +1,3 @@
+ALTER TABLE post_images
+  DROP COLUMN caption,
+  DROP COLUMN alt_text;
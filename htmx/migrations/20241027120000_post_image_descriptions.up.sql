@@ -0,0 +1,3 @@
+ALTER TABLE post_images
+  ADD COLUMN alt_text text,
+  ADD COLUMN caption text;
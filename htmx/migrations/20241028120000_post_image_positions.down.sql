@@ -0,0 +1,2 @@
+ALTER TABLE post_images
+  DROP COLUMN position;
@@ -0,0 +1,16 @@
+ALTER TABLE post_images
+  ADD COLUMN position integer NOT NULL DEFAULT 0;
+
+-- Keep existing images in the order they were uploaded.
+UPDATE
+  post_images
+SET
+  position = ordered.position
+FROM (
+  SELECT
+    id,
+    ROW_NUMBER() OVER (PARTITION BY post_id ORDER BY created_at, id) - 1 AS position
+  FROM
+    post_images) ordered
+WHERE
+  post_images.id = ordered.id;
@@ -1,7 +1,7 @@
 use std::borrow::Cow;
 
 use async_trait::async_trait;
-use axum::routing::{self, Router};
+use axum::{http::Method, Router};
 use error_stack::{Report, ResultExt};
 use filigree::auth::{
     AuthError, AuthInfo as _, ExpiryStyle, OrganizationId, PermissionChecker, RoleId, SessionKey,
@@ -10,7 +10,10 @@ use filigree::auth::{
 use sqlx::{query_file_as, PgPool};
 use uuid::Uuid;
 
-use crate::server::ServerState;
+use crate::server::{
+    openapi::{ApiRoute, ApiRouter},
+    ServerState,
+};
 
 pub mod password_management;
 pub mod passwordless_login;
@@ -182,18 +185,43 @@ where
     filigree::auth::has_auth_predicate(message.into(), f)
 }
 
-pub fn create_routes() -> Router<ServerState> {
-    Router::new()
+fn routes() -> ApiRouter {
+    ApiRouter::new()
         .route(
-            "/auth/email_login",
-            routing::post(passwordless_login::request_passwordless_login),
+            ApiRoute::new(
+                Method::POST,
+                "/auth/email_login",
+                "auth",
+                "Email a passwordless login link",
+            )
+            .request::<passwordless_login::CreatePasswordlessLoginRequestBody>(),
+            passwordless_login::request_passwordless_login,
         )
         .route(
-            "/auth/email_login",
-            routing::get(passwordless_login::process_passwordless_login_token),
+            ApiRoute::new(
+                Method::GET,
+                "/auth/email_login",
+                "auth",
+                "Log in using the token from a passwordless login email",
+            )
+            .query::<passwordless_login::PasswordlessLoginRequestQueryFromEmail>(),
+            passwordless_login::process_passwordless_login_token,
         )
         .route(
-            "/auth/request_password_reset",
-            routing::post(password_management::start_password_reset),
+            ApiRoute::new(
+                Method::POST,
+                "/auth/request_password_reset",
+                "auth",
+                "Email a password reset link",
+            ),
+            password_management::start_password_reset,
         )
 }
+
+pub fn create_routes() -> Router<ServerState> {
+    routes().into_router()
+}
+
+pub fn api_docs() -> Vec<ApiRoute> {
+    routes().into_docs()
+}
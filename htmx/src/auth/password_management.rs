@@ -2,8 +2,13 @@ use axum::{
     extract::{Host, State},
     response::IntoResponse,
 };
-use error_stack::ResultExt;
-use filigree::{auth::password::create_reset_token, extract::FormOrJson, EmailBody};
+use error_stack::{Report, ResultExt};
+use filigree::{
+    auth::password::{create_reset_token, update_password_with_token},
+    extract::FormOrJson,
+    EmailBody,
+};
+use uuid::Uuid;
 
 use crate::{server::ServerState, Error};
 
@@ -12,12 +17,23 @@ pub async fn start_password_reset(
     Host(host): Host,
     FormOrJson(body): FormOrJson<EmailBody>,
 ) -> Result<impl IntoResponse, Error> {
+    send_password_reset_email(&state, host, body.email).await?;
+    Ok(())
+}
+
+/// Create a password reset token and email the reset link to the user. If the email does not
+/// belong to a user, this does nothing so that callers don't reveal which emails have accounts.
+pub async fn send_password_reset_email(
+    state: &ServerState,
+    host: String,
+    email: String,
+) -> Result<(), Report<Error>> {
     if state.host_is_allowed(&host).is_err() {
         // Bail due to some kind of hijinks
-        return Err(Error::InvalidHostHeader);
+        return Err(Report::new(Error::InvalidHostHeader));
     }
 
-    let token = create_reset_token(&state.db, &body.email).await;
+    let token = create_reset_token(&state.db, &email).await;
 
     let token = match token {
         Ok(token) => token,
@@ -27,7 +43,7 @@ pub async fn start_password_reset(
                 // the email doesn't exist.
                 return Ok(());
             } else {
-                return Err(e.change_context(Error::AuthSubsystem).into());
+                return Err(e.change_context(Error::AuthSubsystem));
             }
         }
     };
@@ -36,20 +52,37 @@ pub async fn start_password_reset(
         user_name: None,
         url_scheme: state.site_scheme(),
         host,
-        email: body.email.clone(),
+        email: email.clone(),
         token,
     };
 
     state
         .filigree
         .email
-        .send_template(body.email, template)
+        .send_template(email, template)
         .await
         .change_context(Error::AuthSubsystem)?;
 
     Ok(())
 }
 
+/// Set a new password for the user, if the reset token is valid. Filigree clears the token on
+/// success so that it can not be reused. Returns false if the token was invalid or expired.
+pub async fn reset_password_with_token(
+    state: &ServerState,
+    email: &str,
+    token: Uuid,
+    password: String,
+) -> Result<bool, Report<Error>> {
+    let result = update_password_with_token(&state.db, email, token, password).await;
+
+    match result {
+        Ok(()) => Ok(true),
+        Err(e) if e.current_context().is_unauthenticated() => Ok(false),
+        Err(e) => Err(e.change_context(Error::AuthSubsystem)),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::str::FromStr;
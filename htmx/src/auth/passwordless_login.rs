@@ -36,10 +36,23 @@ pub async fn request_passwordless_login(
         CreatePasswordlessLoginRequestBody,
     >,
 ) -> Result<impl IntoResponse, Error> {
+    send_passwordless_login_email(&state, host, email, redirect_to).await?;
+    Ok(())
+}
+
+/// Create a passwordless login token and email the login link to the user. If the user does not
+/// exist and public signups are disabled, this does nothing so that callers don't reveal which
+/// emails have accounts.
+pub async fn send_passwordless_login_email(
+    state: &ServerState,
+    host: String,
+    email: String,
+    redirect_to: Option<String>,
+) -> Result<(), error_stack::Report<Error>> {
     if state.host_is_allowed(&host).is_err() {
         tracing::event!(tracing::Level::ERROR, %host, "Disallowed host header");
         // Bail due to some kind of hijinks
-        return Err(Error::InvalidHostHeader);
+        return Err(error_stack::Report::new(Error::InvalidHostHeader));
     }
 
     let token = setup_passwordless_login(&state.filigree, email.clone()).await;
@@ -54,7 +67,7 @@ pub async fn request_passwordless_login(
                 event!(Level::INFO, email, "Passwordless login user not found");
                 return Ok(());
             } else {
-                return Err(e.change_context(Error::AuthSubsystem).into());
+                return Err(e.change_context(Error::AuthSubsystem));
             }
         }
     };
@@ -91,7 +104,7 @@ pub async fn request_passwordless_login(
     Ok(())
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct PasswordlessLoginRequestQueryFromEmail {
     email: String,
     token: Uuid,
@@ -100,7 +113,7 @@ pub struct PasswordlessLoginRequestQueryFromEmail {
     invite: bool,
 }
 
-async fn accept_new_user_invite(
+pub async fn accept_new_user_invite(
     state: &ServerState,
     cookies: &Cookies,
     email: String,
@@ -1,13 +1,14 @@
 use axum::{response::IntoResponse, Json};
+use schemars::JsonSchema;
 use serde::Serialize;
 
 use crate::auth::Authed;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 pub struct PermissionInfo {
-    name: &'static str,
-    description: &'static str,
-    key: &'static str,
+    pub name: &'static str,
+    pub description: &'static str,
+    pub key: &'static str,
 }
 
 pub const PERMISSIONS: &[PermissionInfo] = &[
@@ -163,6 +164,14 @@ pub const PERMISSIONS: &[PermissionInfo] = &[
     },
 ];
 
+/// The permission that grants full administrative access to an organization
+pub const ORG_ADMIN_PERMISSION: &str = "org_admin";
+
+/// Return true if `key` is a permission that can be granted to a role.
+pub fn is_known_permission(key: &str) -> bool {
+    key == ORG_ADMIN_PERMISSION || PERMISSIONS.iter().any(|p| p.key == key)
+}
+
 pub async fn list_permissions(_authed: Authed) -> impl IntoResponse {
     Json(PERMISSIONS)
 }
@@ -106,6 +106,7 @@ async fn login_with_nonexistent_email(db: sqlx::PgPool) {
         db,
         TestAppOptions {
             obfuscate_errors: Some(true),
+            ..Default::default()
         },
     )
     .await;
@@ -140,6 +141,7 @@ async fn login_with_wrong_password(db: sqlx::PgPool) {
         db,
         TestAppOptions {
             obfuscate_errors: Some(true),
+            ..Default::default()
         },
     )
     .await;
@@ -4,6 +4,8 @@ use error_stack::{Report, ResultExt};
 use crate::Error;
 
 mod bootstrap;
+mod gc_storage;
+mod migrate_storage_keys;
 
 #[derive(Args, Debug)]
 pub struct DbCommand {
@@ -21,6 +23,10 @@ pub enum DbSubcommand {
     Bootstrap(bootstrap::BootstrapCommand),
     /// Update the database with the latest migrations
     Migrate,
+    /// Move uploaded files to the keys given by the current storage key templates
+    MigrateStorageKeys(migrate_storage_keys::MigrateStorageKeysCommand),
+    /// Delete objects in storage that are no longer referenced by the database
+    GcStorage(gc_storage::GcStorageCommand),
 }
 
 impl DbCommand {
@@ -32,6 +38,8 @@ impl DbCommand {
         match self.command {
             DbSubcommand::Bootstrap(cmd) => cmd.handle(pg_pool).await,
             DbSubcommand::Migrate => crate::db::run_migrations(&pg_pool).await,
+            DbSubcommand::MigrateStorageKeys(cmd) => cmd.handle(pg_pool).await,
+            DbSubcommand::GcStorage(cmd) => cmd.handle(pg_pool).await,
         }
     }
 }
@@ -0,0 +1,52 @@
+use clap::Args;
+use error_stack::{Report, ResultExt};
+use sqlx::PgPool;
+
+use crate::{
+    storage::{AppStorage, AppStorageConfig},
+    storage_gc::{self, GcOptions},
+    Error,
+};
+
+#[derive(Args, Debug)]
+/// Delete objects in storage that are no longer referenced by the database
+pub struct GcStorageCommand {
+    /// Print the orphaned objects, without deleting them
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Only delete objects that are older than this many hours
+    #[clap(long, default_value_t = storage_gc::DEFAULT_GRACE_PERIOD_HOURS)]
+    grace_period_hours: i64,
+}
+
+impl GcStorageCommand {
+    pub async fn handle(self, pg_pool: PgPool) -> Result<(), Report<Error>> {
+        let config = AppStorageConfig::new().change_context(Error::Config)?;
+        let storage = AppStorage::new(config).change_context(Error::Config)?;
+
+        let options = GcOptions {
+            grace_period: chrono::Duration::hours(self.grace_period_hours),
+            dry_run: self.dry_run,
+        };
+        let reports = storage_gc::collect_garbage(&pg_pool, &storage, &options).await?;
+
+        for report in reports {
+            for key in &report.orphaned {
+                println!("{}: {key}", report.bucket);
+            }
+
+            println!(
+                "{}: scanned {} objects, {} orphaned ({} bytes), {} deleted, {} failed",
+                report.bucket,
+                report.scanned,
+                report.orphaned.len(),
+                report.orphaned_bytes,
+                report.deleted,
+                report.failed
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,42 @@
+use clap::Args;
+use error_stack::{Report, ResultExt};
+use sqlx::PgPool;
+
+use crate::{
+    storage::{AppStorage, AppStorageConfig},
+    Error,
+};
+
+#[derive(Args, Debug)]
+/// Move uploaded files to the keys given by the current storage key templates
+pub struct MigrateStorageKeysCommand {
+    /// Print the files that would be moved, without moving them
+    #[clap(long)]
+    dry_run: bool,
+}
+
+impl MigrateStorageKeysCommand {
+    pub async fn handle(self, pg_pool: PgPool) -> Result<(), Report<Error>> {
+        let config = AppStorageConfig::new().change_context(Error::Config)?;
+        let storage = AppStorage::new(config).change_context(Error::Config)?;
+
+        let moved = crate::models::post_image::storage::migrate_storage_keys(
+            &pg_pool,
+            &storage,
+            self.dry_run,
+        )
+        .await?;
+
+        for file in &moved {
+            println!("{}: {} -> {}", file.id, file.from, file.to);
+        }
+
+        if self.dry_run {
+            println!("{} files would be moved", moved.len());
+        } else {
+            println!("Moved {} files", moved.len());
+        }
+
+        Ok(())
+    }
+}
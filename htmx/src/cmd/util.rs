@@ -1,6 +1,8 @@
+use std::path::PathBuf;
+
 use clap::{Args, Subcommand};
 use error_stack::{Report, ResultExt};
-use schemars::schema_for;
+use schemars::{schema::RootSchema, schema_for, JsonSchema};
 
 use crate::Error;
 
@@ -13,6 +15,7 @@ pub struct UtilCommand {
 #[derive(Debug, Subcommand)]
 pub enum UtilSubcommand {
     HashPassword(HashPasswordCommand),
+    ExportTypes(ExportTypesCommand),
 }
 
 #[derive(Args, Debug)]
@@ -20,6 +23,25 @@ pub struct HashPasswordCommand {
     password: String,
 }
 
+/// Where `export-types` writes by default. This is resolved from the crate directory so that the
+/// command works from anywhere, and it doesn't share a name with the types that the sveltekit
+/// app's own `sync-types` writes.
+const DEFAULT_TYPES_OUTPUT: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../sveltekit/web/src/lib/htmx_api_types.ts"
+);
+
+#[derive(Args, Debug)]
+pub struct ExportTypesCommand {
+    /// The file to write the TypeScript types and Zod validators to
+    #[clap(long, default_value = DEFAULT_TYPES_OUTPUT)]
+    output: PathBuf,
+
+    /// Don't write anything, but fail if the existing output file is out of date
+    #[clap(long)]
+    check: bool,
+}
+
 impl UtilCommand {
     pub async fn handle(self) -> Result<(), Report<Error>> {
         match self.command {
@@ -30,8 +52,188 @@ impl UtilCommand {
                     .0;
                 println!("{hash}");
             }
+            UtilSubcommand::ExportTypes(cmd) => export_types(cmd)?,
         }
 
         Ok(())
     }
 }
+
+/// Collect the schema for each type, along with the name that the API code refers to it by. Many
+/// of the model payload types are aliases of a single struct, so the alias name can differ from
+/// the name in the schema.
+macro_rules! api_types {
+    ($($name:ident => $t:ty),* $(,)?) => {
+        vec![
+            $((stringify!($name), <$t as JsonSchema>::schema_name(), schema_for!($t)),)*
+        ]
+    };
+}
+
+fn api_types() -> Vec<(&'static str, String, RootSchema)> {
+    use crate::models::{
+        bulk::{BulkRequest, BulkResponse},
+        comment, organization,
+        pagination::ListResponse,
+        poll, post, post_image, reaction, report, report_section, role, user,
+    };
+
+    api_types![
+        Comment => comment::Comment,
+        CommentCreatePayload => comment::CommentCreatePayload,
+        CommentUpdatePayload => comment::CommentUpdatePayload,
+        CommentPatchPayload => comment::CommentPatchPayload,
+        Organization => organization::Organization,
+        OrganizationCreatePayload => organization::OrganizationCreatePayload,
+        OrganizationUpdatePayload => organization::OrganizationUpdatePayload,
+        Poll => poll::Poll,
+        PollCreatePayload => poll::PollCreatePayload,
+        PollUpdatePayload => poll::PollUpdatePayload,
+        PollPatchPayload => poll::PollPatchPayload,
+        Post => post::Post,
+        PostCreatePayload => post::PostCreatePayload,
+        PostUpdatePayload => post::PostUpdatePayload,
+        PostPatchPayload => post::PostPatchPayload,
+        PostPopulatedGetResult => post::PostPopulatedGetResult,
+        PostCreateResult => post::PostCreateResult,
+        PostPopulatedListResult => post::PostPopulatedListResult,
+        PostListResponse => ListResponse<post::PostPopulatedListResult>,
+        PostBulkRequest =>
+            BulkRequest<post::PostId, post::PostCreatePayload, post::PostUpdatePayload>,
+        PostBulkResponse => BulkResponse<post::PostId>,
+        PostImage => post_image::PostImage,
+        PostImageCreatePayload => post_image::PostImageCreatePayload,
+        PostImageUpdatePayload => post_image::PostImageUpdatePayload,
+        PostImageHashStatus => post_image::PostImageHashStatus,
+        PostImageOrderPayload => post_image::PostImageOrderPayload,
+        Reaction => reaction::Reaction,
+        ReactionCreatePayload => reaction::ReactionCreatePayload,
+        ReactionUpdatePayload => reaction::ReactionUpdatePayload,
+        ReactionPatchPayload => reaction::ReactionPatchPayload,
+        Report => report::Report,
+        ReportCreatePayload => report::ReportCreatePayload,
+        ReportUpdatePayload => report::ReportUpdatePayload,
+        ReportPatchPayload => report::ReportPatchPayload,
+        ReportPopulatedGetResult => report::ReportPopulatedGetResult,
+        ReportCreateResult => report::ReportCreateResult,
+        ReportPopulatedListResult => report::ReportPopulatedListResult,
+        ReportListResponse => ListResponse<report::ReportPopulatedListResult>,
+        ReportBulkRequest =>
+            BulkRequest<report::ReportId, report::ReportCreatePayload, report::ReportUpdatePayload>,
+        ReportBulkResponse => BulkResponse<report::ReportId>,
+        ReportSection => report_section::ReportSection,
+        ReportSectionCreatePayload => report_section::ReportSectionCreatePayload,
+        ReportSectionUpdatePayload => report_section::ReportSectionUpdatePayload,
+        ReportSectionPatchPayload => report_section::ReportSectionPatchPayload,
+        Role => role::Role,
+        RoleCreatePayload => role::RoleCreatePayload,
+        RoleUpdatePayload => role::RoleUpdatePayload,
+        RolePatchPayload => role::RolePatchPayload,
+        RoleListResponse => ListResponse<role::RoleListResult>,
+        RoleBulkRequest =>
+            BulkRequest<role::RoleId, role::RoleCreatePayload, role::RoleUpdatePayload>,
+        RoleBulkResponse => BulkResponse<role::RoleId>,
+        User => user::User,
+        UserCreatePayload => user::UserCreatePayload,
+        UserUpdatePayload => user::UserUpdatePayload,
+        UserPatchPayload => user::UserPatchPayload,
+        UserListResponse => ListResponse<user::UserListResult>,
+        SelfUser => crate::users::users::SelfUser,
+        Favorite => crate::users::favorites::Favorite,
+        FavoriteObjectType => crate::users::favorites::FavoriteObjectType,
+        RolePermissions => crate::users::members::RolePermissions,
+        UserRoles => crate::users::members::UserRoles,
+        CreatePasswordlessLoginRequestBody =>
+            crate::auth::passwordless_login::CreatePasswordlessLoginRequestBody,
+        StorageUsage => crate::storage_quota::StorageUsage,
+        ErrorResponse => crate::server::openapi::ErrorResponse,
+    ]
+}
+
+const TYPES_HEADER: &str = "// This file is autogenerated by the API's `util export-types` command.
+import { z, type ZodTypeAny } from 'zod';
+
+function memoizeOne<T extends ZodTypeAny>(fn: () => T) : () => T {
+  let cached: T|undefined;
+  return function() {
+    if(!cached) {
+      cached = fn();
+    }
+    return cached;
+  };
+}";
+
+/// Generate the TypeScript source containing the Zod validators and inferred types.
+fn generate_types() -> String {
+    let types = api_types();
+
+    let aliases = types
+        .iter()
+        .filter(|(name, schema_name, _)| name != schema_name)
+        .map(|(name, schema_name, _)| {
+            format!("export const {name} = {schema_name};\nexport type {name} = {schema_name};")
+        })
+        .collect::<Vec<_>>();
+
+    let merged = schemars_zod::merge_schemas(types.into_iter().map(|(_, _, schema)| schema));
+    let converted = schemars_zod::convert(merged);
+
+    let mut output = std::iter::once(TYPES_HEADER)
+        .chain(converted.iter().map(|(_, v)| v.as_str()))
+        .chain(aliases.iter().map(|s| s.as_str()))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    output.push('\n');
+    output
+}
+
+fn export_types(cmd: ExportTypesCommand) -> Result<(), Report<Error>> {
+    let output = generate_types();
+
+    if cmd.check {
+        let existing = match std::fs::read_to_string(&cmd.output) {
+            Ok(existing) => existing,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(Report::new(Error::TypeExport)).attach_printable(format!(
+                    "{} does not exist. Run `util export-types` to create it.",
+                    cmd.output.display()
+                ));
+            }
+            Err(e) => {
+                return Err(Report::new(e))
+                    .change_context(Error::TypeExport)
+                    .attach_printable_lazy(|| format!("Reading {}", cmd.output.display()));
+            }
+        };
+
+        if existing != output {
+            return Err(Report::new(Error::TypeExport)).attach_printable(format!(
+                "{} is out of date. Run `util export-types` to update it.",
+                cmd.output.display()
+            ));
+        }
+
+        return Ok(());
+    }
+
+    std::fs::write(&cmd.output, &output)
+        .change_context(Error::TypeExport)
+        .attach_printable_lazy(|| format!("Writing {}", cmd.output.display()))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn generated_types_include_aliases() {
+        let output = super::generate_types();
+        assert!(output.contains("export const Post ="));
+        assert!(
+            output.contains("export const PostCreatePayload = PostCreatePayloadAndUpdatePayload;")
+        );
+        assert!(output.contains("export const SelfUser ="));
+        assert!(output.contains("export const PostBulkRequest ="));
+        assert!(output.contains("export const PostImageOrderPayload ="));
+    }
+}
@@ -3,9 +3,11 @@ use rust_embed::RustEmbed;
 
 mod password_reset_request;
 mod passwordless_login;
+mod storage_quota_warning;
 
 pub use password_reset_request::*;
 pub use passwordless_login::*;
+pub use storage_quota_warning::*;
 
 #[derive(RustEmbed)]
 #[folder = "src/emails/templates"]
@@ -26,7 +26,7 @@ impl EmailTemplate for PasswordResetRequestTemplate {
 
     fn render(&self, renderer: &tera::Tera) -> Result<EmailContent, TeraError> {
         let url = format!(
-            "{scheme}://{host}/reset_password?token={token}&email={email}",
+            "{scheme}://{host}/reset?token={token}&email={email}",
             scheme = self.url_scheme,
             host = self.host,
             token = self.token,
@@ -34,7 +34,7 @@ impl EmailTemplate for PasswordResetRequestTemplate {
         );
 
         let regenerate_url = format!(
-            "{scheme}://{host}/reset_password",
+            "{scheme}://{host}/forgot",
             scheme = self.url_scheme,
             host = self.host
         );
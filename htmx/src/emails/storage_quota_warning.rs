@@ -0,0 +1,34 @@
+use filigree::email::templates::{render_template_pair, EmailContent, EmailTemplate, TeraError};
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub struct StorageQuotaWarningTemplate {
+    pub user_name: Option<String>,
+    pub organization_name: String,
+    /// The current usage, formatted for display
+    pub usage: String,
+    pub soft_limit: String,
+    pub hard_limit: Option<String>,
+}
+
+impl EmailTemplate for StorageQuotaWarningTemplate {
+    fn subject(&self) -> String {
+        format!(
+            "{} is running out of storage on Filigree Htmx Test App",
+            self.organization_name
+        )
+    }
+
+    fn render(&self, renderer: &tera::Tera) -> Result<EmailContent, TeraError> {
+        render_template_pair(
+            renderer,
+            self,
+            "storage_quota_warning.html",
+            "storage_quota_warning.txt",
+        )
+    }
+
+    fn tags(&self) -> Vec<String> {
+        vec!["storage_quota_warning".to_string()]
+    }
+}
@@ -11,6 +11,8 @@ use filigree::{
 };
 use thiserror::Error;
 
+use crate::upload_policy::UploadPolicyError;
+
 /// The top-level error type from the platform
 #[derive(Debug, Error)]
 pub enum Error {
@@ -63,6 +65,54 @@ pub enum Error {
     InvalidHostHeader,
     #[error("Type Export Error")]
     TypeExport,
+    /// A permission was not one of the known permission keys
+    #[error("Unknown permission")]
+    UnknownPermission,
+    /// The change would leave the organization without any active admins
+    #[error("The organization must have at least one admin")]
+    LastAdmin,
+    /// The object was modified since the version named in the `If-Match` header
+    #[error("The object has been modified")]
+    PreconditionFailed,
+    /// The body of a PATCH request was not a valid merge patch for the object
+    #[error("Invalid merge patch")]
+    InvalidPatch,
+    /// The request body was sent with a content type that the endpoint does not accept
+    #[error("Unsupported media type")]
+    UnsupportedMediaType,
+    /// The `Idempotency-Key` header was empty or too long
+    #[error("Invalid Idempotency-Key header")]
+    InvalidIdempotencyKey,
+    /// Another request with the same `Idempotency-Key` is still being processed
+    #[error("A request with this idempotency key is already in progress")]
+    IdempotencyKeyInProgress,
+    /// The `Idempotency-Key` was already used for a different endpoint
+    #[error("This idempotency key was already used for a different request")]
+    IdempotencyKeyReused,
+    /// A bulk request contained more than the allowed number of operations
+    #[error("Too many operations, the maximum is {0}")]
+    TooManyOperations(usize),
+    /// Failed while writing a CSV or NDJSON export
+    #[error("Failed to write export")]
+    Export,
+    /// Failed to decode an image or generate its renditions
+    #[error("Failed to process image")]
+    ImageProcessing,
+    /// An upload request was missing a header or parameter, or had an invalid one
+    #[error("Invalid upload request: {0}")]
+    InvalidUploadRequest(&'static str),
+    /// The `Upload-Offset` of a resumable upload request did not match the upload's current offset
+    #[error("Upload offset does not match")]
+    UploadOffsetMismatch,
+    /// The upload would put the organization over its storage quota
+    #[error("Storage quota exceeded")]
+    StorageQuotaExceeded,
+    /// A signed storage URL had an invalid signature or has expired
+    #[error("Invalid or expired URL")]
+    InvalidSignedUrl,
+    /// A new order for a post's images did not list each of its images exactly once
+    #[error("The new order must list each of the post's images exactly once")]
+    InvalidImageOrder,
 }
 
 impl From<Report<Error>> for Error {
@@ -83,6 +133,7 @@ impl Error {
                 frame,
                 |e| e.status_code(),
                 AuthError,
+                UploadPolicyError,
                 UploadInspectorError,
                 StorageError
             )
@@ -100,6 +151,7 @@ impl Error {
                 frame,
                 |e| e.error_kind(),
                 AuthError,
+                UploadPolicyError,
                 UploadInspectorError,
                 StorageError
             )
@@ -117,6 +169,7 @@ impl Error {
                 frame,
                 |e| e.obfuscate(),
                 AuthError,
+                UploadPolicyError,
                 UploadInspectorError
             )
         })
@@ -149,6 +202,22 @@ impl HttpError for Error {
             Error::MissingId(_) => ErrorKind::MissingId.as_str(),
             Error::InvalidHostHeader => FilErrorKind::InvalidHostHeader.as_str(),
             Error::Storage => FilErrorKind::Storage.as_str(),
+            Error::UnknownPermission => ErrorKind::UnknownPermission.as_str(),
+            Error::LastAdmin => ErrorKind::LastAdmin.as_str(),
+            Error::PreconditionFailed => ErrorKind::PreconditionFailed.as_str(),
+            Error::InvalidPatch => ErrorKind::InvalidPatch.as_str(),
+            Error::UnsupportedMediaType => ErrorKind::UnsupportedMediaType.as_str(),
+            Error::InvalidIdempotencyKey => ErrorKind::InvalidIdempotencyKey.as_str(),
+            Error::IdempotencyKeyInProgress => ErrorKind::IdempotencyKeyInProgress.as_str(),
+            Error::IdempotencyKeyReused => ErrorKind::IdempotencyKeyReused.as_str(),
+            Error::TooManyOperations(_) => ErrorKind::TooManyOperations.as_str(),
+            Error::Export => ErrorKind::Export.as_str(),
+            Error::ImageProcessing => ErrorKind::ImageProcessing.as_str(),
+            Error::InvalidUploadRequest(_) => ErrorKind::InvalidUploadRequest.as_str(),
+            Error::UploadOffsetMismatch => ErrorKind::UploadOffsetMismatch.as_str(),
+            Error::StorageQuotaExceeded => ErrorKind::StorageQuotaExceeded.as_str(),
+            Error::InvalidSignedUrl => ErrorKind::InvalidSignedUrl.as_str(),
+            Error::InvalidImageOrder => ErrorKind::InvalidImageOrder.as_str(),
             // These aren't ever returned, we just need some value to fill out the match
             Error::Config => "config",
             Error::TypeExport => "cli",
@@ -195,6 +264,22 @@ impl HttpError for Error {
             Error::Storage => StatusCode::INTERNAL_SERVER_ERROR,
             Error::Config => StatusCode::INTERNAL_SERVER_ERROR,
             Error::TypeExport => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::UnknownPermission => StatusCode::BAD_REQUEST,
+            Error::LastAdmin => StatusCode::CONFLICT,
+            Error::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+            Error::InvalidPatch => StatusCode::BAD_REQUEST,
+            Error::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Error::InvalidIdempotencyKey => StatusCode::BAD_REQUEST,
+            Error::IdempotencyKeyInProgress => StatusCode::CONFLICT,
+            Error::IdempotencyKeyReused => StatusCode::UNPROCESSABLE_ENTITY,
+            Error::TooManyOperations(_) => StatusCode::BAD_REQUEST,
+            Error::Export => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::ImageProcessing => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::InvalidUploadRequest(_) => StatusCode::BAD_REQUEST,
+            Error::UploadOffsetMismatch => StatusCode::CONFLICT,
+            Error::StorageQuotaExceeded => StatusCode::PAYLOAD_TOO_LARGE,
+            Error::InvalidSignedUrl => StatusCode::FORBIDDEN,
+            Error::InvalidImageOrder => StatusCode::BAD_REQUEST,
         }
     }
 
@@ -219,6 +304,22 @@ pub enum ErrorKind {
     AuthSubsystem,
     Login,
     MissingId,
+    UnknownPermission,
+    LastAdmin,
+    PreconditionFailed,
+    InvalidPatch,
+    UnsupportedMediaType,
+    InvalidIdempotencyKey,
+    IdempotencyKeyInProgress,
+    IdempotencyKeyReused,
+    TooManyOperations,
+    Export,
+    ImageProcessing,
+    InvalidUploadRequest,
+    UploadOffsetMismatch,
+    StorageQuotaExceeded,
+    InvalidSignedUrl,
+    InvalidImageOrder,
 }
 
 impl ErrorKind {
@@ -230,6 +331,22 @@ impl ErrorKind {
             ErrorKind::AuthSubsystem => "auth",
             ErrorKind::MissingId => "missing_id",
             ErrorKind::Login => "auth",
+            ErrorKind::UnknownPermission => "unknown_permission",
+            ErrorKind::LastAdmin => "last_admin",
+            ErrorKind::PreconditionFailed => "precondition_failed",
+            ErrorKind::InvalidPatch => "invalid_patch",
+            ErrorKind::UnsupportedMediaType => "unsupported_media_type",
+            ErrorKind::InvalidIdempotencyKey => "invalid_idempotency_key",
+            ErrorKind::IdempotencyKeyInProgress => "idempotency_key_in_progress",
+            ErrorKind::IdempotencyKeyReused => "idempotency_key_reused",
+            ErrorKind::TooManyOperations => "too_many_operations",
+            ErrorKind::Export => "export",
+            ErrorKind::ImageProcessing => "image_processing",
+            ErrorKind::InvalidUploadRequest => "invalid_upload_request",
+            ErrorKind::UploadOffsetMismatch => "upload_offset_mismatch",
+            ErrorKind::StorageQuotaExceeded => "storage_quota_exceeded",
+            ErrorKind::InvalidSignedUrl => "invalid_signed_url",
+            ErrorKind::InvalidImageOrder => "invalid_image_order",
         }
     }
 }
@@ -0,0 +1,75 @@
+//! expire_uploads background job
+#![allow(unused_imports, unused_variables, dead_code)]
+
+use effectum::{JobBuilder, JobRunner, Queue, RecurringJobSchedule, RunningJob};
+use error_stack::ResultExt;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{event, Level};
+
+use super::JobError;
+use crate::{models::post_image::tus, server::ServerState};
+
+/// The payload data for the expire_uploads background job
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ExpireUploadsJobPayload {}
+
+/// Run the expire_uploads background job
+async fn run(job: RunningJob, state: ServerState) -> Result<(), error_stack::Report<JobError>> {
+    let deleted = tus::delete_expired(&state)
+        .await
+        .change_context(JobError::Db)?;
+
+    if deleted > 0 {
+        event!(Level::INFO, deleted, "Deleted expired uploads");
+    }
+
+    Ok(())
+}
+
+/// Enqueue the expire_uploads job to run immediately
+pub async fn enqueue(
+    state: &ServerState,
+    name: impl ToString,
+    payload: &ExpireUploadsJobPayload,
+) -> Result<uuid::Uuid, effectum::Error> {
+    create_job_builder()
+        .name(name)
+        .json_payload(payload)?
+        .add_to(&state.queue)
+        .await
+}
+
+/// Register this job with the queue and initialize any recurring jobs.
+pub async fn register(
+    queue: &Queue,
+    init_recurring_jobs: bool,
+) -> Result<JobRunner<ServerState>, effectum::Error> {
+    let runner = JobRunner::builder("expire_uploads", run)
+        .autoheartbeat(false)
+        .format_failures_with_debug(true)
+        .build();
+
+    if init_recurring_jobs {
+        let hourly_job = create_job_builder()
+            .name("expire_uploads_hourly")
+            .json_payload(&ExpireUploadsJobPayload::default())?
+            .build();
+        queue
+            .upsert_recurring_job(
+                "expire_uploads_hourly".to_string(),
+                RecurringJobSchedule::Cron {
+                    spec: "0 0 * * * *".to_string(),
+                },
+                hourly_job,
+                false,
+            )
+            .await?;
+    }
+
+    Ok(runner)
+}
+
+fn create_job_builder() -> JobBuilder {
+    JobBuilder::new("expire_uploads").priority(1).weight(1)
+}
@@ -0,0 +1,92 @@
+//! gc_storage background job
+#![allow(unused_imports, unused_variables, dead_code)]
+
+use effectum::{JobBuilder, JobRunner, Queue, RecurringJobSchedule, RunningJob};
+use error_stack::ResultExt;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::JobError;
+use crate::{
+    server::ServerState,
+    storage_gc::{self, GcOptions},
+};
+
+/// The payload data for the gc_storage background job
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct GcStorageJobPayload {
+    /// Only report the orphaned objects, without deleting them
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Override the default grace period
+    #[serde(default)]
+    pub grace_period_hours: Option<i64>,
+}
+
+/// Run the gc_storage background job
+async fn run(job: RunningJob, state: ServerState) -> Result<(), error_stack::Report<JobError>> {
+    let payload: GcStorageJobPayload = job.json_payload().change_context(JobError::Payload)?;
+
+    let options = GcOptions {
+        grace_period: chrono::Duration::hours(
+            payload
+                .grace_period_hours
+                .unwrap_or(storage_gc::DEFAULT_GRACE_PERIOD_HOURS),
+        ),
+        dry_run: payload.dry_run,
+    };
+
+    storage_gc::collect_garbage(&state.db, &state.storage, &options)
+        .await
+        .change_context(JobError::Storage)?;
+
+    Ok(())
+}
+
+/// Enqueue the gc_storage job to run immediately
+pub async fn enqueue(
+    state: &ServerState,
+    name: impl ToString,
+    payload: &GcStorageJobPayload,
+) -> Result<uuid::Uuid, effectum::Error> {
+    create_job_builder()
+        .name(name)
+        .json_payload(payload)?
+        .add_to(&state.queue)
+        .await
+}
+
+/// Register this job with the queue and initialize any recurring jobs.
+pub async fn register(
+    queue: &Queue,
+    init_recurring_jobs: bool,
+) -> Result<JobRunner<ServerState>, effectum::Error> {
+    let runner = JobRunner::builder("gc_storage", run)
+        .autoheartbeat(true)
+        .format_failures_with_debug(true)
+        .build();
+
+    if init_recurring_jobs {
+        let daily_job = create_job_builder()
+            .name("gc_storage_daily")
+            .json_payload(&GcStorageJobPayload::default())?
+            .build();
+        queue
+            .upsert_recurring_job(
+                // Recurring job names are global, so this can't just be "daily".
+                "gc_storage_daily".to_string(),
+                RecurringJobSchedule::Cron {
+                    spec: "0 0 3 * * *".to_string(),
+                },
+                daily_job,
+                false,
+            )
+            .await?;
+    }
+
+    Ok(runner)
+}
+
+fn create_job_builder() -> JobBuilder {
+    JobBuilder::new("gc_storage").priority(1).weight(1)
+}
@@ -0,0 +1,66 @@
+//! generate_renditions background job
+#![allow(unused_imports, unused_variables, dead_code)]
+
+use effectum::{JobBuilder, JobRunner, Queue, RecurringJobSchedule, RunningJob};
+use error_stack::ResultExt;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::JobError;
+use crate::{
+    models::{organization::OrganizationId, post_image::PostImageId},
+    server::ServerState,
+};
+
+/// The payload data for the generate_renditions background job
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GenerateRenditionsJobPayload {
+    pub organization_id: OrganizationId,
+    pub post_image_id: PostImageId,
+}
+
+/// Run the generate_renditions background job
+async fn run(job: RunningJob, state: ServerState) -> Result<(), error_stack::Report<JobError>> {
+    let payload: GenerateRenditionsJobPayload =
+        job.json_payload().change_context(JobError::Payload)?;
+
+    crate::models::post_image::renditions::generate(
+        &state,
+        payload.organization_id,
+        payload.post_image_id,
+    )
+    .await
+    .change_context(JobError::Renditions)?;
+
+    Ok(())
+}
+
+/// Enqueue the generate_renditions job to run immediately
+pub async fn enqueue(
+    state: &ServerState,
+    name: impl ToString,
+    payload: &GenerateRenditionsJobPayload,
+) -> Result<uuid::Uuid, effectum::Error> {
+    create_job_builder()
+        .name(name)
+        .json_payload(payload)?
+        .add_to(&state.queue)
+        .await
+}
+
+/// Register this job with the queue and initialize any recurring jobs.
+pub async fn register(
+    queue: &Queue,
+    init_recurring_jobs: bool,
+) -> Result<JobRunner<ServerState>, effectum::Error> {
+    let runner = JobRunner::builder("generate_renditions", run)
+        .autoheartbeat(false)
+        .format_failures_with_debug(true)
+        .build();
+
+    Ok(runner)
+}
+
+fn create_job_builder() -> JobBuilder {
+    JobBuilder::new("generate_renditions").priority(1).weight(1)
+}
@@ -1,6 +1,11 @@
 //! Background jobs
 
+pub mod expire_uploads;
+pub mod gc_storage;
+pub mod generate_renditions;
+pub mod purge_idempotency_keys;
 pub mod send_annoying_emails;
+pub mod storage_quota_warning;
 pub mod transcode_video;
 
 use std::path::Path;
@@ -15,6 +20,12 @@ use crate::{server::ServerState, Error};
 enum JobError {
     #[error("Failed to read payload")]
     Payload,
+    #[error("Database error")]
+    Db,
+    #[error("Failed to generate image renditions")]
+    Renditions,
+    #[error("Storage error")]
+    Storage,
 }
 
 pub struct QueueWorkers {
@@ -36,10 +47,28 @@ pub async fn init(
     init_recurring_jobs: bool,
 ) -> Result<QueueWorkers, error_stack::Report<Error>> {
     // register the jobs
+    let expire_uploads_runner = expire_uploads::register(&state.queue, init_recurring_jobs)
+        .await
+        .change_context(Error::TaskQueue)?;
+    let gc_storage_runner = gc_storage::register(&state.queue, init_recurring_jobs)
+        .await
+        .change_context(Error::TaskQueue)?;
+    let generate_renditions_runner =
+        generate_renditions::register(&state.queue, init_recurring_jobs)
+            .await
+            .change_context(Error::TaskQueue)?;
+    let purge_idempotency_keys_runner =
+        purge_idempotency_keys::register(&state.queue, init_recurring_jobs)
+            .await
+            .change_context(Error::TaskQueue)?;
     let send_annoying_emails_runner =
         send_annoying_emails::register(&state.queue, init_recurring_jobs)
             .await
             .change_context(Error::TaskQueue)?;
+    let storage_quota_warning_runner =
+        storage_quota_warning::register(&state.queue, init_recurring_jobs)
+            .await
+            .change_context(Error::TaskQueue)?;
     let transcode_video_runner = transcode_video::register(&state.queue, init_recurring_jobs)
         .await
         .change_context(Error::TaskQueue)?;
@@ -57,7 +86,15 @@ pub async fn init(
     let worker_default = Worker::builder(&state.queue, state.clone())
         .min_concurrency(worker_default_min_concurrency)
         .max_concurrency(worker_default_max_concurrency)
-        .jobs([send_annoying_emails_runner, transcode_video_runner])
+        .jobs([
+            expire_uploads_runner,
+            gc_storage_runner,
+            generate_renditions_runner,
+            purge_idempotency_keys_runner,
+            send_annoying_emails_runner,
+            storage_quota_warning_runner,
+            transcode_video_runner,
+        ])
         .build()
         .await
         .change_context(Error::TaskQueue)?;
@@ -0,0 +1,86 @@
+//! purge_idempotency_keys background job
+#![allow(unused_imports, unused_variables, dead_code)]
+
+use effectum::{JobBuilder, JobRunner, Queue, RecurringJobSchedule, RunningJob};
+use error_stack::ResultExt;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{event, Level};
+
+use super::JobError;
+use crate::server::ServerState;
+
+/// The payload data for the purge_idempotency_keys background job
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PurgeIdempotencyKeysJobPayload {}
+
+/// Run the purge_idempotency_keys background job
+async fn run(job: RunningJob, state: ServerState) -> Result<(), error_stack::Report<JobError>> {
+    let payload: PurgeIdempotencyKeysJobPayload =
+        job.json_payload().change_context(JobError::Payload)?;
+
+    let purged = crate::server::idempotency::purge_expired(&state.db)
+        .await
+        .change_context(JobError::Db)?;
+    event!(Level::INFO, purged, "Purged expired idempotency keys");
+
+    Ok(())
+}
+
+/// Enqueue the purge_idempotency_keys job to run immediately
+pub async fn enqueue(
+    state: &ServerState,
+    name: impl ToString,
+    payload: &PurgeIdempotencyKeysJobPayload,
+) -> Result<uuid::Uuid, effectum::Error> {
+    create_job_builder()
+        .name(name)
+        .json_payload(payload)?
+        .add_to(&state.queue)
+        .await
+}
+
+/// Register this job with the queue and initialize any recurring jobs.
+pub async fn register(
+    queue: &Queue,
+    init_recurring_jobs: bool,
+) -> Result<JobRunner<ServerState>, effectum::Error> {
+    let runner = JobRunner::builder("purge_idempotency_keys", run)
+        .autoheartbeat(false)
+        .format_failures_with_debug(true)
+        .build();
+
+    if init_recurring_jobs {
+        // This job was previously registered under the name "hourly".
+        match queue.delete_recurring_job("hourly".to_string()).await {
+            Ok(_) => {}
+            // It's ok if the job doesn't exist. This just means it was already deleted
+            // on a previous execution.
+            Err(effectum::Error::NotFound) => {}
+            Err(e) => return Err(e),
+        };
+
+        let hourly_job = create_job_builder()
+            .name("purge_idempotency_keys_hourly")
+            .json_payload(&PurgeIdempotencyKeysJobPayload {})?
+            .build();
+        queue
+            .upsert_recurring_job(
+                "purge_idempotency_keys_hourly".to_string(),
+                RecurringJobSchedule::Cron {
+                    spec: "0 0 * * * *".to_string(),
+                },
+                hourly_job,
+                false,
+            )
+            .await?;
+    }
+
+    Ok(runner)
+}
+
+fn create_job_builder() -> JobBuilder {
+    JobBuilder::new("purge_idempotency_keys")
+        .priority(1)
+        .weight(1)
+}
@@ -0,0 +1,60 @@
+//! storage_quota_warning background job
+#![allow(unused_imports, unused_variables, dead_code)]
+
+use effectum::{JobBuilder, JobRunner, Queue, RecurringJobSchedule, RunningJob};
+use error_stack::ResultExt;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::JobError;
+use crate::{models::organization::OrganizationId, server::ServerState, storage_quota};
+
+/// The payload data for the storage_quota_warning background job
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StorageQuotaWarningJobPayload {
+    pub organization_id: OrganizationId,
+}
+
+/// Run the storage_quota_warning background job
+async fn run(job: RunningJob, state: ServerState) -> Result<(), error_stack::Report<JobError>> {
+    let payload: StorageQuotaWarningJobPayload =
+        job.json_payload().change_context(JobError::Payload)?;
+
+    storage_quota::send_soft_limit_warning(&state, &payload.organization_id)
+        .await
+        .change_context(JobError::Db)?;
+
+    Ok(())
+}
+
+/// Enqueue the storage_quota_warning job to run immediately
+pub async fn enqueue(
+    state: &ServerState,
+    name: impl ToString,
+    payload: &StorageQuotaWarningJobPayload,
+) -> Result<uuid::Uuid, effectum::Error> {
+    create_job_builder()
+        .name(name)
+        .json_payload(payload)?
+        .add_to(&state.queue)
+        .await
+}
+
+/// Register this job with the queue and initialize any recurring jobs.
+pub async fn register(
+    queue: &Queue,
+    init_recurring_jobs: bool,
+) -> Result<JobRunner<ServerState>, effectum::Error> {
+    let runner = JobRunner::builder("storage_quota_warning", run)
+        .autoheartbeat(false)
+        .format_failures_with_debug(true)
+        .build();
+
+    Ok(runner)
+}
+
+fn create_job_builder() -> JobBuilder {
+    JobBuilder::new("storage_quota_warning")
+        .priority(1)
+        .weight(1)
+}
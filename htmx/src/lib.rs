@@ -8,8 +8,14 @@ pub mod models;
 pub mod pages;
 pub mod server;
 pub mod storage;
+pub mod storage_blobs;
+pub mod storage_gc;
+pub mod storage_keys;
+pub mod storage_quota;
+pub mod storage_urls;
 #[cfg(test)]
 pub mod tests;
+pub mod upload_policy;
 pub mod users;
 
 pub use error::Error;
@@ -131,6 +131,15 @@ struct ServeCommand {
     /// The location to store the queue database
     #[clap(long, env = "QUEUE_PATH", default_value_t = String::from("queue.db"))]
     queue_path: String,
+
+    /// The renditions to generate for uploaded images, in the form `name:max_size:format`
+    #[clap(
+        long,
+        env = "IMAGE_RENDITIONS",
+        value_delimiter = ',',
+        default_value = "thumb:256:webp,large:1024:webp"
+    )]
+    image_renditions: Vec<filigree_htmx_test_app::models::post_image::renditions::RenditionSpec>,
 }
 
 async fn serve(cmd: ServeCommand) -> Result<(), Report<Error>> {
@@ -238,6 +247,7 @@ async fn serve(cmd: ServeCommand) -> Result<(), Report<Error>> {
         init_recurring_jobs: true,
         storage: filigree_htmx_test_app::storage::AppStorageConfig::new()
             .change_context(Error::ServerStart)?,
+        image_renditions: cmd.image_renditions,
     })
     .await?;
 
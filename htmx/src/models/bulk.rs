@@ -0,0 +1,367 @@
+//! Bulk create, update and delete operations.
+//!
+//! A bulk request contains a list of operations which run in a single transaction. The creates run
+//! first, then the updates, and then the deletes.
+//!
+//! Each operation gets its own result. When the request is `atomic`, which is the default, any
+//! failure rolls back the whole request, so all the creates are inserted with one multi-row
+//! statement. Otherwise each operation runs in its own savepoint, so the successful operations are
+//! saved and only the failed ones are skipped.
+
+use async_trait::async_trait;
+use error_stack::{Report, ResultExt};
+use filigree::errors::HttpError;
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sqlx::{Acquire, PgConnection};
+use tracing::{event, Level};
+
+use crate::{
+    auth::{AuthInfo, Authed},
+    server::ServerState,
+    Error,
+};
+
+/// The maximum number of operations in a single bulk request
+pub const MAX_BULK_OPERATIONS: usize = 1000;
+
+/// A single operation in a bulk request
+#[derive(Deserialize, Debug, JsonSchema)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BulkOperation<Id, C, U> {
+    Create { payload: C },
+    Update { id: Id, payload: U },
+    Delete { id: Id },
+}
+
+/// The body of a bulk request.
+///
+/// The operations are parsed one at a time, so that an invalid operation shows up in its own
+/// result instead of rejecting the whole request.
+#[derive(Deserialize, Debug, JsonSchema)]
+#[serde(bound = "")]
+pub struct BulkRequest<Id, C, U> {
+    /// If true, all of the operations are rolled back if any of them fail.
+    #[serde(default = "default_atomic")]
+    pub atomic: bool,
+    #[schemars(with = "Vec<BulkOperation<Id, C, U>>")]
+    pub operations: Vec<serde_json::Value>,
+    #[serde(skip)]
+    _types: std::marker::PhantomData<(Id, C, U)>,
+}
+
+fn default_atomic() -> bool {
+    true
+}
+
+/// The response to a bulk request
+#[derive(Serialize, Debug, JsonSchema)]
+pub struct BulkResponse<Id> {
+    /// True if the changes were saved. This is false when an atomic request had a failure.
+    pub committed: bool,
+    /// The result of each operation, in the same order as the request
+    pub results: Vec<BulkItemResult<Id>>,
+}
+
+/// The result of a single operation in a bulk request
+#[derive(Serialize, Debug, JsonSchema)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum BulkItemResult<Id> {
+    /// The operation succeeded
+    Ok { id: Id },
+    /// The operation failed
+    Error {
+        id: Option<Id>,
+        /// A machine-readable identifier for the type of error
+        kind: String,
+        message: String,
+    },
+    /// The operation was not saved because another operation in an atomic request failed
+    Skipped { id: Option<Id> },
+}
+
+impl<Id> BulkItemResult<Id> {
+    fn error(id: Option<Id>, kind: &str, message: impl ToString) -> Self {
+        Self::Error {
+            id,
+            kind: kind.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    fn from_report(id: Option<Id>, report: &Report<Error>) -> Self {
+        let error = report.current_context();
+        Self::error(id, error.error_kind(), error)
+    }
+
+    fn into_skipped(self) -> Self {
+        match self {
+            Self::Ok { id } => Self::Skipped { id: Some(id) },
+            other => other,
+        }
+    }
+}
+
+/// Objects that were removed by a bulk delete
+pub struct BulkDeleted<Id> {
+    pub ids: Vec<Id>,
+    /// Storage keys for files which should be removed once the transaction commits
+    pub files: Vec<String>,
+}
+
+/// A model which supports bulk operations
+#[async_trait]
+pub trait BulkModel {
+    type Id: Copy + PartialEq + Send + Sync + DeserializeOwned + 'static;
+    type CreatePayload: DeserializeOwned + Send + 'static;
+    type UpdatePayload: DeserializeOwned + Send + 'static;
+
+    /// The ID to use for a new object
+    fn bulk_create_id(payload: &Self::CreatePayload) -> Self::Id;
+
+    /// Insert the objects with a single statement. Objects with an ID that already exists are
+    /// skipped, and the IDs that were actually inserted are returned.
+    async fn bulk_insert(
+        db: &mut PgConnection,
+        auth: &AuthInfo,
+        objects: Vec<(Self::Id, Self::CreatePayload)>,
+    ) -> Result<Vec<Self::Id>, Report<Error>>;
+
+    /// Update an object, returning false if it was not found.
+    async fn bulk_update(
+        db: &mut PgConnection,
+        auth: &AuthInfo,
+        id: &Self::Id,
+        payload: Self::UpdatePayload,
+    ) -> Result<bool, Report<Error>>;
+
+    /// Delete the objects, returning the IDs that were found and deleted.
+    async fn bulk_delete(
+        state: &ServerState,
+        db: &mut PgConnection,
+        auth: &Authed,
+        ids: &[Self::Id],
+    ) -> Result<BulkDeleted<Self::Id>, Report<Error>>;
+
+    /// Remove the files returned from [BulkModel::bulk_delete], after the transaction commits.
+    async fn delete_files(_state: &ServerState, _files: Vec<String>) -> Result<(), Report<Error>> {
+        Ok(())
+    }
+}
+
+/// Run the operations in a bulk request.
+pub async fn run<M: BulkModel>(
+    state: &ServerState,
+    auth: &Authed,
+    request: BulkRequest<M::Id, M::CreatePayload, M::UpdatePayload>,
+) -> Result<BulkResponse<M::Id>, Error> {
+    if request.operations.len() > MAX_BULK_OPERATIONS {
+        return Err(Error::TooManyOperations(MAX_BULK_OPERATIONS));
+    }
+
+    let mut results = Vec::with_capacity(request.operations.len());
+    let mut creates = Vec::new();
+    let mut updates = Vec::new();
+    let mut deletes = Vec::new();
+
+    for (index, operation) in request.operations.into_iter().enumerate() {
+        let operation = serde_json::from_value::<
+            BulkOperation<M::Id, M::CreatePayload, M::UpdatePayload>,
+        >(operation);
+
+        let result = match operation {
+            Ok(BulkOperation::Create { payload }) => {
+                let id = M::bulk_create_id(&payload);
+                creates.push((index, id, payload));
+                BulkItemResult::Ok { id }
+            }
+            Ok(BulkOperation::Update { id, payload }) => {
+                updates.push((index, id, payload));
+                BulkItemResult::Ok { id }
+            }
+            Ok(BulkOperation::Delete { id }) => {
+                deletes.push((index, id));
+                BulkItemResult::Ok { id }
+            }
+            Err(e) => BulkItemResult::error(None, "invalid_operation", e),
+        };
+
+        results.push(result);
+    }
+
+    let has_errors = |results: &[BulkItemResult<M::Id>]| {
+        results
+            .iter()
+            .any(|r| matches!(r, BulkItemResult::Error { .. }))
+    };
+
+    if request.atomic && has_errors(&results) {
+        return Ok(BulkResponse {
+            committed: false,
+            results: results
+                .into_iter()
+                .map(BulkItemResult::into_skipped)
+                .collect(),
+        });
+    }
+
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
+
+    // Each group of operations runs in a savepoint so that a failure doesn't abort the rest of
+    // the transaction.
+    if request.atomic {
+        // Any failure rolls back everything anyway, so insert all the objects at once.
+        let (indexes, objects) = creates
+            .into_iter()
+            .map(|(index, id, payload)| (index, (id, payload)))
+            .unzip();
+        insert_creates::<M>(&mut tx, auth, indexes, objects, &mut results).await?;
+    } else {
+        // Insert the objects one at a time, so that a bad row only fails its own operation.
+        for (index, id, payload) in creates {
+            insert_creates::<M>(
+                &mut tx,
+                auth,
+                vec![index],
+                vec![(id, payload)],
+                &mut results,
+            )
+            .await?;
+        }
+    }
+
+    for (index, id, payload) in updates {
+        let mut savepoint = tx.begin().await.change_context(Error::Db)?;
+        match M::bulk_update(&mut *savepoint, auth, &id, payload).await {
+            Ok(true) => {
+                savepoint.commit().await.change_context(Error::Db)?;
+            }
+            Ok(false) => {
+                savepoint.rollback().await.change_context(Error::Db)?;
+                results[index] = BulkItemResult::error(Some(id), "not_found", "Object not found");
+            }
+            Err(e) => {
+                savepoint.rollback().await.change_context(Error::Db)?;
+                results[index] = BulkItemResult::from_report(Some(id), &e);
+            }
+        }
+    }
+
+    let mut files = Vec::new();
+    if request.atomic {
+        delete_objects::<M>(state, &mut tx, auth, deletes, &mut results, &mut files).await?;
+    } else {
+        for delete in deletes {
+            delete_objects::<M>(state, &mut tx, auth, vec![delete], &mut results, &mut files)
+                .await?;
+        }
+    }
+
+    if request.atomic && has_errors(&results) {
+        tx.rollback().await.change_context(Error::Db)?;
+        return Ok(BulkResponse {
+            committed: false,
+            results: results
+                .into_iter()
+                .map(BulkItemResult::into_skipped)
+                .collect(),
+        });
+    }
+
+    tx.commit().await.change_context(Error::Db)?;
+
+    if !files.is_empty() {
+        // The objects are already gone at this point, so don't fail the request if the files
+        // can't be removed.
+        if let Err(e) = M::delete_files(state, files).await {
+            event!(Level::ERROR, error = ?e, "Failed to remove files after bulk delete");
+        }
+    }
+
+    Ok(BulkResponse {
+        committed: true,
+        results,
+    })
+}
+
+/// Insert a group of objects in a savepoint, and record the result of each one.
+async fn insert_creates<M: BulkModel>(
+    tx: &mut PgConnection,
+    auth: &Authed,
+    indexes: Vec<usize>,
+    objects: Vec<(M::Id, M::CreatePayload)>,
+    results: &mut [BulkItemResult<M::Id>],
+) -> Result<(), Error> {
+    if objects.is_empty() {
+        return Ok(());
+    }
+
+    let ids = objects.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+
+    let mut savepoint = tx.begin().await.change_context(Error::Db)?;
+    match M::bulk_insert(&mut *savepoint, auth, objects).await {
+        Ok(mut inserted) => {
+            savepoint.commit().await.change_context(Error::Db)?;
+            for (index, id) in indexes.into_iter().zip(ids) {
+                // Remove each ID as it's matched, so that a duplicate ID within the request
+                // is reported as a conflict.
+                if let Some(i) = inserted.iter().position(|inserted_id| *inserted_id == id) {
+                    inserted.swap_remove(i);
+                } else {
+                    results[index] = BulkItemResult::error(
+                        Some(id),
+                        "conflict",
+                        "An object with this ID already exists",
+                    );
+                }
+            }
+        }
+        Err(e) => {
+            savepoint.rollback().await.change_context(Error::Db)?;
+            for (index, id) in indexes.into_iter().zip(ids) {
+                results[index] = BulkItemResult::from_report(Some(id), &e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Delete a group of objects in a savepoint, and record the result of each one. The storage keys
+/// of any files to remove are added to `files`.
+async fn delete_objects<M: BulkModel>(
+    state: &ServerState,
+    tx: &mut PgConnection,
+    auth: &Authed,
+    deletes: Vec<(usize, M::Id)>,
+    results: &mut [BulkItemResult<M::Id>],
+    files: &mut Vec<String>,
+) -> Result<(), Error> {
+    if deletes.is_empty() {
+        return Ok(());
+    }
+
+    let ids = deletes.iter().map(|(_, id)| *id).collect::<Vec<_>>();
+
+    let mut savepoint = tx.begin().await.change_context(Error::Db)?;
+    match M::bulk_delete(state, &mut *savepoint, auth, &ids).await {
+        Ok(deleted) => {
+            savepoint.commit().await.change_context(Error::Db)?;
+            files.extend(deleted.files);
+            for (index, id) in deletes {
+                if !deleted.ids.contains(&id) {
+                    results[index] =
+                        BulkItemResult::error(Some(id), "not_found", "Object not found");
+                }
+            }
+        }
+        Err(e) => {
+            savepoint.rollback().await.change_context(Error::Db)?;
+            for (index, id) in deletes {
+                results[index] = BulkItemResult::from_report(Some(id), &e);
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,9 @@
+UPDATE
+  public.comments
+SET
+  body = COALESCE($1, body),
+  updated_at = NOW()
+WHERE
+  id = $2
+  AND post_id = $3
+  AND organization_id = $4
@@ -7,16 +7,22 @@ use filigree::{
     errors::OrderByError,
     sql::{BindingOperator, FilterBuilder, ValuesBuilder},
 };
+use schemars::JsonSchema;
 use serde::Deserialize;
 use sqlx::{
-    postgres::PgRow, query_file, query_file_as, query_file_scalar, PgConnection, PgExecutor,
+    postgres::PgRow, query_file, query_file_as, query_file_scalar, PgConnection, PgExecutor, PgPool,
 };
 use tracing::{event, instrument, Level};
 
 use super::{types::*, CommentId};
 use crate::{
-    auth::AuthInfo,
-    models::{organization::OrganizationId, post::PostId},
+    auth::{AuthInfo, Authed},
+    models::{
+        export::{self, ExportFormat},
+        filters::ExtraFilters,
+        organization::OrganizationId,
+        post::PostId,
+    },
     Error,
 };
 
@@ -67,7 +73,7 @@ fn parse_order_by(field: &str) -> Result<(bool, OrderByField), OrderByError> {
     Ok((descending, value))
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Deserialize, Debug, Default, JsonSchema)]
 pub struct ListQueryFilters {
     pub page: Option<u32>,
     pub per_page: Option<u32>,
@@ -76,21 +82,41 @@ pub struct ListQueryFilters {
     #[serde(default)]
     pub id: Vec<CommentId>,
     #[serde(default)]
+    pub body: Vec<String>,
+    #[serde(default)]
     pub post_id: Vec<PostId>,
     pub updated_at_lte: Option<chrono::DateTime<chrono::Utc>>,
     pub updated_at_gte: Option<chrono::DateTime<chrono::Utc>>,
     pub created_at_lte: Option<chrono::DateTime<chrono::Utc>>,
     pub created_at_gte: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only return objects where `body` contains this string
+    pub body_contains: Option<String>,
+    /// Only return objects where `body` contains this string, ignoring case
+    pub body_icontains: Option<String>,
 }
 
 impl ListQueryFilters {
     fn build_where_clause(&self) -> String {
-        let mut bindings = FilterBuilder::new(4);
+        let mut filters = ExtraFilters::new(4);
+
+        if self.body_contains.is_some() {
+            filters.contains("body", false);
+        }
+
+        if self.body_icontains.is_some() {
+            filters.contains("body", true);
+        }
+
+        let mut bindings = FilterBuilder::new(filters.next_binding());
 
         if !self.id.is_empty() {
             bindings.add_vec("id", &self.id);
         }
 
+        if !self.body.is_empty() {
+            bindings.add_vec("body", &self.body);
+        }
+
         if !self.post_id.is_empty() {
             bindings.add_vec("post_id", &self.post_id);
         }
@@ -111,17 +137,33 @@ impl ListQueryFilters {
             bindings.add_option("created_at", &self.created_at_gte, BindingOperator::Gte);
         }
 
-        let query = bindings.to_string();
+        let mut query = bindings.to_string();
+        filters.append_to(&mut query);
         event!(Level::DEBUG, %query);
         query
     }
 
     fn bind_to_query<'a, T>(&'a self, mut query: QueryAs<'a, T>) -> QueryAs<'a, T> {
+        if self.body_contains.is_some() {
+            event!(Level::DEBUG, body_contains = ?self.body_contains);
+            query = query.bind(&self.body_contains);
+        }
+
+        if self.body_icontains.is_some() {
+            event!(Level::DEBUG, body_icontains = ?self.body_icontains);
+            query = query.bind(&self.body_icontains);
+        }
+
         if !self.id.is_empty() {
             event!(Level::DEBUG, id = ?self.id);
             query = query.bind(&self.id);
         }
 
+        if !self.body.is_empty() {
+            event!(Level::DEBUG, body = ?self.body);
+            query = query.bind(&self.body);
+        }
+
         if !self.post_id.is_empty() {
             event!(Level::DEBUG, post_id = ?self.post_id);
             query = query.bind(&self.post_id);
@@ -204,6 +246,43 @@ impl Comment {
         Self::list_internal(q, db, auth, filters).await
     }
 
+    /// Stream all the Comment objects matching the filters as CSV or NDJSON. Unlike the list
+    /// functions, this does not limit the number of rows returned.
+    pub fn export(
+        db: &PgPool,
+        auth: Authed,
+        filters: ListQueryFilters,
+        format: ExportFormat,
+    ) -> Result<axum::response::Response, error_stack::Report<Error>> {
+        auth.require_permission(super::READ_PERMISSION)?;
+
+        let (descending, order_by_field) =
+            parse_order_by(filters.order_by.as_deref().unwrap_or("-updated_at"))
+                .change_context(Error::Filter)?;
+        let order_direction = if descending { "DESC" } else { "ASC" };
+
+        let q = include_str!("list.sql")
+            .replace(
+                "__insertion_point_order_by",
+                &format!("{} {}", order_by_field.as_str(), order_direction),
+            )
+            .replace("__insertion_point_filters", &filters.build_where_clause());
+        let (limit, offset) = export::bounds(filters.page, filters.per_page);
+
+        let response = export::stream(db, "comments", format, move |mut conn, writer| async move {
+            let mut query = sqlx::query_as::<_, CommentListResult>(q.as_str())
+                .bind(&auth.organization_id)
+                .bind(limit)
+                .bind(offset);
+
+            query = filters.bind_to_query(query);
+
+            writer.write_rows(query.fetch(&mut *conn)).await
+        });
+
+        Ok(response)
+    }
+
     async fn list_internal<T>(
         query_template: &str,
         db: impl PgExecutor<'_>,
@@ -419,6 +498,29 @@ impl Comment {
         Ok(result.rows_affected() > 0)
     }
 
+    /// Update only the fields of a single child that are present in a JSON Merge Patch.
+    #[instrument(skip(db))]
+    pub async fn patch_one_with_parent_post(
+        db: impl PgExecutor<'_>,
+        auth: &AuthInfo,
+        parent_id: &PostId,
+        id: &CommentId,
+        payload: CommentPatchPayload,
+    ) -> Result<bool, error_stack::Report<Error>> {
+        let result = query_file!(
+            "src/models/comment/patch_one_with_parent_post.sql",
+            payload.body.as_ref() as _,
+            id.as_uuid(),
+            parent_id.as_uuid(),
+            auth.organization_id.as_uuid()
+        )
+        .execute(db)
+        .await
+        .change_context(Error::Db)?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
     /// Update the children of the given parent.
     /// Insert new values that are not yet in the database and
     /// delete existing values that are not in the payload.
@@ -109,3 +109,11 @@ impl Default for CommentCreatePayloadAndUpdatePayload {
         }
     }
 }
+
+/// A JSON Merge Patch for a Comment. Fields that are omitted from the patch are left unchanged.
+#[derive(Deserialize, Debug, Clone, schemars::JsonSchema)]
+pub struct CommentPatchPayload {
+    #[serde(default, deserialize_with = "crate::models::merge_patch::required")]
+    #[schemars(with = "String")]
+    pub body: Option<String>,
+}
@@ -0,0 +1,361 @@
+//! Streaming CSV and NDJSON exports for list endpoints.
+//!
+//! When the `Accept` header of a list request asks for `text/csv` or `application/x-ndjson`, the
+//! endpoint streams every matching row instead of returning a page of JSON. Rows are read from a
+//! database cursor and written to the response as they arrive, so a large export is never held in
+//! memory. The usual page size limit does not apply, though `page` and `per_page` are still honored
+//! when they are given.
+
+use std::future::Future;
+
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use bytes::{BufMut, Bytes, BytesMut};
+use error_stack::{Report, ResultExt};
+use futures::{Stream, TryStreamExt};
+use serde::Serialize;
+use sqlx::{pool::PoolConnection, PgPool, Postgres};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{event, Level};
+
+use crate::Error;
+
+/// Buffered output is sent to the client once it reaches this size.
+const FLUSH_BYTES: usize = 16 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Ndjson,
+}
+
+impl ExportFormat {
+    /// Return the export format requested by the `Accept` header, or `None` if JSON is preferred.
+    ///
+    /// The media type with the highest `q` value wins. Between equal `q` values, an exact type is
+    /// preferred over a wildcard, and then the earlier one in the header.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let accept = headers.get(header::ACCEPT)?.to_str().ok()?;
+
+        // The format, quality, and specificity of the best match so far
+        let mut best: Option<(Option<Self>, f32, u8)> = None;
+        for media_range in accept.split(',') {
+            let mut params = media_range.split(';');
+            let media_type = params.next().unwrap_or_default().trim();
+            let quality = params
+                .filter_map(|param| param.split_once('='))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+                .map(|(_, value)| value.trim().parse::<f32>().unwrap_or(0.0))
+                .unwrap_or(1.0);
+
+            if quality <= 0.0 {
+                continue;
+            }
+
+            let (format, specificity) = match media_type.to_ascii_lowercase().as_str() {
+                "text/csv" => (Some(Self::Csv), 2),
+                "application/x-ndjson" => (Some(Self::Ndjson), 2),
+                "application/json" => (None, 2),
+                "application/*" => (None, 1),
+                "*/*" => (None, 0),
+                _ => continue,
+            };
+
+            let better = match &best {
+                Some((_, best_quality, best_specificity)) => {
+                    quality > *best_quality
+                        || (quality == *best_quality && specificity > *best_specificity)
+                }
+                None => true,
+            };
+            if better {
+                best = Some((format, quality, specificity));
+            }
+        }
+
+        best.and_then(|(format, _, _)| format)
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Ndjson => "ndjson",
+        }
+    }
+}
+
+/// Return the LIMIT and OFFSET for an export. Without `per_page` there is no limit.
+pub fn bounds(page: Option<u32>, per_page: Option<u32>) -> (Option<i32>, i32) {
+    let Some(per_page) = per_page else {
+        return (None, 0);
+    };
+
+    let per_page = per_page.clamp(1, i32::MAX as u32) as i32;
+    let offset = (page.unwrap_or(0) as i32).saturating_mul(per_page);
+    (Some(per_page), offset)
+}
+
+/// Stream an export to the client. `run` reads the rows and passes them to the [ExportWriter].
+/// It runs in its own task with its own connection, since the rows are still being read after the
+/// handler returns.
+pub fn stream<F, Fut>(db: &PgPool, name: &str, format: ExportFormat, run: F) -> Response
+where
+    F: FnOnce(PoolConnection<Postgres>, ExportWriter) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<(), Report<Error>>> + Send + 'static,
+{
+    // A small channel applies backpressure, so rows are only read as fast as the client consumes
+    // them.
+    let (sender, receiver) = mpsc::channel(4);
+    let db = db.clone();
+
+    tokio::spawn(async move {
+        let error_sender = sender.clone();
+        let result = async move {
+            let conn = db.acquire().await.change_context(Error::Db)?;
+            run(conn, ExportWriter::new(format, sender)).await
+        }
+        .await;
+
+        if let Err(e) = result {
+            event!(Level::ERROR, error = ?e, "Export failed");
+            // The status has already been sent, so end the body with an error to show the client
+            // that the export is incomplete.
+            error_sender
+                .send(Err(std::io::Error::other("export failed")))
+                .await
+                .ok();
+        }
+    });
+
+    let mut response = Body::from_stream(ReceiverStream::new(receiver)).into_response();
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(format.content_type()),
+    );
+    if let Ok(disposition) = HeaderValue::from_str(&format!(
+        "attachment; filename=\"{name}.{}\"",
+        format.extension()
+    )) {
+        headers.insert(header::CONTENT_DISPOSITION, disposition);
+    }
+
+    response
+}
+
+/// Formats rows for an export and sends them to the client.
+pub struct ExportWriter {
+    format: ExportFormat,
+    sender: mpsc::Sender<Result<Bytes, std::io::Error>>,
+    buffer: BytesMut,
+    /// The CSV columns, taken from the first row
+    columns: Option<Vec<String>>,
+}
+
+impl ExportWriter {
+    fn new(format: ExportFormat, sender: mpsc::Sender<Result<Bytes, std::io::Error>>) -> Self {
+        Self {
+            format,
+            sender,
+            buffer: BytesMut::with_capacity(FLUSH_BYTES),
+            columns: None,
+        }
+    }
+
+    /// Write all the rows from a query and finish the export.
+    pub async fn write_rows<T: Serialize>(
+        mut self,
+        rows: impl Stream<Item = Result<T, sqlx::Error>>,
+    ) -> Result<(), Report<Error>> {
+        futures::pin_mut!(rows);
+        while let Some(row) = rows.try_next().await.change_context(Error::Db)? {
+            if !self.write(&row).await? {
+                // The client went away, so there's no reason to keep reading.
+                return Ok(());
+            }
+        }
+
+        self.flush().await;
+        Ok(())
+    }
+
+    /// Write a row, returning false if the client has disconnected.
+    pub async fn write<T: Serialize>(&mut self, row: &T) -> Result<bool, Report<Error>> {
+        let row = flatten_row(serde_json::to_value(row).change_context(Error::Export)?);
+
+        match self.format {
+            ExportFormat::Ndjson => {
+                serde_json::to_writer((&mut self.buffer).writer(), &row)
+                    .change_context(Error::Export)?;
+                self.buffer.put_u8(b'\n');
+            }
+            ExportFormat::Csv => {
+                let columns = self.columns.get_or_insert_with(|| {
+                    let columns = csv_columns(&row);
+                    write_csv_line(&mut self.buffer, columns.iter().map(|c| c.as_str()));
+                    columns
+                });
+
+                let cells = columns
+                    .iter()
+                    .map(|c| row.get(c).map(csv_cell).unwrap_or_default())
+                    .collect::<Vec<_>>();
+                write_csv_line(&mut self.buffer, cells.iter().map(|c| c.as_str()));
+            }
+        }
+
+        if self.buffer.len() >= FLUSH_BYTES {
+            return Ok(self.flush().await);
+        }
+
+        Ok(true)
+    }
+
+    async fn flush(&mut self) -> bool {
+        if self.buffer.is_empty() {
+            return true;
+        }
+
+        let chunk = self.buffer.split().freeze();
+        self.sender.send(Ok(chunk)).await.is_ok()
+    }
+}
+
+/// Replace arrays of child objects with a list of their IDs.
+fn flatten_row(row: serde_json::Value) -> serde_json::Map<String, serde_json::Value> {
+    let serde_json::Value::Object(row) = row else {
+        return serde_json::Map::from_iter([("value".to_string(), row)]);
+    };
+
+    row.into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                serde_json::Value::Array(items) => serde_json::Value::Array(
+                    items
+                        .into_iter()
+                        .map(|item| match item {
+                            serde_json::Value::Object(mut child) if child.contains_key("id") => {
+                                child.remove("id").unwrap_or_default()
+                            }
+                            item => item,
+                        })
+                        .collect(),
+                ),
+                value => value,
+            };
+
+            (key, value)
+        })
+        .collect()
+}
+
+/// The CSV columns for a row. `id` comes first, followed by the rest of the fields in alphabetical
+/// order.
+fn csv_columns(row: &serde_json::Map<String, serde_json::Value>) -> Vec<String> {
+    let id = row.contains_key("id").then(|| "id".to_string());
+    id.into_iter()
+        .chain(row.keys().filter(|k| *k != "id").cloned())
+        .collect()
+}
+
+fn csv_cell(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => String::new(),
+        // Spreadsheets run cells that look like formulas, so make sure that text is always
+        // treated as text.
+        serde_json::Value::String(s) if s.starts_with(['=', '+', '-', '@']) => format!("'{s}"),
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Array(items)
+            if items.iter().all(|i| !i.is_object() && !i.is_array()) =>
+        {
+            items.iter().map(csv_cell).collect::<Vec<_>>().join(",")
+        }
+        value => value.to_string(),
+    }
+}
+
+fn write_csv_line<'a>(buffer: &mut BytesMut, cells: impl Iterator<Item = &'a str>) {
+    for (i, cell) in cells.enumerate() {
+        if i > 0 {
+            buffer.put_u8(b',');
+        }
+
+        if cell.contains([',', '"', '\n', '\r']) {
+            buffer.put_u8(b'"');
+            buffer.put_slice(cell.replace('"', "\"\"").as_bytes());
+            buffer.put_u8(b'"');
+        } else {
+            buffer.put_slice(cell.as_bytes());
+        }
+    }
+
+    buffer.put_slice(b"\r\n");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn accept_header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(ExportFormat::from_headers(&headers), None);
+
+        let mut check = |accept: &'static str, expected: Option<ExportFormat>| {
+            headers.insert(header::ACCEPT, HeaderValue::from_static(accept));
+            assert_eq!(ExportFormat::from_headers(&headers), expected, "{accept}");
+        };
+
+        check("application/json, text/csv;q=0.9", None);
+        check("text/csv;q=0.9, application/json", None);
+        check("application/json;q=0.5, text/csv", Some(ExportFormat::Csv));
+        check("text/csv, */*", Some(ExportFormat::Csv));
+        check(
+            "text/csv;q=0, application/x-ndjson;q=0.1",
+            Some(ExportFormat::Ndjson),
+        );
+        check("text/html, */*;q=0.8", None);
+        check("text/csv, application/x-ndjson", Some(ExportFormat::Csv));
+        check("application/x-ndjson", Some(ExportFormat::Ndjson));
+    }
+
+    #[test]
+    fn csv_rows() {
+        let row = flatten_row(serde_json::json!({
+            "subject": "Hello, \"world\"",
+            "id": "abc",
+            "body": "=SUM(A1)",
+            "comments": [{ "id": "c1", "body": "x" }, { "id": "c2", "body": "y" }],
+            "poll_id": null,
+            "ui": { "key": 1 },
+        }));
+
+        let columns = csv_columns(&row);
+        assert_eq!(
+            columns,
+            ["id", "body", "comments", "poll_id", "subject", "ui"]
+        );
+
+        let mut buffer = BytesMut::new();
+        let cells = columns
+            .iter()
+            .map(|c| csv_cell(&row[c]))
+            .collect::<Vec<_>>();
+        write_csv_line(&mut buffer, cells.iter().map(|c| c.as_str()));
+        assert_eq!(
+            std::str::from_utf8(&buffer).unwrap(),
+            "abc,'=SUM(A1),\"c1,c2\",,\"Hello, \"\"world\"\"\",\"{\"\"key\"\":1}\"\r\n"
+        );
+    }
+}
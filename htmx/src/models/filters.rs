@@ -0,0 +1,171 @@
+//! Filter operators for list queries beyond the equality and range comparisons handled by
+//! [FilterBuilder](filigree::sql::FilterBuilder).
+//!
+//! These clauses are placed before the `FilterBuilder` clauses in the query. Once all of them are
+//! added, create the `FilterBuilder` starting at [ExtraFilters::next_binding], and bind the values
+//! for the extra filters first, in the same order that they were added here.
+
+use error_stack::{Report, ResultExt};
+use sqlx::PgExecutor;
+
+use crate::Error;
+
+/// Builds the extra filter clauses for a list query. Columns refer to the listed table through
+/// the `tb` alias used by the list queries.
+#[derive(Debug)]
+pub struct ExtraFilters {
+    next_binding: usize,
+    clauses: Vec<String>,
+}
+
+impl ExtraFilters {
+    pub fn new(first_binding: usize) -> Self {
+        Self {
+            next_binding: first_binding,
+            clauses: Vec::new(),
+        }
+    }
+
+    /// The first binding index not used by these filters.
+    pub fn next_binding(&self) -> usize {
+        self.next_binding
+    }
+
+    fn binding(&mut self) -> usize {
+        let binding = self.next_binding;
+        self.next_binding += 1;
+        binding
+    }
+
+    /// The column equals the bound value.
+    pub fn equals(&mut self, column: &str) {
+        let binding = self.binding();
+        self.clauses.push(format!("tb.{column} = ${binding}"));
+    }
+
+    /// The column contains the bound string.
+    pub fn contains(&mut self, column: &str, case_insensitive: bool) {
+        let binding = self.binding();
+        // strpos is used instead of LIKE so that `%` and `_` in the value don't act as wildcards.
+        let clause = if case_insensitive {
+            format!("strpos(lower(tb.{column}), lower(${binding})) > 0")
+        } else {
+            format!("strpos(tb.{column}, ${binding}) > 0")
+        };
+        self.clauses.push(clause);
+    }
+
+    /// The column is (or is not) null. This does not use a binding.
+    pub fn is_null(&mut self, column: &str, is_null: bool) {
+        let not = if is_null { "" } else { "NOT " };
+        self.clauses.push(format!("tb.{column} IS {not}NULL"));
+    }
+
+    /// The bound JSON path expression returns at least one item from the JSON column.
+    pub fn json_path(&mut self, column: &str) {
+        let binding = self.binding();
+        self.clauses
+            .push(format!("tb.{column} @? CAST(${binding} AS jsonpath)"));
+    }
+
+    /// The object has (or does not have) at least one child in `child_table`. This does not use a
+    /// binding.
+    pub fn has_child(&mut self, child_table: &str, parent_column: &str, exists: bool) {
+        let not = if exists { "" } else { "NOT " };
+        self.clauses.push(format!(
+            "{not}EXISTS (SELECT 1 FROM public.{child_table} child WHERE child.{parent_column} = tb.id)"
+        ));
+    }
+
+    /// The object has (or does not have) at least one child in `child_table` that matches
+    /// `condition`, which refers to the child as `child`. This does not use a binding.
+    pub fn has_child_where(
+        &mut self,
+        child_table: &str,
+        parent_column: &str,
+        condition: &str,
+        exists: bool,
+    ) {
+        let not = if exists { "" } else { "NOT " };
+        self.clauses.push(format!(
+            "{not}EXISTS (SELECT 1 FROM public.{child_table} child WHERE child.{parent_column} = tb.id AND {condition})"
+        ));
+    }
+
+    /// The object has at least one child in `child_table` whose `column` is in the bound array.
+    pub fn child_in(&mut self, child_table: &str, parent_column: &str, column: &str) {
+        let binding = self.binding();
+        self.clauses.push(format!(
+            "EXISTS (SELECT 1 FROM public.{child_table} child WHERE child.{parent_column} = tb.id AND child.{column} = ANY(${binding}))"
+        ));
+    }
+
+    /// Append the clauses to a WHERE clause built by `FilterBuilder`.
+    pub fn append_to(self, query: &mut String) {
+        for clause in self.clauses {
+            query.push_str(" AND ");
+            query.push_str(&clause);
+        }
+    }
+}
+
+/// Check that a JSON path filter from the query string is a valid `jsonpath`, so that a bad path
+/// returns [Error::Filter] instead of failing the list query. Exports need this in particular,
+/// since they have already sent their response status by the time the query runs.
+pub async fn validate_json_path(
+    db: impl PgExecutor<'_>,
+    path: Option<&str>,
+) -> Result<(), Report<Error>> {
+    let Some(path) = path else {
+        return Ok(());
+    };
+
+    sqlx::query("SELECT CAST($1 AS jsonpath)")
+        .bind(path)
+        .execute(db)
+        .await
+        .change_context(Error::Filter)
+        .attach_printable_lazy(|| format!("Invalid JSON path {path:?}"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bindings_in_order() {
+        let mut filters = ExtraFilters::new(5);
+        filters.contains("title", true);
+        filters.is_null("description", false);
+        filters.json_path("ui");
+        filters.child_in("report_sections", "report_id", "viz");
+        assert_eq!(filters.next_binding(), 8);
+
+        let mut query = String::from("true");
+        filters.append_to(&mut query);
+        assert_eq!(
+            query,
+            "true AND strpos(lower(tb.title), lower($5)) > 0 \
+            AND tb.description IS NOT NULL \
+            AND tb.ui @? CAST($6 AS jsonpath) \
+            AND EXISTS (SELECT 1 FROM public.report_sections child WHERE child.report_id = tb.id AND child.viz = ANY($7))"
+        );
+    }
+
+    #[test]
+    fn has_child_where() {
+        let mut filters = ExtraFilters::new(4);
+        filters.has_child_where("images", "report_id", "child.alt_text IS NULL", true);
+        filters.has_child_where("notes", "report_id", "child.done", false);
+        assert_eq!(filters.next_binding(), 4);
+
+        let mut query = String::from("true");
+        filters.append_to(&mut query);
+        assert_eq!(
+            query,
+            "true AND EXISTS (SELECT 1 FROM public.images child WHERE child.report_id = tb.id AND child.alt_text IS NULL) \
+            AND NOT EXISTS (SELECT 1 FROM public.notes child WHERE child.report_id = tb.id AND child.done)"
+        );
+    }
+}
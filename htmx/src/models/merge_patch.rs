@@ -0,0 +1,97 @@
+//! Support for updating objects with [JSON Merge Patch](https://www.rfc-editor.org/rfc/rfc7396)
+//! documents.
+//!
+//! Each model's `PatchPayload` wraps its fields in `Option` so that only the fields present in the
+//! patch are updated. Nullable fields use `Option<Option<T>>` so that an explicit `null` can be
+//! told apart from a missing field. JSON columns are merged into the existing value with the
+//! `jsonb_merge_patch` database function.
+
+use async_trait::async_trait;
+use axum::{
+    body::Bytes,
+    extract::{FromRequest, Request},
+    http::header,
+};
+use error_stack::ResultExt;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
+
+use crate::Error;
+
+pub const MERGE_PATCH_CONTENT_TYPE: &str = "application/merge-patch+json";
+
+/// Extract a merge patch document from a request with a `Content-Type` of
+/// `application/merge-patch+json` or `application/json`.
+pub struct MergePatch<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for MergePatch<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Error;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let content_type = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(';').next())
+            .map(|v| v.trim().to_ascii_lowercase());
+
+        match content_type.as_deref() {
+            Some(MERGE_PATCH_CONTENT_TYPE) | Some("application/json") => {}
+            _ => return Err(Error::UnsupportedMediaType),
+        }
+
+        let body = Bytes::from_request(req, state)
+            .await
+            .map_err(|_| Error::InvalidPatch)?;
+
+        let value = serde_json::from_slice(&body)
+            .change_context(Error::InvalidPatch)
+            .attach_printable("Failed to parse merge patch")?;
+
+        Ok(MergePatch(value))
+    }
+}
+
+/// Deserialize a field which may be omitted from a patch, but can not be set to `null` because
+/// the column is not nullable.
+pub fn required<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// Deserialize a JSON column patch. Unlike [required], `T` would happily accept a `null`, so
+/// reject that explicitly.
+pub fn required_json<'de, D>(deserializer: D) -> Result<Option<serde_json::Value>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Null => Err(serde::de::Error::custom("field can not be null")),
+        value => Ok(Some(value)),
+    }
+}
+
+/// Deserialize a nullable field, distinguishing a missing field (`None`) from an explicit
+/// `null` (`Some(None)`).
+pub fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Split a nullable patch field into the "was it set" flag and the value, for binding to a query.
+pub fn nullable_binding<T>(field: &Option<Option<T>>) -> (bool, Option<&T>) {
+    match field {
+        Some(value) => (true, value.as_ref()),
+        None => (false, None),
+    }
+}
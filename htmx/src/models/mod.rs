@@ -1,5 +1,10 @@
+pub mod bulk;
 pub mod comment;
+pub mod export;
+pub mod filters;
+pub mod merge_patch;
 pub mod organization;
+pub mod pagination;
 pub mod poll;
 pub mod post;
 pub mod post_image;
@@ -11,12 +16,25 @@ pub mod user;
 
 use axum::Router;
 
-use crate::server::ServerState;
+use crate::server::{openapi::ApiRoute, ServerState};
 
 pub fn create_routes() -> Router<ServerState> {
     Router::new()
+        .merge(organization::endpoints::create_routes())
         .merge(post::endpoints::create_routes())
         .merge(report::endpoints::create_routes())
         .merge(role::endpoints::create_routes())
         .merge(user::endpoints::create_routes())
 }
+
+/// Describe the model routes for the OpenAPI document.
+pub fn api_docs() -> Vec<ApiRoute> {
+    let mut routes = organization::endpoints::api_docs();
+    routes.extend(post::endpoints::api_docs());
+    routes.extend(report::endpoints::api_docs());
+    routes.extend(role::endpoints::api_docs());
+    routes.extend(user::endpoints::api_docs());
+
+    // All of these routes are covered by the idempotency middleware.
+    routes.into_iter().map(ApiRoute::idempotency_key).collect()
+}
@@ -0,0 +1,217 @@
+use axum::{extract::State, http::Method, response::IntoResponse};
+use axum_jsonschema::Json;
+
+use super::READ_PERMISSION;
+use crate::{
+    auth::Authed,
+    server::{
+        openapi::{ApiRoute, ApiRouter},
+        ServerState,
+    },
+    storage_quota::{self, StorageUsage},
+    Error,
+};
+
+async fn get_current_usage(
+    State(state): State<ServerState>,
+    auth: Authed,
+) -> Result<impl IntoResponse, Error> {
+    let usage =
+        storage_quota::get_usage_report(&state.db, &state.storage.quota, &auth.organization_id)
+            .await?;
+
+    Ok(Json(usage))
+}
+
+fn routes() -> ApiRouter {
+    ApiRouter::new().route(
+        ApiRoute::new(
+            Method::GET,
+            "/organizations/current/usage",
+            "organization",
+            "Get the current organization's storage usage and quota",
+        )
+        .permissions(&[READ_PERMISSION, "org_admin"])
+        .response::<StorageUsage>(),
+        get_current_usage,
+    )
+}
+
+pub fn create_routes() -> axum::Router<ServerState> {
+    routes().into_router()
+}
+
+pub fn api_docs() -> Vec<ApiRoute> {
+    routes().into_docs()
+}
+
+#[cfg(test)]
+mod test {
+    use filigree::testing::ResponseExt;
+
+    use super::*;
+    use crate::{
+        models::{
+            post::{Post, PostId},
+            post_image::PostImage,
+        },
+        storage::AppStorageConfig,
+        storage_quota::QuotaLimits,
+        tests::{start_app_with_options, BootstrappedData, TestAppOptions},
+    };
+
+    #[sqlx::test]
+    async fn storage_quota(pool: sqlx::PgPool) {
+        let mut storage = AppStorageConfig::new_in_memory();
+        storage.quota = QuotaLimits {
+            soft_limit: None,
+            hard_limit: Some(1024 * 1024),
+        };
+        let (
+            app,
+            BootstrappedData {
+                organization,
+                admin_user,
+                ..
+            },
+        ) = start_app_with_options(
+            pool.clone(),
+            TestAppOptions {
+                storage: Some(storage),
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let post_id = PostId::new();
+        let mut conn = pool.acquire().await.unwrap();
+        Post::create_raw(
+            &mut *conn,
+            &post_id,
+            &organization.id,
+            crate::models::post::testing::make_create_payload(0),
+        )
+        .await
+        .unwrap();
+        drop(conn);
+
+        let mut image = Vec::new();
+        ::image::DynamicImage::ImageRgb8(::image::RgbImage::new(32, 32))
+            .write_to(
+                &mut std::io::Cursor::new(&mut image),
+                ::image::ImageFormat::Png,
+            )
+            .unwrap();
+
+        admin_user
+            .client
+            .post(&format!("posts/{post_id}/post_images?filename=one.png"))
+            .body(image.clone())
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap();
+
+        let usage: StorageUsage = admin_user
+            .client
+            .get("organizations/current/usage")
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(usage.limits.hard_limit, Some(1024 * 1024));
+        let uploads = usage
+            .items
+            .iter()
+            .find(|i| i.bucket == "image_uploads" && i.model == "post_image")
+            .expect("usage for uploaded images");
+        assert_eq!(uploads.objects, 1);
+        assert_eq!(uploads.bytes, image.len() as i64);
+        // Renditions may also have been generated by now.
+        assert!(usage.total_bytes >= image.len() as i64);
+
+        // Going over the organization's soft limit warns its administrators.
+        storage_quota::set_limits(
+            &pool,
+            &organization.id,
+            &QuotaLimits {
+                soft_limit: Some(1),
+                hard_limit: None,
+            },
+        )
+        .await
+        .unwrap();
+
+        admin_user
+            .client
+            .post(&format!("posts/{post_id}/post_images?filename=two.png"))
+            .body(image.clone())
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap();
+
+        let mut warning = None;
+        for _ in 0..50 {
+            warning = app
+                .sent_emails
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|email| email.text.contains("over its limit"))
+                .cloned();
+            if warning.is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        let warning = warning.expect("a soft limit warning was sent");
+        assert!(warning.text.contains(&organization.name));
+
+        // Uploads past the hard limit are rejected.
+        storage_quota::set_limits(
+            &pool,
+            &organization.id,
+            &QuotaLimits {
+                soft_limit: None,
+                hard_limit: Some(image.len() as i64),
+            },
+        )
+        .await
+        .unwrap();
+
+        let response = admin_user
+            .client
+            .post(&format!("posts/{post_id}/post_images?filename=three.png"))
+            .body(image.clone())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::PAYLOAD_TOO_LARGE);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"]["kind"], "storage_quota_exceeded");
+
+        let images: Vec<PostImage> = admin_user
+            .client
+            .get(&format!("posts/{post_id}/post_images"))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(images.len(), 2);
+    }
+}
@@ -7,6 +7,7 @@ use filigree::{
     errors::OrderByError,
     sql::{BindingOperator, FilterBuilder, ValuesBuilder},
 };
+use schemars::JsonSchema;
 use serde::Deserialize;
 use sqlx::{
     postgres::PgRow, query_file, query_file_as, query_file_scalar, PgConnection, PgExecutor,
@@ -14,7 +15,7 @@ use sqlx::{
 use tracing::{event, instrument, Level};
 
 use super::{types::*, OrganizationId};
-use crate::{auth::AuthInfo, Error};
+use crate::{auth::AuthInfo, models::filters::ExtraFilters, Error};
 
 #[derive(Debug, Default)]
 enum OrderByField {
@@ -66,7 +67,7 @@ fn parse_order_by(field: &str) -> Result<(bool, OrderByField), OrderByError> {
     Ok((descending, value))
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Deserialize, Debug, Default, JsonSchema)]
 pub struct ListQueryFilters {
     pub page: Option<u32>,
     pub per_page: Option<u32>,
@@ -74,20 +75,70 @@ pub struct ListQueryFilters {
     pub order_by: Option<String>,
     #[serde(default)]
     pub id: Vec<OrganizationId>,
+    #[serde(default)]
+    pub name: Vec<String>,
+    #[serde(default)]
+    pub owner: Vec<crate::models::user::UserId>,
+    #[serde(default)]
+    pub default_role: Vec<crate::models::role::RoleId>,
     pub updated_at_lte: Option<chrono::DateTime<chrono::Utc>>,
     pub updated_at_gte: Option<chrono::DateTime<chrono::Utc>>,
     pub created_at_lte: Option<chrono::DateTime<chrono::Utc>>,
     pub created_at_gte: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only return objects where `name` contains this string
+    pub name_contains: Option<String>,
+    /// Only return objects where `name` contains this string, ignoring case
+    pub name_icontains: Option<String>,
+    /// Only return objects where `owner` is (or is not) null
+    pub owner_is_null: Option<bool>,
+    /// Only return objects where `default_role` is (or is not) null
+    pub default_role_is_null: Option<bool>,
+    /// Only return active (or inactive) organizations
+    pub active: Option<bool>,
 }
 
 impl ListQueryFilters {
     fn build_where_clause(&self) -> String {
-        let mut bindings = FilterBuilder::new(4);
+        let mut filters = ExtraFilters::new(4);
+
+        if self.name_contains.is_some() {
+            filters.contains("name", false);
+        }
+
+        if self.name_icontains.is_some() {
+            filters.contains("name", true);
+        }
+
+        if let Some(owner_is_null) = self.owner_is_null {
+            filters.is_null("owner", owner_is_null);
+        }
+
+        if let Some(default_role_is_null) = self.default_role_is_null {
+            filters.is_null("default_role", default_role_is_null);
+        }
+
+        if self.active.is_some() {
+            filters.equals("active");
+        }
+
+        let mut bindings = FilterBuilder::new(filters.next_binding());
 
         if !self.id.is_empty() {
             bindings.add_vec("id", &self.id);
         }
 
+        if !self.name.is_empty() {
+            bindings.add_vec("name", &self.name);
+        }
+
+        if !self.owner.is_empty() {
+            bindings.add_vec("owner", &self.owner);
+        }
+
+        if !self.default_role.is_empty() {
+            bindings.add_vec("default_role", &self.default_role);
+        }
+
         if self.updated_at_lte.is_some() {
             bindings.add_option("updated_at", &self.updated_at_lte, BindingOperator::Lte);
         }
@@ -104,17 +155,48 @@ impl ListQueryFilters {
             bindings.add_option("created_at", &self.created_at_gte, BindingOperator::Gte);
         }
 
-        let query = bindings.to_string();
+        let mut query = bindings.to_string();
+        filters.append_to(&mut query);
         event!(Level::DEBUG, %query);
         query
     }
 
     fn bind_to_query<'a, T>(&'a self, mut query: QueryAs<'a, T>) -> QueryAs<'a, T> {
+        if self.name_contains.is_some() {
+            event!(Level::DEBUG, name_contains = ?self.name_contains);
+            query = query.bind(&self.name_contains);
+        }
+
+        if self.name_icontains.is_some() {
+            event!(Level::DEBUG, name_icontains = ?self.name_icontains);
+            query = query.bind(&self.name_icontains);
+        }
+
+        if self.active.is_some() {
+            event!(Level::DEBUG, active = ?self.active);
+            query = query.bind(&self.active);
+        }
+
         if !self.id.is_empty() {
             event!(Level::DEBUG, id = ?self.id);
             query = query.bind(&self.id);
         }
 
+        if !self.name.is_empty() {
+            event!(Level::DEBUG, name = ?self.name);
+            query = query.bind(&self.name);
+        }
+
+        if !self.owner.is_empty() {
+            event!(Level::DEBUG, owner = ?self.owner);
+            query = query.bind(&self.owner);
+        }
+
+        if !self.default_role.is_empty() {
+            event!(Level::DEBUG, default_role = ?self.default_role);
+            query = query.bind(&self.default_role);
+        }
+
         if self.updated_at_lte.is_some() {
             event!(Level::DEBUG, updated_at_lte = ?self.updated_at_lte);
             query = query.bind(&self.updated_at_lte);
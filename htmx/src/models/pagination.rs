@@ -0,0 +1,206 @@
+//! Pagination metadata for list endpoints.
+//!
+//! List endpoints return a bare array by default. When the `include_total` query flag is set, the
+//! results are wrapped in a [ListResponse] envelope with the total count. Either way, the response
+//! carries RFC 8288 `Link` headers pointing at the neighbouring pages.
+
+use axum::{
+    http::{header, HeaderValue, Uri},
+    response::{IntoResponse, Response},
+    Json,
+};
+use error_stack::{Report, ResultExt};
+use schemars::JsonSchema;
+use serde::Serialize;
+use sqlx::PgConnection;
+
+use crate::Error;
+
+// These match the limits applied by each model's `list_internal`.
+pub const MAX_PER_PAGE: u32 = 200;
+pub const DEFAULT_PER_PAGE: u32 = 50;
+
+/// Tables with more rows than this, according to the planner's statistics, are counted using an
+/// estimate from the query plan instead of an exact `COUNT(*)`.
+pub const ESTIMATE_COUNT_ROWS: f32 = 1_000_000.0;
+
+/// Return the page size and row offset for the given query parameters.
+pub fn page_bounds(page: Option<u32>, per_page: Option<u32>) -> (u32, u32) {
+    let per_page = per_page
+        .unwrap_or(DEFAULT_PER_PAGE)
+        .min(MAX_PER_PAGE)
+        .max(1);
+    let page = page.unwrap_or(0);
+    (per_page, page.saturating_mul(per_page))
+}
+
+/// The number of objects matching a list query
+#[derive(Debug, Clone, Copy)]
+pub struct ListTotal {
+    pub count: i64,
+    /// If true, the count is the query planner's estimate rather than an exact count.
+    pub estimated: bool,
+}
+
+/// Return true if the table is large enough that the total should be estimated.
+pub async fn use_estimate(
+    db: &mut PgConnection,
+    table: &'static str,
+) -> Result<bool, Report<Error>> {
+    let rows = sqlx::query_scalar::<_, f32>(
+        "SELECT reltuples FROM pg_catalog.pg_class WHERE oid = to_regclass($1)",
+    )
+    .bind(format!("public.{table}"))
+    .fetch_optional(&mut *db)
+    .await
+    .change_context(Error::Db)?;
+
+    Ok(rows.unwrap_or(0.0) > ESTIMATE_COUNT_ROWS)
+}
+
+/// The output of `EXPLAIN (FORMAT JSON)`, which Postgres returns as a `json` value.
+pub type ExplainPlan = sqlx::types::Json<serde_json::Value>;
+
+/// Extract the estimated row count from the output of `EXPLAIN (FORMAT JSON)`.
+pub fn plan_rows(plan: &serde_json::Value) -> i64 {
+    plan.get(0)
+        .and_then(|p| p.get("Plan"))
+        .and_then(|p| p.get("Plan Rows"))
+        .and_then(|r| r.as_f64())
+        .map(|r| r.round() as i64)
+        .unwrap_or(0)
+}
+
+/// The envelope returned by list endpoints when `include_total` is set.
+#[derive(Serialize, Debug, JsonSchema)]
+pub struct ListResponse<T> {
+    pub items: Vec<T>,
+    pub total: i64,
+    /// True if `total` is an estimate, which happens for very large tables.
+    pub total_is_estimate: bool,
+    pub page: u32,
+    pub per_page: u32,
+    pub has_more: bool,
+}
+
+/// Build the response for a list endpoint, wrapping the results in a [ListResponse] if a total
+/// was requested, and adding `Link` headers for the neighbouring pages.
+pub fn list_response<T: Serialize>(
+    uri: &Uri,
+    page: Option<u32>,
+    per_page: Option<u32>,
+    items: Vec<T>,
+    total: Option<ListTotal>,
+) -> Response {
+    let (per_page, offset) = page_bounds(page, per_page);
+    let page = page.unwrap_or(0);
+
+    let has_more = match total {
+        Some(total) => i64::from(offset) + (items.len() as i64) < total.count,
+        // Without a count, a full page is the best hint that there might be more.
+        None => items.len() as u32 >= per_page,
+    };
+    let last_page = total.map(|t| (t.count.max(1) as u64 - 1) / u64::from(per_page));
+
+    let link = link_header(uri, page, per_page, has_more, last_page);
+    let mut response = match total {
+        Some(total) => Json(ListResponse {
+            items,
+            total: total.count,
+            total_is_estimate: total.estimated,
+            page,
+            per_page,
+            has_more,
+        })
+        .into_response(),
+        None => Json(items).into_response(),
+    };
+
+    if let Some(link) = link {
+        response.headers_mut().insert(header::LINK, link);
+    }
+
+    response
+}
+
+fn link_header(
+    uri: &Uri,
+    page: u32,
+    per_page: u32,
+    has_more: bool,
+    last_page: Option<u64>,
+) -> Option<HeaderValue> {
+    // Keep all the other query parameters, such as filters, when building the links.
+    let other_params = url::form_urlencoded::parse(uri.query().unwrap_or_default().as_bytes())
+        .filter(|(k, _)| k != "page" && k != "per_page")
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect::<Vec<_>>();
+
+    let link_to = |target: u64, rel: &str| {
+        let query = url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(other_params.iter())
+            .append_pair("page", &target.to_string())
+            .append_pair("per_page", &per_page.to_string())
+            .finish();
+        format!("<{}?{query}>; rel=\"{rel}\"", uri.path())
+    };
+
+    let page = u64::from(page);
+    let mut links = vec![link_to(0, "first")];
+    if page > 0 {
+        links.push(link_to(page - 1, "prev"));
+    }
+    if has_more {
+        links.push(link_to(page + 1, "next"));
+    }
+    if let Some(last_page) = last_page {
+        links.push(link_to(last_page, "last"));
+    }
+
+    HeaderValue::from_str(&links.join(", ")).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn links() {
+        let uri = Uri::from_static("/api/posts?page=1&per_page=10&subject_contains=a%20b");
+        let link = link_header(&uri, 1, 10, true, Some(4)).unwrap();
+        assert_eq!(
+            link.to_str().unwrap(),
+            [
+                "</api/posts?subject_contains=a+b&page=0&per_page=10>; rel=\"first\"",
+                "</api/posts?subject_contains=a+b&page=0&per_page=10>; rel=\"prev\"",
+                "</api/posts?subject_contains=a+b&page=2&per_page=10>; rel=\"next\"",
+                "</api/posts?subject_contains=a+b&page=4&per_page=10>; rel=\"last\"",
+            ]
+            .join(", ")
+        );
+
+        let link = link_header(&Uri::from_static("/api/posts"), 0, 50, false, None).unwrap();
+        assert_eq!(
+            link.to_str().unwrap(),
+            "</api/posts?page=0&per_page=50>; rel=\"first\""
+        );
+    }
+
+    #[test]
+    fn explain_rows() {
+        let plan = serde_json::json!([{ "Plan": { "Node Type": "Seq Scan", "Plan Rows": 1234 } }]);
+        assert_eq!(plan_rows(&plan), 1234);
+        assert_eq!(plan_rows(&serde_json::json!("not a plan")), 0);
+    }
+
+    #[sqlx::test]
+    async fn explain_rows_from_postgres(pool: sqlx::PgPool) {
+        let (plan,) = sqlx::query_as::<_, (ExplainPlan,)>(
+            "EXPLAIN (FORMAT JSON) SELECT * FROM generate_series(1, 1000)",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(plan_rows(&plan), 1000);
+    }
+}
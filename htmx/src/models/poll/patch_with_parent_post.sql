@@ -0,0 +1,9 @@
+UPDATE
+  public.polls
+SET
+  question = COALESCE($1, question),
+  answers = COALESCE(jsonb_merge_patch(answers, $2), answers),
+  updated_at = NOW()
+WHERE
+  post_id = $3
+  AND organization_id = $4
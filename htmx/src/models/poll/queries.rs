@@ -7,6 +7,7 @@ use filigree::{
     errors::OrderByError,
     sql::{BindingOperator, FilterBuilder, ValuesBuilder},
 };
+use schemars::JsonSchema;
 use serde::Deserialize;
 use sqlx::{
     postgres::PgRow, query_file, query_file_as, query_file_scalar, PgConnection, PgExecutor,
@@ -16,7 +17,7 @@ use tracing::{event, instrument, Level};
 use super::{types::*, PollId};
 use crate::{
     auth::AuthInfo,
-    models::{organization::OrganizationId, post::PostId},
+    models::{filters::ExtraFilters, organization::OrganizationId, post::PostId},
     Error,
 };
 
@@ -67,7 +68,7 @@ fn parse_order_by(field: &str) -> Result<(bool, OrderByField), OrderByError> {
     Ok((descending, value))
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Deserialize, Debug, Default, JsonSchema)]
 pub struct ListQueryFilters {
     pub page: Option<u32>,
     pub per_page: Option<u32>,
@@ -76,21 +77,47 @@ pub struct ListQueryFilters {
     #[serde(default)]
     pub id: Vec<PollId>,
     #[serde(default)]
+    pub question: Vec<String>,
+    #[serde(default)]
     pub post_id: Vec<PostId>,
     pub updated_at_lte: Option<chrono::DateTime<chrono::Utc>>,
     pub updated_at_gte: Option<chrono::DateTime<chrono::Utc>>,
     pub created_at_lte: Option<chrono::DateTime<chrono::Utc>>,
     pub created_at_gte: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only return objects where `question` contains this string
+    pub question_contains: Option<String>,
+    /// Only return objects where `question` contains this string, ignoring case
+    pub question_icontains: Option<String>,
+    /// Only return objects where this JSON path expression matches `answers`
+    pub answers_path: Option<String>,
 }
 
 impl ListQueryFilters {
     fn build_where_clause(&self) -> String {
-        let mut bindings = FilterBuilder::new(4);
+        let mut filters = ExtraFilters::new(4);
+
+        if self.question_contains.is_some() {
+            filters.contains("question", false);
+        }
+
+        if self.question_icontains.is_some() {
+            filters.contains("question", true);
+        }
+
+        if self.answers_path.is_some() {
+            filters.json_path("answers");
+        }
+
+        let mut bindings = FilterBuilder::new(filters.next_binding());
 
         if !self.id.is_empty() {
             bindings.add_vec("id", &self.id);
         }
 
+        if !self.question.is_empty() {
+            bindings.add_vec("question", &self.question);
+        }
+
         if !self.post_id.is_empty() {
             bindings.add_vec("post_id", &self.post_id);
         }
@@ -111,17 +138,38 @@ impl ListQueryFilters {
             bindings.add_option("created_at", &self.created_at_gte, BindingOperator::Gte);
         }
 
-        let query = bindings.to_string();
+        let mut query = bindings.to_string();
+        filters.append_to(&mut query);
         event!(Level::DEBUG, %query);
         query
     }
 
     fn bind_to_query<'a, T>(&'a self, mut query: QueryAs<'a, T>) -> QueryAs<'a, T> {
+        if self.question_contains.is_some() {
+            event!(Level::DEBUG, question_contains = ?self.question_contains);
+            query = query.bind(&self.question_contains);
+        }
+
+        if self.question_icontains.is_some() {
+            event!(Level::DEBUG, question_icontains = ?self.question_icontains);
+            query = query.bind(&self.question_icontains);
+        }
+
+        if self.answers_path.is_some() {
+            event!(Level::DEBUG, answers_path = ?self.answers_path);
+            query = query.bind(&self.answers_path);
+        }
+
         if !self.id.is_empty() {
             event!(Level::DEBUG, id = ?self.id);
             query = query.bind(&self.id);
         }
 
+        if !self.question.is_empty() {
+            event!(Level::DEBUG, question = ?self.question);
+            query = query.bind(&self.question);
+        }
+
         if !self.post_id.is_empty() {
             event!(Level::DEBUG, post_id = ?self.post_id);
             query = query.bind(&self.post_id);
@@ -395,6 +443,28 @@ impl Poll {
         Self::check_missing_parent_error(result)
     }
 
+    /// Update only the fields of the parent's Poll that are present in a JSON Merge Patch.
+    #[instrument(skip(db))]
+    pub async fn patch_with_parent_post(
+        db: impl PgExecutor<'_>,
+        auth: &AuthInfo,
+        parent_id: &PostId,
+        payload: PollPatchPayload,
+    ) -> Result<bool, error_stack::Report<Error>> {
+        let result = query_file!(
+            "src/models/poll/patch_with_parent_post.sql",
+            payload.question.as_ref() as _,
+            payload.answers.as_ref() as _,
+            parent_id.as_uuid(),
+            auth.organization_id.as_uuid()
+        )
+        .execute(db)
+        .await
+        .change_context(Error::Db)?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
     /// Delete a child object, making sure that its parent ID matches.
     #[instrument(skip(db))]
     pub async fn delete_with_parent_post(
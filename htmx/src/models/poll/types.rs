@@ -121,3 +121,18 @@ impl Default for PollCreatePayloadAndUpdatePayload {
         }
     }
 }
+
+/// A JSON Merge Patch for a Poll. Fields that are omitted from the patch are left unchanged.
+#[derive(Deserialize, Debug, Clone, schemars::JsonSchema)]
+pub struct PollPatchPayload {
+    #[serde(default, deserialize_with = "crate::models::merge_patch::required")]
+    #[schemars(with = "String")]
+    pub question: Option<String>,
+    /// Merged into the existing value
+    #[serde(
+        default,
+        deserialize_with = "crate::models::merge_patch::required_json"
+    )]
+    #[schemars(with = "serde_json::Value")]
+    pub answers: Option<serde_json::Value>,
+}
@@ -0,0 +1,5 @@
+DELETE FROM public.posts
+WHERE id = ANY ($1)
+  AND organization_id = $2
+RETURNING
+  id AS "id: PostId"
@@ -0,0 +1,12 @@
+INSERT INTO public.posts (
+  id,
+  organization_id,
+  subject,
+  body)
+VALUES
+  __insertion_point_insert_values
+ON CONFLICT (
+  id)
+  DO NOTHING
+RETURNING
+  id
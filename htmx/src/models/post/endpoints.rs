@@ -2,10 +2,9 @@
 use std::{borrow::Cow, str::FromStr};
 
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::IntoResponse,
-    routing,
+    extract::{DefaultBodyLimit, FromRequest, OriginalUri, Path, State},
+    http::{HeaderMap, Method, StatusCode},
+    response::{IntoResponse, Response},
 };
 use axum_extra::extract::Query;
 use axum_jsonschema::Json;
@@ -21,22 +20,31 @@ use super::{
     WRITE_PERMISSION,
 };
 use crate::{
-    auth::{has_any_permission, Authed},
+    auth::Authed,
     models::{
+        bulk::{self, BulkRequest, BulkResponse},
         comment::{
             Comment, CommentCreatePayload, CommentCreateResult, CommentId, CommentUpdatePayload,
         },
+        export::ExportFormat,
+        filters,
+        merge_patch::MergePatch,
+        pagination,
         poll::{Poll, PollCreatePayload, PollCreateResult, PollId, PollUpdatePayload},
         post_image::{
-            PostImage, PostImageCreatePayload, PostImageCreateResult, PostImageId,
-            PostImageUpdatePayload,
+            PostImage, PostImageCreatePayload, PostImageCreateResult, PostImageHashStatus,
+            PostImageId, PostImageOrderPayload, PostImageUpdatePayload,
         },
         reaction::{
             Reaction, ReactionCreatePayload, ReactionCreateResult, ReactionId,
             ReactionUpdatePayload,
         },
     },
-    server::ServerState,
+    server::{
+        etag::{self, etag_header, IfMatch},
+        openapi::{child_model_routes, model_routes, ApiRoute, ApiRouter},
+        ServerState,
+    },
     Error,
 };
 
@@ -45,19 +53,39 @@ async fn get(
     auth: Authed,
     Path(id): Path<PostId>,
 ) -> Result<impl IntoResponse, Error> {
-    let object = Post::get_populated(&state.db, &auth, &id).await?;
+    let mut object = Post::get_populated(&state.db, &auth, &id).await?;
+    crate::models::post_image::storage::add_signed_urls(&state, &auth, &mut object.images);
 
-    Ok(Json(object))
+    Ok((etag_header(&object.updated_at), Json(object)))
 }
 
 async fn list(
     State(state): State<ServerState>,
     auth: Authed,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
     Query(qs): Query<queries::ListQueryFilters>,
 ) -> Result<impl IntoResponse, Error> {
+    if let Some(format) = ExportFormat::from_headers(&headers) {
+        return Ok(Post::export(&state.db, auth, qs, format)?);
+    }
+
     let results = Post::list_populated(&state.db, &auth, &qs).await?;
 
-    Ok(Json(results))
+    let total = if qs.include_total.unwrap_or(false) {
+        let mut conn = state.db.acquire().await.change_context(Error::Db)?;
+        Some(Post::count(&mut conn, &auth, &qs).await?)
+    } else {
+        None
+    };
+
+    Ok(pagination::list_response(
+        &uri,
+        qs.page,
+        qs.per_page,
+        results,
+        total,
+    ))
 }
 
 async fn create(
@@ -72,14 +100,28 @@ async fn create(
     Ok((StatusCode::CREATED, Json(result)))
 }
 
+async fn bulk_operations(
+    State(state): State<ServerState>,
+    auth: Authed,
+    axum::Json(request): axum::Json<BulkRequest<PostId, PostCreatePayload, PostUpdatePayload>>,
+) -> Result<impl IntoResponse, Error> {
+    let result = bulk::run::<Post>(&state, &auth, request).await?;
+
+    Ok(Json(result))
+}
+
 async fn update(
     State(state): State<ServerState>,
     auth: Authed,
     Path(id): Path<PostId>,
+    if_match: IfMatch,
     FormOrJson(payload): FormOrJson<PostUpdatePayload>,
 ) -> Result<impl IntoResponse, Error> {
     let mut tx = state.db.begin().await.change_context(Error::Db)?;
 
+    if_match
+        .lock_and_check(&mut *tx, "posts", id.as_uuid(), &auth.organization_id)
+        .await?;
     let result = Post::update(&mut *tx, &auth, &id, payload).await?;
 
     tx.commit().await.change_context(Error::Db)?;
@@ -91,6 +133,29 @@ async fn update(
     }
 }
 
+async fn patch(
+    State(state): State<ServerState>,
+    auth: Authed,
+    Path(id): Path<PostId>,
+    if_match: IfMatch,
+    MergePatch(payload): MergePatch<PostPatchPayload>,
+) -> Result<impl IntoResponse, Error> {
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
+
+    if_match
+        .lock_and_check(&mut *tx, "posts", id.as_uuid(), &auth.organization_id)
+        .await?;
+    let result = Post::patch(&mut *tx, &auth, &id, payload).await?;
+
+    tx.commit().await.change_context(Error::Db)?;
+
+    if result {
+        Ok(StatusCode::OK)
+    } else {
+        Ok(StatusCode::NOT_FOUND)
+    }
+}
+
 async fn delete(
     State(state): State<ServerState>,
     auth: Authed,
@@ -109,10 +174,21 @@ async fn delete(
         return Ok(StatusCode::NOT_FOUND);
     }
 
+    crate::users::favorites::remove_object(&mut *tx, &auth.organization_id, id.as_uuid()).await?;
+
+    // Files shared with other posts stay in storage.
+    let post_image_files =
+        crate::models::post_image::storage::release_keys(&state, &auth, &mut *tx, post_image_files)
+            .await?;
+
     tx.commit().await.change_context(Error::Db)?;
 
+    // The post is already gone, so keep going if a file can't be deleted. Anything left behind
+    // is cleaned up by the gc_storage job.
     for file in post_image_files {
-        crate::models::post_image::storage::delete_by_key(&state, &file).await?;
+        if let Err(e) = crate::models::post_image::storage::delete_by_key(&state, &file).await {
+            event!(Level::WARN, key = file, error = ?e, "Failed to delete post image");
+        }
     }
 
     Ok(StatusCode::OK)
@@ -122,13 +198,20 @@ async fn list_child_comment(
     State(state): State<ServerState>,
     auth: Authed,
     Path(parent_id): Path<PostId>,
+    headers: HeaderMap,
     Query(mut qs): Query<crate::models::comment::queries::ListQueryFilters>,
 ) -> Result<impl IntoResponse, Error> {
     qs.post_id = vec![parent_id];
 
+    if let Some(format) = ExportFormat::from_headers(&headers) {
+        return Ok(crate::models::comment::Comment::export(
+            &state.db, auth, qs, format,
+        )?);
+    }
+
     let object = crate::models::comment::Comment::list(&state.db, &auth, &qs).await?;
 
-    Ok(Json(object))
+    Ok(Json(object).into_response())
 }
 
 async fn get_child_comment(
@@ -141,7 +224,7 @@ async fn get_child_comment(
         return Err(Error::NotFound("Parent Post"));
     }
 
-    Ok(Json(object))
+    Ok((etag_header(&object.updated_at), Json(object)))
 }
 
 async fn create_child_comment(
@@ -155,6 +238,13 @@ async fn create_child_comment(
     payload.post_id = parent_id;
 
     let result = crate::models::comment::Comment::create(&mut *tx, &auth, payload).await?;
+    etag::touch(
+        &mut *tx,
+        "posts",
+        parent_id.as_uuid(),
+        &auth.organization_id,
+    )
+    .await?;
 
     tx.commit().await.change_context(Error::Db)?;
 
@@ -165,6 +255,7 @@ async fn update_child_comment(
     State(state): State<ServerState>,
     auth: Authed,
     Path((parent_id, child_id)): Path<(PostId, CommentId)>,
+    if_match: IfMatch,
     FormOrJson(mut payload): FormOrJson<CommentUpdatePayload>,
 ) -> Result<impl IntoResponse, Error> {
     payload.id = Some(child_id);
@@ -176,10 +267,73 @@ async fn update_child_comment(
 
     object_perm.must_be_writable(WRITE_PERMISSION)?;
 
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
+    if_match
+        .lock_and_check(
+            &mut *tx,
+            "comments",
+            child_id.as_uuid(),
+            &auth.organization_id,
+        )
+        .await?;
+
     let result = crate::models::comment::Comment::update_one_with_parent_post(
-        &state.db, &auth, &parent_id, &child_id, payload,
+        &mut *tx, &auth, &parent_id, &child_id, payload,
+    )
+    .await?;
+    if result {
+        etag::touch(
+            &mut *tx,
+            "posts",
+            parent_id.as_uuid(),
+            &auth.organization_id,
+        )
+        .await?;
+    }
+
+    tx.commit().await.change_context(Error::Db)?;
+
+    Ok(Json(result))
+}
+
+async fn patch_child_comment(
+    State(state): State<ServerState>,
+    auth: Authed,
+    Path((parent_id, child_id)): Path<(PostId, CommentId)>,
+    if_match: IfMatch,
+    MergePatch(payload): MergePatch<crate::models::comment::CommentPatchPayload>,
+) -> Result<impl IntoResponse, Error> {
+    let object_perm = Post::lookup_object_permissions(&state.db, &auth, &parent_id)
+        .await?
+        .unwrap_or(ObjectPermission::Read);
+
+    object_perm.must_be_writable(WRITE_PERMISSION)?;
+
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
+    if_match
+        .lock_and_check(
+            &mut *tx,
+            "comments",
+            child_id.as_uuid(),
+            &auth.organization_id,
+        )
+        .await?;
+
+    let result = crate::models::comment::Comment::patch_one_with_parent_post(
+        &mut *tx, &auth, &parent_id, &child_id, payload,
     )
     .await?;
+    if result {
+        etag::touch(
+            &mut *tx,
+            "posts",
+            parent_id.as_uuid(),
+            &auth.organization_id,
+        )
+        .await?;
+    }
+
+    tx.commit().await.change_context(Error::Db)?;
 
     Ok(Json(result))
 }
@@ -189,10 +343,21 @@ async fn delete_child_comment(
     auth: Authed,
     Path((parent_id, child_id)): Path<(PostId, CommentId)>,
 ) -> Result<impl IntoResponse, Error> {
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
     let deleted = crate::models::comment::Comment::delete_with_parent_post(
-        &state.db, &auth, &parent_id, &child_id,
+        &mut *tx, &auth, &parent_id, &child_id,
     )
     .await?;
+    if deleted {
+        etag::touch(
+            &mut *tx,
+            "posts",
+            parent_id.as_uuid(),
+            &auth.organization_id,
+        )
+        .await?;
+    }
+    tx.commit().await.change_context(Error::Db)?;
 
     if deleted {
         Ok(StatusCode::OK)
@@ -205,13 +370,20 @@ async fn list_child_reaction(
     State(state): State<ServerState>,
     auth: Authed,
     Path(parent_id): Path<PostId>,
+    headers: HeaderMap,
     Query(mut qs): Query<crate::models::reaction::queries::ListQueryFilters>,
 ) -> Result<impl IntoResponse, Error> {
     qs.post_id = vec![parent_id];
 
+    if let Some(format) = ExportFormat::from_headers(&headers) {
+        return Ok(crate::models::reaction::Reaction::export(
+            &state.db, auth, qs, format,
+        )?);
+    }
+
     let object = crate::models::reaction::Reaction::list(&state.db, &auth, &qs).await?;
 
-    Ok(Json(object))
+    Ok(Json(object).into_response())
 }
 
 async fn get_child_reaction(
@@ -224,7 +396,7 @@ async fn get_child_reaction(
         return Err(Error::NotFound("Parent Post"));
     }
 
-    Ok(Json(object))
+    Ok((etag_header(&object.updated_at), Json(object)))
 }
 
 async fn create_child_reaction(
@@ -238,6 +410,13 @@ async fn create_child_reaction(
     payload.post_id = parent_id;
 
     let result = crate::models::reaction::Reaction::create(&mut *tx, &auth, payload).await?;
+    etag::touch(
+        &mut *tx,
+        "posts",
+        parent_id.as_uuid(),
+        &auth.organization_id,
+    )
+    .await?;
 
     tx.commit().await.change_context(Error::Db)?;
 
@@ -248,6 +427,7 @@ async fn update_child_reaction(
     State(state): State<ServerState>,
     auth: Authed,
     Path((parent_id, child_id)): Path<(PostId, ReactionId)>,
+    if_match: IfMatch,
     FormOrJson(mut payload): FormOrJson<ReactionUpdatePayload>,
 ) -> Result<impl IntoResponse, Error> {
     payload.id = Some(child_id);
@@ -259,10 +439,73 @@ async fn update_child_reaction(
 
     object_perm.must_be_writable(WRITE_PERMISSION)?;
 
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
+    if_match
+        .lock_and_check(
+            &mut *tx,
+            "reactions",
+            child_id.as_uuid(),
+            &auth.organization_id,
+        )
+        .await?;
+
     let result = crate::models::reaction::Reaction::update_one_with_parent_post(
-        &state.db, &auth, &parent_id, &child_id, payload,
+        &mut *tx, &auth, &parent_id, &child_id, payload,
+    )
+    .await?;
+    if result {
+        etag::touch(
+            &mut *tx,
+            "posts",
+            parent_id.as_uuid(),
+            &auth.organization_id,
+        )
+        .await?;
+    }
+
+    tx.commit().await.change_context(Error::Db)?;
+
+    Ok(Json(result))
+}
+
+async fn patch_child_reaction(
+    State(state): State<ServerState>,
+    auth: Authed,
+    Path((parent_id, child_id)): Path<(PostId, ReactionId)>,
+    if_match: IfMatch,
+    MergePatch(payload): MergePatch<crate::models::reaction::ReactionPatchPayload>,
+) -> Result<impl IntoResponse, Error> {
+    let object_perm = Post::lookup_object_permissions(&state.db, &auth, &parent_id)
+        .await?
+        .unwrap_or(ObjectPermission::Read);
+
+    object_perm.must_be_writable(WRITE_PERMISSION)?;
+
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
+    if_match
+        .lock_and_check(
+            &mut *tx,
+            "reactions",
+            child_id.as_uuid(),
+            &auth.organization_id,
+        )
+        .await?;
+
+    let result = crate::models::reaction::Reaction::patch_one_with_parent_post(
+        &mut *tx, &auth, &parent_id, &child_id, payload,
     )
     .await?;
+    if result {
+        etag::touch(
+            &mut *tx,
+            "posts",
+            parent_id.as_uuid(),
+            &auth.organization_id,
+        )
+        .await?;
+    }
+
+    tx.commit().await.change_context(Error::Db)?;
 
     Ok(Json(result))
 }
@@ -272,10 +515,21 @@ async fn delete_child_reaction(
     auth: Authed,
     Path((parent_id, child_id)): Path<(PostId, ReactionId)>,
 ) -> Result<impl IntoResponse, Error> {
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
     let deleted = crate::models::reaction::Reaction::delete_with_parent_post(
-        &state.db, &auth, &parent_id, &child_id,
+        &mut *tx, &auth, &parent_id, &child_id,
     )
     .await?;
+    if deleted {
+        etag::touch(
+            &mut *tx,
+            "posts",
+            parent_id.as_uuid(),
+            &auth.organization_id,
+        )
+        .await?;
+    }
+    tx.commit().await.change_context(Error::Db)?;
 
     if deleted {
         Ok(StatusCode::OK)
@@ -291,18 +545,20 @@ async fn list_child_poll(
     Query(mut qs): Query<crate::models::poll::queries::ListQueryFilters>,
 ) -> Result<impl IntoResponse, Error> {
     qs.post_id = vec![parent_id];
+    filters::validate_json_path(&state.db, qs.answers_path.as_deref()).await?;
 
     let object = crate::models::poll::Poll::list(&state.db, &auth, &qs).await?;
 
     let object = object.into_iter().next().ok_or(Error::NotFound("Poll"))?;
 
-    Ok(Json(object))
+    Ok((etag_header(&object.updated_at), Json(object)))
 }
 
 async fn upsert_child_poll(
     State(state): State<ServerState>,
     auth: Authed,
     Path(parent_id): Path<PostId>,
+    if_match: IfMatch,
     FormOrJson(mut payload): FormOrJson<PollUpdatePayload>,
 ) -> Result<impl IntoResponse, Error> {
     payload.post_id = parent_id;
@@ -313,13 +569,75 @@ async fn upsert_child_poll(
 
     object_perm.must_be_writable(WRITE_PERMISSION)?;
 
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
+    if_match
+        .lock_and_check_by_parent(
+            &mut *tx,
+            "polls",
+            "post_id",
+            parent_id.as_uuid(),
+            &auth.organization_id,
+        )
+        .await?;
+
     let result = crate::models::poll::Poll::upsert_with_parent_post(
-        &state.db,
+        &mut *tx,
         &auth.organization_id,
         &parent_id,
         &payload,
     )
     .await?;
+    etag::touch(
+        &mut *tx,
+        "posts",
+        parent_id.as_uuid(),
+        &auth.organization_id,
+    )
+    .await?;
+
+    tx.commit().await.change_context(Error::Db)?;
+
+    Ok(Json(result))
+}
+
+async fn patch_child_poll(
+    State(state): State<ServerState>,
+    auth: Authed,
+    Path(parent_id): Path<PostId>,
+    if_match: IfMatch,
+    MergePatch(payload): MergePatch<crate::models::poll::PollPatchPayload>,
+) -> Result<impl IntoResponse, Error> {
+    let object_perm = Post::lookup_object_permissions(&state.db, &auth, &parent_id)
+        .await?
+        .unwrap_or(ObjectPermission::Read);
+
+    object_perm.must_be_writable(WRITE_PERMISSION)?;
+
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
+    if_match
+        .lock_and_check_by_parent(
+            &mut *tx,
+            "polls",
+            "post_id",
+            parent_id.as_uuid(),
+            &auth.organization_id,
+        )
+        .await?;
+
+    let result =
+        crate::models::poll::Poll::patch_with_parent_post(&mut *tx, &auth, &parent_id, payload)
+            .await?;
+    if result {
+        etag::touch(
+            &mut *tx,
+            "posts",
+            parent_id.as_uuid(),
+            &auth.organization_id,
+        )
+        .await?;
+    }
+
+    tx.commit().await.change_context(Error::Db)?;
 
     Ok(Json(result))
 }
@@ -335,12 +653,23 @@ async fn delete_child_poll(
 
     object_perm.must_be_writable(WRITE_PERMISSION)?;
 
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
     let deleted = crate::models::poll::Poll::delete_all_children_of_post(
-        &state.db,
+        &mut *tx,
         &auth.organization_id,
         &parent_id,
     )
     .await?;
+    if deleted {
+        etag::touch(
+            &mut *tx,
+            "posts",
+            parent_id.as_uuid(),
+            &auth.organization_id,
+        )
+        .await?;
+    }
+    tx.commit().await.change_context(Error::Db)?;
 
     if deleted {
         Ok(StatusCode::OK)
@@ -356,8 +685,12 @@ async fn list_child_post_image(
     Query(mut qs): Query<crate::models::post_image::queries::ListQueryFilters>,
 ) -> Result<impl IntoResponse, Error> {
     qs.post_id = vec![parent_id];
+    if qs.order_by.is_none() {
+        qs.order_by = Some("position".to_string());
+    }
 
-    let object = crate::models::post_image::PostImage::list(&state.db, &auth, &qs).await?;
+    let mut object = crate::models::post_image::PostImage::list(&state.db, &auth, &qs).await?;
+    crate::models::post_image::storage::add_signed_urls(&state, &auth, &mut object);
 
     Ok(Json(object))
 }
@@ -367,24 +700,94 @@ async fn get_child_post_image(
     auth: Authed,
     Path((parent_id, child_id)): Path<(PostId, PostImageId)>,
 ) -> Result<impl IntoResponse, Error> {
-    let object = crate::models::post_image::PostImage::get(&state.db, &auth, &child_id).await?;
+    let mut object = crate::models::post_image::PostImage::get(&state.db, &auth, &child_id).await?;
     if object.post_id != parent_id {
         return Err(Error::NotFound("Parent Post"));
     }
+    crate::models::post_image::storage::add_signed_urls(&state, &auth, [&mut object]);
 
     Ok(Json(object))
 }
 
+async fn reorder_child_post_image(
+    State(state): State<ServerState>,
+    auth: Authed,
+    Path(parent_id): Path<PostId>,
+    Json(payload): Json<PostImageOrderPayload>,
+) -> Result<impl IntoResponse, Error> {
+    let object_perm = Post::lookup_object_permissions(&state.db, &auth, &parent_id)
+        .await?
+        .unwrap_or(ObjectPermission::Read);
+
+    object_perm.must_be_writable(WRITE_PERMISSION)?;
+
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
+    let mut results = crate::models::post_image::PostImage::reorder_children_of_post(
+        &mut *tx,
+        &auth.organization_id,
+        &parent_id,
+        &payload.ids,
+    )
+    .await?;
+    etag::touch(
+        &mut *tx,
+        "posts",
+        parent_id.as_uuid(),
+        &auth.organization_id,
+    )
+    .await?;
+    tx.commit().await.change_context(Error::Db)?;
+
+    crate::models::post_image::storage::add_signed_urls(&state, &auth, &mut results);
+
+    Ok(Json(results))
+}
+
+/// Returns true if the request has a `multipart/form-data` body.
+fn is_multipart(headers: &HeaderMap) -> bool {
+    headers
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("multipart/form-data"))
+}
+
 async fn create_child_post_image(
     State(state): State<ServerState>,
     auth: Authed,
     Path(parent_id): Path<PostId>,
     Query(qs): Query<filigree::storage::QueryFilename>,
-    body: axum::body::Body,
-) -> Result<impl IntoResponse, Error> {
+    request: axum::extract::Request,
+) -> Result<Response, Error> {
+    if is_multipart(request.headers()) {
+        let multipart = axum::extract::Multipart::from_request(request, &state)
+            .await
+            .map_err(|_| Error::InvalidUploadRequest("Invalid multipart body"))?;
+
+        let mut tx = state.db.begin().await.change_context(Error::Db)?;
+        let mut results = crate::models::post_image::storage::upload_multipart(
+            &state, &auth, &mut *tx, parent_id, multipart,
+        )
+        .await?;
+        etag::touch(
+            &mut *tx,
+            "posts",
+            parent_id.as_uuid(),
+            &auth.organization_id,
+        )
+        .await?;
+        tx.commit().await.change_context(Error::Db)?;
+
+        for result in &results {
+            crate::models::post_image::renditions::enqueue(&state, result).await;
+        }
+        crate::models::post_image::storage::add_signed_urls(&state, &auth, &mut results);
+
+        return Ok(Json(results).into_response());
+    }
+
     let mut tx = state.db.begin().await.change_context(Error::Db)?;
 
-    let result = crate::models::post_image::storage::upload_stream(
+    let mut result = crate::models::post_image::storage::upload_stream(
         &state,
         &auth,
         &mut *tx,
@@ -393,12 +796,76 @@ async fn create_child_post_image(
         qs.filename.clone(),
         None,
         None,
-        body.into_data_stream(),
+        request.into_body().into_data_stream(),
+    )
+    .await?;
+    etag::touch(
+        &mut *tx,
+        "posts",
+        parent_id.as_uuid(),
+        &auth.organization_id,
+    )
+    .await?;
+
+    tx.commit().await.change_context(Error::Db)?;
+
+    crate::models::post_image::renditions::enqueue(&state, &result).await;
+    crate::models::post_image::storage::add_signed_urls(&state, &auth, [&mut result]);
+
+    Ok(Json(result).into_response())
+}
+
+fn parse_file_hash(hash: &str) -> Result<Vec<u8>, Error> {
+    crate::storage_blobs::parse_hash(hash)
+        .ok_or(Error::InvalidUploadRequest("The hash must be hex-encoded"))
+}
+
+async fn check_child_post_image_hash(
+    State(state): State<ServerState>,
+    auth: Authed,
+    Path((_parent_id, hash)): Path<(PostId, String)>,
+) -> Result<impl IntoResponse, Error> {
+    let hash = parse_file_hash(&hash)?;
+    let blob = crate::models::post_image::storage::find_by_hash(&state, &auth, &hash).await?;
+
+    Ok(Json(PostImageHashStatus {
+        exists: blob.is_some(),
+        file_size: blob.map(|b| b.size),
+    }))
+}
+
+async fn create_child_post_image_from_hash(
+    State(state): State<ServerState>,
+    auth: Authed,
+    Path((parent_id, hash)): Path<(PostId, String)>,
+    Query(qs): Query<filigree::storage::QueryFilename>,
+) -> Result<impl IntoResponse, Error> {
+    let hash = parse_file_hash(&hash)?;
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
+
+    let mut result = crate::models::post_image::storage::create_from_hash(
+        &state,
+        &auth,
+        &mut *tx,
+        parent_id,
+        &hash,
+        qs.filename.clone(),
+    )
+    .await?
+    .ok_or(Error::NotFound("File"))?;
+    etag::touch(
+        &mut *tx,
+        "posts",
+        parent_id.as_uuid(),
+        &auth.organization_id,
     )
     .await?;
 
     tx.commit().await.change_context(Error::Db)?;
 
+    crate::models::post_image::renditions::enqueue(&state, &result).await;
+    crate::models::post_image::storage::add_signed_urls(&state, &auth, [&mut result]);
+
     Ok(Json(result))
 }
 
@@ -412,6 +879,15 @@ async fn delete_child_post_image(
         &state, &auth, &mut *tx, parent_id, child_id,
     )
     .await?;
+    if deleted {
+        etag::touch(
+            &mut *tx,
+            "posts",
+            parent_id.as_uuid(),
+            &auth.organization_id,
+        )
+        .await?;
+    }
     tx.commit().await.change_context(Error::Db)?;
 
     if deleted {
@@ -421,139 +897,326 @@ async fn delete_child_post_image(
     }
 }
 
-pub fn create_routes() -> axum::Router<ServerState> {
-    axum::Router::new()
-        .route(
-            "/posts",
-            routing::get(list).route_layer(has_any_permission(vec![READ_PERMISSION, "org_admin"])),
-        )
-        .route(
-            "/posts/:id",
-            routing::get(get).route_layer(has_any_permission(vec![READ_PERMISSION, "org_admin"])),
-        )
-        .route(
-            "/posts",
-            routing::post(create)
-                .route_layer(has_any_permission(vec![CREATE_PERMISSION, "org_admin"])),
-        )
-        .route(
-            "/posts/:id",
-            routing::put(update).route_layer(has_any_permission(vec![
-                WRITE_PERMISSION,
-                OWNER_PERMISSION,
-                "org_admin",
-            ])),
-        )
-        .route(
-            "/posts/:id",
-            routing::delete(delete)
-                .route_layer(has_any_permission(vec![CREATE_PERMISSION, "org_admin"])),
-        )
+fn routes() -> ApiRouter {
+    use crate::models::{comment, poll, post_image, reaction};
+
+    let read_perms = &[READ_PERMISSION, "org_admin"];
+    let write_perms = &[WRITE_PERMISSION, OWNER_PERMISSION, "org_admin"];
+    let create_perms = &[CREATE_PERMISSION, "org_admin"];
+
+    let model = model_routes::<
+        PostId,
+        PostPopulatedGetResult,
+        PostPopulatedListResult,
+        queries::ListQueryFilters,
+        PostCreatePayload,
+        PostCreateResult,
+        PostUpdatePayload,
+        PostPatchPayload,
+    >(
+        "post",
+        "/posts",
+        "/posts/:id",
+        read_perms,
+        write_perms,
+        create_perms,
+    );
+
+    let comments = child_model_routes::<
+        PostId,
+        CommentId,
+        Comment,
+        comment::queries::ListQueryFilters,
+        CommentCreatePayload,
+        CommentCreateResult,
+        CommentUpdatePayload,
+        comment::CommentPatchPayload,
+    >(
+        "comment",
+        "/posts/:id/comments",
+        "/posts/:id/comments/:child_id",
+        read_perms,
+        write_perms,
+        create_perms,
+    );
+
+    let reactions = child_model_routes::<
+        PostId,
+        ReactionId,
+        Reaction,
+        reaction::queries::ListQueryFilters,
+        ReactionCreatePayload,
+        ReactionCreateResult,
+        ReactionUpdatePayload,
+        reaction::ReactionPatchPayload,
+    >(
+        "reaction",
+        "/posts/:id/reactions",
+        "/posts/:id/reactions/:child_id",
+        read_perms,
+        write_perms,
+        create_perms,
+    );
+
+    ApiRouter::new()
+        .route(model.list, list)
+        .route(model.get, get)
+        .route(model.create, create)
+        .route(model.update, update)
+        .route(model.patch, patch)
+        .route(model.delete, delete)
         .route(
-            "/posts/:id/comments",
-            routing::get(list_child_comment)
-                .route_layer(has_any_permission(vec![READ_PERMISSION, "org_admin"])),
+            ApiRoute::new(
+                Method::POST,
+                "/posts/bulk",
+                "post",
+                "Create, update and delete posts in one transaction",
+            )
+            .permissions(&[CREATE_PERMISSION, WRITE_PERMISSION, "org_admin"])
+            .request::<BulkRequest<PostId, PostCreatePayload, PostUpdatePayload>>()
+            .response::<BulkResponse<PostId>>(),
+            bulk_operations,
         )
+        .route(comments.list.exports(), list_child_comment)
+        .route(comments.create, create_child_comment)
+        .route(comments.get, get_child_comment)
+        .route(comments.update, update_child_comment)
+        .route(comments.patch, patch_child_comment)
+        .route(comments.delete, delete_child_comment)
+        .route(reactions.list.exports(), list_child_reaction)
+        .route(reactions.create, create_child_reaction)
+        .route(reactions.get, get_child_reaction)
+        .route(reactions.update, update_child_reaction)
+        .route(reactions.patch, patch_child_reaction)
+        .route(reactions.delete, delete_child_reaction)
         .route(
-            "/posts/:id/comments",
-            routing::post(create_child_comment)
-                .route_layer(has_any_permission(vec![CREATE_PERMISSION, "org_admin"])),
+            ApiRoute::new(
+                Method::GET,
+                "/posts/:id/poll",
+                "poll",
+                "Get the poll for a post",
+            )
+            .permissions(read_perms)
+            .path_param::<PostId>()
+            .response::<Poll>(),
+            list_child_poll,
         )
         .route(
-            "/posts/:id/comments/:child_id",
-            routing::get(get_child_comment)
-                .route_layer(has_any_permission(vec![READ_PERMISSION, "org_admin"])),
+            ApiRoute::new(
+                Method::POST,
+                "/posts/:id/poll",
+                "poll",
+                "Create or update the poll for a post",
+            )
+            .permissions(create_perms)
+            .path_param::<PostId>()
+            .request::<PollCreatePayload>()
+            .response::<Poll>(),
+            upsert_child_poll,
         )
         .route(
-            "/posts/:id/comments/:child_id",
-            routing::put(update_child_comment).route_layer(has_any_permission(vec![
-                WRITE_PERMISSION,
-                OWNER_PERMISSION,
-                "org_admin",
-            ])),
+            ApiRoute::new(
+                Method::PUT,
+                "/posts/:id/poll",
+                "poll",
+                "Create or update the poll for a post",
+            )
+            .permissions(write_perms)
+            .path_param::<PostId>()
+            .request::<PollUpdatePayload>()
+            .response::<Poll>(),
+            upsert_child_poll,
         )
         .route(
-            "/posts/:id/comments/:child_id",
-            routing::delete(delete_child_comment)
-                .route_layer(has_any_permission(vec![CREATE_PERMISSION, "org_admin"])),
+            ApiRoute::new(
+                Method::PATCH,
+                "/posts/:id/poll",
+                "poll",
+                "Partially update the poll for a post",
+            )
+            .permissions(write_perms)
+            .path_param::<PostId>()
+            .request::<poll::PollPatchPayload>(),
+            patch_child_poll,
         )
         .route(
-            "/posts/:id/reactions",
-            routing::get(list_child_reaction)
-                .route_layer(has_any_permission(vec![READ_PERMISSION, "org_admin"])),
+            ApiRoute::new(
+                Method::DELETE,
+                "/posts/:id/poll",
+                "poll",
+                "Delete the poll for a post",
+            )
+            .permissions(create_perms)
+            .path_param::<PostId>(),
+            delete_child_poll,
         )
         .route(
-            "/posts/:id/reactions",
-            routing::post(create_child_reaction)
-                .route_layer(has_any_permission(vec![CREATE_PERMISSION, "org_admin"])),
+            ApiRoute::new(
+                Method::GET,
+                "/posts/:id/post_images",
+                "post_image",
+                "List post_image objects",
+            )
+            .permissions(read_perms)
+            .path_param::<PostId>()
+            .query::<post_image::queries::ListQueryFilters>()
+            .response::<Vec<PostImage>>(),
+            list_child_post_image,
         )
-        .route(
-            "/posts/:id/reactions/:child_id",
-            routing::get(get_child_reaction)
-                .route_layer(has_any_permission(vec![READ_PERMISSION, "org_admin"])),
+        .route_with(
+            ApiRoute::new(
+                Method::POST,
+                "/posts/:id/post_images",
+                "post_image",
+                "Upload an image. The request body is the file contents, or a multipart form with files.",
+            )
+            .permissions(create_perms)
+            .path_param::<PostId>()
+            .response::<PostImageCreateResult>(),
+            create_child_post_image,
+            // The upload policy limits the size of each file instead.
+            |r| r.layer(DefaultBodyLimit::disable()),
         )
         .route(
-            "/posts/:id/reactions/:child_id",
-            routing::put(update_child_reaction).route_layer(has_any_permission(vec![
-                WRITE_PERMISSION,
-                OWNER_PERMISSION,
-                "org_admin",
-            ])),
+            ApiRoute::new(
+                Method::PUT,
+                "/posts/:id/post_images/order",
+                "post_image",
+                "Change the order of a post's images. The request must list each of the post's images exactly once.",
+            )
+            .permissions(write_perms)
+            .path_param::<PostId>()
+            .request::<PostImageOrderPayload>()
+            .response::<Vec<PostImage>>(),
+            reorder_child_post_image,
         )
         .route(
-            "/posts/:id/reactions/:child_id",
-            routing::delete(delete_child_reaction)
-                .route_layer(has_any_permission(vec![CREATE_PERMISSION, "org_admin"])),
+            ApiRoute::new(
+                Method::GET,
+                "/posts/:id/post_images/hashes/:hash",
+                "post_image",
+                "Check if a file with this hex-encoded Blake3 hash has already been uploaded",
+            )
+            .permissions(create_perms)
+            .path_param::<PostId>()
+            .path_param::<String>()
+            .response::<PostImageHashStatus>(),
+            check_child_post_image_hash,
         )
         .route(
-            "/posts/:id/poll",
-            routing::get(list_child_poll)
-                .route_layer(has_any_permission(vec![READ_PERMISSION, "org_admin"])),
+            ApiRoute::new(
+                Method::POST,
+                "/posts/:id/post_images/hashes/:hash",
+                "post_image",
+                "Create a post_image from an already-uploaded file with this hash, instead of uploading it again",
+            )
+            .permissions(create_perms)
+            .path_param::<PostId>()
+            .path_param::<String>()
+            .response::<PostImageCreateResult>(),
+            create_child_post_image_from_hash,
         )
         .route(
-            "/posts/:id/poll",
-            routing::post(upsert_child_poll)
-                .route_layer(has_any_permission(vec![CREATE_PERMISSION, "org_admin"])),
+            ApiRoute::new(
+                Method::GET,
+                "/posts/:id/post_images/:child_id",
+                "post_image",
+                "Get a post_image",
+            )
+            .permissions(read_perms)
+            .path_param::<PostId>()
+            .path_param::<PostImageId>()
+            .response::<PostImage>(),
+            get_child_post_image,
         )
         .route(
-            "/posts/:id/poll",
-            routing::put(upsert_child_poll).route_layer(has_any_permission(vec![
-                WRITE_PERMISSION,
-                OWNER_PERMISSION,
-                "org_admin",
-            ])),
+            ApiRoute::new(
+                Method::DELETE,
+                "/posts/:id/post_images/:child_id",
+                "post_image",
+                "Delete a post_image",
+            )
+            .permissions(create_perms)
+            .path_param::<PostId>()
+            .path_param::<PostImageId>(),
+            delete_child_post_image,
         )
         .route(
-            "/posts/:id/poll",
-            routing::delete(delete_child_poll)
-                .route_layer(has_any_permission(vec![CREATE_PERMISSION, "org_admin"])),
+            ApiRoute::new(
+                Method::OPTIONS,
+                "/posts/:id/post_image_uploads",
+                "post_image",
+                "Describe the server's support for tus resumable uploads",
+            )
+            .permissions(create_perms)
+            .path_param::<PostId>()
+            .status(204),
+            post_image::tus::options_upload,
         )
         .route(
-            "/posts/:id/post_images",
-            routing::get(list_child_post_image)
-                .route_layer(has_any_permission(vec![READ_PERMISSION, "org_admin"])),
+            ApiRoute::new(
+                Method::POST,
+                "/posts/:id/post_image_uploads",
+                "post_image",
+                "Start a tus resumable upload. The Location header is the URL of the upload.",
+            )
+            .permissions(create_perms)
+            .path_param::<PostId>()
+            .status(201),
+            post_image::tus::create_upload,
         )
         .route(
-            "/posts/:id/post_images",
-            routing::post(create_child_post_image)
-                .route_layer(has_any_permission(vec![CREATE_PERMISSION, "org_admin"])),
+            ApiRoute::new(
+                Method::HEAD,
+                "/posts/:id/post_image_uploads/:child_id",
+                "post_image",
+                "Get the offset of a tus resumable upload",
+            )
+            .permissions(create_perms)
+            .path_param::<PostId>()
+            .path_param::<PostImageId>(),
+            post_image::tus::head_upload,
         )
         .route(
-            "/posts/:id/post_images/:child_id",
-            routing::get(get_child_post_image)
-                .route_layer(has_any_permission(vec![READ_PERMISSION, "org_admin"])),
+            ApiRoute::new(
+                Method::PATCH,
+                "/posts/:id/post_image_uploads/:child_id",
+                "post_image",
+                "Append data to a tus resumable upload. The post_image is created once the upload is complete.",
+            )
+            .permissions(create_perms)
+            .path_param::<PostId>()
+            .path_param::<PostImageId>()
+            .status(204),
+            post_image::tus::patch_upload,
         )
         .route(
-            "/posts/:id/post_images/:child_id",
-            routing::delete(delete_child_post_image)
-                .route_layer(has_any_permission(vec![CREATE_PERMISSION, "org_admin"])),
+            ApiRoute::new(
+                Method::DELETE,
+                "/posts/:id/post_image_uploads/:child_id",
+                "post_image",
+                "Cancel a tus resumable upload",
+            )
+            .permissions(create_perms)
+            .path_param::<PostId>()
+            .path_param::<PostImageId>()
+            .status(204),
+            post_image::tus::delete_upload,
         )
 }
 
-#[cfg(test)]
-mod test {
-    use filigree::testing::ResponseExt;
-    use futures::{StreamExt, TryStreamExt};
+pub fn create_routes() -> axum::Router<ServerState> {
+    routes().into_router()
+}
+
+pub fn api_docs() -> Vec<ApiRoute> {
+    routes().into_docs()
+}
+
+#[cfg(test)]
+mod test {
+    use filigree::testing::ResponseExt;
+    use futures::{StreamExt, TryStreamExt};
     use tracing::{event, Level};
 
     use super::{
@@ -562,7 +1225,7 @@ mod test {
     };
     use crate::{
         models::organization::OrganizationId,
-        tests::{start_app, BootstrappedData},
+        tests::{start_app, start_app_with_options, BootstrappedData, TestAppOptions},
     };
 
     async fn setup_test_objects(
@@ -700,8 +1363,239 @@ mod test {
     async fn list_paginated(_pool: sqlx::PgPool) {}
 
     #[sqlx::test]
-    #[ignore = "todo"]
-    async fn list_filters(_pool: sqlx::PgPool) {}
+    async fn list_filters(pool: sqlx::PgPool) {
+        let (
+            _app,
+            BootstrappedData {
+                organization,
+                admin_user,
+                user,
+                ..
+            },
+        ) = start_app(pool.clone()).await;
+
+        let added_objects = setup_test_objects(&pool, organization.id, 3).await;
+
+        admin_user
+            .client
+            .post(&format!("posts/{}/poll", added_objects[1].1.id))
+            .json(&crate::models::poll::testing::make_create_payload(1))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap();
+
+        let fetch_ids = |query: Vec<(&'static str, &'static str)>| {
+            let client = &user.client;
+            async move {
+                let results = client
+                    .get("posts")
+                    .query(&query)
+                    .send()
+                    .await
+                    .unwrap()
+                    .log_error()
+                    .await
+                    .unwrap()
+                    .json::<Vec<serde_json::Value>>()
+                    .await
+                    .unwrap();
+
+                let mut ids = results
+                    .iter()
+                    .map(|o| o["id"].as_str().unwrap().to_string())
+                    .collect::<Vec<_>>();
+                ids.sort();
+                ids
+            }
+        };
+
+        let expected = |indexes: &[usize]| {
+            let mut ids = indexes
+                .iter()
+                .map(|&i| added_objects[i].1.id.to_string())
+                .collect::<Vec<_>>();
+            ids.sort();
+            ids
+        };
+
+        assert_eq!(
+            fetch_ids(vec![("has_poll", "true")]).await,
+            expected(&[1]),
+            "has_poll"
+        );
+        assert_eq!(
+            fetch_ids(vec![("has_poll", "false")]).await,
+            expected(&[0, 2]),
+            "not has_poll"
+        );
+        assert_eq!(
+            fetch_ids(vec![("subject", "Test object 2")]).await,
+            expected(&[2]),
+            "subject"
+        );
+        assert_eq!(
+            fetch_ids(vec![
+                ("subject_icontains", "test OBJECT"),
+                ("has_poll", "false")
+            ])
+            .await,
+            expected(&[0, 2]),
+            "combined filters"
+        );
+        assert_eq!(
+            fetch_ids(vec![("body_contains", "%")]).await,
+            expected(&[]),
+            "contains does not treat % as a wildcard"
+        );
+
+        let post_id = added_objects[0].1.id;
+        for i in 1..3 {
+            admin_user
+                .client
+                .post(&format!("posts/{post_id}/reactions"))
+                .json(&crate::models::reaction::testing::make_create_payload(i))
+                .send()
+                .await
+                .unwrap()
+                .log_error()
+                .await
+                .unwrap();
+        }
+
+        let fetch_reaction_types = |query: Vec<(&'static str, &'static str)>| {
+            let client = &user.client;
+            async move {
+                let results = client
+                    .get(&format!("posts/{post_id}/reactions"))
+                    .query(&query)
+                    .send()
+                    .await
+                    .unwrap()
+                    .log_error()
+                    .await
+                    .unwrap()
+                    .json::<Vec<serde_json::Value>>()
+                    .await
+                    .unwrap();
+
+                let mut types = results
+                    .iter()
+                    .map(|o| o["typ"].as_str().unwrap().to_string())
+                    .collect::<Vec<_>>();
+                types.sort();
+                types
+            }
+        };
+
+        assert_eq!(
+            fetch_reaction_types(vec![("typ_contains", "object 2")]).await,
+            vec!["Test object 2"],
+            "typ_contains"
+        );
+        assert_eq!(
+            fetch_reaction_types(vec![("typ_contains", "TEST")]).await,
+            Vec::<String>::new(),
+            "typ_contains is case sensitive"
+        );
+        assert_eq!(
+            fetch_reaction_types(vec![("typ_icontains", "TEST")]).await,
+            vec!["Test object 1", "Test object 2"],
+            "typ_icontains"
+        );
+    }
+
+    #[sqlx::test]
+    async fn list_export(pool: sqlx::PgPool) {
+        let (
+            _app,
+            BootstrappedData {
+                organization,
+                admin_user,
+                no_roles_user,
+                ..
+            },
+        ) = start_app(pool.clone()).await;
+
+        let added_objects = setup_test_objects(&pool, organization.id, 3).await;
+        let post_id = added_objects[0].1.id;
+        admin_user
+            .client
+            .post(&format!("posts/{post_id}/comments"))
+            .json(&crate::models::comment::testing::make_create_payload(1))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap();
+
+        let response = admin_user
+            .client
+            .get("posts")
+            .header("Accept", "text/csv")
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()["content-type"],
+            "text/csv; charset=utf-8"
+        );
+        let csv = response.text().await.unwrap();
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4, "header and one line per post");
+        assert!(lines[0].starts_with("id,body,comment_ids,"));
+
+        let ndjson = admin_user
+            .client
+            .get("posts")
+            .query(&[("per_page", "2")])
+            .header("Accept", "application/x-ndjson")
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        let rows = ndjson
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(rows.len(), 2, "per_page is honored when given");
+
+        let comments = admin_user
+            .client
+            .get(&format!("posts/{post_id}/comments"))
+            .header("Accept", "application/x-ndjson")
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        let comment = serde_json::from_str::<serde_json::Value>(comments.trim()).unwrap();
+        assert_eq!(comment["post_id"], post_id.to_string());
+
+        let response = no_roles_user
+            .client
+            .get("posts")
+            .header("Accept", "text/csv")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+    }
 
     #[sqlx::test]
     async fn get_object(pool: sqlx::PgPool) {
@@ -912,83 +1806,115 @@ mod test {
     }
 
     #[sqlx::test]
-    async fn delete_object(pool: sqlx::PgPool) {
-        let (
-            _app,
-            BootstrappedData {
-                organization,
-                admin_user,
-                no_roles_user,
-                ..
-            },
-        ) = start_app(pool.clone()).await;
-
-        let added_objects = setup_test_objects(&pool, organization.id, 2).await;
+    async fn create_with_children(pool: sqlx::PgPool) {
+        let (_app, BootstrappedData { admin_user, .. }) = start_app(pool.clone()).await;
+
+        let create_payload = PostCreatePayload {
+            poll: Some(crate::models::poll::testing::make_create_payload(1)),
+            comments: Some(vec![
+                crate::models::comment::testing::make_create_payload(1),
+                crate::models::comment::testing::make_create_payload(2),
+            ]),
+            ..make_create_payload(10)
+        };
 
-        admin_user
+        let created_result: serde_json::Value = admin_user
             .client
-            .delete(&format!("posts/{}", added_objects[1].1.id))
+            .post("posts")
+            .json(&create_payload)
             .send()
             .await
             .unwrap()
             .log_error()
             .await
-            .unwrap();
-
-        let response = admin_user
-            .client
-            .get(&format!("posts/{}", added_objects[1].1.id))
-            .send()
+            .unwrap()
+            .json()
             .await
             .unwrap();
-        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
 
-        // Delete should not happen without permissions
-        let response = no_roles_user
+        let created_id = created_result["id"].as_str().unwrap();
+        assert_eq!(
+            created_result["poll"]["question"],
+            serde_json::to_value(&create_payload.poll.as_ref().unwrap().question).unwrap(),
+            "poll from create response"
+        );
+        assert_eq!(created_result["poll"]["post_id"], created_id);
+
+        let comments = created_result["comments"].as_array().unwrap();
+        assert_eq!(comments.len(), 2);
+        for comment in comments {
+            assert_eq!(comment["post_id"], created_id);
+        }
+
+        // Updating with a single comment replaces the existing comments and the poll.
+        let update_payload = PostUpdatePayload {
+            poll: Some(crate::models::poll::testing::make_update_payload(2)),
+            comments: Some(vec![crate::models::comment::testing::make_update_payload(
+                3,
+            )]),
+            ..make_update_payload(11)
+        };
+
+        admin_user
             .client
-            .delete(&format!("posts/{}", added_objects[0].1.id))
+            .put(&format!("posts/{created_id}"))
+            .json(&update_payload)
             .send()
             .await
+            .unwrap()
+            .log_error()
+            .await
             .unwrap();
 
-        assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
-
-        // Make sure other objects still exist
-        let response = admin_user
+        let updated: serde_json::Value = admin_user
             .client
-            .get(&format!("posts/{}", added_objects[0].1.id))
+            .get(&format!("posts/{created_id}"))
             .send()
             .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json()
+            .await
             .unwrap();
-        assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+        assert_eq!(
+            updated["poll"]["question"],
+            serde_json::to_value(&update_payload.poll.as_ref().unwrap().question).unwrap(),
+            "poll after update"
+        );
+        assert_eq!(updated["poll"]["id"], created_result["poll"]["id"]);
+        assert_eq!(updated["comment_ids"].as_array().unwrap().len(), 1);
     }
 
     #[sqlx::test]
-
-    async fn child_comment(pool: sqlx::PgPool) {
-        // Create a test object
+    async fn bulk_operations(pool: sqlx::PgPool) {
         let (
             _app,
             BootstrappedData {
                 organization,
                 admin_user,
-                no_roles_user,
                 ..
             },
         ) = start_app(pool.clone()).await;
 
-        let (parent_payload, parent_result) = setup_test_objects(&pool, organization.id, 2)
-            .await
-            .into_iter()
-            .next()
-            .unwrap();
+        let added_objects = setup_test_objects(&pool, organization.id, 2).await;
+        let missing_id = PostId::new();
 
-        // Create a test object
-        let payload_one = crate::models::comment::testing::make_create_payload(1);
-        let create_result_one = admin_user
+        let response = admin_user
             .client
-            .post(&format!("posts/{}/comments", parent_result.id))
-            .json(&payload_one)
+            .post("posts/bulk")
+            .json(&serde_json::json!({
+                "atomic": false,
+                "operations": [
+                    { "op": "create", "payload": make_create_payload(10) },
+                    { "op": "create", "payload": { "subject": "no body" } },
+                    { "op": "update", "id": added_objects[0].1.id, "payload": make_update_payload(11) },
+                    { "op": "delete", "id": added_objects[1].1.id },
+                    { "op": "delete", "id": missing_id },
+                ]
+            }))
             .send()
             .await
             .unwrap()
@@ -998,26 +1924,22 @@ mod test {
             .json::<serde_json::Value>()
             .await
             .unwrap();
-        let id_one = CommentId::from_str(create_result_one["id"].as_str().unwrap()).unwrap();
 
-        // Try to create a test object with a bad parent id
-        let bad_parent_id = PostId::new();
-        let payload_two = crate::models::comment::testing::make_create_payload(1);
-        let response = admin_user
-            .client
-            .post(&format!("posts/{}/comments", bad_parent_id))
-            .json(&payload_two)
-            .send()
-            .await
-            .unwrap();
-        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+        assert_eq!(response["committed"], true);
+        let results = response["results"].as_array().unwrap();
+        assert_eq!(results.len(), 5);
+        assert_eq!(results[0]["status"], "ok");
+        assert_eq!(results[1]["status"], "error");
+        assert_eq!(results[1]["kind"], "invalid_operation");
+        assert_eq!(results[2]["status"], "ok");
+        assert_eq!(results[3]["status"], "ok");
+        assert_eq!(results[4]["status"], "error");
+        assert_eq!(results[4]["kind"], "not_found");
 
-        // Create another test object
-        let payload_two = crate::models::comment::testing::make_create_payload(2);
-        let create_result_two = admin_user
+        let created_id = PostId::from_str(results[0]["id"].as_str().unwrap()).unwrap();
+        let created = admin_user
             .client
-            .post(&format!("posts/{}/comments", parent_result.id))
-            .json(&payload_two)
+            .get(&format!("posts/{created_id}"))
             .send()
             .await
             .unwrap()
@@ -1027,56 +1949,76 @@ mod test {
             .json::<serde_json::Value>()
             .await
             .unwrap();
-        let id_two = CommentId::from_str(create_result_two["id"].as_str().unwrap()).unwrap();
+        assert_eq!(created["subject"], make_create_payload(10).subject);
 
-        // Check create without permissions
-        let bad_create_payload = crate::models::comment::testing::make_create_payload(9);
-        let res = no_roles_user
+        let updated = admin_user
             .client
-            .post(&format!("posts/{}/comments", parent_result.id))
-            .json(&bad_create_payload)
+            .get(&format!("posts/{}", added_objects[0].1.id))
             .send()
             .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json::<serde_json::Value>()
+            .await
             .unwrap();
-        assert_eq!(res.status(), reqwest::StatusCode::FORBIDDEN);
+        assert_eq!(updated["subject"], make_update_payload(11).subject);
 
-        // Check get without permissions
-        let res = no_roles_user
+        let deleted = admin_user
             .client
-            .get(&format!("posts/{}/comments/{}", parent_result.id, id_one))
+            .get(&format!("posts/{}", added_objects[1].1.id))
             .send()
             .await
             .unwrap();
-        assert_eq!(res.status(), reqwest::StatusCode::FORBIDDEN);
+        assert_eq!(deleted.status(), reqwest::StatusCode::NOT_FOUND);
 
-        // Check update without permissions
-        let bad_update_payload = crate::models::comment::testing::make_update_payload(8);
-        let res = no_roles_user
+        // A create that the database rejects only fails its own operation.
+        let mut bad_payload = make_create_payload(13);
+        bad_payload.subject = "Contains a \0 byte".to_string();
+        let response = admin_user
             .client
-            .put(&format!("posts/{}/comments/{}", parent_result.id, id_one))
-            .json(&bad_update_payload)
+            .post("posts/bulk")
+            .json(&serde_json::json!({
+                "atomic": false,
+                "operations": [
+                    { "op": "create", "payload": make_create_payload(12) },
+                    { "op": "create", "payload": bad_payload },
+                ]
+            }))
             .send()
             .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json::<serde_json::Value>()
+            .await
             .unwrap();
-        assert_eq!(res.status(), reqwest::StatusCode::FORBIDDEN);
 
-        // Check delete without permissions
-        let res = no_roles_user
+        assert_eq!(response["committed"], true);
+        assert_eq!(response["results"][0]["status"], "ok");
+        assert_eq!(response["results"][1]["status"], "error");
+
+        let created_id = PostId::from_str(response["results"][0]["id"].as_str().unwrap()).unwrap();
+        let created = admin_user
             .client
-            .delete(&format!("posts/{}/comments/{}", parent_result.id, id_one))
+            .get(&format!("posts/{created_id}"))
             .send()
             .await
             .unwrap();
-        assert_eq!(res.status(), reqwest::StatusCode::FORBIDDEN);
+        assert_eq!(created.status(), reqwest::StatusCode::OK);
 
-        // Check get of test object
-        let get_result_one = admin_user
+        // An atomic request with a failure doesn't save anything.
+        let response = admin_user
             .client
-            .get(&format!(
-                "posts/{}/comments/{}",
-                parent_result.id,
-                create_result_one["id"].as_str().unwrap()
-            ))
+            .post("posts/bulk")
+            .json(&serde_json::json!({
+                "operations": [
+                    { "op": "delete", "id": added_objects[0].1.id },
+                    { "op": "delete", "id": missing_id },
+                ]
+            }))
             .send()
             .await
             .unwrap()
@@ -1086,9 +2028,198 @@ mod test {
             .json::<serde_json::Value>()
             .await
             .unwrap();
-        assert_eq!(create_result_one, get_result_one);
 
-        // Check get of test object with a different parent ID
+        assert_eq!(response["committed"], false);
+        assert_eq!(response["results"][0]["status"], "skipped");
+        assert_eq!(response["results"][1]["status"], "error");
+
+        let still_exists = admin_user
+            .client
+            .get(&format!("posts/{}", added_objects[0].1.id))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(still_exists.status(), reqwest::StatusCode::OK);
+    }
+
+    #[sqlx::test]
+    async fn delete_object(pool: sqlx::PgPool) {
+        let (
+            _app,
+            BootstrappedData {
+                organization,
+                admin_user,
+                no_roles_user,
+                ..
+            },
+        ) = start_app(pool.clone()).await;
+
+        let added_objects = setup_test_objects(&pool, organization.id, 2).await;
+
+        admin_user
+            .client
+            .delete(&format!("posts/{}", added_objects[1].1.id))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap();
+
+        let response = admin_user
+            .client
+            .get(&format!("posts/{}", added_objects[1].1.id))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+        // Delete should not happen without permissions
+        let response = no_roles_user
+            .client
+            .delete(&format!("posts/{}", added_objects[0].1.id))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+
+        // Make sure other objects still exist
+        let response = admin_user
+            .client
+            .get(&format!("posts/{}", added_objects[0].1.id))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+    }
+
+    #[sqlx::test]
+
+    async fn child_comment(pool: sqlx::PgPool) {
+        // Create a test object
+        let (
+            _app,
+            BootstrappedData {
+                organization,
+                admin_user,
+                no_roles_user,
+                ..
+            },
+        ) = start_app(pool.clone()).await;
+
+        let (parent_payload, parent_result) = setup_test_objects(&pool, organization.id, 2)
+            .await
+            .into_iter()
+            .next()
+            .unwrap();
+
+        // Create a test object
+        let payload_one = crate::models::comment::testing::make_create_payload(1);
+        let create_result_one = admin_user
+            .client
+            .post(&format!("posts/{}/comments", parent_result.id))
+            .json(&payload_one)
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json::<serde_json::Value>()
+            .await
+            .unwrap();
+        let id_one = CommentId::from_str(create_result_one["id"].as_str().unwrap()).unwrap();
+
+        // Try to create a test object with a bad parent id
+        let bad_parent_id = PostId::new();
+        let payload_two = crate::models::comment::testing::make_create_payload(1);
+        let response = admin_user
+            .client
+            .post(&format!("posts/{}/comments", bad_parent_id))
+            .json(&payload_two)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+        // Create another test object
+        let payload_two = crate::models::comment::testing::make_create_payload(2);
+        let create_result_two = admin_user
+            .client
+            .post(&format!("posts/{}/comments", parent_result.id))
+            .json(&payload_two)
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json::<serde_json::Value>()
+            .await
+            .unwrap();
+        let id_two = CommentId::from_str(create_result_two["id"].as_str().unwrap()).unwrap();
+
+        // Check create without permissions
+        let bad_create_payload = crate::models::comment::testing::make_create_payload(9);
+        let res = no_roles_user
+            .client
+            .post(&format!("posts/{}/comments", parent_result.id))
+            .json(&bad_create_payload)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), reqwest::StatusCode::FORBIDDEN);
+
+        // Check get without permissions
+        let res = no_roles_user
+            .client
+            .get(&format!("posts/{}/comments/{}", parent_result.id, id_one))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), reqwest::StatusCode::FORBIDDEN);
+
+        // Check update without permissions
+        let bad_update_payload = crate::models::comment::testing::make_update_payload(8);
+        let res = no_roles_user
+            .client
+            .put(&format!("posts/{}/comments/{}", parent_result.id, id_one))
+            .json(&bad_update_payload)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), reqwest::StatusCode::FORBIDDEN);
+
+        // Check delete without permissions
+        let res = no_roles_user
+            .client
+            .delete(&format!("posts/{}/comments/{}", parent_result.id, id_one))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), reqwest::StatusCode::FORBIDDEN);
+
+        // Check get of test object
+        let get_result_one = admin_user
+            .client
+            .get(&format!(
+                "posts/{}/comments/{}",
+                parent_result.id,
+                create_result_one["id"].as_str().unwrap()
+            ))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json::<serde_json::Value>()
+            .await
+            .unwrap();
+        assert_eq!(create_result_one, get_result_one);
+
+        // Check get of test object with a different parent ID
         let get_result_bad_parent = admin_user
             .client
             .get(&format!(
@@ -1665,5 +2796,992 @@ mod test {
         assert_eq!(res.status(), reqwest::StatusCode::NOT_FOUND);
     }
 
-    // TODO file upload test for post_image
+    #[sqlx::test]
+    async fn child_changes_update_post_etag(pool: sqlx::PgPool) {
+        let (
+            _app,
+            BootstrappedData {
+                organization,
+                admin_user,
+                ..
+            },
+        ) = start_app(pool.clone()).await;
+
+        let added_objects = setup_test_objects(&pool, organization.id, 1).await;
+        let id = added_objects[0].1.id;
+
+        let get_etag = |path: String| {
+            let client = &admin_user.client;
+            async move {
+                let response = client
+                    .get(&path)
+                    .send()
+                    .await
+                    .unwrap()
+                    .log_error()
+                    .await
+                    .unwrap();
+                response.headers()["etag"].to_str().unwrap().to_string()
+            }
+        };
+
+        let post_etag = get_etag(format!("posts/{id}")).await;
+
+        admin_user
+            .client
+            .post(&format!("posts/{id}/comments"))
+            .json(&crate::models::comment::testing::make_create_payload(1))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap();
+
+        // Adding a comment changes the post's ETag, so an update based on the old one fails.
+        let response = admin_user
+            .client
+            .put(&format!("posts/{id}"))
+            .header("If-Match", &post_etag)
+            .json(&make_update_payload(1))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::PRECONDITION_FAILED);
+
+        admin_user
+            .client
+            .post(&format!("posts/{id}/poll"))
+            .json(&crate::models::poll::testing::make_create_payload(1))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap();
+
+        let poll_etag = get_etag(format!("posts/{id}/poll")).await;
+
+        admin_user
+            .client
+            .put(&format!("posts/{id}/poll"))
+            .header("If-Match", &poll_etag)
+            .json(&crate::models::poll::testing::make_update_payload(2))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap();
+
+        // The poll changed since `poll_etag` was fetched.
+        let response = admin_user
+            .client
+            .patch(&format!("posts/{id}/poll"))
+            .header("If-Match", &poll_etag)
+            .json(&serde_json::json!({ "question": "Changed" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::PRECONDITION_FAILED);
+
+        let response = admin_user
+            .client
+            .put(&format!("posts/{id}/poll"))
+            .header("If-Match", &poll_etag)
+            .json(&crate::models::poll::testing::make_update_payload(3))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::PRECONDITION_FAILED);
+    }
+
+    #[sqlx::test]
+    async fn child_post_image_renditions(pool: sqlx::PgPool) {
+        let (
+            _app,
+            BootstrappedData {
+                organization,
+                admin_user,
+                ..
+            },
+        ) = start_app(pool.clone()).await;
+
+        let added_objects = setup_test_objects(&pool, organization.id, 1).await;
+        let post_id = added_objects[0].1.id;
+
+        let mut image = Vec::new();
+        ::image::DynamicImage::ImageRgb8(::image::RgbImage::new(800, 400))
+            .write_to(
+                &mut std::io::Cursor::new(&mut image),
+                ::image::ImageFormat::Png,
+            )
+            .unwrap();
+
+        let uploaded: PostImage = admin_user
+            .client
+            .post(&format!("posts/{post_id}/post_images?filename=photo.png"))
+            .body(image)
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(uploaded.file_original_name.as_deref(), Some("photo.png"));
+        assert!(uploaded.renditions.0.is_empty());
+
+        // Wait for the background job to generate the renditions.
+        let mut result = uploaded;
+        for _ in 0..50 {
+            result = admin_user
+                .client
+                .get(&format!("posts/{post_id}/post_images/{}", result.id))
+                .send()
+                .await
+                .unwrap()
+                .log_error()
+                .await
+                .unwrap()
+                .json()
+                .await
+                .unwrap();
+
+            if !result.renditions.0.is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+
+        assert_eq!(result.width, Some(800));
+        assert_eq!(result.height, Some(400));
+        let renditions = &result.renditions.0;
+        assert_eq!(renditions.len(), 2);
+        assert_eq!(renditions[0].name, "thumb");
+        assert_eq!(renditions[0].content_type, "image/webp");
+        assert_eq!((renditions[0].width, renditions[0].height), (256, 128));
+        assert_eq!(renditions[1].name, "large");
+        assert_eq!((renditions[1].width, renditions[1].height), (800, 400));
+        for rendition in renditions {
+            let url = rendition.url.as_deref().expect("rendition has a URL");
+            assert!(url.starts_with(&format!(
+                "https://cdn.example.com/{}/post_image/{}-photo.png/",
+                organization.id, result.id
+            )));
+        }
+    }
+
+    #[sqlx::test]
+    async fn child_post_image_upload_policy(pool: sqlx::PgPool) {
+        let (
+            _app,
+            BootstrappedData {
+                organization,
+                admin_user,
+                ..
+            },
+        ) = start_app(pool.clone()).await;
+
+        let added_objects = setup_test_objects(&pool, organization.id, 1).await;
+        let post_id = added_objects[0].1.id;
+
+        // The type comes from the contents, not the filename.
+        let response = admin_user
+            .client
+            .post(&format!("posts/{post_id}/post_images?filename=photo.png"))
+            .body("<html><body>not an image</body></html>")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(
+            response.status(),
+            reqwest::StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+
+        let response = admin_user
+            .client
+            .post(&format!(
+                "posts/{post_id}/post_images?filename=document.pdf"
+            ))
+            .body("%PDF-1.7 a document that is not an image")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(
+            response.status(),
+            reqwest::StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+
+        let images: Vec<PostImage> = admin_user
+            .client
+            .get(&format!("posts/{post_id}/post_images"))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert!(images.is_empty(), "rejected files should not be saved");
+    }
+
+    #[sqlx::test]
+    async fn child_post_image_resumable_upload(pool: sqlx::PgPool) {
+        let (
+            app,
+            BootstrappedData {
+                organization,
+                admin_user,
+                ..
+            },
+        ) = start_app(pool.clone()).await;
+
+        let added_objects = setup_test_objects(&pool, organization.id, 1).await;
+        let post_id = added_objects[0].1.id;
+
+        let mut image = Vec::new();
+        ::image::DynamicImage::ImageRgb8(::image::RgbImage::new(64, 32))
+            .write_to(
+                &mut std::io::Cursor::new(&mut image),
+                ::image::ImageFormat::Png,
+            )
+            .unwrap();
+
+        // The test client doesn't support HEAD or PATCH, so use a plain client for the tus requests.
+        let client = reqwest::Client::new();
+        let request = |method: reqwest::Method, url: &str| {
+            client
+                .request(method, format!("{}{url}", app.base_url))
+                .header("Authorization", format!("Bearer {}", admin_user.api_key))
+                .header("Tus-Resumable", "1.0.0")
+        };
+        let patch = |url: &str, offset: usize, body: Vec<u8>| {
+            request(reqwest::Method::PATCH, url)
+                .header("Upload-Offset", offset.to_string())
+                .header("Content-Type", "application/offset+octet-stream")
+                .body(body)
+                .send()
+        };
+
+        let response = request(
+            reqwest::Method::POST,
+            &format!("/api/posts/{post_id}/post_image_uploads"),
+        )
+        .header("Upload-Length", image.len().to_string())
+        .header("Upload-Metadata", "filename cGhvdG8ucG5n")
+        .send()
+        .await
+        .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+        let location = response.headers()["location"].to_str().unwrap().to_string();
+        assert!(location.starts_with(&format!("/api/posts/{post_id}/post_image_uploads/")));
+        let id = location.rsplit('/').next().unwrap().to_string();
+
+        let half = image.len() / 2;
+        let response = patch(&location, 0, image[..half].to_vec()).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+        assert_eq!(
+            response.headers()["upload-offset"],
+            half.to_string().as_str()
+        );
+
+        let response = request(reqwest::Method::HEAD, &location)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(
+            response.headers()["upload-offset"],
+            half.to_string().as_str()
+        );
+        assert_eq!(
+            response.headers()["upload-length"],
+            image.len().to_string().as_str()
+        );
+
+        // Resending data from a stale offset is rejected.
+        let response = patch(&location, 0, image[..half].to_vec()).await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::CONFLICT);
+
+        // The image doesn't exist until the upload is complete.
+        let response = admin_user
+            .client
+            .get(&format!("posts/{post_id}/post_images/{id}"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+        let response = patch(&location, half, image[half..].to_vec())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+        assert_eq!(
+            response.headers()["upload-offset"],
+            image.len().to_string().as_str()
+        );
+
+        let result: PostImage = admin_user
+            .client
+            .get(&format!("posts/{post_id}/post_images/{id}"))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(result.file_original_name.as_deref(), Some("photo.png"));
+        assert_eq!(result.file_size, Some(image.len() as i64));
+
+        // Start another upload and cancel it.
+        let response = request(
+            reqwest::Method::POST,
+            &format!("/api/posts/{post_id}/post_image_uploads"),
+        )
+        .header("Upload-Length", image.len().to_string())
+        .send()
+        .await
+        .unwrap();
+        let location = response.headers()["location"].to_str().unwrap().to_string();
+
+        let response = request(reqwest::Method::DELETE, &location)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+
+        let response = request(reqwest::Method::HEAD, &location)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+        // Clients using another version of the protocol are turned away.
+        let response = client
+            .post(format!(
+                "{}/api/posts/{post_id}/post_image_uploads",
+                app.base_url
+            ))
+            .header("Authorization", format!("Bearer {}", admin_user.api_key))
+            .header("Upload-Length", "10")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::PRECONDITION_FAILED);
+        assert_eq!(response.headers()["tus-version"], "1.0.0");
+    }
+
+    #[sqlx::test]
+    async fn child_post_image_deduplication(pool: sqlx::PgPool) {
+        let mut storage = crate::storage::AppStorageConfig::new_in_memory();
+        storage.image_uploads.deduplicate = true;
+        let (
+            _app,
+            BootstrappedData {
+                organization,
+                admin_user,
+                ..
+            },
+        ) = start_app_with_options(
+            pool.clone(),
+            TestAppOptions {
+                storage: Some(storage),
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let added_objects = setup_test_objects(&pool, organization.id, 3).await;
+        let post_ids = added_objects
+            .iter()
+            .map(|(_, post)| post.id)
+            .collect::<Vec<_>>();
+
+        let mut image = Vec::new();
+        ::image::DynamicImage::ImageRgb8(::image::RgbImage::new(32, 32))
+            .write_to(
+                &mut std::io::Cursor::new(&mut image),
+                ::image::ImageFormat::Png,
+            )
+            .unwrap();
+        let hash = blake3::hash(&image).to_hex().to_string();
+
+        let status: PostImageHashStatus = admin_user
+            .client
+            .get(&format!("posts/{}/post_images/hashes/{hash}", post_ids[0]))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert!(!status.exists);
+
+        let mut uploaded = Vec::new();
+        for post_id in &post_ids[0..2] {
+            let image: PostImage = admin_user
+                .client
+                .post(&format!("posts/{post_id}/post_images?filename=photo.png"))
+                .body(image.clone())
+                .send()
+                .await
+                .unwrap()
+                .log_error()
+                .await
+                .unwrap()
+                .json()
+                .await
+                .unwrap();
+            uploaded.push(image);
+        }
+
+        assert_eq!(
+            uploaded[0].file_storage_key,
+            format!("{}/post_image/blobs/{hash}", organization.id)
+        );
+        assert_eq!(uploaded[0].file_storage_key, uploaded[1].file_storage_key);
+
+        let status: PostImageHashStatus = admin_user
+            .client
+            .get(&format!("posts/{}/post_images/hashes/{hash}", post_ids[2]))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert!(status.exists);
+        assert_eq!(status.file_size, Some(image.len() as i64));
+
+        // The third post reuses the file without uploading it.
+        let from_hash: PostImage = admin_user
+            .client
+            .post(&format!(
+                "posts/{}/post_images/hashes/{hash}?filename=copy.png",
+                post_ids[2]
+            ))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(from_hash.file_storage_key, uploaded[0].file_storage_key);
+        assert_eq!(from_hash.file_original_name.as_deref(), Some("copy.png"));
+        assert_eq!(from_hash.file_size, Some(image.len() as i64));
+
+        let ref_count = || {
+            sqlx::query_scalar!(
+                "SELECT ref_count FROM public.storage_blobs WHERE storage_key = $1",
+                uploaded[0].file_storage_key
+            )
+            .fetch_optional(&pool)
+        };
+        assert_eq!(ref_count().await.unwrap(), Some(3));
+
+        admin_user
+            .client
+            .delete(&format!(
+                "posts/{}/post_images/{}",
+                post_ids[0], uploaded[0].id
+            ))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap();
+        assert_eq!(ref_count().await.unwrap(), Some(2));
+
+        // Deleting a post releases its images.
+        admin_user
+            .client
+            .delete(&format!("posts/{}", post_ids[1]))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap();
+        assert_eq!(ref_count().await.unwrap(), Some(1));
+
+        admin_user
+            .client
+            .delete(&format!(
+                "posts/{}/post_images/{}",
+                post_ids[2], from_hash.id
+            ))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap();
+        assert_eq!(ref_count().await.unwrap(), None);
+
+        let response = admin_user
+            .client
+            .post(&format!("posts/{}/post_images/hashes/{hash}", post_ids[2]))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test]
+    async fn child_post_image_signed_url(pool: sqlx::PgPool) {
+        let (
+            app,
+            BootstrappedData {
+                organization,
+                admin_user,
+                ..
+            },
+        ) = start_app(pool.clone()).await;
+
+        let added_objects = setup_test_objects(&pool, organization.id, 1).await;
+        let post_id = added_objects[0].1.id;
+
+        let mut image = Vec::new();
+        ::image::DynamicImage::ImageRgb8(::image::RgbImage::new(32, 32))
+            .write_to(
+                &mut std::io::Cursor::new(&mut image),
+                ::image::ImageFormat::Png,
+            )
+            .unwrap();
+
+        let uploaded: PostImage = admin_user
+            .client
+            .post(&format!(
+                "posts/{post_id}/post_images?filename=my photo.png"
+            ))
+            .body(image.clone())
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let url = uploaded.url.expect("upload result has a URL");
+        assert!(url.starts_with("/api/storage/image_uploads/"));
+
+        let post: PostPopulatedGetResult = admin_user
+            .client
+            .get(&format!("posts/{post_id}"))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert!(post.images[0].url.is_some());
+
+        // The signed URL works without any other authentication.
+        let client = reqwest::Client::new();
+        let response = client
+            .get(format!("{}{url}", app.base_url))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(
+            response.headers()[reqwest::header::CONTENT_TYPE],
+            "image/png"
+        );
+        assert_eq!(response.bytes().await.unwrap().as_ref(), image.as_slice());
+
+        let tampered = url.replace("filename=my+photo.png", "filename=my+photo.svg");
+        assert_ne!(tampered, url);
+        let response = client
+            .get(format!("{}{tampered}", app.base_url))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+
+        let other_bucket = url.replace("/image_uploads/", "/pdfs/");
+        let response = client
+            .get(format!("{}{other_bucket}", app.base_url))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+    }
+
+    #[sqlx::test]
+    async fn child_post_image_multipart_upload(pool: sqlx::PgPool) {
+        use crate::tests::{multipart_body, MultipartPart, MULTIPART_BOUNDARY};
+
+        let (
+            _app,
+            BootstrappedData {
+                organization,
+                admin_user,
+                ..
+            },
+        ) = start_app(pool.clone()).await;
+
+        let added_objects = setup_test_objects(&pool, organization.id, 1).await;
+        let post_id = added_objects[0].1.id;
+
+        let mut image = Vec::new();
+        ::image::DynamicImage::ImageRgb8(::image::RgbImage::new(32, 32))
+            .write_to(
+                &mut std::io::Cursor::new(&mut image),
+                ::image::ImageFormat::Png,
+            )
+            .unwrap();
+
+        let body = multipart_body(&[
+            MultipartPart {
+                name: "file",
+                filename: Some("one.png"),
+                data: &image,
+            },
+            MultipartPart {
+                name: "alt_text",
+                filename: None,
+                data: b"A black square",
+            },
+            MultipartPart {
+                name: "caption",
+                filename: None,
+                data: b"The first one",
+            },
+            MultipartPart {
+                name: "file",
+                filename: Some("two.png"),
+                data: &image,
+            },
+            MultipartPart {
+                name: "caption",
+                filename: None,
+                data: b"The second one",
+            },
+        ]);
+
+        let uploaded: Vec<PostImage> = admin_user
+            .client
+            .post(&format!("posts/{post_id}/post_images"))
+            .header(
+                "Content-Type",
+                format!("multipart/form-data; boundary={MULTIPART_BOUNDARY}"),
+            )
+            .body(body)
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        assert_eq!(uploaded.len(), 2);
+        assert_eq!(uploaded[0].file_original_name.as_deref(), Some("one.png"));
+        assert_eq!(uploaded[1].file_original_name.as_deref(), Some("two.png"));
+        assert_eq!(uploaded[0].file_size, Some(image.len() as i64));
+        // A single alt text applies to every file, and multiple captions are matched in order.
+        assert_eq!(uploaded[0].alt_text.as_deref(), Some("A black square"));
+        assert_eq!(uploaded[1].alt_text.as_deref(), Some("A black square"));
+        assert_eq!(uploaded[0].caption.as_deref(), Some("The first one"));
+        assert_eq!(uploaded[1].caption.as_deref(), Some("The second one"));
+
+        let fetched: PostImage = admin_user
+            .client
+            .get(&format!("posts/{post_id}/post_images/{}", uploaded[1].id))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(fetched.caption.as_deref(), Some("The second one"));
+
+        let empty = multipart_body(&[MultipartPart {
+            name: "caption",
+            filename: None,
+            data: b"No files",
+        }]);
+        let response = admin_user
+            .client
+            .post(&format!("posts/{post_id}/post_images"))
+            .header(
+                "Content-Type",
+                format!("multipart/form-data; boundary={MULTIPART_BOUNDARY}"),
+            )
+            .body(empty)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+        let long_caption = vec![b'a'; 5000];
+        let body = multipart_body(&[
+            MultipartPart {
+                name: "file",
+                filename: Some("three.png"),
+                data: &image,
+            },
+            MultipartPart {
+                name: "caption",
+                filename: None,
+                data: &long_caption,
+            },
+        ]);
+        let response = admin_user
+            .client
+            .post(&format!("posts/{post_id}/post_images"))
+            .header(
+                "Content-Type",
+                format!("multipart/form-data; boundary={MULTIPART_BOUNDARY}"),
+            )
+            .body(body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx::test]
+    async fn child_post_image_upsert_text(pool: sqlx::PgPool) {
+        let (_app, BootstrappedData { organization, .. }) = start_app(pool.clone()).await;
+
+        let added_objects = setup_test_objects(&pool, organization.id, 1).await;
+        let post_id = added_objects[0].1.id;
+
+        let mut payload = PostImageUpdatePayload {
+            id: Some(PostImageId::new()),
+            post_id,
+            file_storage_key: "one.png".to_string(),
+            file_storage_bucket: "image_uploads".to_string(),
+            alt_text: Some("A black square".to_string()),
+            caption: Some("The first one".to_string()),
+            ..Default::default()
+        };
+
+        let mut tx = pool.begin().await.unwrap();
+        let created =
+            PostImage::upsert_with_parent_post(&mut *tx, &organization.id, &post_id, &payload)
+                .await
+                .unwrap();
+        assert_eq!(created.alt_text.as_deref(), Some("A black square"));
+        assert_eq!(created.caption.as_deref(), Some("The first one"));
+
+        payload.caption = None;
+        let updated =
+            PostImage::upsert_with_parent_post(&mut *tx, &organization.id, &post_id, &payload)
+                .await
+                .unwrap();
+        assert_eq!(updated.id, created.id);
+        assert_eq!(updated.alt_text.as_deref(), Some("A black square"));
+        assert_eq!(updated.caption, None);
+        tx.commit().await.unwrap();
+    }
+
+    #[sqlx::test]
+    async fn child_post_image_order(pool: sqlx::PgPool) {
+        use crate::tests::{multipart_body, MultipartPart, MULTIPART_BOUNDARY};
+
+        let (
+            _app,
+            BootstrappedData {
+                organization,
+                admin_user,
+                ..
+            },
+        ) = start_app(pool.clone()).await;
+
+        let added_objects = setup_test_objects(&pool, organization.id, 2).await;
+        let post_id = added_objects[0].1.id;
+        let other_post_id = added_objects[1].1.id;
+
+        let mut image = Vec::new();
+        ::image::DynamicImage::ImageRgb8(::image::RgbImage::new(32, 32))
+            .write_to(
+                &mut std::io::Cursor::new(&mut image),
+                ::image::ImageFormat::Png,
+            )
+            .unwrap();
+
+        let file = |filename: &'static str| MultipartPart {
+            name: "file",
+            filename: Some(filename),
+            data: &image,
+        };
+        let alt_text = |data: &'static [u8]| MultipartPart {
+            name: "alt_text",
+            filename: None,
+            data,
+        };
+        // The third image has no alt text.
+        let body = multipart_body(&[
+            file("one.png"),
+            alt_text(b"One"),
+            file("two.png"),
+            alt_text(b"Two"),
+            file("three.png"),
+        ]);
+
+        let uploaded: Vec<PostImage> = admin_user
+            .client
+            .post(&format!("posts/{post_id}/post_images"))
+            .header(
+                "Content-Type",
+                format!("multipart/form-data; boundary={MULTIPART_BOUNDARY}"),
+            )
+            .body(body)
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        let positions = uploaded.iter().map(|i| i.position).collect::<Vec<_>>();
+        assert_eq!(positions, vec![0, 1, 2]);
+
+        let new_order = vec![uploaded[2].id, uploaded[0].id, uploaded[1].id];
+        let reordered: Vec<PostImage> = admin_user
+            .client
+            .put(&format!("posts/{post_id}/post_images/order"))
+            .json(&serde_json::json!({ "ids": new_order }))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(
+            reordered.iter().map(|i| i.id).collect::<Vec<_>>(),
+            new_order
+        );
+        assert_eq!(
+            reordered.iter().map(|i| i.position).collect::<Vec<_>>(),
+            vec![0, 1, 2]
+        );
+
+        let listed: Vec<PostImage> = admin_user
+            .client
+            .get(&format!("posts/{post_id}/post_images"))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(listed.iter().map(|i| i.id).collect::<Vec<_>>(), new_order);
+
+        let post: serde_json::Value = admin_user
+            .client
+            .get(&format!("posts/{post_id}"))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let populated_order = post["images"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|i| i["file_original_name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(populated_order, vec!["three.png", "one.png", "two.png"]);
+
+        // The new order has to list every image exactly once.
+        for ids in [
+            vec![uploaded[0].id, uploaded[1].id],
+            vec![uploaded[0].id, uploaded[1].id, uploaded[1].id],
+            vec![
+                uploaded[0].id,
+                uploaded[1].id,
+                uploaded[2].id,
+                PostImageId::new(),
+            ],
+        ] {
+            let response = admin_user
+                .client
+                .put(&format!("posts/{post_id}/post_images/order"))
+                .json(&serde_json::json!({ "ids": ids }))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+            let body: serde_json::Value = response.json().await.unwrap();
+            assert_eq!(body["error"]["kind"], "invalid_image_order");
+        }
+
+        // Only the first post has an image without alt text.
+        let missing_alt_text: Vec<serde_json::Value> = admin_user
+            .client
+            .get("posts")
+            .query(&[("has_images_missing_alt_text", "true")])
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(missing_alt_text.len(), 1);
+        assert_eq!(missing_alt_text[0]["id"], post_id.to_string());
+
+        let not_missing_alt_text: Vec<serde_json::Value> = admin_user
+            .client
+            .get("posts")
+            .query(&[("has_images_missing_alt_text", "false")])
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(not_missing_alt_text.len(), 1);
+        assert_eq!(not_missing_alt_text[0]["id"], other_post_id.to_string());
+    }
 }
@@ -0,0 +1,9 @@
+UPDATE
+  public.posts
+SET
+  subject = COALESCE($1, subject),
+  body = COALESCE($2, body),
+  updated_at = NOW()
+WHERE
+  id = $3
+  AND organization_id = $4
@@ -7,20 +7,25 @@ use filigree::{
     errors::OrderByError,
     sql::{BindingOperator, FilterBuilder, ValuesBuilder},
 };
+use schemars::JsonSchema;
 use serde::Deserialize;
 use sqlx::{
-    postgres::PgRow, query_file, query_file_as, query_file_scalar, PgConnection, PgExecutor,
+    postgres::PgRow, query_file, query_file_as, query_file_scalar, PgConnection, PgExecutor, PgPool,
 };
 use tracing::{event, instrument, Level};
 
 use super::{types::*, PostId};
 use crate::{
-    auth::AuthInfo,
+    auth::{AuthInfo, Authed},
     models::{
+        bulk::{BulkDeleted, BulkModel},
         comment::{
             Comment, CommentCreatePayload, CommentCreateResult, CommentId, CommentUpdatePayload,
         },
+        export::{self, ExportFormat},
+        filters::ExtraFilters,
         organization::OrganizationId,
+        pagination::{self, ListTotal},
         poll::{Poll, PollCreatePayload, PollCreateResult, PollId, PollUpdatePayload},
         post_image::{
             PostImage, PostImageCreatePayload, PostImageCreateResult, PostImageId,
@@ -31,6 +36,7 @@ use crate::{
             ReactionUpdatePayload,
         },
     },
+    server::ServerState,
     Error,
 };
 
@@ -81,7 +87,7 @@ fn parse_order_by(field: &str) -> Result<(bool, OrderByField), OrderByError> {
     Ok((descending, value))
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Deserialize, Debug, Default, JsonSchema)]
 pub struct ListQueryFilters {
     pub page: Option<u32>,
     pub per_page: Option<u32>,
@@ -89,20 +95,106 @@ pub struct ListQueryFilters {
     pub order_by: Option<String>,
     #[serde(default)]
     pub id: Vec<PostId>,
+    #[serde(default)]
+    pub subject: Vec<String>,
+    #[serde(default)]
+    pub body: Vec<String>,
     pub updated_at_lte: Option<chrono::DateTime<chrono::Utc>>,
     pub updated_at_gte: Option<chrono::DateTime<chrono::Utc>>,
     pub created_at_lte: Option<chrono::DateTime<chrono::Utc>>,
     pub created_at_gte: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only return objects where `subject` contains this string
+    pub subject_contains: Option<String>,
+    /// Only return objects where `subject` contains this string, ignoring case
+    pub subject_icontains: Option<String>,
+    /// Only return objects where `body` contains this string
+    pub body_contains: Option<String>,
+    /// Only return objects where `body` contains this string, ignoring case
+    pub body_icontains: Option<String>,
+    /// Only return posts that have (or don't have) a poll
+    pub has_poll: Option<bool>,
+    /// Only return posts that have (or don't have) any comments
+    pub has_comments: Option<bool>,
+    /// Only return posts that have (or don't have) any reactions
+    pub has_reactions: Option<bool>,
+    /// Only return posts that have (or don't have) any images
+    pub has_images: Option<bool>,
+    /// Only return posts that have (or don't have) any images without alt text
+    pub has_images_missing_alt_text: Option<bool>,
+    /// Only return posts with a reaction of one of these types
+    #[serde(default)]
+    pub reaction_typ: Vec<String>,
+    /// Only return objects that the current user has (or has not) favorited
+    pub favorited: Option<bool>,
+    /// Wrap the results in an envelope that includes the total number of matching objects
+    pub include_total: Option<bool>,
 }
 
 impl ListQueryFilters {
     fn build_where_clause(&self) -> String {
-        let mut bindings = FilterBuilder::new(4);
+        // The current user's ID is bound at $4 when filtering on favorites.
+        let first_binding = if self.favorited.is_some() { 5 } else { 4 };
+        let mut filters = ExtraFilters::new(first_binding);
+
+        if self.subject_contains.is_some() {
+            filters.contains("subject", false);
+        }
+
+        if self.subject_icontains.is_some() {
+            filters.contains("subject", true);
+        }
+
+        if self.body_contains.is_some() {
+            filters.contains("body", false);
+        }
+
+        if self.body_icontains.is_some() {
+            filters.contains("body", true);
+        }
+
+        if let Some(has_poll) = self.has_poll {
+            filters.has_child("polls", "post_id", has_poll);
+        }
+
+        if let Some(has_comments) = self.has_comments {
+            filters.has_child("comments", "post_id", has_comments);
+        }
+
+        if let Some(has_reactions) = self.has_reactions {
+            filters.has_child("reactions", "post_id", has_reactions);
+        }
+
+        if let Some(has_images) = self.has_images {
+            filters.has_child("post_images", "post_id", has_images);
+        }
+
+        if let Some(missing_alt_text) = self.has_images_missing_alt_text {
+            filters.has_child_where(
+                "post_images",
+                "post_id",
+                "(child.alt_text IS NULL OR child.alt_text = '')",
+                missing_alt_text,
+            );
+        }
+
+        if !self.reaction_typ.is_empty() {
+            filters.child_in("reactions", "post_id", "typ");
+        }
+
+        let mut bindings = FilterBuilder::new(filters.next_binding());
 
         if !self.id.is_empty() {
             bindings.add_vec("id", &self.id);
         }
 
+        if !self.subject.is_empty() {
+            bindings.add_vec("subject", &self.subject);
+        }
+
+        if !self.body.is_empty() {
+            bindings.add_vec("body", &self.body);
+        }
+
         if self.updated_at_lte.is_some() {
             bindings.add_option("updated_at", &self.updated_at_lte, BindingOperator::Lte);
         }
@@ -119,17 +211,58 @@ impl ListQueryFilters {
             bindings.add_option("created_at", &self.created_at_gte, BindingOperator::Gte);
         }
 
-        let query = bindings.to_string();
+        let mut query = bindings.to_string();
+        filters.append_to(&mut query);
+        if let Some(favorited) = self.favorited {
+            query.push_str(" AND ");
+            query.push_str(&crate::users::favorites::filter_clause(favorited, 4));
+        }
+
         event!(Level::DEBUG, %query);
         query
     }
 
     fn bind_to_query<'a, T>(&'a self, mut query: QueryAs<'a, T>) -> QueryAs<'a, T> {
+        if self.subject_contains.is_some() {
+            event!(Level::DEBUG, subject_contains = ?self.subject_contains);
+            query = query.bind(&self.subject_contains);
+        }
+
+        if self.subject_icontains.is_some() {
+            event!(Level::DEBUG, subject_icontains = ?self.subject_icontains);
+            query = query.bind(&self.subject_icontains);
+        }
+
+        if self.body_contains.is_some() {
+            event!(Level::DEBUG, body_contains = ?self.body_contains);
+            query = query.bind(&self.body_contains);
+        }
+
+        if self.body_icontains.is_some() {
+            event!(Level::DEBUG, body_icontains = ?self.body_icontains);
+            query = query.bind(&self.body_icontains);
+        }
+
+        if !self.reaction_typ.is_empty() {
+            event!(Level::DEBUG, reaction_typ = ?self.reaction_typ);
+            query = query.bind(&self.reaction_typ);
+        }
+
         if !self.id.is_empty() {
             event!(Level::DEBUG, id = ?self.id);
             query = query.bind(&self.id);
         }
 
+        if !self.subject.is_empty() {
+            event!(Level::DEBUG, subject = ?self.subject);
+            query = query.bind(&self.subject);
+        }
+
+        if !self.body.is_empty() {
+            event!(Level::DEBUG, body = ?self.body);
+            query = query.bind(&self.body);
+        }
+
         if self.updated_at_lte.is_some() {
             event!(Level::DEBUG, updated_at_lte = ?self.updated_at_lte);
             query = query.bind(&self.updated_at_lte);
@@ -161,6 +294,12 @@ type QueryAs<'q, T> = sqlx::query::QueryAs<
     <sqlx::Postgres as sqlx::database::HasArguments<'q>>::Arguments,
 >;
 
+#[derive(Default)]
+struct PostCreatePayloadChildrenResult {
+    poll: Option<Poll>,
+    comments: Vec<Comment>,
+}
+
 impl Post {
     /// Get a Post from the database
     #[instrument(skip(db))]
@@ -229,6 +368,96 @@ impl Post {
         Self::list_internal(q, db, auth, filters).await
     }
 
+    /// Count the objects matching the filters, ignoring pagination. Very large tables are
+    /// estimated using the query planner instead of being counted exactly.
+    #[instrument(skip(db))]
+    pub async fn count(
+        db: &mut PgConnection,
+        auth: &AuthInfo,
+        filters: &ListQueryFilters,
+    ) -> Result<ListTotal, error_stack::Report<Error>> {
+        auth.require_permission(super::READ_PERMISSION)?;
+
+        let estimated = pagination::use_estimate(&mut *db, "posts").await?;
+        let q = include_str!("count.sql")
+            .replace("__insertion_point_filters", &filters.build_where_clause());
+
+        let count = if estimated {
+            let q = format!("EXPLAIN (FORMAT JSON) {q}");
+            let query = Self::bind_count_query(
+                sqlx::query_as::<_, (pagination::ExplainPlan,)>(&q),
+                auth,
+                filters,
+            );
+            let (plan,) = query.fetch_one(&mut *db).await.change_context(Error::Db)?;
+            pagination::plan_rows(&plan)
+        } else {
+            let q = format!("SELECT COUNT(*) FROM ({q}) matching");
+            let query = Self::bind_count_query(sqlx::query_as::<_, (i64,)>(&q), auth, filters);
+            let (count,) = query.fetch_one(&mut *db).await.change_context(Error::Db)?;
+            count
+        };
+
+        Ok(ListTotal { count, estimated })
+    }
+
+    fn bind_count_query<'a, T>(
+        mut query: QueryAs<'a, T>,
+        auth: &'a AuthInfo,
+        filters: &'a ListQueryFilters,
+    ) -> QueryAs<'a, T> {
+        // $2 and $3 are the LIMIT and OFFSET in the list queries. The count query doesn't use them,
+        // but they are still bound so that the filters use the same binding numbers.
+        query = query.bind(&auth.organization_id).bind(0i32).bind(0i32);
+
+        if filters.favorited.is_some() {
+            query = query.bind(&auth.user_id);
+        }
+
+        filters.bind_to_query(query)
+    }
+
+    /// Stream all the Post objects matching the filters as CSV or NDJSON. Unlike the list
+    /// functions, this does not limit the number of rows returned.
+    pub fn export(
+        db: &PgPool,
+        auth: Authed,
+        filters: ListQueryFilters,
+        format: ExportFormat,
+    ) -> Result<axum::response::Response, error_stack::Report<Error>> {
+        auth.require_permission(super::READ_PERMISSION)?;
+
+        let (descending, order_by_field) =
+            parse_order_by(filters.order_by.as_deref().unwrap_or("-updated_at"))
+                .change_context(Error::Filter)?;
+        let order_direction = if descending { "DESC" } else { "ASC" };
+
+        let q = include_str!("list_populated.sql")
+            .replace(
+                "__insertion_point_order_by",
+                &format!("{} {}", order_by_field.as_str(), order_direction),
+            )
+            .replace("__insertion_point_filters", &filters.build_where_clause());
+        let (limit, offset) = export::bounds(filters.page, filters.per_page);
+
+        let response = export::stream(db, "posts", format, move |mut conn, writer| async move {
+            let mut query = sqlx::query_as::<_, PostPopulatedListResult>(q.as_str())
+                .bind(&auth.organization_id)
+                .bind(limit)
+                .bind(offset);
+
+            if filters.favorited.is_some() {
+                query = query.bind(&auth.user_id);
+            }
+
+            query = filters.bind_to_query(query);
+
+            writer.write_rows(query.fetch(&mut *conn)).await
+        });
+
+        Ok(response)
+    }
+
     async fn list_internal<T>(
         query_template: &str,
         db: impl PgExecutor<'_>,
@@ -270,6 +499,10 @@ impl Post {
             .bind(per_page)
             .bind(offset);
 
+        if filters.favorited.is_some() {
+            query = query.bind(&auth.user_id);
+        }
+
         query = filters.bind_to_query(query);
 
         let results = query.fetch_all(db).await.change_context(Error::Db)?;
@@ -311,6 +544,67 @@ impl Post {
         .await
         .change_context(Error::Db)?;
 
+        let child_result =
+            Self::create_payload_children(&mut *db, id, organization_id, payload).await?;
+
+        let result = PostCreateResult {
+            id: result.id,
+            organization_id: result.organization_id,
+            updated_at: result.updated_at,
+            created_at: result.created_at,
+            subject: result.subject,
+            body: result.body,
+            poll: child_result.poll,
+            comments: child_result.comments,
+        };
+
+        Ok(result)
+    }
+
+    async fn create_payload_children(
+        db: &mut PgConnection,
+        parent_id: &PostId,
+        organization_id: &OrganizationId,
+        payload: PostCreatePayload,
+    ) -> Result<PostCreatePayloadChildrenResult, error_stack::Report<Error>> {
+        let poll_result = if let Some(mut child) = payload.poll {
+            child.id = Some(PollId::new());
+            child.post_id = parent_id.clone();
+
+            let result =
+                Poll::upsert_with_parent_post(&mut *db, organization_id, parent_id, &child).await?;
+            Some(result)
+        } else {
+            None
+        };
+
+        let comments_result = if let Some(mut children) = payload.comments {
+            if !children.is_empty() {
+                for child in children.iter_mut() {
+                    child.id = Some(CommentId::new());
+
+                    child.post_id = parent_id.clone();
+                }
+
+                Comment::update_all_with_parent_post(
+                    &mut *db,
+                    organization_id,
+                    parent_id,
+                    &children,
+                )
+                .await?
+            } else {
+                vec![]
+            }
+        } else {
+            vec![]
+        };
+
+        let result = PostCreatePayloadChildrenResult {
+            poll: poll_result,
+            comments: comments_result,
+        };
+
         Ok(result)
     }
 
@@ -338,9 +632,59 @@ impl Post {
             return Ok(false);
         }
 
+        Self::update_payload_children(&mut *db, &auth.organization_id, id, payload).await?;
+
         Ok(true)
     }
 
+    /// Update only the fields present in a JSON Merge Patch.
+    #[instrument(skip(db))]
+    pub async fn patch(
+        db: &mut PgConnection,
+        auth: &AuthInfo,
+        id: &PostId,
+        payload: PostPatchPayload,
+    ) -> Result<bool, error_stack::Report<Error>> {
+        auth.require_permission(super::WRITE_PERMISSION)?;
+
+        let result = query_file!(
+            "src/models/post/patch.sql",
+            payload.subject.as_ref() as _,
+            payload.body.as_ref() as _,
+            id.as_uuid(),
+            auth.organization_id.as_uuid()
+        )
+        .execute(&mut *db)
+        .await
+        .change_context(Error::Db)?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn update_payload_children(
+        db: &mut PgConnection,
+        organization_id: &OrganizationId,
+        parent_id: &PostId,
+        payload: PostUpdatePayload,
+    ) -> Result<(), error_stack::Report<Error>> {
+        if let Some(mut child) = payload.poll {
+            child.post_id = parent_id.clone();
+
+            Poll::upsert_with_parent_post(&mut *db, organization_id, parent_id, &child).await?;
+        }
+
+        if let Some(mut children) = payload.comments {
+            for child in children.iter_mut() {
+                child.post_id = parent_id.clone();
+            }
+
+            Comment::update_all_with_parent_post(&mut *db, organization_id, parent_id, &children)
+                .await?;
+        }
+
+        Ok(())
+    }
+
     #[instrument(skip(db))]
     pub async fn delete(
         db: impl PgExecutor<'_>,
@@ -556,12 +900,12 @@ impl Post {
     ) -> Result<Vec<PostImage>, error_stack::Report<Error>> {
         auth.require_permission(super::READ_PERMISSION)?;
 
-        let filters = crate::models::post_image::queries::ListQueryFilters {
-            per_page: Some(131072),
-            post_id: vec![parent_id.clone()],
-            ..Default::default()
-        };
-        let result = crate::models::post_image::PostImage::list(db, auth, &filters).await?;
+        let result = crate::models::post_image::PostImage::list_for_posts(
+            db,
+            auth,
+            std::slice::from_ref(parent_id),
+        )
+        .await?;
 
         Ok(result)
     }
@@ -598,7 +942,7 @@ impl Post {
     }
 
     pub async fn upsert_child_post_image(
-        db: impl PgExecutor<'_>,
+        db: &mut PgConnection,
         auth: &AuthInfo,
         payload: &PostImageUpdatePayload,
     ) -> Result<PostImage, error_stack::Report<Error>> {
@@ -613,3 +957,125 @@ impl Post {
         .await
     }
 }
+
+#[async_trait::async_trait]
+impl BulkModel for Post {
+    type Id = PostId;
+    type CreatePayload = PostCreatePayload;
+    type UpdatePayload = PostUpdatePayload;
+
+    fn bulk_create_id(payload: &PostCreatePayload) -> PostId {
+        payload.id.unwrap_or_else(|| PostId::new())
+    }
+
+    async fn bulk_insert(
+        db: &mut PgConnection,
+        auth: &AuthInfo,
+        objects: Vec<(PostId, PostCreatePayload)>,
+    ) -> Result<Vec<PostId>, error_stack::Report<Error>> {
+        auth.require_permission(super::CREATE_PERMISSION)?;
+
+        let q = include_str!("bulk_insert.sql");
+        let bindings = ValuesBuilder {
+            first_parameter: 1,
+            num_values: objects.len(),
+            num_columns: 4,
+        };
+        let q = q.replace("__insertion_point_insert_values", &bindings.to_string());
+
+        let mut query = sqlx::query_scalar::<_, PostId>(q.as_str());
+        for (id, payload) in &objects {
+            query = query
+                .bind(id)
+                .bind(&auth.organization_id)
+                .bind(&payload.subject)
+                .bind(&payload.body);
+        }
+
+        let inserted = query.fetch_all(&mut *db).await.change_context(Error::Db)?;
+
+        for (id, payload) in objects {
+            if inserted.contains(&id) {
+                Self::create_payload_children(&mut *db, &id, &auth.organization_id, payload)
+                    .await?;
+            }
+        }
+
+        Ok(inserted)
+    }
+
+    async fn bulk_update(
+        db: &mut PgConnection,
+        auth: &AuthInfo,
+        id: &PostId,
+        payload: PostUpdatePayload,
+    ) -> Result<bool, error_stack::Report<Error>> {
+        Self::update(db, auth, id, payload).await
+    }
+
+    async fn bulk_delete(
+        state: &ServerState,
+        db: &mut PgConnection,
+        auth: &Authed,
+        ids: &[PostId],
+    ) -> Result<BulkDeleted<PostId>, error_stack::Report<Error>> {
+        auth.require_permission(super::CREATE_PERMISSION)?;
+
+        // Get the files before deleting, since the image rows are removed along with the posts.
+        let mut files_by_post = Vec::with_capacity(ids.len());
+        for id in ids {
+            let files = crate::models::post_image::storage::get_storage_keys_by_parent_id(
+                state, auth, &mut *db, *id,
+            )
+            .await?;
+            files_by_post.push((*id, files));
+        }
+
+        let uuids = ids
+            .iter()
+            .map(|id| id.as_uuid().clone())
+            .collect::<Vec<_>>();
+        let deleted = query_file_scalar!(
+            "src/models/post/bulk_delete.sql",
+            &uuids,
+            auth.organization_id.as_uuid()
+        )
+        .fetch_all(&mut *db)
+        .await
+        .change_context(Error::Db)?;
+
+        for id in &deleted {
+            crate::users::favorites::remove_object(&mut *db, &auth.organization_id, id.as_uuid())
+                .await?;
+        }
+
+        let files = files_by_post
+            .into_iter()
+            .filter(|(id, _)| deleted.contains(id))
+            .flat_map(|(_, files)| files)
+            .collect();
+        // Files shared with other posts stay in storage.
+        let files =
+            crate::models::post_image::storage::release_keys(state, auth, &mut *db, files).await?;
+
+        Ok(BulkDeleted {
+            ids: deleted,
+            files,
+        })
+    }
+
+    async fn delete_files(
+        state: &ServerState,
+        files: Vec<String>,
+    ) -> Result<(), error_stack::Report<Error>> {
+        // The posts are already gone, so keep going if a file can't be deleted. Anything left
+        // behind is cleaned up by the gc_storage job.
+        for file in files {
+            if let Err(e) = crate::models::post_image::storage::delete_by_key(state, &file).await {
+                event!(Level::WARN, key = file, error = ?e, "Failed to delete post image");
+            }
+        }
+
+        Ok(())
+    }
+}
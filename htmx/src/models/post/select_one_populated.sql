@@ -40,7 +40,9 @@ SELECT
       t.organization_id, 'updated_at', t.updated_at, 'created_at', t.created_at,
       'file_storage_key', t.file_storage_key, 'file_storage_bucket', t.file_storage_bucket, 'file_original_name',
       t.file_original_name, 'file_size', t.file_size, 'file_hash', t.file_hash,
-      'post_id', t.post_id)), ARRAY[]::jsonb[])
+      'post_id', t.post_id, 'width', t.width, 'height', t.height, 'renditions',
+      t.renditions, 'alt_text', t.alt_text, 'caption', t.caption, 'position', t.position)
+      ORDER BY t.position, t.created_at), ARRAY[]::jsonb[])
   FROM
     public.post_images t
   WHERE
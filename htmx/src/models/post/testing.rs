@@ -23,6 +23,9 @@ pub fn make_create_payload(i: usize) -> PostCreatePayload {
 
         subject: format!("Test object {i}"),
         body: format!("Test object {i}"),
+
+        poll: None,
+        comments: None,
     }
 }
 
@@ -35,5 +38,8 @@ pub fn make_update_payload(i: usize) -> PostUpdatePayload {
 
         subject: format!("Test object {i}"),
         body: format!("Test object {i}"),
+
+        poll: None,
+        comments: None,
     }
 }
@@ -34,8 +34,6 @@ pub struct Post {
 
 pub type PostListResult = Post;
 
-pub type PostCreateResult = Post;
-
 impl Post {
     // The <T as Default> syntax here is weird but lets us generate from the template without needing to
     // detect whether to add the extra :: in cases like DateTime::<Utc>::default
@@ -86,6 +84,8 @@ pub struct PostCreatePayloadAndUpdatePayload {
     pub id: Option<PostId>,
     pub subject: String,
     pub body: String,
+    pub poll: Option<PollCreatePayload>,
+    pub comments: Option<Vec<CommentCreatePayload>>,
 }
 
 pub type PostCreatePayload = PostCreatePayloadAndUpdatePayload;
@@ -107,6 +107,14 @@ impl PostCreatePayloadAndUpdatePayload {
     pub fn default_body() -> String {
         <String as Default>::default().into()
     }
+
+    pub fn default_poll() -> Option<PollCreatePayload> {
+        None
+    }
+
+    pub fn default_comments() -> Option<Vec<CommentCreatePayload>> {
+        None
+    }
 }
 
 impl Default for PostCreatePayloadAndUpdatePayload {
@@ -115,10 +123,88 @@ impl Default for PostCreatePayloadAndUpdatePayload {
             id: Self::default_id(),
             subject: Self::default_subject(),
             body: Self::default_body(),
+            poll: Self::default_poll(),
+            comments: Self::default_comments(),
         }
     }
 }
 
+#[derive(Deserialize, Debug, Clone, schemars::JsonSchema, sqlx::FromRow, Serialize)]
+pub struct PostCreateResult {
+    pub id: PostId,
+    pub organization_id: crate::models::organization::OrganizationId,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub subject: String,
+    pub body: String,
+    pub poll: Option<Poll>,
+    pub comments: Vec<Comment>,
+}
+
+impl PostCreateResult {
+    // The <T as Default> syntax here is weird but lets us generate from the template without needing to
+    // detect whether to add the extra :: in cases like DateTime::<Utc>::default
+
+    pub fn default_id() -> PostId {
+        <PostId as Default>::default().into()
+    }
+
+    pub fn default_organization_id() -> crate::models::organization::OrganizationId {
+        <crate::models::organization::OrganizationId as Default>::default().into()
+    }
+
+    pub fn default_updated_at() -> chrono::DateTime<chrono::Utc> {
+        <chrono::DateTime<chrono::Utc> as Default>::default().into()
+    }
+
+    pub fn default_created_at() -> chrono::DateTime<chrono::Utc> {
+        <chrono::DateTime<chrono::Utc> as Default>::default().into()
+    }
+
+    pub fn default_subject() -> String {
+        <String as Default>::default().into()
+    }
+
+    pub fn default_body() -> String {
+        <String as Default>::default().into()
+    }
+
+    pub fn default_poll() -> Option<Poll> {
+        None
+    }
+
+    pub fn default_comments() -> Vec<Comment> {
+        <Vec<Comment> as Default>::default().into()
+    }
+}
+
+sqlx_json_decode!(PostCreateResult);
+
+impl Default for PostCreateResult {
+    fn default() -> Self {
+        Self {
+            id: Self::default_id(),
+            organization_id: Self::default_organization_id(),
+            updated_at: Self::default_updated_at(),
+            created_at: Self::default_created_at(),
+            subject: Self::default_subject(),
+            body: Self::default_body(),
+            poll: Self::default_poll(),
+            comments: Self::default_comments(),
+        }
+    }
+}
+
+/// A JSON Merge Patch for a Post. Fields that are omitted from the patch are left unchanged.
+#[derive(Deserialize, Debug, Clone, schemars::JsonSchema)]
+pub struct PostPatchPayload {
+    #[serde(default, deserialize_with = "crate::models::merge_patch::required")]
+    #[schemars(with = "String")]
+    pub subject: Option<String>,
+    #[serde(default, deserialize_with = "crate::models::merge_patch::required")]
+    #[schemars(with = "String")]
+    pub body: Option<String>,
+}
 #[derive(Deserialize, Debug, Clone, schemars::JsonSchema, sqlx::FromRow, Serialize)]
 pub struct PostPopulatedGetResult {
     pub id: PostId,
@@ -6,7 +6,10 @@ INSERT INTO public.post_images (
   file_original_name,
   file_size,
   file_hash,
-  post_id)
+  post_id,
+  alt_text,
+  caption,
+  position)
 VALUES (
   $1,
   $2,
@@ -15,7 +18,16 @@ VALUES (
   $5,
   $6,
   $7,
-  $8)
+  $8,
+  $9,
+  $10,
+  (
+    SELECT
+      COALESCE(MAX(position) + 1, 0)
+    FROM
+      public.post_images
+    WHERE
+      post_id = $8))
 RETURNING
   id AS "id: PostImageId",
   organization_id AS "organization_id: crate::models::organization::OrganizationId",
@@ -26,4 +38,11 @@ RETURNING
   file_original_name,
   file_size,
   file_hash,
-  post_id AS "post_id: PostId"
+  post_id AS "post_id: PostId",
+  width,
+  height,
+  renditions AS "renditions: PostImageRenditions",
+  alt_text,
+  caption,
+  position,
+  NULL::text AS "url"
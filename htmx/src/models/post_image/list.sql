@@ -8,10 +8,19 @@ SELECT
   file_original_name,
   file_size,
   file_hash,
-  post_id
+  post_id,
+  width,
+  height,
+  renditions,
+  alt_text,
+  caption,
+  position,
+  NULL::text AS url
 FROM
   public.post_images tb
 WHERE
   organization_id = $1
   AND __insertion_point_filters
+ORDER BY
+  __insertion_point_order_by
 LIMIT $2 OFFSET $3
@@ -0,0 +1,27 @@
+SELECT
+  id AS "id: PostImageId",
+  organization_id AS "organization_id: crate::models::organization::OrganizationId",
+  updated_at,
+  created_at,
+  file_storage_key,
+  file_storage_bucket,
+  file_original_name,
+  file_size,
+  file_hash,
+  post_id AS "post_id: PostId",
+  width,
+  height,
+  renditions AS "renditions: PostImageRenditions",
+  alt_text,
+  caption,
+  position,
+  NULL::text AS "url"
+FROM
+  public.post_images tb
+WHERE
+  tb.organization_id = $1
+  AND tb.post_id = ANY ($2)
+ORDER BY
+  tb.post_id,
+  tb.position,
+  tb.created_at
@@ -0,0 +1,8 @@
+SELECT
+  id
+FROM
+  public.post_images
+WHERE
+  organization_id = $1
+  AND post_id = $2
+FOR UPDATE
@@ -1,7 +1,9 @@
 pub mod queries;
+pub mod renditions;
 pub mod storage;
 #[cfg(test)]
 pub mod testing;
+pub mod tus;
 pub mod types;
 
 pub use types::*;
@@ -7,6 +7,7 @@ use filigree::{
     errors::OrderByError,
     sql::{BindingOperator, FilterBuilder, ValuesBuilder},
 };
+use schemars::JsonSchema;
 use serde::Deserialize;
 use sqlx::{
     postgres::PgRow, query_file, query_file_as, query_file_scalar, PgConnection, PgExecutor,
@@ -16,7 +17,7 @@ use tracing::{event, instrument, Level};
 use super::{types::*, PostImageId};
 use crate::{
     auth::AuthInfo,
-    models::{organization::OrganizationId, post::PostId},
+    models::{filters::ExtraFilters, organization::OrganizationId, post::PostId},
     Error,
 };
 
@@ -25,6 +26,7 @@ enum OrderByField {
     #[default]
     UpdatedAt,
     CreatedAt,
+    Position,
 }
 
 impl OrderByField {
@@ -32,6 +34,7 @@ impl OrderByField {
         match self {
             Self::UpdatedAt => "updated_at",
             Self::CreatedAt => "created_at",
+            Self::Position => "position",
         }
     }
 
@@ -49,6 +52,7 @@ impl std::str::FromStr for OrderByField {
         let value = match s {
             "updated_at" => OrderByField::UpdatedAt,
             "created_at" => OrderByField::CreatedAt,
+            "position" => OrderByField::Position,
             _ => return Err(OrderByError::InvalidField),
         };
 
@@ -67,7 +71,7 @@ fn parse_order_by(field: &str) -> Result<(bool, OrderByField), OrderByError> {
     Ok((descending, value))
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Deserialize, Debug, Default, JsonSchema)]
 pub struct ListQueryFilters {
     pub page: Option<u32>,
     pub per_page: Option<u32>,
@@ -78,6 +82,8 @@ pub struct ListQueryFilters {
     #[serde(default)]
     pub file_original_name: Vec<String>,
     #[serde(default)]
+    pub file_size: Vec<i64>,
+    #[serde(default)]
     pub file_hash: Vec<Vec<u8>>,
     #[serde(default)]
     pub post_id: Vec<PostId>,
@@ -85,11 +91,43 @@ pub struct ListQueryFilters {
     pub updated_at_gte: Option<chrono::DateTime<chrono::Utc>>,
     pub created_at_lte: Option<chrono::DateTime<chrono::Utc>>,
     pub created_at_gte: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only return objects where `file_original_name` contains this string
+    pub file_original_name_contains: Option<String>,
+    /// Only return objects where `file_original_name` contains this string, ignoring case
+    pub file_original_name_icontains: Option<String>,
+    /// Only return objects where `file_original_name` is (or is not) null
+    pub file_original_name_is_null: Option<bool>,
+    /// Only return objects where `file_size` is (or is not) null
+    pub file_size_is_null: Option<bool>,
+    /// Only return objects where `file_hash` is (or is not) null
+    pub file_hash_is_null: Option<bool>,
 }
 
 impl ListQueryFilters {
     fn build_where_clause(&self) -> String {
-        let mut bindings = FilterBuilder::new(4);
+        let mut filters = ExtraFilters::new(4);
+
+        if self.file_original_name_contains.is_some() {
+            filters.contains("file_original_name", false);
+        }
+
+        if self.file_original_name_icontains.is_some() {
+            filters.contains("file_original_name", true);
+        }
+
+        if let Some(file_original_name_is_null) = self.file_original_name_is_null {
+            filters.is_null("file_original_name", file_original_name_is_null);
+        }
+
+        if let Some(file_size_is_null) = self.file_size_is_null {
+            filters.is_null("file_size", file_size_is_null);
+        }
+
+        if let Some(file_hash_is_null) = self.file_hash_is_null {
+            filters.is_null("file_hash", file_hash_is_null);
+        }
+
+        let mut bindings = FilterBuilder::new(filters.next_binding());
 
         if !self.id.is_empty() {
             bindings.add_vec("id", &self.id);
@@ -99,6 +137,10 @@ impl ListQueryFilters {
             bindings.add_vec("file_original_name", &self.file_original_name);
         }
 
+        if !self.file_size.is_empty() {
+            bindings.add_vec("file_size", &self.file_size);
+        }
+
         if !self.file_hash.is_empty() {
             bindings.add_vec("file_hash", &self.file_hash);
         }
@@ -123,12 +165,23 @@ impl ListQueryFilters {
             bindings.add_option("created_at", &self.created_at_gte, BindingOperator::Gte);
         }
 
-        let query = bindings.to_string();
+        let mut query = bindings.to_string();
+        filters.append_to(&mut query);
         event!(Level::DEBUG, %query);
         query
     }
 
     fn bind_to_query<'a, T>(&'a self, mut query: QueryAs<'a, T>) -> QueryAs<'a, T> {
+        if self.file_original_name_contains.is_some() {
+            event!(Level::DEBUG, file_original_name_contains = ?self.file_original_name_contains);
+            query = query.bind(&self.file_original_name_contains);
+        }
+
+        if self.file_original_name_icontains.is_some() {
+            event!(Level::DEBUG, file_original_name_icontains = ?self.file_original_name_icontains);
+            query = query.bind(&self.file_original_name_icontains);
+        }
+
         if !self.id.is_empty() {
             event!(Level::DEBUG, id = ?self.id);
             query = query.bind(&self.id);
@@ -139,6 +192,11 @@ impl ListQueryFilters {
             query = query.bind(&self.file_original_name);
         }
 
+        if !self.file_size.is_empty() {
+            event!(Level::DEBUG, file_size = ?self.file_size);
+            query = query.bind(&self.file_size);
+        }
+
         if !self.file_hash.is_empty() {
             event!(Level::DEBUG, file_hash = ?self.file_hash);
             query = query.bind(&self.file_hash);
@@ -226,6 +284,34 @@ impl PostImage {
         Self::list_internal(q, db, auth, filters).await
     }
 
+    /// List all the images of the given posts, in order. Unlike [PostImage::list], this does not
+    /// paginate the results.
+    #[instrument(skip(db))]
+    pub async fn list_for_posts(
+        db: impl PgExecutor<'_>,
+        auth: &AuthInfo,
+        post_ids: &[PostId],
+    ) -> Result<Vec<PostImage>, error_stack::Report<Error>> {
+        auth.require_permission(super::READ_PERMISSION)?;
+
+        let post_ids = post_ids
+            .iter()
+            .map(|id| id.as_uuid().clone())
+            .collect::<Vec<_>>();
+
+        let objects = query_file_as!(
+            PostImage,
+            "src/models/post_image/list_for_posts.sql",
+            auth.organization_id.as_uuid(),
+            &post_ids
+        )
+        .fetch_all(db)
+        .await
+        .change_context(Error::Db)?;
+
+        Ok(objects)
+    }
+
     async fn list_internal<T>(
         query_template: &str,
         db: impl PgExecutor<'_>,
@@ -296,6 +382,10 @@ impl PostImage {
         organization_id: &OrganizationId,
         payload: PostImageCreatePayload,
     ) -> Result<PostImageCreateResult, error_stack::Report<Error>> {
+        // New images go after the existing ones, so keep other requests from adding an image to
+        // the post at the same position.
+        super::storage::lock_parent(&mut *db, organization_id, &payload.post_id).await?;
+
         let result = query_file_as!(
             PostImage,
             "src/models/post_image/insert.sql",
@@ -306,7 +396,9 @@ impl PostImage {
             payload.file_original_name.as_ref() as _,
             payload.file_size.as_ref() as _,
             payload.file_hash.as_ref() as _,
-            &payload.post_id as _
+            &payload.post_id as _,
+            payload.alt_text.as_ref() as _,
+            payload.caption.as_ref() as _
         )
         .fetch_one(&mut *db)
         .await;
@@ -333,6 +425,8 @@ impl PostImage {
             payload.file_size.as_ref() as _,
             payload.file_hash.as_ref() as _,
             &payload.post_id as _,
+            payload.alt_text.as_ref() as _,
+            payload.caption.as_ref() as _,
             id.as_uuid(),
             auth.organization_id.as_uuid()
         )
@@ -400,12 +494,14 @@ impl PostImage {
 
     #[instrument(skip(db))]
     pub async fn upsert_with_parent_post(
-        db: impl PgExecutor<'_>,
+        db: &mut PgConnection,
         organization_id: &OrganizationId,
         parent_id: &PostId,
         payload: &PostImageUpdatePayload,
     ) -> Result<PostImage, error_stack::Report<Error>> {
         let id = payload.id.clone().unwrap_or_else(|| PostImageId::new());
+        // See `create_raw`. The position is only used when this inserts a new image.
+        super::storage::lock_parent(&mut *db, organization_id, parent_id).await?;
 
         let result = query_file_as!(
             PostImage,
@@ -418,6 +514,8 @@ impl PostImage {
             payload.file_size.as_ref() as _,
             payload.file_hash.as_ref() as _,
             &payload.post_id as _,
+            payload.alt_text.as_ref() as _,
+            payload.caption.as_ref() as _,
             parent_id.as_uuid()
         )
         .fetch_one(db)
@@ -446,6 +544,8 @@ impl PostImage {
             payload.file_size.as_ref() as _,
             payload.file_hash.as_ref() as _,
             &payload.post_id as _,
+            payload.alt_text.as_ref() as _,
+            payload.caption.as_ref() as _,
             id.as_uuid(),
             parent_id.as_uuid(),
             auth.organization_id.as_uuid()
@@ -477,7 +577,7 @@ impl PostImage {
             let bindings = ValuesBuilder {
                 first_parameter: 3,
                 num_values: payload.len(),
-                num_columns: 1 + 1 + 6,
+                num_columns: 1 + 1 + 8 + 1,
             };
             let q = q.replace("__insertion_point_insert_values", &bindings.to_string());
 
@@ -485,7 +585,7 @@ impl PostImage {
                 .bind(organization_id)
                 .bind(parent_id);
 
-            for p in payload {
+            for (position, p) in payload.iter().enumerate() {
                 let id = p.id.unwrap_or_else(|| PostImageId::new());
 
                 query = query
@@ -497,10 +597,14 @@ impl PostImage {
                     .bind(p.file_size.as_ref())
                     .bind(p.file_hash.as_ref())
                     .bind(p.post_id.as_uuid())
+                    .bind(p.alt_text.as_ref())
+                    .bind(p.caption.as_ref())
+                    .bind(position as i32)
             }
 
             let results = query.fetch_all(&mut *db).await;
-            let results = Self::check_missing_parent_error(results)?;
+            let mut results = Self::check_missing_parent_error(results)?;
+            results.sort_by_key(|o| o.position);
 
             // Delete any of the children that were not sent in.
             let ids = results
@@ -522,6 +626,51 @@ impl PostImage {
         }
     }
 
+    /// Change the order of the children of the given parent. `ids` must list each of the parent's
+    /// children exactly once. This function does not do permissions checks.
+    #[instrument(skip(db))]
+    pub async fn reorder_children_of_post(
+        db: &mut PgConnection,
+        organization_id: &OrganizationId,
+        parent_id: &PostId,
+        ids: &[PostImageId],
+    ) -> Result<Vec<PostImage>, error_stack::Report<Error>> {
+        let mut existing = query_file_scalar!(
+            "src/models/post_image/lock_ids_of_post.sql",
+            organization_id.as_uuid(),
+            parent_id.as_uuid()
+        )
+        .fetch_all(&mut *db)
+        .await
+        .change_context(Error::Db)?;
+
+        let new_order = ids
+            .iter()
+            .map(|id| id.as_uuid().clone())
+            .collect::<Vec<_>>();
+
+        existing.sort_unstable();
+        let mut sorted_ids = new_order.clone();
+        sorted_ids.sort_unstable();
+        if existing != sorted_ids {
+            return Err(error_stack::Report::new(Error::InvalidImageOrder));
+        }
+
+        let mut results = query_file_as!(
+            PostImage,
+            "src/models/post_image/update_positions_of_post.sql",
+            organization_id.as_uuid(),
+            parent_id.as_uuid(),
+            &new_order
+        )
+        .fetch_all(db)
+        .await
+        .change_context(Error::Db)?;
+
+        results.sort_by_key(|o| o.position);
+        Ok(results)
+    }
+
     /// Delete a child object, making sure that its parent ID matches.
     #[instrument(skip(db))]
     pub async fn delete_with_parent_post(
@@ -0,0 +1,386 @@
+//! Resized renditions of uploaded PostImages
+//!
+//! After an image is uploaded, the `generate_renditions` job decodes it and writes a resized copy
+//! for each configured [RenditionSpec] to the `image_hosting` bucket, where it can be served from
+//! that bucket's public URL. The renditions are encoded from the decoded pixels, so EXIF and other
+//! metadata in the original file is never published.
+
+use std::{io::Cursor, str::FromStr};
+
+use bytes::Bytes;
+use error_stack::{Report, ResultExt};
+use image::{
+    codecs::{jpeg::JpegEncoder, png::PngEncoder, webp::WebPEncoder},
+    imageops::FilterType,
+    metadata::Orientation,
+    DynamicImage, ImageDecoder, ImageReader,
+};
+use tracing::{event, Level};
+
+use super::{PostImage, PostImageId, PostImageRendition, PostImageRenditions};
+use crate::{models::organization::OrganizationId, server::ServerState, Error};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenditionFormat {
+    Webp,
+    Jpeg,
+    Png,
+}
+
+impl RenditionFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Webp => "image/webp",
+            Self::Jpeg => "image/jpeg",
+            Self::Png => "image/png",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Webp => "webp",
+            Self::Jpeg => "jpg",
+            Self::Png => "png",
+        }
+    }
+}
+
+/// A rendition to generate for each uploaded image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenditionSpec {
+    /// The name of the rendition, which is also used in its storage key
+    pub name: String,
+    /// The maximum width and height of the rendition. Smaller images are not scaled up.
+    pub max_size: u32,
+    pub format: RenditionFormat,
+}
+
+/// Parses a rendition in the form `name:max_size[:format]`, such as `thumb:256:webp`. The format
+/// defaults to WebP.
+impl FromStr for RenditionSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().split(':');
+        let name = parts.next().unwrap_or_default();
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!("Invalid rendition name in {s}"));
+        }
+
+        let max_size = parts
+            .next()
+            .and_then(|size| size.parse::<u32>().ok())
+            .filter(|size| *size > 0)
+            .ok_or_else(|| format!("Invalid rendition size in {s}"))?;
+
+        let format = match parts.next().map(|f| f.to_ascii_lowercase()).as_deref() {
+            None | Some("webp") => RenditionFormat::Webp,
+            Some("jpeg") | Some("jpg") => RenditionFormat::Jpeg,
+            Some("png") => RenditionFormat::Png,
+            Some(_) => return Err(format!("Invalid rendition format in {s}")),
+        };
+
+        if parts.next().is_some() {
+            return Err(format!("Invalid rendition {s}"));
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            max_size,
+            format,
+        })
+    }
+}
+
+/// The renditions used when none are configured
+pub fn default_renditions() -> Vec<RenditionSpec> {
+    vec![
+        RenditionSpec {
+            name: "thumb".to_string(),
+            max_size: 256,
+            format: RenditionFormat::Webp,
+        },
+        RenditionSpec {
+            name: "large".to_string(),
+            max_size: 1024,
+            format: RenditionFormat::Webp,
+        },
+    ]
+}
+
+/// The storage key of a rendition in the `image_hosting` bucket. This starts with the key of the
+/// original, so renditions share the original's organization prefix.
+pub fn rendition_key(original_key: &str, spec: &RenditionSpec) -> String {
+    format!("{original_key}/{}.{}", spec.name, spec.format.extension())
+}
+
+/// An encoded rendition, ready to be uploaded
+pub struct RenderedImage {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+/// The result of decoding an image and rendering it at each size
+pub struct RenderedRenditions {
+    /// The width of the original image, after applying its EXIF orientation
+    pub width: u32,
+    /// The height of the original image, after applying its EXIF orientation
+    pub height: u32,
+    pub renditions: Vec<RenderedImage>,
+}
+
+/// Decode an image and encode a rendition for each spec. This is CPU-bound, so it should be run
+/// with `spawn_blocking`.
+pub fn render(data: &[u8], specs: &[RenditionSpec]) -> Result<RenderedRenditions, Report<Error>> {
+    let mut decoder = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .change_context(Error::ImageProcessing)?
+        .into_decoder()
+        .change_context(Error::ImageProcessing)?;
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+
+    let mut image = DynamicImage::from_decoder(decoder).change_context(Error::ImageProcessing)?;
+    image.apply_orientation(orientation);
+
+    let renditions = specs
+        .iter()
+        .map(|spec| render_one(&image, spec))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(RenderedRenditions {
+        width: image.width(),
+        height: image.height(),
+        renditions,
+    })
+}
+
+fn render_one(image: &DynamicImage, spec: &RenditionSpec) -> Result<RenderedImage, Report<Error>> {
+    let resized;
+    let image = if image.width() > spec.max_size || image.height() > spec.max_size {
+        resized = image.resize(spec.max_size, spec.max_size, FilterType::Lanczos3);
+        &resized
+    } else {
+        image
+    };
+
+    let mut data = Vec::new();
+    match spec.format {
+        RenditionFormat::Webp => image
+            .to_rgba8()
+            .write_with_encoder(WebPEncoder::new_lossless(&mut data)),
+        RenditionFormat::Png => image
+            .to_rgba8()
+            .write_with_encoder(PngEncoder::new(&mut data)),
+        RenditionFormat::Jpeg => image
+            .to_rgb8()
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut data, 85)),
+    }
+    .change_context(Error::ImageProcessing)
+    .attach_printable_lazy(|| format!("Encoding rendition {}", spec.name))?;
+
+    Ok(RenderedImage {
+        width: image.width(),
+        height: image.height(),
+        data,
+    })
+}
+
+/// Queue the job that generates the renditions of a newly uploaded image. A failure is only
+/// logged, since the upload itself has already succeeded.
+pub async fn enqueue(state: &ServerState, image: &PostImage) {
+    let payload = crate::jobs::generate_renditions::GenerateRenditionsJobPayload {
+        organization_id: image.organization_id,
+        post_image_id: image.id,
+    };
+
+    if let Err(e) =
+        crate::jobs::generate_renditions::enqueue(state, image.id.to_string(), &payload).await
+    {
+        event!(Level::ERROR, id = %image.id, error = ?e, "Failed to enqueue image renditions");
+    }
+}
+
+/// Generate the renditions for a PostImage and record them, along with the image's dimensions.
+pub async fn generate(
+    state: &ServerState,
+    organization_id: OrganizationId,
+    id: PostImageId,
+) -> Result<(), Report<Error>> {
+    let Some(image) = sqlx::query!(
+        "SELECT file_storage_key, file_hash FROM public.post_images
+        WHERE id = $1 AND organization_id = $2",
+        id.as_uuid(),
+        organization_id.as_uuid()
+    )
+    .fetch_optional(&state.db)
+    .await
+    .change_context(Error::Db)?
+    else {
+        // The image was deleted before the job ran.
+        return Ok(());
+    };
+
+    let original = super::storage::get_storage(state)
+        .get(&image.file_storage_key)
+        .await
+        .change_context(Error::Storage)?
+        .bytes()
+        .await
+        .change_context(Error::Storage)?;
+
+    let specs = state.image_renditions.clone();
+    let render_specs = specs.clone();
+    let rendered = tokio::task::spawn_blocking(move || render(&original, &render_specs))
+        .await
+        .change_context(Error::ImageProcessing)?;
+
+    let rendered = match rendered {
+        Ok(rendered) => rendered,
+        Err(e) => {
+            // Retrying won't help if the file isn't an image we can decode, so just leave it
+            // without renditions.
+            event!(Level::WARN, %id, error = ?e, "Unable to generate image renditions");
+            return Ok(());
+        }
+    };
+
+    let storage = &state.storage.image_hosting;
+    let mut renditions = Vec::with_capacity(specs.len());
+    for (spec, output) in specs.iter().zip(rendered.renditions) {
+        let key = rendition_key(&image.file_storage_key, spec);
+        let file_size = output.data.len() as i64;
+        storage
+            .put(&key, Bytes::from(output.data))
+            .await
+            .change_context(Error::Storage)?;
+
+        renditions.push(PostImageRendition {
+            name: spec.name.clone(),
+            content_type: spec.format.content_type().to_string(),
+            width: output.width,
+            height: output.height,
+            file_size,
+            url: public_url(storage.public_url.as_ref(), &key),
+            file_storage_key: key,
+        });
+    }
+
+    // The image may have been replaced while the job was running, in which case the new upload
+    // will have queued its own job.
+    sqlx::query!(
+        "UPDATE public.post_images
+        SET width = $3, height = $4, renditions = $5, updated_at = now()
+        WHERE id = $1 AND organization_id = $2
+            AND file_storage_key = $6 AND file_hash IS NOT DISTINCT FROM $7",
+        id.as_uuid(),
+        organization_id.as_uuid(),
+        rendered.width as i32,
+        rendered.height as i32,
+        sqlx::types::Json(PostImageRenditions(renditions)) as _,
+        image.file_storage_key,
+        image.file_hash,
+    )
+    .execute(&state.db)
+    .await
+    .change_context(Error::Db)?;
+
+    Ok(())
+}
+
+/// Delete the renditions generated from an original image.
+pub async fn delete_for_key(state: &ServerState, original_key: &str) {
+    let storage = &state.storage.image_hosting;
+    for spec in &state.image_renditions {
+        let key = rendition_key(original_key, spec);
+        if let Err(e) = storage.delete(&key).await {
+            event!(Level::WARN, key, error = ?e, "Failed to delete image rendition");
+        }
+    }
+}
+
+/// The public URL of an object, given the public URL of its bucket
+pub fn public_url(base: Option<&url::Url>, key: &str) -> Option<String> {
+    let mut url = base?.clone();
+    url.path_segments_mut()
+        .ok()?
+        .pop_if_empty()
+        .extend(key.split('/'));
+    Some(url.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use image::{ImageFormat, RgbaImage};
+
+    use super::*;
+
+    #[test]
+    fn parse_spec() {
+        assert_eq!(
+            "thumb:256".parse::<RenditionSpec>().unwrap(),
+            RenditionSpec {
+                name: "thumb".to_string(),
+                max_size: 256,
+                format: RenditionFormat::Webp,
+            }
+        );
+        assert_eq!(
+            "large:1024:JPEG".parse::<RenditionSpec>().unwrap().format,
+            RenditionFormat::Jpeg
+        );
+        assert!("thumb".parse::<RenditionSpec>().is_err());
+        assert!("thumb:0".parse::<RenditionSpec>().is_err());
+        assert!("../thumb:256".parse::<RenditionSpec>().is_err());
+        assert!("thumb:256:gif".parse::<RenditionSpec>().is_err());
+    }
+
+    #[test]
+    fn render_renditions() {
+        let mut original = Vec::new();
+        DynamicImage::ImageRgba8(RgbaImage::new(600, 300))
+            .write_to(&mut Cursor::new(&mut original), ImageFormat::Png)
+            .unwrap();
+
+        let specs = vec![
+            "thumb:256:webp".parse().unwrap(),
+            "large:1024:jpeg".parse().unwrap(),
+        ];
+        let rendered = render(&original, &specs).unwrap();
+
+        assert_eq!((rendered.width, rendered.height), (600, 300));
+        assert_eq!(
+            (rendered.renditions[0].width, rendered.renditions[0].height),
+            (256, 128)
+        );
+        assert_eq!(
+            image::guess_format(&rendered.renditions[0].data).unwrap(),
+            ImageFormat::WebP
+        );
+        // Smaller images are not scaled up
+        assert_eq!(
+            (rendered.renditions[1].width, rendered.renditions[1].height),
+            (600, 300)
+        );
+        assert_eq!(
+            image::guess_format(&rendered.renditions[1].data).unwrap(),
+            ImageFormat::Jpeg
+        );
+
+        assert!(render(b"not an image", &specs).is_err());
+    }
+
+    #[test]
+    fn rendition_url() {
+        let base = url::Url::parse("https://cdn.example.com/").unwrap();
+        assert_eq!(
+            public_url(Some(&base), "renditions/abc-my photo.png/thumb.webp").as_deref(),
+            Some("https://cdn.example.com/renditions/abc-my%20photo.png/thumb.webp")
+        );
+        assert_eq!(public_url(None, "renditions/a/thumb.webp"), None);
+    }
+}
@@ -8,7 +8,14 @@ SELECT
   file_original_name,
   file_size,
   file_hash,
-  post_id AS "post_id: PostId"
+  post_id AS "post_id: PostId",
+  width,
+  height,
+  renditions AS "renditions: PostImageRenditions",
+  alt_text,
+  caption,
+  position,
+  NULL::text AS "url"
 FROM
   public.post_images tb
 WHERE
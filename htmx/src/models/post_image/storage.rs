@@ -1,27 +1,130 @@
 //! Object storage functionality for PostImage
 #![allow(unused_imports, unused_variables, dead_code)]
 
+use axum::extract::Multipart;
 use bytes::Bytes;
 use error_stack::ResultExt;
 use filigree::{
     storage::{Storage, StorageError},
     uploads::{self, UploadInspector, UploadInspectorError},
 };
-use futures::stream::Stream;
-use sqlx::PgConnection;
+use futures::{stream::Stream, TryStreamExt};
+use sqlx::{PgConnection, PgPool};
+use tracing::{event, Level};
 
-use super::{PostImage, PostImageId, PostImageUpdatePayload};
-use crate::{auth::Authed, error::Error, models::post::PostId, server::ServerState};
+use super::{PostImage, PostImageId, PostImageRenditions, PostImageUpdatePayload};
+use crate::{
+    auth::{permissions::ORG_ADMIN_PERMISSION, AuthInfo, Authed},
+    error::Error,
+    models::{organization::OrganizationId, post::PostId},
+    server::ServerState,
+    storage::AppStorage,
+    storage_blobs, storage_quota,
+    upload_policy::{UploadPolicy, UploadPolicyError, UploadPolicyInspector},
+};
 
 /// Apply the storage key template
-pub fn generate_object_key(auth: &Authed, id: PostImageId, filename: &str) -> String {
-    format!(r##"{id}-{filename}"##, id = id, filename = filename,)
+pub fn generate_object_key(
+    storage: &AppStorage,
+    organization_id: &OrganizationId,
+    id: PostImageId,
+    filename: &str,
+) -> String {
+    storage
+        .key_templates
+        .post_image
+        .render(organization_id, "post_image", id, filename)
 }
 
 pub fn get_storage(state: &ServerState) -> &Storage {
     &state.storage.image_uploads
 }
 
+pub fn get_policy(state: &ServerState) -> &UploadPolicy {
+    &state.storage.image_uploads_policy
+}
+
+/// The bucket that PostImage files are stored in
+const BUCKET: &str = "image_uploads";
+
+/// Set the signed URL of each image if the user can read its post, so that clients can display
+/// images from the `image_uploads` bucket, which has no public URL.
+pub fn add_signed_urls<'a>(
+    state: &ServerState,
+    auth: &AuthInfo,
+    images: impl IntoIterator<Item = &'a mut PostImage>,
+) {
+    use filigree::auth::AuthInfo as _;
+    let can_read = auth.has_permission(crate::models::post::READ_PERMISSION)
+        || auth.has_permission(ORG_ADMIN_PERMISSION);
+
+    for image in images {
+        image.url = can_read.then(|| {
+            state.storage.signed_url(
+                &image.file_storage_bucket,
+                &image.file_storage_key,
+                image.file_original_name.as_deref(),
+            )
+        });
+    }
+}
+
+fn deduplicate_enabled(state: &ServerState) -> bool {
+    state.storage.image_uploads_deduplicate
+}
+
+/// Lock the parent post for the rest of the transaction, so that concurrent uploads to the same
+/// post see each other's images.
+pub(super) async fn lock_parent(
+    tx: &mut PgConnection,
+    organization_id: &OrganizationId,
+    parent_id: &PostId,
+) -> Result<(), error_stack::Report<Error>> {
+    sqlx::query!(
+        "SELECT id FROM public.posts WHERE id = $1 AND organization_id = $2 FOR UPDATE",
+        parent_id.as_uuid(),
+        organization_id.as_uuid()
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .change_context(Error::Db)?;
+    Ok(())
+}
+
+/// Check that the parent object can accept another file under the upload policy. `id` is
+/// excluded from the count, since uploading to an existing ID replaces that file.
+pub(super) async fn check_upload_count(
+    state: &ServerState,
+    auth: &Authed,
+    tx: &mut PgConnection,
+    parent_id: PostId,
+    id: PostImageId,
+) -> Result<(), error_stack::Report<Error>> {
+    let policy = get_policy(state);
+    if policy.max_per_parent.is_none() {
+        return Ok(());
+    }
+
+    // Without the lock, two concurrent uploads could both see room for one more file.
+    lock_parent(&mut *tx, &auth.organization_id, &parent_id).await?;
+
+    let existing = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM public.post_images
+        WHERE organization_id = $1 AND post_id = $2 AND id <> $3"#,
+        auth.organization_id.as_uuid(),
+        parent_id.as_uuid(),
+        id.as_uuid()
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .change_context(Error::Db)?;
+
+    policy
+        .check_count(existing)
+        .map_err(error_stack::Report::new)
+        .change_context(Error::Upload)
+}
+
 pub async fn upload_stream<E>(
     state: &ServerState,
     auth: &Authed,
@@ -40,30 +143,97 @@ where
     let storage = get_storage(state);
 
     let id = id.unwrap_or_else(|| PostImageId::new());
+    check_upload_count(state, auth, &mut *tx, parent_id, id).await?;
 
+    let deduplicate = key.is_none() && deduplicate_enabled(state);
     let file_storage_key = key
-        .unwrap_or_else(|| generate_object_key(auth, id, filename.as_deref().unwrap_or_default()));
+        .unwrap_or_else(|| {
+            generate_object_key(
+                &state.storage,
+                &auth.organization_id,
+                id,
+                filename.as_deref().unwrap_or_default(),
+            )
+        });
 
-    let mut file_size = uploads::UploadSize::new(limit);
+    // Stop the upload early once it can't fit in the quota. A deduplicated file might already be
+    // stored and so not use any space, so those are only checked once the hash is known.
+    let quota_remaining = if deduplicate {
+        None
+    } else {
+        storage_quota::remaining(&mut *tx, &state.storage.quota, &auth.organization_id).await?
+    };
+
+    let mut file_size = uploads::UploadSize::new(None);
     let mut hasher = uploads::UploadHasher::<blake3::Hasher>::new();
+    let mut policy =
+        UploadPolicyInspector::new(get_policy(state), limit).with_quota(quota_remaining);
 
     storage
         .save_and_inspect_request_body(&file_storage_key, body, |chunk| {
+            // Check the policy first, so that a disallowed file is rejected before anything
+            // else looks at it.
+            policy.inspect(chunk)?;
             file_size.inspect(chunk)?;
             hasher.inspect(chunk)?;
-            Ok::<(), UploadInspectorError>(())
+            Ok::<(), UploadPolicyError>(())
         })
         .await
         .change_context(Error::Upload)?;
 
+    // Files too short to be sniffed while streaming are only checked once the upload finishes.
+    if let Err(e) = policy.finish() {
+        storage.delete(&file_storage_key).await.ok();
+        return Err(error_stack::Report::new(e)).change_context(Error::Upload);
+    }
+
+    let file_hash = hasher.finish().to_vec();
+    let file_size = file_size.finish() as i64;
+
+    if let Err(e) = check_quota(state, auth, &mut *tx, &file_hash, file_size, deduplicate).await {
+        storage.delete(&file_storage_key).await.ok();
+        return Err(e);
+    }
+
+    // The hash isn't known until the whole file has been read, so a deduplicated file is
+    // streamed to its usual key first and then moved into its blob.
+    let file_storage_key = if deduplicate {
+        let blob_key = storage_blobs::blob_key(&auth.organization_id, "post_image", &file_hash);
+        let blob = storage_blobs::acquire(
+            &mut *tx,
+            &auth.organization_id,
+            BUCKET,
+            &file_hash,
+            &blob_key,
+            file_size,
+        )
+        .await?;
+
+        let copied = if blob.created {
+            copy_object(storage, &file_storage_key, &blob.storage_key).await
+        } else {
+            Ok(())
+        };
+        storage.delete(&file_storage_key).await.ok();
+        copied?;
+
+        blob.storage_key
+    } else {
+        file_storage_key
+    };
+
+    let replaced = release_replaced(auth, &mut *tx, id).await?;
+
     let db_payload = PostImageUpdatePayload {
         id: Some(id),
         post_id: parent_id,
         file_storage_key,
-        file_storage_bucket: "image_uploads".to_string(),
+        file_storage_bucket: BUCKET.to_string(),
         file_original_name: filename,
-        file_hash: Some(hasher.finish().to_vec()),
-        file_size: Some(file_size.finish() as i64),
+        file_hash: Some(file_hash),
+        file_size: Some(file_size),
+        alt_text: replaced.alt_text,
+        caption: replaced.caption,
         ..Default::default()
     };
 
@@ -86,14 +256,12 @@ pub async fn upload(
     body: Bytes,
 ) -> Result<PostImage, error_stack::Report<Error>> {
     let file_size = body.len();
-    if let Some(limit) = limit {
-        if file_size > limit {
-            return Err(error_stack::Report::new(
-                UploadInspectorError::FileSizeTooLarge,
-            ))
-            .change_context(Error::Upload);
-        }
-    }
+    let mut policy = UploadPolicyInspector::new(get_policy(state), limit);
+    policy
+        .inspect(&body)
+        .and_then(|_| policy.finish())
+        .map_err(error_stack::Report::new)
+        .change_context(Error::Upload)?;
 
     let b = body.clone();
     let hash = tokio::task::spawn_blocking(move || {
@@ -105,18 +273,49 @@ pub async fn upload(
     .change_context(Error::Upload)?;
 
     let id = id.unwrap_or_else(|| PostImageId::new());
+    check_upload_count(state, auth, &mut *tx, parent_id, id).await?;
 
-    let file_storage_key = key
-        .unwrap_or_else(|| generate_object_key(auth, id, filename.as_deref().unwrap_or_default()));
+    let deduplicate = key.is_none() && deduplicate_enabled(state);
+    check_quota(state, auth, &mut *tx, &hash, file_size as i64, deduplicate).await?;
+
+    let (file_storage_key, needs_write) = match key {
+        Some(key) => (key, true),
+        None if deduplicate => {
+            let blob_key = storage_blobs::blob_key(&auth.organization_id, "post_image", &hash);
+            let blob = storage_blobs::acquire(
+                &mut *tx,
+                &auth.organization_id,
+                BUCKET,
+                &hash,
+                &blob_key,
+                file_size as i64,
+            )
+            .await?;
+            (blob.storage_key, blob.created)
+        }
+        None => (
+            generate_object_key(
+                &state.storage,
+                &auth.organization_id,
+                id,
+                filename.as_deref().unwrap_or_default(),
+            ),
+            true,
+        ),
+    };
+
+    let replaced = release_replaced(auth, &mut *tx, id).await?;
 
     let db_payload = PostImageUpdatePayload {
         id: Some(id),
         post_id: parent_id,
         file_storage_key: file_storage_key.clone(),
-        file_storage_bucket: "image_uploads".to_string(),
+        file_storage_bucket: BUCKET.to_string(),
         file_original_name: filename,
         file_hash: Some(hash),
         file_size: Some(file_size as i64),
+        alt_text: replaced.alt_text,
+        caption: replaced.caption,
         ..Default::default()
     };
 
@@ -124,15 +323,280 @@ pub async fn upload(
         PostImage::upsert_with_parent_post(tx, &auth.organization_id, &parent_id, &db_payload)
             .await?;
 
-    let storage = get_storage(state);
-    storage
-        .put(&file_storage_key, body)
-        .await
-        .change_context(Error::Upload)?;
+    if needs_write {
+        let storage = get_storage(state);
+        storage
+            .put(&file_storage_key, body)
+            .await
+            .change_context(Error::Upload)?;
+    }
 
     Ok(result)
 }
 
+/// The longest alt text or caption accepted in a multipart upload. The request body limit is
+/// disabled for uploads, so the text fields need their own limit.
+const MAX_TEXT_FIELD_BYTES: usize = 4096;
+
+/// Read a text field from a multipart upload, without buffering more than
+/// [MAX_TEXT_FIELD_BYTES] of it.
+async fn read_text_field(
+    mut field: axum::extract::multipart::Field<'_>,
+) -> Result<String, error_stack::Report<Error>> {
+    let mut value = Vec::new();
+    while let Some(chunk) = field
+        .chunk()
+        .await
+        .change_context(Error::InvalidUploadRequest("Invalid multipart body"))?
+    {
+        if value.len() + chunk.len() > MAX_TEXT_FIELD_BYTES {
+            return Err(error_stack::Report::new(Error::InvalidUploadRequest(
+                "Alt text and captions must be at most 4096 bytes",
+            )));
+        }
+
+        value.extend_from_slice(&chunk);
+    }
+
+    String::from_utf8(value).change_context(Error::InvalidUploadRequest("Invalid multipart body"))
+}
+
+/// Upload every file in a `multipart/form-data` body. Each file is streamed to storage as it
+/// arrives, so that whole files are never held in memory.
+///
+/// The form can also have `alt_text` and `caption` fields. A single value applies to every file,
+/// and otherwise the values are matched to the files in order.
+pub async fn upload_multipart(
+    state: &ServerState,
+    auth: &Authed,
+    tx: &mut PgConnection,
+    parent_id: PostId,
+    mut multipart: Multipart,
+) -> Result<Vec<PostImage>, error_stack::Report<Error>> {
+    let mut images = Vec::new();
+    let mut alt_texts = Vec::new();
+    let mut captions = Vec::new();
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .change_context(Error::InvalidUploadRequest("Invalid multipart body"))?
+    {
+        let name = field.name().map(|n| n.to_string());
+        match name.as_deref() {
+            Some(name @ ("alt_text" | "caption")) => {
+                let value = read_text_field(field).await?;
+                let value = Some(value.trim().to_string()).filter(|v| !v.is_empty());
+                if name == "alt_text" {
+                    alt_texts.push(value);
+                } else {
+                    captions.push(value);
+                }
+            }
+            // Browsers send an empty file part when no file was chosen.
+            _ if field.file_name().is_some_and(|f| !f.is_empty()) => {
+                let filename = field.file_name().map(|f| f.to_string());
+                let image = upload_stream(
+                    state,
+                    auth,
+                    &mut *tx,
+                    parent_id,
+                    None,
+                    filename,
+                    None,
+                    None,
+                    field.map_err(axum::Error::new),
+                )
+                .await?;
+                images.push(image);
+            }
+            _ => {}
+        }
+    }
+
+    if images.is_empty() {
+        return Err(error_stack::Report::new(Error::InvalidUploadRequest(
+            "The form did not contain any files",
+        )));
+    }
+
+    let value_for = |values: &[Option<String>], i: usize| {
+        if values.len() == 1 {
+            values[0].clone()
+        } else {
+            values.get(i).cloned().flatten()
+        }
+    };
+
+    for (i, image) in images.iter_mut().enumerate() {
+        image.alt_text = value_for(&alt_texts, i);
+        image.caption = value_for(&captions, i);
+        if image.alt_text.is_none() && image.caption.is_none() {
+            continue;
+        }
+
+        sqlx::query!(
+            "UPDATE public.post_images SET alt_text = $2, caption = $3 WHERE id = $1",
+            image.id.as_uuid(),
+            image.alt_text,
+            image.caption
+        )
+        .execute(&mut *tx)
+        .await
+        .change_context(Error::Db)?;
+    }
+
+    Ok(images)
+}
+
+/// Check that a new file fits in the organization's storage quota, and queue a warning email if
+/// it takes usage over the soft limit. A file that is already stored as a blob uses no more space.
+async fn check_quota(
+    state: &ServerState,
+    auth: &Authed,
+    tx: &mut PgConnection,
+    file_hash: &[u8],
+    file_size: i64,
+    deduplicate: bool,
+) -> Result<(), error_stack::Report<Error>> {
+    let already_stored = deduplicate
+        && storage_blobs::find(&mut *tx, &auth.organization_id, BUCKET, file_hash)
+            .await?
+            .is_some();
+    let new_bytes = if already_stored { 0 } else { file_size };
+
+    let quota = storage_quota::check(
+        &mut *tx,
+        &state.storage.quota,
+        &auth.organization_id,
+        new_bytes,
+    )
+    .await?;
+
+    if quota.over_soft_limit {
+        let payload = crate::jobs::storage_quota_warning::StorageQuotaWarningJobPayload {
+            organization_id: auth.organization_id,
+        };
+        if let Err(e) = crate::jobs::storage_quota_warning::enqueue(
+            state,
+            format!("storage_quota_warning-{}", auth.organization_id),
+            &payload,
+        )
+        .await
+        {
+            event!(Level::ERROR, error = ?e, "Failed to enqueue storage quota warning");
+        }
+    }
+
+    Ok(())
+}
+
+/// The text of a PostImage whose file is being replaced, which carries over to the new file.
+#[derive(Debug, Default)]
+struct ReplacedImage {
+    alt_text: Option<String>,
+    caption: Option<String>,
+}
+
+/// Release the reference held by an existing PostImage that is about to be replaced. The old
+/// object is left for the `gc_storage` job, since the replacement hasn't been committed yet.
+async fn release_replaced(
+    auth: &Authed,
+    tx: &mut PgConnection,
+    id: PostImageId,
+) -> Result<ReplacedImage, error_stack::Report<Error>> {
+    let existing = sqlx::query!(
+        "SELECT file_storage_key, alt_text, caption FROM public.post_images
+        WHERE id = $1 AND organization_id = $2",
+        id.as_uuid(),
+        auth.organization_id.as_uuid()
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .change_context(Error::Db)?;
+
+    let Some(existing) = existing else {
+        return Ok(ReplacedImage::default());
+    };
+
+    storage_blobs::release(
+        &mut *tx,
+        &auth.organization_id,
+        BUCKET,
+        &existing.file_storage_key,
+    )
+    .await?;
+
+    Ok(ReplacedImage {
+        alt_text: existing.alt_text,
+        caption: existing.caption,
+    })
+}
+
+/// Release the references held by PostImages that are being deleted, and return the keys of the
+/// objects that nothing refers to anymore and so can be deleted.
+pub async fn release_keys(
+    state: &ServerState,
+    auth: &Authed,
+    tx: &mut PgConnection,
+    keys: Vec<String>,
+) -> Result<Vec<String>, error_stack::Report<Error>> {
+    let mut unreferenced = Vec::with_capacity(keys.len());
+    for key in keys {
+        if storage_blobs::release(&mut *tx, &auth.organization_id, BUCKET, &key).await? {
+            unreferenced.push(key);
+        }
+    }
+
+    Ok(unreferenced)
+}
+
+/// Find a deduplicated file by the hash of its contents.
+pub async fn find_by_hash(
+    state: &ServerState,
+    auth: &Authed,
+    hash: &[u8],
+) -> Result<Option<storage_blobs::Blob>, error_stack::Report<Error>> {
+    storage_blobs::find(&state.db, &auth.organization_id, BUCKET, hash).await
+}
+
+/// Create a PostImage that refers to an already-stored file with the given hash, so that the
+/// file doesn't have to be uploaded again. Returns `None` if no file has that hash.
+pub async fn create_from_hash(
+    state: &ServerState,
+    auth: &Authed,
+    tx: &mut PgConnection,
+    parent_id: PostId,
+    hash: &[u8],
+    filename: Option<String>,
+) -> Result<Option<PostImage>, error_stack::Report<Error>> {
+    let id = PostImageId::new();
+    check_upload_count(state, auth, &mut *tx, parent_id, id).await?;
+
+    let Some(blob) =
+        storage_blobs::acquire_existing(&mut *tx, &auth.organization_id, BUCKET, hash).await?
+    else {
+        return Ok(None);
+    };
+
+    let db_payload = PostImageUpdatePayload {
+        id: Some(id),
+        post_id: parent_id,
+        file_storage_key: blob.storage_key,
+        file_storage_bucket: BUCKET.to_string(),
+        file_original_name: filename,
+        file_hash: Some(hash.to_vec()),
+        file_size: Some(blob.size),
+        ..Default::default()
+    };
+
+    let result =
+        PostImage::upsert_with_parent_post(tx, &auth.organization_id, &parent_id, &db_payload)
+            .await?;
+
+    Ok(Some(result))
+}
+
 /// Delete an object given the storage key
 pub async fn delete_by_key(
     state: &ServerState,
@@ -140,6 +604,7 @@ pub async fn delete_by_key(
 ) -> Result<(), error_stack::Report<Error>> {
     let storage = get_storage(state);
     storage.delete(key).await.change_context(Error::Storage)?;
+    super::renditions::delete_for_key(state, key).await;
     Ok(())
 }
 
@@ -154,7 +619,9 @@ pub async fn delete_by_id(
     let storage_key = get_storage_key_by_id(state, auth, &mut *tx, id).await?;
     let deleted = PostImage::delete_with_parent_post(&mut *tx, auth, &parent_id, &id).await?;
 
-    if deleted {
+    if deleted
+        && storage_blobs::release(&mut *tx, &auth.organization_id, BUCKET, &storage_key).await?
+    {
         delete_by_key(state, &storage_key).await?;
     }
 
@@ -173,8 +640,11 @@ pub async fn delete_by_parent_id(
         PostImage::delete_all_children_of_post(&mut *tx, &auth.organization_id, &parent_id).await?;
 
     if deleted {
+        let storage_keys = release_keys(state, auth, &mut *tx, storage_keys).await?;
         for key in storage_keys {
-            delete_by_key(state, &key).await?;
+            if let Err(e) = delete_by_key(state, &key).await {
+                event!(Level::WARN, key, error = ?e, "Failed to delete post image");
+            }
         }
     }
 
@@ -207,3 +677,153 @@ pub async fn get_storage_key_by_id(
     let storage_key = PostImage::get(&mut *tx, auth, &id).await?.file_storage_key;
     Ok(storage_key)
 }
+
+/// Copy an object to a new key within the same bucket.
+async fn copy_object(
+    storage: &Storage,
+    from: &str,
+    to: &str,
+) -> Result<(), error_stack::Report<Error>> {
+    let data = storage
+        .get(from)
+        .await
+        .change_context(Error::Storage)?
+        .bytes()
+        .await
+        .change_context(Error::Storage)?;
+    storage.put(to, data).await.change_context(Error::Storage)?;
+    Ok(())
+}
+
+/// A file that was moved by [migrate_storage_keys]
+#[derive(Debug)]
+pub struct MovedStorageKey {
+    pub id: PostImageId,
+    pub from: String,
+    pub to: String,
+}
+
+/// Move existing files, and their renditions, to the keys given by the current key template,
+/// and return the files that were moved. With `dry_run`, the files that would be moved are
+/// returned without moving them.
+///
+/// The objects are copied before `file_storage_key` is updated and only deleted afterwards, so an
+/// interrupted migration can be run again without losing any files.
+pub async fn migrate_storage_keys(
+    db: &PgPool,
+    storage: &AppStorage,
+    dry_run: bool,
+) -> Result<Vec<MovedStorageKey>, error_stack::Report<Error>> {
+    let files = sqlx::query!(
+        r#"SELECT id AS "id: PostImageId",
+            organization_id AS "organization_id: OrganizationId",
+            file_storage_key,
+            file_original_name,
+            renditions AS "renditions: PostImageRenditions"
+        FROM public.post_images p
+        -- Deduplicated files are keyed by their hash, not by the template.
+        WHERE NOT EXISTS (
+            SELECT 1 FROM public.storage_blobs b
+            WHERE b.bucket = p.file_storage_bucket AND b.storage_key = p.file_storage_key
+        )
+        ORDER BY created_at"#
+    )
+    .fetch_all(db)
+    .await
+    .change_context(Error::Db)?;
+
+    let mut moved = Vec::new();
+    for file in files {
+        let new_key = generate_object_key(
+            storage,
+            &file.organization_id,
+            file.id,
+            file.file_original_name.as_deref().unwrap_or_default(),
+        );
+        if new_key == file.file_storage_key {
+            continue;
+        }
+
+        if dry_run {
+            moved.push(MovedStorageKey {
+                id: file.id,
+                from: file.file_storage_key,
+                to: new_key,
+            });
+            continue;
+        }
+
+        copy_object(&storage.image_uploads, &file.file_storage_key, &new_key).await?;
+
+        let mut renditions = file.renditions;
+        let mut old_rendition_keys = Vec::with_capacity(renditions.0.len());
+        for rendition in &mut renditions.0 {
+            let name = rendition
+                .file_storage_key
+                .rsplit('/')
+                .next()
+                .unwrap_or_default();
+            let new_rendition_key = format!("{new_key}/{name}");
+            copy_object(
+                &storage.image_hosting,
+                &rendition.file_storage_key,
+                &new_rendition_key,
+            )
+            .await?;
+
+            rendition.url = super::renditions::public_url(
+                storage.image_hosting.public_url.as_ref(),
+                &new_rendition_key,
+            );
+            old_rendition_keys.push(std::mem::replace(
+                &mut rendition.file_storage_key,
+                new_rendition_key,
+            ));
+        }
+
+        let result = sqlx::query!(
+            "UPDATE public.post_images SET file_storage_key = $2, renditions = $3
+            WHERE id = $1 AND file_storage_key = $4",
+            file.id.as_uuid(),
+            new_key,
+            sqlx::types::Json(&renditions) as _,
+            file.file_storage_key,
+        )
+        .execute(db)
+        .await
+        .change_context(Error::Db)?;
+
+        if result.rows_affected() == 0 {
+            // The image was deleted or replaced while it was being copied, so the row still
+            // points somewhere else. Remove the copies instead of the objects it may use.
+            event!(Level::WARN, id = %file.id, "Image changed during migration, skipping");
+            if let Err(e) = storage.image_uploads.delete(&new_key).await {
+                event!(Level::WARN, key = new_key, error = ?e, "Failed to delete copied object");
+            }
+            for rendition in &renditions.0 {
+                let key = &rendition.file_storage_key;
+                if let Err(e) = storage.image_hosting.delete(key).await {
+                    event!(Level::WARN, key, error = ?e, "Failed to delete copied rendition");
+                }
+            }
+            continue;
+        }
+
+        if let Err(e) = storage.image_uploads.delete(&file.file_storage_key).await {
+            event!(Level::WARN, key = file.file_storage_key, error = ?e, "Failed to delete old object");
+        }
+        for key in old_rendition_keys {
+            if let Err(e) = storage.image_hosting.delete(&key).await {
+                event!(Level::WARN, key, error = ?e, "Failed to delete old rendition");
+            }
+        }
+
+        moved.push(MovedStorageKey {
+            id: file.id,
+            from: file.file_storage_key,
+            to: new_key,
+        });
+    }
+
+    Ok(moved)
+}
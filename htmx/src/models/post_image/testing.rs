@@ -15,6 +15,8 @@ pub fn make_create_payload(i: usize) -> PostImageCreatePayload {
         file_size: (i > 1).then(|| i as i64),
         file_hash: (i > 1).then(|| <Vec<u8> as Default>::default()),
         post_id: <PostId as Default>::default(),
+        alt_text: (i > 1).then(|| format!("Test object {i}")),
+        caption: (i > 1).then(|| format!("Test object {i}")),
     }
 }
 
@@ -31,5 +33,7 @@ pub fn make_update_payload(i: usize) -> PostImageUpdatePayload {
         file_size: Some(i as i64),
         file_hash: Some(<Vec<u8> as Default>::default()),
         post_id: <PostId as Default>::default(),
+        alt_text: Some(format!("Test object {i}")),
+        caption: Some(format!("Test object {i}")),
     }
 }
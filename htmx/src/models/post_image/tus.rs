@@ -0,0 +1,513 @@
+//! Resumable uploads of PostImages, using the tus 1.0 protocol.
+//!
+//! This supports the core protocol along with the creation, termination and expiration
+//! extensions. Each PATCH request stores its data as a separate chunk in the `image_uploads`
+//! bucket. Once every byte has arrived, the chunks are streamed through [super::storage::upload_stream],
+//! which checks the upload policy, hashes the file and creates the PostImage, and then the chunks are
+//! deleted. The PostImage has the same ID as the upload. Uploads that are not finished within a day
+//! of their last PATCH are removed by the `expire_uploads` job.
+
+use axum::{
+    body::Body,
+    extract::{OriginalUri, Path, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use error_stack::{Report, ResultExt};
+use filigree::uploads::{self, UploadInspector, UploadInspectorError};
+use futures::StreamExt;
+use tracing::{event, Level};
+
+use super::{storage, PostImageId};
+use crate::{
+    auth::Authed,
+    models::{
+        organization::OrganizationId,
+        post::{Post, PostId},
+    },
+    server::ServerState,
+    storage_quota,
+    upload_policy::UploadPolicyError,
+    Error,
+};
+
+pub const TUS_VERSION: &str = "1.0.0";
+const TUS_EXTENSIONS: &str = "creation,termination,expiration";
+const OFFSET_CONTENT_TYPE: &str = "application/offset+octet-stream";
+
+pub const TUS_RESUMABLE: HeaderName = HeaderName::from_static("tus-resumable");
+pub const TUS_VERSION_HEADER: HeaderName = HeaderName::from_static("tus-version");
+pub const TUS_EXTENSION: HeaderName = HeaderName::from_static("tus-extension");
+pub const TUS_MAX_SIZE: HeaderName = HeaderName::from_static("tus-max-size");
+pub const UPLOAD_LENGTH: HeaderName = HeaderName::from_static("upload-length");
+pub const UPLOAD_OFFSET: HeaderName = HeaderName::from_static("upload-offset");
+pub const UPLOAD_METADATA: HeaderName = HeaderName::from_static("upload-metadata");
+pub const UPLOAD_EXPIRES: HeaderName = HeaderName::from_static("upload-expires");
+
+struct PostImageUpload {
+    upload_length: i64,
+    upload_offset: i64,
+    completed_at: Option<chrono::DateTime<chrono::Utc>>,
+    expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+async fn get_upload(
+    db: &sqlx::PgPool,
+    auth: &Authed,
+    post_id: PostId,
+    id: PostImageId,
+) -> Result<PostImageUpload, Report<Error>> {
+    let upload = sqlx::query_as!(
+        PostImageUpload,
+        "SELECT upload_length, upload_offset, completed_at, expires_at
+        FROM public.post_image_uploads
+        WHERE id = $1 AND organization_id = $2 AND post_id = $3 AND expires_at > now()",
+        id.as_uuid(),
+        auth.organization_id.as_uuid(),
+        post_id.as_uuid()
+    )
+    .fetch_optional(db)
+    .await
+    .change_context(Error::Db)?
+    .ok_or(Error::NotFound("Upload"))?;
+
+    Ok(upload)
+}
+
+/// The prefix for the staged chunks of an upload
+fn chunk_prefix(organization_id: &OrganizationId, id: PostImageId) -> String {
+    format!("{organization_id}/post_image/uploads/{id}")
+}
+
+/// Delete staged chunks. Failures are only logged, since unreferenced chunks are eventually
+/// removed by the `gc_storage` job.
+async fn delete_chunks(state: &ServerState, keys: &[String]) {
+    let storage = storage::get_storage(state);
+    for key in keys {
+        if let Err(e) = storage.delete(key).await {
+            event!(Level::WARN, key, error = ?e, "Failed to delete upload chunk");
+        }
+    }
+}
+
+/// Return a 412 response if the client is using a different version of the protocol.
+fn check_version(headers: &HeaderMap) -> Option<Response> {
+    let version = headers.get(&TUS_RESUMABLE).and_then(|v| v.to_str().ok());
+    if version == Some(TUS_VERSION) {
+        return None;
+    }
+
+    Some(
+        (
+            StatusCode::PRECONDITION_FAILED,
+            [(TUS_VERSION_HEADER, HeaderValue::from_static(TUS_VERSION))],
+        )
+            .into_response(),
+    )
+}
+
+fn parse_length_header(headers: &HeaderMap, name: &HeaderName) -> Option<i64> {
+    headers
+        .get(name)?
+        .to_str()
+        .ok()?
+        .parse::<i64>()
+        .ok()
+        .filter(|n| *n >= 0)
+}
+
+/// Read the filename from the `Upload-Metadata` header. The header is a comma-separated list of
+/// keys and base64-encoded values.
+fn metadata_filename(headers: &HeaderMap) -> Result<Option<String>, Error> {
+    let Some(metadata) = headers.get(&UPLOAD_METADATA) else {
+        return Ok(None);
+    };
+
+    let invalid = || Error::InvalidUploadRequest("Invalid Upload-Metadata header");
+    let metadata = metadata.to_str().map_err(|_| invalid())?;
+    for pair in metadata.split(',') {
+        let (key, value) = pair.trim().split_once(' ').unwrap_or((pair.trim(), ""));
+        if key == "filename" || key == "name" {
+            let filename = BASE64_STANDARD
+                .decode(value.trim())
+                .ok()
+                .and_then(|v| String::from_utf8(v).ok())
+                .ok_or_else(invalid)?;
+            return Ok(Some(filename));
+        }
+    }
+
+    Ok(None)
+}
+
+fn tus_response(status: StatusCode, expires_at: Option<chrono::DateTime<chrono::Utc>>) -> Response {
+    let mut response = status.into_response();
+    let headers = response.headers_mut();
+    headers.insert(TUS_RESUMABLE, HeaderValue::from_static(TUS_VERSION));
+    if let Some(expires_at) = expires_at {
+        let expires = expires_at.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        if let Ok(expires) = HeaderValue::from_str(&expires) {
+            headers.insert(UPLOAD_EXPIRES, expires);
+        }
+    }
+
+    response
+}
+
+/// Describe the server's tus support.
+pub async fn options_upload(State(state): State<ServerState>) -> Response {
+    let mut response = tus_response(StatusCode::NO_CONTENT, None);
+    let headers = response.headers_mut();
+    headers.insert(TUS_VERSION_HEADER, HeaderValue::from_static(TUS_VERSION));
+    headers.insert(TUS_EXTENSION, HeaderValue::from_static(TUS_EXTENSIONS));
+    if let Some(max_size) = storage::get_policy(&state).max_size {
+        headers.insert(TUS_MAX_SIZE, HeaderValue::from(max_size));
+    }
+
+    response
+}
+
+/// Start a new upload.
+pub async fn create_upload(
+    State(state): State<ServerState>,
+    auth: Authed,
+    Path(post_id): Path<PostId>,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    if let Some(response) = check_version(&headers) {
+        return Ok(response);
+    }
+
+    let upload_length = parse_length_header(&headers, &UPLOAD_LENGTH)
+        .filter(|length| *length > 0)
+        .ok_or(Error::InvalidUploadRequest("Invalid Upload-Length header"))?;
+    if let Some(max_size) = storage::get_policy(&state).max_size {
+        if upload_length as u64 > max_size as u64 {
+            return Err(Report::new(UploadPolicyError::TooLarge(max_size))
+                .change_context(Error::Upload)
+                .into());
+        }
+    }
+
+    let filename = metadata_filename(&headers)?;
+
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
+
+    // Make sure that the post exists and has room for another image before accepting any data.
+    Post::get(&mut *tx, &auth, &post_id).await?;
+    let id = PostImageId::new();
+    storage::check_upload_count(&state, &auth, &mut *tx, post_id, id).await?;
+
+    let remaining =
+        storage_quota::remaining(&mut *tx, &state.storage.quota, &auth.organization_id).await?;
+    if remaining.is_some_and(|remaining| upload_length > remaining) {
+        return Err(Error::StorageQuotaExceeded);
+    }
+
+    let expires_at = sqlx::query_scalar!(
+        "INSERT INTO public.post_image_uploads
+            (id, organization_id, post_id, file_original_name, upload_length, expires_at)
+        VALUES ($1, $2, $3, $4, $5, now() + interval '24 hours')
+        RETURNING expires_at",
+        id.as_uuid(),
+        auth.organization_id.as_uuid(),
+        post_id.as_uuid(),
+        filename,
+        upload_length
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .change_context(Error::Db)?;
+
+    tx.commit().await.change_context(Error::Db)?;
+
+    let location = format!("{}/{id}", uri.path().trim_end_matches('/'));
+    let mut response = tus_response(StatusCode::CREATED, Some(expires_at));
+    if let Ok(location) = HeaderValue::from_str(&location) {
+        response.headers_mut().insert(header::LOCATION, location);
+    }
+
+    Ok(response)
+}
+
+/// Return the current offset of an upload.
+pub async fn head_upload(
+    State(state): State<ServerState>,
+    auth: Authed,
+    Path((post_id, id)): Path<(PostId, PostImageId)>,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    if let Some(response) = check_version(&headers) {
+        return Ok(response);
+    }
+
+    let upload = get_upload(&state.db, &auth, post_id, id).await?;
+
+    let mut response = tus_response(StatusCode::OK, Some(upload.expires_at));
+    let headers = response.headers_mut();
+    headers.insert(UPLOAD_OFFSET, HeaderValue::from(upload.upload_offset));
+    headers.insert(UPLOAD_LENGTH, HeaderValue::from(upload.upload_length));
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    Ok(response)
+}
+
+/// Append data to an upload, and create the PostImage once the upload is complete.
+pub async fn patch_upload(
+    State(state): State<ServerState>,
+    auth: Authed,
+    Path((post_id, id)): Path<(PostId, PostImageId)>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response, Error> {
+    if let Some(response) = check_version(&headers) {
+        return Ok(response);
+    }
+
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+    if content_type != Some(OFFSET_CONTENT_TYPE) {
+        return Err(Error::UnsupportedMediaType);
+    }
+
+    let offset = parse_length_header(&headers, &UPLOAD_OFFSET)
+        .ok_or(Error::InvalidUploadRequest("Invalid Upload-Offset header"))?;
+
+    let upload = get_upload(&state.db, &auth, post_id, id).await?;
+    if upload.completed_at.is_some() || offset != upload.upload_offset {
+        return Err(Error::UploadOffsetMismatch);
+    }
+
+    // Each chunk gets a unique key, so that concurrent requests for the same offset can't
+    // overwrite each other's data.
+    let chunk_key = format!(
+        "{}/{offset:020}-{}",
+        chunk_prefix(&auth.organization_id, id),
+        uuid::Uuid::new_v4()
+    );
+    let storage = storage::get_storage(&state);
+    let mut size = uploads::UploadSize::new(Some((upload.upload_length - offset) as usize));
+    storage
+        .save_and_inspect_request_body(&chunk_key, body.into_data_stream(), |chunk| {
+            size.inspect(chunk)?;
+            Ok::<(), UploadInspectorError>(())
+        })
+        .await
+        .change_context(Error::Upload)?;
+
+    let written = size.finish() as i64;
+    if written == 0 {
+        storage.delete(&chunk_key).await.ok();
+        if offset == upload.upload_length {
+            // All the data has arrived, but finishing the upload failed before. Try again.
+            complete_upload(&state, &auth, post_id, id).await?;
+        }
+
+        let mut response = tus_response(StatusCode::NO_CONTENT, Some(upload.expires_at));
+        response
+            .headers_mut()
+            .insert(UPLOAD_OFFSET, HeaderValue::from(offset));
+        return Ok(response);
+    }
+
+    let updated = sqlx::query!(
+        "UPDATE public.post_image_uploads
+        SET upload_offset = upload_offset + $4,
+            chunk_keys = array_append(chunk_keys, $5),
+            updated_at = now(),
+            expires_at = now() + interval '24 hours'
+        WHERE id = $1 AND organization_id = $2 AND upload_offset = $3 AND completed_at IS NULL
+        RETURNING upload_offset, expires_at",
+        id.as_uuid(),
+        auth.organization_id.as_uuid(),
+        offset,
+        written,
+        chunk_key
+    )
+    .fetch_optional(&state.db)
+    .await
+    .change_context(Error::Db)?;
+
+    let Some(updated) = updated else {
+        // Another request appended data at this offset first.
+        storage.delete(&chunk_key).await.ok();
+        return Err(Error::UploadOffsetMismatch);
+    };
+
+    if updated.upload_offset == upload.upload_length {
+        complete_upload(&state, &auth, post_id, id).await?;
+    }
+
+    let mut response = tus_response(StatusCode::NO_CONTENT, Some(updated.expires_at));
+    response
+        .headers_mut()
+        .insert(UPLOAD_OFFSET, HeaderValue::from(updated.upload_offset));
+    Ok(response)
+}
+
+/// Turn the staged chunks of a finished upload into a PostImage.
+async fn complete_upload(
+    state: &ServerState,
+    auth: &Authed,
+    post_id: PostId,
+    id: PostImageId,
+) -> Result<(), Report<Error>> {
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
+
+    // Lock the upload so that only one request can finish it.
+    let Some(upload) = sqlx::query!(
+        "SELECT file_original_name, chunk_keys FROM public.post_image_uploads
+        WHERE id = $1 AND organization_id = $2 AND completed_at IS NULL
+        FOR UPDATE",
+        id.as_uuid(),
+        auth.organization_id.as_uuid()
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .change_context(Error::Db)?
+    else {
+        return Err(Report::new(Error::UploadOffsetMismatch));
+    };
+
+    // Mark the upload as complete first, so that its staged chunks aren't counted against the
+    // storage quota along with the finished file.
+    sqlx::query!(
+        "UPDATE public.post_image_uploads SET completed_at = now(), updated_at = now()
+        WHERE id = $1",
+        id.as_uuid()
+    )
+    .execute(&mut *tx)
+    .await
+    .change_context(Error::Db)?;
+
+    let stream_state = state.clone();
+    let body = futures::stream::iter(upload.chunk_keys.clone()).then(move |key| {
+        let state = stream_state.clone();
+        async move {
+            let data = storage::get_storage(&state)
+                .get(&key)
+                .await
+                .map_err(chunk_read_error)?;
+            data.bytes().await.map_err(chunk_read_error)
+        }
+    });
+
+    let result = storage::upload_stream(
+        state,
+        auth,
+        &mut *tx,
+        post_id,
+        Some(id),
+        upload.file_original_name,
+        None,
+        None,
+        Box::pin(body),
+    )
+    .await;
+
+    let image = match result {
+        Ok(image) => image,
+        Err(e) if !is_rejection(&e) => {
+            // Rolling back clears `completed_at` and keeps the chunks, so the client can try to
+            // finish the upload again.
+            return Err(e);
+        }
+        Err(e) => {
+            // The file was rejected, so resuming the upload won't help.
+            drop(tx);
+            sqlx::query!(
+                "DELETE FROM public.post_image_uploads WHERE id = $1 AND organization_id = $2",
+                id.as_uuid(),
+                auth.organization_id.as_uuid()
+            )
+            .execute(&state.db)
+            .await
+            .change_context(Error::Db)?;
+            delete_chunks(state, &upload.chunk_keys).await;
+            return Err(e);
+        }
+    };
+
+    sqlx::query!(
+        "UPDATE public.post_image_uploads
+        SET chunk_keys = '{}'
+        WHERE id = $1",
+        id.as_uuid()
+    )
+    .execute(&mut *tx)
+    .await
+    .change_context(Error::Db)?;
+    crate::server::etag::touch(&mut *tx, "posts", post_id.as_uuid(), &auth.organization_id).await?;
+
+    tx.commit().await.change_context(Error::Db)?;
+
+    delete_chunks(state, &upload.chunk_keys).await;
+    super::renditions::enqueue(state, &image).await;
+
+    Ok(())
+}
+
+/// Return true if the finished file was rejected by the upload policy or the storage quota, as
+/// opposed to failing for a reason that might not happen again.
+fn is_rejection(report: &Report<Error>) -> bool {
+    report.frames().any(|frame| {
+        frame.downcast_ref::<UploadPolicyError>().is_some()
+            || matches!(
+                frame.downcast_ref::<Error>(),
+                Some(Error::StorageQuotaExceeded)
+            )
+    })
+}
+
+fn chunk_read_error(e: impl std::fmt::Debug) -> axum::Error {
+    axum::Error::new(std::io::Error::other(format!(
+        "Failed to read upload chunk: {e:?}"
+    )))
+}
+
+/// Cancel an upload and delete its data.
+pub async fn delete_upload(
+    State(state): State<ServerState>,
+    auth: Authed,
+    Path((post_id, id)): Path<(PostId, PostImageId)>,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    if let Some(response) = check_version(&headers) {
+        return Ok(response);
+    }
+
+    let chunk_keys = sqlx::query_scalar!(
+        "DELETE FROM public.post_image_uploads
+        WHERE id = $1 AND organization_id = $2 AND post_id = $3
+        RETURNING chunk_keys",
+        id.as_uuid(),
+        auth.organization_id.as_uuid(),
+        post_id.as_uuid()
+    )
+    .fetch_optional(&state.db)
+    .await
+    .change_context(Error::Db)?
+    .ok_or(Error::NotFound("Upload"))?;
+
+    delete_chunks(&state, &chunk_keys).await;
+
+    Ok(tus_response(StatusCode::NO_CONTENT, None))
+}
+
+/// Remove expired uploads and their staged chunks, returning the number of uploads removed.
+pub async fn delete_expired(state: &ServerState) -> Result<usize, Report<Error>> {
+    let expired = sqlx::query_scalar!(
+        "DELETE FROM public.post_image_uploads WHERE expires_at <= now() RETURNING chunk_keys"
+    )
+    .fetch_all(&state.db)
+    .await
+    .change_context(Error::Db)?;
+
+    for chunk_keys in &expired {
+        delete_chunks(state, chunk_keys).await;
+    }
+
+    Ok(expired.len())
+}
@@ -21,6 +21,20 @@ pub struct PostImage {
     pub file_size: Option<i64>,
     pub file_hash: Option<Vec<u8>>,
     pub post_id: PostId,
+    /// The width of the original image, set once its renditions have been generated
+    pub width: Option<i32>,
+    /// The height of the original image, set once its renditions have been generated
+    pub height: Option<i32>,
+    pub renditions: PostImageRenditions,
+    /// A description of the image for screen readers
+    pub alt_text: Option<String>,
+    /// A caption to show with the image
+    pub caption: Option<String>,
+    /// The order of the image among the post's images, starting from 0
+    pub position: i32,
+    /// A signed, expiring URL for the file, present if the user can read the post
+    #[serde(default)]
+    pub url: Option<String>,
 }
 
 pub type PostImageListResult = PostImage;
@@ -74,6 +88,34 @@ impl PostImage {
     pub fn default_post_id() -> PostId {
         <PostId as Default>::default().into()
     }
+
+    pub fn default_width() -> Option<i32> {
+        None
+    }
+
+    pub fn default_height() -> Option<i32> {
+        None
+    }
+
+    pub fn default_renditions() -> PostImageRenditions {
+        <PostImageRenditions as Default>::default().into()
+    }
+
+    pub fn default_alt_text() -> Option<String> {
+        None
+    }
+
+    pub fn default_caption() -> Option<String> {
+        None
+    }
+
+    pub fn default_position() -> i32 {
+        <i32 as Default>::default().into()
+    }
+
+    pub fn default_url() -> Option<String> {
+        None
+    }
 }
 
 sqlx_json_decode!(PostImage);
@@ -91,10 +133,53 @@ impl Default for PostImage {
             file_size: Self::default_file_size(),
             file_hash: Self::default_file_hash(),
             post_id: Self::default_post_id(),
+            width: Self::default_width(),
+            height: Self::default_height(),
+            renditions: Self::default_renditions(),
+            alt_text: Self::default_alt_text(),
+            caption: Self::default_caption(),
+            position: Self::default_position(),
+            url: Self::default_url(),
         }
     }
 }
 
+/// A resized copy of a PostImage, published to the `image_hosting` bucket.
+#[derive(Deserialize, Debug, Clone, schemars::JsonSchema, Serialize)]
+pub struct PostImageRendition {
+    /// The name of the rendition, such as "thumb"
+    pub name: String,
+    pub content_type: String,
+    pub width: u32,
+    pub height: u32,
+    pub file_size: i64,
+    pub file_storage_key: String,
+    /// The public URL of the rendition, if the `image_hosting` bucket has one.
+    pub url: Option<String>,
+}
+
+/// The renditions of a PostImage, stored as a JSON array.
+#[derive(Deserialize, Debug, Clone, Default, schemars::JsonSchema, Serialize)]
+#[serde(transparent)]
+pub struct PostImageRenditions(pub Vec<PostImageRendition>);
+
+sqlx_json_decode!(PostImageRenditions);
+
+/// Whether a file with a given hash has already been uploaded
+#[derive(Deserialize, Debug, Clone, schemars::JsonSchema, Serialize)]
+pub struct PostImageHashStatus {
+    pub exists: bool,
+    /// The size of the existing file, in bytes
+    pub file_size: Option<i64>,
+}
+
+/// A new order for a post's images
+#[derive(Deserialize, Debug, Clone, schemars::JsonSchema, Serialize)]
+pub struct PostImageOrderPayload {
+    /// The IDs of all the post's images, in their new order
+    pub ids: Vec<PostImageId>,
+}
+
 #[derive(Deserialize, Debug, Clone, schemars::JsonSchema, sqlx::FromRow)]
 #[cfg_attr(test, derive(Serialize))]
 pub struct PostImageCreatePayloadAndUpdatePayload {
@@ -105,6 +190,10 @@ pub struct PostImageCreatePayloadAndUpdatePayload {
     pub file_size: Option<i64>,
     pub file_hash: Option<Vec<u8>>,
     pub post_id: PostId,
+    /// A description of the image for screen readers
+    pub alt_text: Option<String>,
+    /// A caption to show with the image
+    pub caption: Option<String>,
 }
 
 pub type PostImageCreatePayload = PostImageCreatePayloadAndUpdatePayload;
@@ -142,6 +231,14 @@ impl PostImageCreatePayloadAndUpdatePayload {
     pub fn default_post_id() -> PostId {
         <PostId as Default>::default().into()
     }
+
+    pub fn default_alt_text() -> Option<String> {
+        None
+    }
+
+    pub fn default_caption() -> Option<String> {
+        None
+    }
 }
 
 impl Default for PostImageCreatePayloadAndUpdatePayload {
@@ -154,6 +251,8 @@ impl Default for PostImageCreatePayloadAndUpdatePayload {
             file_size: Self::default_file_size(),
             file_hash: Self::default_file_hash(),
             post_id: Self::default_post_id(),
+            alt_text: Self::default_alt_text(),
+            caption: Self::default_caption(),
         }
     }
 }
@@ -7,7 +7,9 @@ SET
   file_size = $4,
   file_hash = $5,
   post_id = $6,
+  alt_text = $7,
+  caption = $8,
   updated_at = NOW()
 WHERE
-  id = $7
-  AND organization_id = $8
+  id = $9
+  AND organization_id = $10
@@ -7,8 +7,10 @@ SET
   file_size = $4,
   file_hash = $5,
   post_id = $6,
+  alt_text = $7,
+  caption = $8,
   updated_at = NOW()
 WHERE
-  id = $7
-  AND post_id = $8
-  AND organization_id = $9
+  id = $9
+  AND post_id = $10
+  AND organization_id = $11
@@ -0,0 +1,30 @@
+UPDATE
+  public.post_images
+SET
+  position = new_order.position - 1,
+  updated_at = now()
+FROM
+  UNNEST($3::uuid[])
+  WITH ORDINALITY AS new_order (id, position)
+WHERE
+  post_images.id = new_order.id
+  AND post_images.organization_id = $1
+  AND post_images.post_id = $2
+RETURNING
+  post_images.id AS "id: PostImageId",
+  post_images.organization_id AS "organization_id: crate::models::organization::OrganizationId",
+  post_images.updated_at,
+  post_images.created_at,
+  post_images.file_storage_key,
+  post_images.file_storage_bucket,
+  post_images.file_original_name,
+  post_images.file_size,
+  post_images.file_hash,
+  post_images.post_id AS "post_id: PostId",
+  post_images.width,
+  post_images.height,
+  post_images.renditions AS "renditions: PostImageRenditions",
+  post_images.alt_text,
+  post_images.caption,
+  post_images.position,
+  NULL::text AS "url"
@@ -6,7 +6,10 @@ INSERT INTO public.post_images (
   file_original_name,
   file_size,
   file_hash,
-  post_id)
+  post_id,
+  alt_text,
+  caption,
+  position)
 VALUES
   __insertion_point_insert_values
 ON CONFLICT (
@@ -18,6 +21,9 @@ ON CONFLICT (
     file_size = EXCLUDED.file_size,
     file_hash = EXCLUDED.file_hash,
     post_id = EXCLUDED.post_id,
+    alt_text = EXCLUDED.alt_text,
+    caption = EXCLUDED.caption,
+    position = EXCLUDED.position,
     updated_at = now()
   WHERE
     post_images.organization_id = $1
@@ -32,4 +38,11 @@ ON CONFLICT (
     file_original_name,
     file_size,
     file_hash,
-    post_id
+    post_id,
+    width,
+    height,
+    renditions,
+    alt_text,
+    caption,
+    position,
+    NULL::text AS url
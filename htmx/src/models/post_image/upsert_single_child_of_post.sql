@@ -6,7 +6,10 @@ INSERT INTO public.post_images (
   file_original_name,
   file_size,
   file_hash,
-  post_id)
+  post_id,
+  alt_text,
+  caption,
+  position)
 VALUES (
   $1,
   $2,
@@ -15,7 +18,16 @@ VALUES (
   $5,
   $6,
   $7,
-  $8)
+  $8,
+  $9,
+  $10,
+  (
+    SELECT
+      COALESCE(MAX(position) + 1, 0)
+    FROM
+      public.post_images
+    WHERE
+      post_id = $8))
 ON CONFLICT (
   id)
   DO UPDATE SET
@@ -25,10 +37,12 @@ ON CONFLICT (
     file_size = EXCLUDED.file_size,
     file_hash = EXCLUDED.file_hash,
     post_id = EXCLUDED.post_id,
+    alt_text = EXCLUDED.alt_text,
+    caption = EXCLUDED.caption,
     updated_at = now()
   WHERE
     post_images.organization_id = $2
-    AND post_images.post_id = $9
+    AND post_images.post_id = $11
   RETURNING
     id AS "id: PostImageId",
     organization_id AS "organization_id: crate::models::organization::OrganizationId",
@@ -39,4 +53,11 @@ ON CONFLICT (
     file_original_name,
     file_size,
     file_hash,
-    post_id AS "post_id: PostId"
+    post_id AS "post_id: PostId",
+    width,
+    height,
+    renditions AS "renditions: PostImageRenditions",
+    alt_text,
+    caption,
+    position,
+    NULL::text AS "url"
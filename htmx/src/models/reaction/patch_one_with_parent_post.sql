@@ -0,0 +1,9 @@
+UPDATE
+  public.reactions
+SET
+  type = COALESCE($1, type),
+  updated_at = NOW()
+WHERE
+  id = $2
+  AND post_id = $3
+  AND organization_id = $4
@@ -113,3 +113,12 @@ impl Default for ReactionCreatePayloadAndUpdatePayload {
         }
     }
 }
+
+/// A JSON Merge Patch for a Reaction. Fields that are omitted from the patch are left unchanged.
+#[derive(Deserialize, Debug, Clone, schemars::JsonSchema)]
+pub struct ReactionPatchPayload {
+    #[serde(rename = "type")]
+    #[serde(default, deserialize_with = "crate::models::merge_patch::required")]
+    #[schemars(with = "String")]
+    pub typ: Option<String>,
+}
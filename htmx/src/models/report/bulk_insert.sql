@@ -0,0 +1,13 @@
+INSERT INTO public.reports (
+  id,
+  organization_id,
+  title,
+  description,
+  ui)
+VALUES
+  __insertion_point_insert_values
+ON CONFLICT (
+  id)
+  DO NOTHING
+RETURNING
+  id
@@ -0,0 +1,7 @@
+SELECT
+  1
+FROM
+  public.reports tb
+WHERE
+  organization_id = $1
+  AND __insertion_point_filters
@@ -2,10 +2,9 @@
 use std::{borrow::Cow, str::FromStr};
 
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    extract::{OriginalUri, Path, State},
+    http::{HeaderMap, Method, StatusCode},
     response::IntoResponse,
-    routing,
 };
 use axum_extra::extract::Query;
 use axum_jsonschema::Json;
@@ -21,12 +20,23 @@ use super::{
     WRITE_PERMISSION,
 };
 use crate::{
-    auth::{has_any_permission, Authed},
-    models::report_section::{
-        ReportSection, ReportSectionCreatePayload, ReportSectionCreateResult, ReportSectionId,
-        ReportSectionUpdatePayload,
+    auth::Authed,
+    models::{
+        bulk::{self, BulkRequest, BulkResponse},
+        export::ExportFormat,
+        filters,
+        merge_patch::MergePatch,
+        pagination,
+        report_section::{
+            ReportSection, ReportSectionCreatePayload, ReportSectionCreateResult, ReportSectionId,
+            ReportSectionUpdatePayload,
+        },
+    },
+    server::{
+        etag::{self, etag_header, IfMatch},
+        openapi::{child_model_routes, model_routes, ApiRoute, ApiRouter},
+        ServerState,
     },
-    server::ServerState,
     Error,
 };
 
@@ -37,17 +47,38 @@ async fn get(
 ) -> Result<impl IntoResponse, Error> {
     let object = Report::get_populated(&state.db, &auth, &id).await?;
 
-    Ok(Json(object))
+    Ok((etag_header(&object.updated_at), Json(object)))
 }
 
 async fn list(
     State(state): State<ServerState>,
     auth: Authed,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
     Query(qs): Query<queries::ListQueryFilters>,
 ) -> Result<impl IntoResponse, Error> {
+    filters::validate_json_path(&state.db, qs.ui_path.as_deref()).await?;
+
+    if let Some(format) = ExportFormat::from_headers(&headers) {
+        return Ok(Report::export(&state.db, auth, qs, format)?);
+    }
+
     let results = Report::list_populated(&state.db, &auth, &qs).await?;
 
-    Ok(Json(results))
+    let total = if qs.include_total.unwrap_or(false) {
+        let mut conn = state.db.acquire().await.change_context(Error::Db)?;
+        Some(Report::count(&mut conn, &auth, &qs).await?)
+    } else {
+        None
+    };
+
+    Ok(pagination::list_response(
+        &uri,
+        qs.page,
+        qs.per_page,
+        results,
+        total,
+    ))
 }
 
 async fn create(
@@ -62,14 +93,30 @@ async fn create(
     Ok((StatusCode::CREATED, Json(result)))
 }
 
+async fn bulk_operations(
+    State(state): State<ServerState>,
+    auth: Authed,
+    axum::Json(request): axum::Json<
+        BulkRequest<ReportId, ReportCreatePayload, ReportUpdatePayload>,
+    >,
+) -> Result<impl IntoResponse, Error> {
+    let result = bulk::run::<Report>(&state, &auth, request).await?;
+
+    Ok(Json(result))
+}
+
 async fn update(
     State(state): State<ServerState>,
     auth: Authed,
     Path(id): Path<ReportId>,
+    if_match: IfMatch,
     FormOrJson(payload): FormOrJson<ReportUpdatePayload>,
 ) -> Result<impl IntoResponse, Error> {
     let mut tx = state.db.begin().await.change_context(Error::Db)?;
 
+    if_match
+        .lock_and_check(&mut *tx, "reports", id.as_uuid(), &auth.organization_id)
+        .await?;
     let result = Report::update(&mut *tx, &auth, &id, payload).await?;
 
     tx.commit().await.change_context(Error::Db)?;
@@ -81,6 +128,29 @@ async fn update(
     }
 }
 
+async fn patch(
+    State(state): State<ServerState>,
+    auth: Authed,
+    Path(id): Path<ReportId>,
+    if_match: IfMatch,
+    MergePatch(payload): MergePatch<ReportPatchPayload>,
+) -> Result<impl IntoResponse, Error> {
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
+
+    if_match
+        .lock_and_check(&mut *tx, "reports", id.as_uuid(), &auth.organization_id)
+        .await?;
+    let result = Report::patch(&mut *tx, &auth, &id, payload).await?;
+
+    tx.commit().await.change_context(Error::Db)?;
+
+    if result {
+        Ok(StatusCode::OK)
+    } else {
+        Ok(StatusCode::NOT_FOUND)
+    }
+}
+
 async fn delete(
     State(state): State<ServerState>,
     auth: Authed,
@@ -94,6 +164,8 @@ async fn delete(
         return Ok(StatusCode::NOT_FOUND);
     }
 
+    crate::users::favorites::remove_object(&mut *tx, &auth.organization_id, id.as_uuid()).await?;
+
     tx.commit().await.change_context(Error::Db)?;
 
     Ok(StatusCode::OK)
@@ -106,6 +178,7 @@ async fn list_child_report_section(
     Query(mut qs): Query<crate::models::report_section::queries::ListQueryFilters>,
 ) -> Result<impl IntoResponse, Error> {
     qs.report_id = vec![parent_id];
+    filters::validate_json_path(&state.db, qs.options_path.as_deref()).await?;
 
     let object = crate::models::report_section::ReportSection::list(&state.db, &auth, &qs).await?;
 
@@ -123,7 +196,7 @@ async fn get_child_report_section(
         return Err(Error::NotFound("Parent Report"));
     }
 
-    Ok(Json(object))
+    Ok((etag_header(&object.updated_at), Json(object)))
 }
 
 async fn create_child_report_section(
@@ -138,6 +211,13 @@ async fn create_child_report_section(
 
     let result =
         crate::models::report_section::ReportSection::create(&mut *tx, &auth, payload).await?;
+    etag::touch(
+        &mut *tx,
+        "reports",
+        parent_id.as_uuid(),
+        &auth.organization_id,
+    )
+    .await?;
 
     tx.commit().await.change_context(Error::Db)?;
 
@@ -148,6 +228,7 @@ async fn update_child_report_section(
     State(state): State<ServerState>,
     auth: Authed,
     Path((parent_id, child_id)): Path<(ReportId, ReportSectionId)>,
+    if_match: IfMatch,
     FormOrJson(mut payload): FormOrJson<ReportSectionUpdatePayload>,
 ) -> Result<impl IntoResponse, Error> {
     payload.id = Some(child_id);
@@ -159,10 +240,73 @@ async fn update_child_report_section(
 
     object_perm.must_be_writable(WRITE_PERMISSION)?;
 
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
+    if_match
+        .lock_and_check(
+            &mut *tx,
+            "report_sections",
+            child_id.as_uuid(),
+            &auth.organization_id,
+        )
+        .await?;
+
     let result = crate::models::report_section::ReportSection::update_one_with_parent_report(
-        &state.db, &auth, &parent_id, &child_id, payload,
+        &mut *tx, &auth, &parent_id, &child_id, payload,
     )
     .await?;
+    if result {
+        etag::touch(
+            &mut *tx,
+            "reports",
+            parent_id.as_uuid(),
+            &auth.organization_id,
+        )
+        .await?;
+    }
+
+    tx.commit().await.change_context(Error::Db)?;
+
+    Ok(Json(result))
+}
+
+async fn patch_child_report_section(
+    State(state): State<ServerState>,
+    auth: Authed,
+    Path((parent_id, child_id)): Path<(ReportId, ReportSectionId)>,
+    if_match: IfMatch,
+    MergePatch(payload): MergePatch<crate::models::report_section::ReportSectionPatchPayload>,
+) -> Result<impl IntoResponse, Error> {
+    let object_perm = Report::lookup_object_permissions(&state.db, &auth, &parent_id)
+        .await?
+        .unwrap_or(ObjectPermission::Read);
+
+    object_perm.must_be_writable(WRITE_PERMISSION)?;
+
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
+    if_match
+        .lock_and_check(
+            &mut *tx,
+            "report_sections",
+            child_id.as_uuid(),
+            &auth.organization_id,
+        )
+        .await?;
+
+    let result = crate::models::report_section::ReportSection::patch_one_with_parent_report(
+        &mut *tx, &auth, &parent_id, &child_id, payload,
+    )
+    .await?;
+    if result {
+        etag::touch(
+            &mut *tx,
+            "reports",
+            parent_id.as_uuid(),
+            &auth.organization_id,
+        )
+        .await?;
+    }
+
+    tx.commit().await.change_context(Error::Db)?;
 
     Ok(Json(result))
 }
@@ -172,10 +316,21 @@ async fn delete_child_report_section(
     auth: Authed,
     Path((parent_id, child_id)): Path<(ReportId, ReportSectionId)>,
 ) -> Result<impl IntoResponse, Error> {
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
     let deleted = crate::models::report_section::ReportSection::delete_with_parent_report(
-        &state.db, &auth, &parent_id, &child_id,
+        &mut *tx, &auth, &parent_id, &child_id,
     )
     .await?;
+    if deleted {
+        etag::touch(
+            &mut *tx,
+            "reports",
+            parent_id.as_uuid(),
+            &auth.organization_id,
+        )
+        .await?;
+    }
+    tx.commit().await.change_context(Error::Db)?;
 
     if deleted {
         Ok(StatusCode::OK)
@@ -184,62 +339,82 @@ async fn delete_child_report_section(
     }
 }
 
-pub fn create_routes() -> axum::Router<ServerState> {
-    axum::Router::new()
-        .route(
-            "/reports",
-            routing::get(list).route_layer(has_any_permission(vec![READ_PERMISSION, "org_admin"])),
-        )
-        .route(
-            "/reports/:id",
-            routing::get(get).route_layer(has_any_permission(vec![READ_PERMISSION, "org_admin"])),
-        )
-        .route(
-            "/reports",
-            routing::post(create)
-                .route_layer(has_any_permission(vec![CREATE_PERMISSION, "org_admin"])),
-        )
-        .route(
-            "/reports/:id",
-            routing::put(update).route_layer(has_any_permission(vec![
-                WRITE_PERMISSION,
-                OWNER_PERMISSION,
-                "org_admin",
-            ])),
-        )
-        .route(
-            "/reports/:id",
-            routing::delete(delete)
-                .route_layer(has_any_permission(vec![CREATE_PERMISSION, "org_admin"])),
-        )
-        .route(
-            "/reports/:id/report_sections",
-            routing::get(list_child_report_section)
-                .route_layer(has_any_permission(vec![READ_PERMISSION, "org_admin"])),
-        )
-        .route(
-            "/reports/:id/report_sections",
-            routing::post(create_child_report_section)
-                .route_layer(has_any_permission(vec![CREATE_PERMISSION, "org_admin"])),
-        )
-        .route(
-            "/reports/:id/report_sections/:child_id",
-            routing::get(get_child_report_section)
-                .route_layer(has_any_permission(vec![READ_PERMISSION, "org_admin"])),
-        )
-        .route(
-            "/reports/:id/report_sections/:child_id",
-            routing::put(update_child_report_section).route_layer(has_any_permission(vec![
-                WRITE_PERMISSION,
-                OWNER_PERMISSION,
-                "org_admin",
-            ])),
-        )
+fn routes() -> ApiRouter {
+    use crate::models::report_section;
+
+    let read_perms = &[READ_PERMISSION, "org_admin"];
+    let write_perms = &[WRITE_PERMISSION, OWNER_PERMISSION, "org_admin"];
+    let create_perms = &[CREATE_PERMISSION, "org_admin"];
+
+    let model = model_routes::<
+        ReportId,
+        ReportPopulatedGetResult,
+        ReportPopulatedListResult,
+        queries::ListQueryFilters,
+        ReportCreatePayload,
+        ReportCreateResult,
+        ReportUpdatePayload,
+        ReportPatchPayload,
+    >(
+        "report",
+        "/reports",
+        "/reports/:id",
+        read_perms,
+        write_perms,
+        create_perms,
+    );
+
+    let sections = child_model_routes::<
+        ReportId,
+        ReportSectionId,
+        ReportSection,
+        report_section::queries::ListQueryFilters,
+        ReportSectionCreatePayload,
+        ReportSectionCreateResult,
+        ReportSectionUpdatePayload,
+        report_section::ReportSectionPatchPayload,
+    >(
+        "report_section",
+        "/reports/:id/report_sections",
+        "/reports/:id/report_sections/:child_id",
+        read_perms,
+        write_perms,
+        create_perms,
+    );
+
+    ApiRouter::new()
+        .route(model.list, list)
+        .route(model.get, get)
+        .route(model.create, create)
+        .route(model.update, update)
+        .route(model.patch, patch)
+        .route(model.delete, delete)
         .route(
-            "/reports/:id/report_sections/:child_id",
-            routing::delete(delete_child_report_section)
-                .route_layer(has_any_permission(vec![CREATE_PERMISSION, "org_admin"])),
+            ApiRoute::new(
+                Method::POST,
+                "/reports/bulk",
+                "report",
+                "Create, update and delete reports in one transaction",
+            )
+            .permissions(&[CREATE_PERMISSION, WRITE_PERMISSION, "org_admin"])
+            .request::<BulkRequest<ReportId, ReportCreatePayload, ReportUpdatePayload>>()
+            .response::<BulkResponse<ReportId>>(),
+            bulk_operations,
         )
+        .route(sections.list, list_child_report_section)
+        .route(sections.create, create_child_report_section)
+        .route(sections.get, get_child_report_section)
+        .route(sections.update, update_child_report_section)
+        .route(sections.patch, patch_child_report_section)
+        .route(sections.delete, delete_child_report_section)
+}
+
+pub fn create_routes() -> axum::Router<ServerState> {
+    routes().into_router()
+}
+
+pub fn api_docs() -> Vec<ApiRoute> {
+    routes().into_docs()
 }
 
 #[cfg(test)]
@@ -392,12 +567,217 @@ mod test {
     async fn list_order_by(_pool: sqlx::PgPool) {}
 
     #[sqlx::test]
-    #[ignore = "todo"]
-    async fn list_paginated(_pool: sqlx::PgPool) {}
+    async fn list_paginated(pool: sqlx::PgPool) {
+        let (
+            _app,
+            BootstrappedData {
+                organization, user, ..
+            },
+        ) = start_app(pool.clone()).await;
+
+        setup_test_objects(&pool, organization.id, 3).await;
+
+        let response = user
+            .client
+            .get("reports")
+            .query(&[("per_page", "2"), ("include_total", "true")])
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap();
+
+        let link = response.headers()[reqwest::header::LINK]
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(link.contains("page=1&per_page=2>; rel=\"next\""), "{link}");
+        assert!(link.contains("page=1&per_page=2>; rel=\"last\""), "{link}");
+        assert!(!link.contains("rel=\"prev\""), "{link}");
+
+        let body = response.json::<serde_json::Value>().await.unwrap();
+        assert_eq!(body["items"].as_array().unwrap().len(), 2);
+        assert_eq!(body["total"], 3);
+        assert_eq!(body["total_is_estimate"], false);
+        assert_eq!(body["page"], 0);
+        assert_eq!(body["per_page"], 2);
+        assert_eq!(body["has_more"], true);
+
+        let body = user
+            .client
+            .get("reports")
+            .query(&[("per_page", "2"), ("page", "1"), ("include_total", "true")])
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json::<serde_json::Value>()
+            .await
+            .unwrap();
+        assert_eq!(body["items"].as_array().unwrap().len(), 1);
+        assert_eq!(body["total"], 3);
+        assert_eq!(body["has_more"], false);
+
+        // Without the flag, the response is still a plain array.
+        let results = user
+            .client
+            .get("reports")
+            .query(&[("per_page", "2")])
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json::<Vec<serde_json::Value>>()
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 2);
+    }
 
     #[sqlx::test]
-    #[ignore = "todo"]
-    async fn list_filters(_pool: sqlx::PgPool) {}
+    async fn list_filters(pool: sqlx::PgPool) {
+        let (
+            _app,
+            BootstrappedData {
+                organization, user, ..
+            },
+        ) = start_app(pool.clone()).await;
+
+        let added_objects = setup_test_objects(&pool, organization.id, 3).await;
+
+        let fetch_indexes = |query: Vec<(&'static str, &'static str)>| {
+            let client = &user.client;
+            let added_objects = &added_objects;
+            async move {
+                let results = client
+                    .get("reports")
+                    .query(&query)
+                    .send()
+                    .await
+                    .unwrap()
+                    .log_error()
+                    .await
+                    .unwrap()
+                    .json::<Vec<serde_json::Value>>()
+                    .await
+                    .unwrap();
+
+                let mut indexes = results
+                    .iter()
+                    .map(|o| {
+                        added_objects
+                            .iter()
+                            .position(|(_, added)| o["id"] == added.id.to_string())
+                            .expect("Result should be one of the added objects")
+                    })
+                    .collect::<Vec<_>>();
+                indexes.sort();
+                indexes
+            }
+        };
+
+        assert_eq!(
+            fetch_indexes(vec![("title", "Test object 0"), ("title", "Test object 2")]).await,
+            vec![0, 2],
+            "title in list"
+        );
+        assert_eq!(
+            fetch_indexes(vec![("title_icontains", "OBJECT 1")]).await,
+            vec![1],
+            "title_icontains"
+        );
+        assert_eq!(
+            fetch_indexes(vec![("title_contains", "OBJECT 1")]).await,
+            Vec::<usize>::new(),
+            "title_contains is case sensitive"
+        );
+        assert_eq!(
+            fetch_indexes(vec![("description_is_null", "true")]).await,
+            vec![0, 1],
+            "description_is_null"
+        );
+        assert_eq!(
+            fetch_indexes(vec![("ui_path", "$.key ? (@ >= 1)")]).await,
+            vec![1, 2],
+            "ui_path"
+        );
+        assert_eq!(
+            fetch_indexes(vec![("has_sections", "false")]).await,
+            vec![0],
+            "has_sections"
+        );
+        assert_eq!(
+            fetch_indexes(vec![("section_viz", "Test object 3")]).await,
+            vec![2],
+            "section_viz"
+        );
+        assert_eq!(
+            fetch_indexes(vec![
+                ("has_sections", "true"),
+                ("description_is_null", "true")
+            ])
+            .await,
+            vec![1],
+            "combined filters"
+        );
+
+        let response = user
+            .client
+            .get("reports")
+            .query(&[("ui_path", "$.key ? (")])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"]["kind"], "invalid_filter");
+
+        let fetch_section_viz = |query: Vec<(&'static str, &'static str)>| {
+            let client = &user.client;
+            let url = format!("reports/{}/report_sections", added_objects[2].1.id);
+            async move {
+                let results = client
+                    .get(&url)
+                    .query(&query)
+                    .send()
+                    .await
+                    .unwrap()
+                    .log_error()
+                    .await
+                    .unwrap()
+                    .json::<Vec<serde_json::Value>>()
+                    .await
+                    .unwrap();
+
+                let mut viz = results
+                    .iter()
+                    .map(|o| o["viz"].as_str().unwrap().to_string())
+                    .collect::<Vec<_>>();
+                viz.sort();
+                viz
+            }
+        };
+
+        assert_eq!(
+            fetch_section_viz(vec![("viz_contains", "object 3")]).await,
+            vec!["Test object 3"],
+            "viz_contains"
+        );
+        assert_eq!(
+            fetch_section_viz(vec![("viz_contains", "TEST")]).await,
+            Vec::<String>::new(),
+            "viz_contains is case sensitive"
+        );
+        assert_eq!(
+            fetch_section_viz(vec![("viz_icontains", "TEST")]).await,
+            vec!["Test object 2", "Test object 3"],
+            "viz_icontains"
+        );
+    }
 
     #[sqlx::test]
     async fn get_object(pool: sqlx::PgPool) {
@@ -543,6 +923,180 @@ mod test {
         assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
     }
 
+    #[sqlx::test]
+    async fn update_with_if_match(pool: sqlx::PgPool) {
+        let (
+            _app,
+            BootstrappedData {
+                organization,
+                admin_user,
+                ..
+            },
+        ) = start_app(pool.clone()).await;
+
+        let added_objects = setup_test_objects(&pool, organization.id, 1).await;
+        let id = added_objects[0].1.id;
+
+        let get_etag = || async {
+            let response = admin_user
+                .client
+                .get(&format!("reports/{id}"))
+                .send()
+                .await
+                .unwrap()
+                .log_error()
+                .await
+                .unwrap();
+            response.headers()["etag"].to_str().unwrap().to_string()
+        };
+
+        let etag = get_etag().await;
+
+        admin_user
+            .client
+            .put(&format!("reports/{id}"))
+            .header("If-Match", &etag)
+            .json(&make_update_payload(1))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap();
+
+        // The first update changed the ETag, so reusing the old one should fail.
+        let response = admin_user
+            .client
+            .put(&format!("reports/{id}"))
+            .header("If-Match", &etag)
+            .json(&make_update_payload(2))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::PRECONDITION_FAILED);
+
+        let report: serde_json::Value = admin_user
+            .client
+            .get(&format!("reports/{id}"))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(report["title"], "Test object 1");
+        assert_eq!(report["report_sections"].as_array().unwrap().len(), 1);
+
+        // Updating a section changes the report's ETag too.
+        let etag = get_etag().await;
+        let section_id = report["report_sections"][0]["id"].as_str().unwrap();
+        admin_user
+            .client
+            .put(&format!("reports/{id}/report_sections/{section_id}"))
+            .json(&crate::models::report_section::testing::make_update_payload(5))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap();
+
+        let response = admin_user
+            .client
+            .put(&format!("reports/{id}"))
+            .header("If-Match", &etag)
+            .json(&make_update_payload(2))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::PRECONDITION_FAILED);
+    }
+
+    #[sqlx::test]
+    async fn patch_object(pool: sqlx::PgPool) {
+        let (
+            app,
+            BootstrappedData {
+                organization,
+                admin_user,
+                ..
+            },
+        ) = start_app(pool.clone()).await;
+
+        let added_objects = setup_test_objects(&pool, organization.id, 1).await;
+        let id = added_objects[0].1.id;
+
+        // The test client doesn't support PATCH, so use a plain client for those requests.
+        let patch = |body: String, content_type: &str| {
+            reqwest::Client::new()
+                .patch(format!("{}/api/reports/{id}", app.base_url))
+                .header("Authorization", format!("Bearer {}", admin_user.api_key))
+                .header("Content-Type", content_type)
+                .body(body)
+                .send()
+        };
+
+        admin_user
+            .client
+            .put(&format!("reports/{id}"))
+            .json(&serde_json::json!({
+                "title": "Original title",
+                "description": "Original description",
+                "ui": { "layout": "grid", "columns": 2, "theme": { "dark": true } },
+            }))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap();
+
+        patch(
+            serde_json::json!({
+                "description": null,
+                "ui": { "columns": 3, "theme": { "dark": null } },
+            })
+            .to_string(),
+            "application/merge-patch+json",
+        )
+        .await
+        .unwrap()
+        .log_error()
+        .await
+        .unwrap();
+
+        let updated: serde_json::Value = admin_user
+            .client
+            .get(&format!("reports/{id}"))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        assert_eq!(updated["title"], "Original title");
+        assert_eq!(updated["description"], serde_json::Value::Null);
+        assert_eq!(
+            updated["ui"],
+            serde_json::json!({ "layout": "grid", "columns": 3, "theme": {} })
+        );
+
+        let response = patch(
+            serde_json::json!({ "title": null }).to_string(),
+            "application/merge-patch+json",
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+        let response = patch("title=abc".to_string(), "text/plain").await.unwrap();
+        assert_eq!(
+            response.status(),
+            reqwest::StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+    }
+
     #[sqlx::test]
     async fn create_object(pool: sqlx::PgPool) {
         let (
@@ -0,0 +1,12 @@
+UPDATE
+  public.reports
+SET
+  title = COALESCE($1, title),
+  description = CASE WHEN $2 THEN $3
+    ELSE description
+  END,
+  ui = COALESCE(jsonb_merge_patch(ui, $4), ui),
+  updated_at = NOW()
+WHERE
+  id = $5
+  AND organization_id = $6
@@ -7,22 +7,28 @@ use filigree::{
     errors::OrderByError,
     sql::{BindingOperator, FilterBuilder, ValuesBuilder},
 };
+use schemars::JsonSchema;
 use serde::Deserialize;
 use sqlx::{
-    postgres::PgRow, query_file, query_file_as, query_file_scalar, PgConnection, PgExecutor,
+    postgres::PgRow, query_file, query_file_as, query_file_scalar, PgConnection, PgExecutor, PgPool,
 };
 use tracing::{event, instrument, Level};
 
 use super::{types::*, ReportId};
 use crate::{
-    auth::AuthInfo,
+    auth::{AuthInfo, Authed},
     models::{
+        bulk::{BulkDeleted, BulkModel},
+        export::{self, ExportFormat},
+        filters::ExtraFilters,
         organization::OrganizationId,
+        pagination::{self, ListTotal},
         report_section::{
             ReportSection, ReportSectionCreatePayload, ReportSectionCreateResult, ReportSectionId,
             ReportSectionUpdatePayload,
         },
     },
+    server::ServerState,
     Error,
 };
 
@@ -73,7 +79,7 @@ fn parse_order_by(field: &str) -> Result<(bool, OrderByField), OrderByError> {
     Ok((descending, value))
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Deserialize, Debug, Default, JsonSchema)]
 pub struct ListQueryFilters {
     pub page: Option<u32>,
     pub per_page: Option<u32>,
@@ -81,20 +87,89 @@ pub struct ListQueryFilters {
     pub order_by: Option<String>,
     #[serde(default)]
     pub id: Vec<ReportId>,
+    #[serde(default)]
+    pub title: Vec<String>,
+    #[serde(default)]
+    pub description: Vec<String>,
     pub updated_at_lte: Option<chrono::DateTime<chrono::Utc>>,
     pub updated_at_gte: Option<chrono::DateTime<chrono::Utc>>,
     pub created_at_lte: Option<chrono::DateTime<chrono::Utc>>,
     pub created_at_gte: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only return objects where `title` contains this string
+    pub title_contains: Option<String>,
+    /// Only return objects where `title` contains this string, ignoring case
+    pub title_icontains: Option<String>,
+    /// Only return objects where `description` contains this string
+    pub description_contains: Option<String>,
+    /// Only return objects where `description` contains this string, ignoring case
+    pub description_icontains: Option<String>,
+    /// Only return objects where `description` is (or is not) null
+    pub description_is_null: Option<bool>,
+    /// Only return objects where this JSON path expression matches `ui`
+    pub ui_path: Option<String>,
+    /// Only return reports that have (or don't have) any sections
+    pub has_sections: Option<bool>,
+    /// Only return reports with a section using one of these visualizations
+    #[serde(default)]
+    pub section_viz: Vec<String>,
+    /// Only return objects that the current user has (or has not) favorited
+    pub favorited: Option<bool>,
+    /// Wrap the results in an envelope that includes the total number of matching objects
+    pub include_total: Option<bool>,
 }
 
 impl ListQueryFilters {
     fn build_where_clause(&self) -> String {
-        let mut bindings = FilterBuilder::new(4);
+        // The current user's ID is bound at $4 when filtering on favorites.
+        let first_binding = if self.favorited.is_some() { 5 } else { 4 };
+        let mut filters = ExtraFilters::new(first_binding);
+
+        if self.title_contains.is_some() {
+            filters.contains("title", false);
+        }
+
+        if self.title_icontains.is_some() {
+            filters.contains("title", true);
+        }
+
+        if self.description_contains.is_some() {
+            filters.contains("description", false);
+        }
+
+        if self.description_icontains.is_some() {
+            filters.contains("description", true);
+        }
+
+        if let Some(description_is_null) = self.description_is_null {
+            filters.is_null("description", description_is_null);
+        }
+
+        if self.ui_path.is_some() {
+            filters.json_path("ui");
+        }
+
+        if let Some(has_sections) = self.has_sections {
+            filters.has_child("report_sections", "report_id", has_sections);
+        }
+
+        if !self.section_viz.is_empty() {
+            filters.child_in("report_sections", "report_id", "viz");
+        }
+
+        let mut bindings = FilterBuilder::new(filters.next_binding());
 
         if !self.id.is_empty() {
             bindings.add_vec("id", &self.id);
         }
 
+        if !self.title.is_empty() {
+            bindings.add_vec("title", &self.title);
+        }
+
+        if !self.description.is_empty() {
+            bindings.add_vec("description", &self.description);
+        }
+
         if self.updated_at_lte.is_some() {
             bindings.add_option("updated_at", &self.updated_at_lte, BindingOperator::Lte);
         }
@@ -111,17 +186,63 @@ impl ListQueryFilters {
             bindings.add_option("created_at", &self.created_at_gte, BindingOperator::Gte);
         }
 
-        let query = bindings.to_string();
+        let mut query = bindings.to_string();
+        filters.append_to(&mut query);
+        if let Some(favorited) = self.favorited {
+            query.push_str(" AND ");
+            query.push_str(&crate::users::favorites::filter_clause(favorited, 4));
+        }
+
         event!(Level::DEBUG, %query);
         query
     }
 
     fn bind_to_query<'a, T>(&'a self, mut query: QueryAs<'a, T>) -> QueryAs<'a, T> {
+        if self.title_contains.is_some() {
+            event!(Level::DEBUG, title_contains = ?self.title_contains);
+            query = query.bind(&self.title_contains);
+        }
+
+        if self.title_icontains.is_some() {
+            event!(Level::DEBUG, title_icontains = ?self.title_icontains);
+            query = query.bind(&self.title_icontains);
+        }
+
+        if self.description_contains.is_some() {
+            event!(Level::DEBUG, description_contains = ?self.description_contains);
+            query = query.bind(&self.description_contains);
+        }
+
+        if self.description_icontains.is_some() {
+            event!(Level::DEBUG, description_icontains = ?self.description_icontains);
+            query = query.bind(&self.description_icontains);
+        }
+
+        if self.ui_path.is_some() {
+            event!(Level::DEBUG, ui_path = ?self.ui_path);
+            query = query.bind(&self.ui_path);
+        }
+
+        if !self.section_viz.is_empty() {
+            event!(Level::DEBUG, section_viz = ?self.section_viz);
+            query = query.bind(&self.section_viz);
+        }
+
         if !self.id.is_empty() {
             event!(Level::DEBUG, id = ?self.id);
             query = query.bind(&self.id);
         }
 
+        if !self.title.is_empty() {
+            event!(Level::DEBUG, title = ?self.title);
+            query = query.bind(&self.title);
+        }
+
+        if !self.description.is_empty() {
+            event!(Level::DEBUG, description = ?self.description);
+            query = query.bind(&self.description);
+        }
+
         if self.updated_at_lte.is_some() {
             event!(Level::DEBUG, updated_at_lte = ?self.updated_at_lte);
             query = query.bind(&self.updated_at_lte);
@@ -226,6 +347,96 @@ impl Report {
         Self::list_internal(q, db, auth, filters).await
     }
 
+    /// Count the objects matching the filters, ignoring pagination. Very large tables are
+    /// estimated using the query planner instead of being counted exactly.
+    #[instrument(skip(db))]
+    pub async fn count(
+        db: &mut PgConnection,
+        auth: &AuthInfo,
+        filters: &ListQueryFilters,
+    ) -> Result<ListTotal, error_stack::Report<Error>> {
+        auth.require_permission(super::READ_PERMISSION)?;
+
+        let estimated = pagination::use_estimate(&mut *db, "reports").await?;
+        let q = include_str!("count.sql")
+            .replace("__insertion_point_filters", &filters.build_where_clause());
+
+        let count = if estimated {
+            let q = format!("EXPLAIN (FORMAT JSON) {q}");
+            let query = Self::bind_count_query(
+                sqlx::query_as::<_, (pagination::ExplainPlan,)>(&q),
+                auth,
+                filters,
+            );
+            let (plan,) = query.fetch_one(&mut *db).await.change_context(Error::Db)?;
+            pagination::plan_rows(&plan)
+        } else {
+            let q = format!("SELECT COUNT(*) FROM ({q}) matching");
+            let query = Self::bind_count_query(sqlx::query_as::<_, (i64,)>(&q), auth, filters);
+            let (count,) = query.fetch_one(&mut *db).await.change_context(Error::Db)?;
+            count
+        };
+
+        Ok(ListTotal { count, estimated })
+    }
+
+    fn bind_count_query<'a, T>(
+        mut query: QueryAs<'a, T>,
+        auth: &'a AuthInfo,
+        filters: &'a ListQueryFilters,
+    ) -> QueryAs<'a, T> {
+        // $2 and $3 are the LIMIT and OFFSET in the list queries. The count query doesn't use them,
+        // but they are still bound so that the filters use the same binding numbers.
+        query = query.bind(&auth.organization_id).bind(0i32).bind(0i32);
+
+        if filters.favorited.is_some() {
+            query = query.bind(&auth.user_id);
+        }
+
+        filters.bind_to_query(query)
+    }
+
+    /// Stream all the Report objects matching the filters as CSV or NDJSON. Unlike the list
+    /// functions, this does not limit the number of rows returned.
+    pub fn export(
+        db: &PgPool,
+        auth: Authed,
+        filters: ListQueryFilters,
+        format: ExportFormat,
+    ) -> Result<axum::response::Response, error_stack::Report<Error>> {
+        auth.require_permission(super::READ_PERMISSION)?;
+
+        let (descending, order_by_field) =
+            parse_order_by(filters.order_by.as_deref().unwrap_or("-updated_at"))
+                .change_context(Error::Filter)?;
+        let order_direction = if descending { "DESC" } else { "ASC" };
+
+        let q = include_str!("list_populated.sql")
+            .replace(
+                "__insertion_point_order_by",
+                &format!("{} {}", order_by_field.as_str(), order_direction),
+            )
+            .replace("__insertion_point_filters", &filters.build_where_clause());
+        let (limit, offset) = export::bounds(filters.page, filters.per_page);
+
+        let response = export::stream(db, "reports", format, move |mut conn, writer| async move {
+            let mut query = sqlx::query_as::<_, ReportPopulatedListResult>(q.as_str())
+                .bind(&auth.organization_id)
+                .bind(limit)
+                .bind(offset);
+
+            if filters.favorited.is_some() {
+                query = query.bind(&auth.user_id);
+            }
+
+            query = filters.bind_to_query(query);
+
+            writer.write_rows(query.fetch(&mut *conn)).await
+        });
+
+        Ok(response)
+    }
+
     async fn list_internal<T>(
         query_template: &str,
         db: impl PgExecutor<'_>,
@@ -267,6 +478,10 @@ impl Report {
             .bind(per_page)
             .bind(offset);
 
+        if filters.favorited.is_some() {
+            query = query.bind(&auth.user_id);
+        }
+
         query = filters.bind_to_query(query);
 
         let results = query.fetch_all(db).await.change_context(Error::Db)?;
@@ -391,6 +606,39 @@ impl Report {
         Ok(true)
     }
 
+    /// Update only the fields present in a JSON Merge Patch.
+    #[instrument(skip(db))]
+    pub async fn patch(
+        db: &mut PgConnection,
+        auth: &AuthInfo,
+        id: &ReportId,
+        payload: ReportPatchPayload,
+    ) -> Result<bool, error_stack::Report<Error>> {
+        auth.require_permission(super::WRITE_PERMISSION)?;
+        if payload.ui.is_some() {
+            // `ui` is only writable by owners
+            auth.require_permission(super::OWNER_PERMISSION)?;
+        }
+
+        let (description_set, description) =
+            crate::models::merge_patch::nullable_binding(&payload.description);
+
+        let result = query_file!(
+            "src/models/report/patch.sql",
+            payload.title.as_ref() as _,
+            description_set,
+            description as _,
+            payload.ui.as_ref() as _,
+            id.as_uuid(),
+            auth.organization_id.as_uuid()
+        )
+        .execute(&mut *db)
+        .await
+        .change_context(Error::Db)?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
     async fn update_payload_children(
         db: &mut PgConnection,
         organization_id: &OrganizationId,
@@ -532,3 +780,92 @@ impl Report {
         .await
     }
 }
+
+#[async_trait::async_trait]
+impl BulkModel for Report {
+    type Id = ReportId;
+    type CreatePayload = ReportCreatePayload;
+    type UpdatePayload = ReportUpdatePayload;
+
+    fn bulk_create_id(payload: &ReportCreatePayload) -> ReportId {
+        ReportId::new()
+    }
+
+    async fn bulk_insert(
+        db: &mut PgConnection,
+        auth: &AuthInfo,
+        objects: Vec<(ReportId, ReportCreatePayload)>,
+    ) -> Result<Vec<ReportId>, error_stack::Report<Error>> {
+        auth.require_permission(super::CREATE_PERMISSION)?;
+
+        let q = include_str!("bulk_insert.sql");
+        let bindings = ValuesBuilder {
+            first_parameter: 1,
+            num_values: objects.len(),
+            num_columns: 5,
+        };
+        let q = q.replace("__insertion_point_insert_values", &bindings.to_string());
+
+        let mut query = sqlx::query_scalar::<_, ReportId>(q.as_str());
+        for (id, payload) in &objects {
+            query = query
+                .bind(id)
+                .bind(&auth.organization_id)
+                .bind(&payload.title)
+                .bind(&payload.description)
+                .bind(&payload.ui);
+        }
+
+        let inserted = query.fetch_all(&mut *db).await.change_context(Error::Db)?;
+
+        for (id, payload) in objects {
+            if inserted.contains(&id) {
+                Self::create_payload_children(&mut *db, &id, &auth.organization_id, payload)
+                    .await?;
+            }
+        }
+
+        Ok(inserted)
+    }
+
+    async fn bulk_update(
+        db: &mut PgConnection,
+        auth: &AuthInfo,
+        id: &ReportId,
+        payload: ReportUpdatePayload,
+    ) -> Result<bool, error_stack::Report<Error>> {
+        Self::update(db, auth, id, payload).await
+    }
+
+    async fn bulk_delete(
+        state: &ServerState,
+        db: &mut PgConnection,
+        auth: &Authed,
+        ids: &[ReportId],
+    ) -> Result<BulkDeleted<ReportId>, error_stack::Report<Error>> {
+        auth.require_permission(super::CREATE_PERMISSION)?;
+
+        let uuids = ids
+            .iter()
+            .map(|id| id.as_uuid().clone())
+            .collect::<Vec<_>>();
+        let deleted = query_file_scalar!(
+            "src/models/report/bulk_delete.sql",
+            &uuids,
+            auth.organization_id.as_uuid()
+        )
+        .fetch_all(&mut *db)
+        .await
+        .change_context(Error::Db)?;
+
+        for id in &deleted {
+            crate::users::favorites::remove_object(&mut *db, &auth.organization_id, id.as_uuid())
+                .await?;
+        }
+
+        Ok(BulkDeleted {
+            ids: deleted,
+            files: Vec::new(),
+        })
+    }
+}
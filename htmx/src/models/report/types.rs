@@ -306,3 +306,21 @@ impl Default for ReportUpdatePayload {
         }
     }
 }
+
+/// A JSON Merge Patch for a Report. Fields that are omitted from the patch are left unchanged.
+#[derive(Deserialize, Debug, Clone, schemars::JsonSchema)]
+pub struct ReportPatchPayload {
+    #[serde(default, deserialize_with = "crate::models::merge_patch::required")]
+    #[schemars(with = "String")]
+    pub title: Option<String>,
+    #[serde(default, deserialize_with = "crate::models::merge_patch::nullable")]
+    #[schemars(with = "Option<String>")]
+    pub description: Option<Option<String>>,
+    /// Merged into the existing value
+    #[serde(
+        default,
+        deserialize_with = "crate::models::merge_patch::required_json"
+    )]
+    #[schemars(with = "serde_json::Value")]
+    pub ui: Option<serde_json::Value>,
+}
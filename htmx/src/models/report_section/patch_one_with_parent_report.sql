@@ -0,0 +1,11 @@
+UPDATE
+  public.report_sections
+SET
+  name = COALESCE($1, name),
+  viz = COALESCE($2, viz),
+  options = COALESCE(jsonb_merge_patch(options, $3), options),
+  updated_at = NOW()
+WHERE
+  id = $4
+  AND report_id = $5
+  AND organization_id = $6
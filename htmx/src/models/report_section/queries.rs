@@ -7,6 +7,7 @@ use filigree::{
     errors::OrderByError,
     sql::{BindingOperator, FilterBuilder, ValuesBuilder},
 };
+use schemars::JsonSchema;
 use serde::Deserialize;
 use sqlx::{
     postgres::PgRow, query_file, query_file_as, query_file_scalar, PgConnection, PgExecutor,
@@ -16,7 +17,7 @@ use tracing::{event, instrument, Level};
 use super::{types::*, ReportSectionId};
 use crate::{
     auth::AuthInfo,
-    models::{organization::OrganizationId, report::ReportId},
+    models::{filters::ExtraFilters, organization::OrganizationId, report::ReportId},
     Error,
 };
 
@@ -67,7 +68,7 @@ fn parse_order_by(field: &str) -> Result<(bool, OrderByField), OrderByError> {
     Ok((descending, value))
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Deserialize, Debug, Default, JsonSchema)]
 pub struct ListQueryFilters {
     pub page: Option<u32>,
     pub per_page: Option<u32>,
@@ -76,21 +77,65 @@ pub struct ListQueryFilters {
     #[serde(default)]
     pub id: Vec<ReportSectionId>,
     #[serde(default)]
+    pub name: Vec<String>,
+    #[serde(default)]
+    pub viz: Vec<String>,
+    #[serde(default)]
     pub report_id: Vec<ReportId>,
     pub updated_at_lte: Option<chrono::DateTime<chrono::Utc>>,
     pub updated_at_gte: Option<chrono::DateTime<chrono::Utc>>,
     pub created_at_lte: Option<chrono::DateTime<chrono::Utc>>,
     pub created_at_gte: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only return objects where `name` contains this string
+    pub name_contains: Option<String>,
+    /// Only return objects where `name` contains this string, ignoring case
+    pub name_icontains: Option<String>,
+    /// Only return objects where `viz` contains this string
+    pub viz_contains: Option<String>,
+    /// Only return objects where `viz` contains this string, ignoring case
+    pub viz_icontains: Option<String>,
+    /// Only return objects where this JSON path expression matches `options`
+    pub options_path: Option<String>,
 }
 
 impl ListQueryFilters {
     fn build_where_clause(&self) -> String {
-        let mut bindings = FilterBuilder::new(4);
+        let mut filters = ExtraFilters::new(4);
+
+        if self.name_contains.is_some() {
+            filters.contains("name", false);
+        }
+
+        if self.name_icontains.is_some() {
+            filters.contains("name", true);
+        }
+
+        if self.viz_contains.is_some() {
+            filters.contains("viz", false);
+        }
+
+        if self.viz_icontains.is_some() {
+            filters.contains("viz", true);
+        }
+
+        if self.options_path.is_some() {
+            filters.json_path("options");
+        }
+
+        let mut bindings = FilterBuilder::new(filters.next_binding());
 
         if !self.id.is_empty() {
             bindings.add_vec("id", &self.id);
         }
 
+        if !self.name.is_empty() {
+            bindings.add_vec("name", &self.name);
+        }
+
+        if !self.viz.is_empty() {
+            bindings.add_vec("viz", &self.viz);
+        }
+
         if !self.report_id.is_empty() {
             bindings.add_vec("report_id", &self.report_id);
         }
@@ -111,17 +156,53 @@ impl ListQueryFilters {
             bindings.add_option("created_at", &self.created_at_gte, BindingOperator::Gte);
         }
 
-        let query = bindings.to_string();
+        let mut query = bindings.to_string();
+        filters.append_to(&mut query);
         event!(Level::DEBUG, %query);
         query
     }
 
     fn bind_to_query<'a, T>(&'a self, mut query: QueryAs<'a, T>) -> QueryAs<'a, T> {
+        if self.name_contains.is_some() {
+            event!(Level::DEBUG, name_contains = ?self.name_contains);
+            query = query.bind(&self.name_contains);
+        }
+
+        if self.name_icontains.is_some() {
+            event!(Level::DEBUG, name_icontains = ?self.name_icontains);
+            query = query.bind(&self.name_icontains);
+        }
+
+        if self.viz_contains.is_some() {
+            event!(Level::DEBUG, viz_contains = ?self.viz_contains);
+            query = query.bind(&self.viz_contains);
+        }
+
+        if self.viz_icontains.is_some() {
+            event!(Level::DEBUG, viz_icontains = ?self.viz_icontains);
+            query = query.bind(&self.viz_icontains);
+        }
+
+        if self.options_path.is_some() {
+            event!(Level::DEBUG, options_path = ?self.options_path);
+            query = query.bind(&self.options_path);
+        }
+
         if !self.id.is_empty() {
             event!(Level::DEBUG, id = ?self.id);
             query = query.bind(&self.id);
         }
 
+        if !self.name.is_empty() {
+            event!(Level::DEBUG, name = ?self.name);
+            query = query.bind(&self.name);
+        }
+
+        if !self.viz.is_empty() {
+            event!(Level::DEBUG, viz = ?self.viz);
+            query = query.bind(&self.viz);
+        }
+
         if !self.report_id.is_empty() {
             event!(Level::DEBUG, report_id = ?self.report_id);
             query = query.bind(&self.report_id);
@@ -429,6 +510,31 @@ impl ReportSection {
         Ok(result.rows_affected() > 0)
     }
 
+    /// Update only the fields of a single child that are present in a JSON Merge Patch.
+    #[instrument(skip(db))]
+    pub async fn patch_one_with_parent_report(
+        db: impl PgExecutor<'_>,
+        auth: &AuthInfo,
+        parent_id: &ReportId,
+        id: &ReportSectionId,
+        payload: ReportSectionPatchPayload,
+    ) -> Result<bool, error_stack::Report<Error>> {
+        let result = query_file!(
+            "src/models/report_section/patch_one_with_parent_report.sql",
+            payload.name.as_ref() as _,
+            payload.viz.as_ref() as _,
+            payload.options.as_ref() as _,
+            id.as_uuid(),
+            parent_id.as_uuid(),
+            auth.organization_id.as_uuid()
+        )
+        .execute(db)
+        .await
+        .change_context(Error::Db)?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
     /// Update the children of the given parent.
     /// Insert new values that are not yet in the database and
     /// delete existing values that are not in the payload.
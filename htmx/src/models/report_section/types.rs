@@ -133,3 +133,21 @@ impl Default for ReportSectionCreatePayloadAndUpdatePayload {
         }
     }
 }
+
+/// A JSON Merge Patch for a ReportSection. Fields that are omitted from the patch are left unchanged.
+#[derive(Deserialize, Debug, Clone, schemars::JsonSchema)]
+pub struct ReportSectionPatchPayload {
+    #[serde(default, deserialize_with = "crate::models::merge_patch::required")]
+    #[schemars(with = "String")]
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "crate::models::merge_patch::required")]
+    #[schemars(with = "String")]
+    pub viz: Option<String>,
+    /// Merged into the existing value
+    #[serde(
+        default,
+        deserialize_with = "crate::models::merge_patch::required_json"
+    )]
+    #[schemars(with = "serde_json::Value")]
+    pub options: Option<serde_json::Value>,
+}
@@ -0,0 +1,12 @@
+INSERT INTO public.roles (
+  id,
+  organization_id,
+  name,
+  description)
+VALUES
+  __insertion_point_insert_values
+ON CONFLICT (
+  id)
+  DO NOTHING
+RETURNING
+  id
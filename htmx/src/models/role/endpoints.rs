@@ -2,10 +2,9 @@
 use std::{borrow::Cow, str::FromStr};
 
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    extract::{OriginalUri, Path, State},
+    http::{HeaderMap, Method, StatusCode},
     response::IntoResponse,
-    routing,
 };
 use axum_extra::extract::Query;
 use axum_jsonschema::Json;
@@ -21,8 +20,19 @@ use super::{
     WRITE_PERMISSION,
 };
 use crate::{
-    auth::{has_any_permission, Authed},
-    server::ServerState,
+    auth::{permissions::ORG_ADMIN_PERMISSION, Authed},
+    models::{
+        bulk::{self, BulkRequest, BulkResponse},
+        export::ExportFormat,
+        merge_patch::MergePatch,
+        pagination,
+    },
+    server::{
+        etag::{etag_header, IfMatch},
+        openapi::{model_routes, ApiRoute, ApiRouter},
+        ServerState,
+    },
+    users::members::{self, RolePermissions},
     Error,
 };
 
@@ -33,17 +43,36 @@ async fn get(
 ) -> Result<impl IntoResponse, Error> {
     let object = Role::get(&state.db, &auth, &id).await?;
 
-    Ok(Json(object))
+    Ok((etag_header(&object.updated_at), Json(object)))
 }
 
 async fn list(
     State(state): State<ServerState>,
     auth: Authed,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
     Query(qs): Query<queries::ListQueryFilters>,
 ) -> Result<impl IntoResponse, Error> {
+    if let Some(format) = ExportFormat::from_headers(&headers) {
+        return Ok(Role::export(&state.db, auth, qs, format)?);
+    }
+
     let results = Role::list(&state.db, &auth, &qs).await?;
 
-    Ok(Json(results))
+    let total = if qs.include_total.unwrap_or(false) {
+        let mut conn = state.db.acquire().await.change_context(Error::Db)?;
+        Some(Role::count(&mut conn, &auth, &qs).await?)
+    } else {
+        None
+    };
+
+    Ok(pagination::list_response(
+        &uri,
+        qs.page,
+        qs.per_page,
+        results,
+        total,
+    ))
 }
 
 async fn create(
@@ -58,14 +87,28 @@ async fn create(
     Ok((StatusCode::CREATED, Json(result)))
 }
 
+async fn bulk_operations(
+    State(state): State<ServerState>,
+    auth: Authed,
+    axum::Json(request): axum::Json<BulkRequest<RoleId, RoleCreatePayload, RoleUpdatePayload>>,
+) -> Result<impl IntoResponse, Error> {
+    let result = bulk::run::<Role>(&state, &auth, request).await?;
+
+    Ok(Json(result))
+}
+
 async fn update(
     State(state): State<ServerState>,
     auth: Authed,
     Path(id): Path<RoleId>,
+    if_match: IfMatch,
     FormOrJson(payload): FormOrJson<RoleUpdatePayload>,
 ) -> Result<impl IntoResponse, Error> {
     let mut tx = state.db.begin().await.change_context(Error::Db)?;
 
+    if_match
+        .lock_and_check(&mut *tx, "roles", id.as_uuid(), &auth.organization_id)
+        .await?;
     let result = Role::update(&mut *tx, &auth, &id, payload).await?;
 
     tx.commit().await.change_context(Error::Db)?;
@@ -77,6 +120,29 @@ async fn update(
     }
 }
 
+async fn patch(
+    State(state): State<ServerState>,
+    auth: Authed,
+    Path(id): Path<RoleId>,
+    if_match: IfMatch,
+    MergePatch(payload): MergePatch<RolePatchPayload>,
+) -> Result<impl IntoResponse, Error> {
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
+
+    if_match
+        .lock_and_check(&mut *tx, "roles", id.as_uuid(), &auth.organization_id)
+        .await?;
+    let result = Role::patch(&mut *tx, &auth, &id, payload).await?;
+
+    tx.commit().await.change_context(Error::Db)?;
+
+    if result {
+        Ok(StatusCode::OK)
+    } else {
+        Ok(StatusCode::NOT_FOUND)
+    }
+}
+
 async fn delete(
     State(state): State<ServerState>,
     auth: Authed,
@@ -90,41 +156,116 @@ async fn delete(
         return Ok(StatusCode::NOT_FOUND);
     }
 
+    members::ensure_org_has_admin(&mut *tx, &auth.organization_id).await?;
+
     tx.commit().await.change_context(Error::Db)?;
 
     Ok(StatusCode::OK)
 }
 
-pub fn create_routes() -> axum::Router<ServerState> {
-    axum::Router::new()
-        .route(
-            "/roles",
-            routing::get(list).route_layer(has_any_permission(vec![READ_PERMISSION, "org_admin"])),
-        )
-        .route(
-            "/roles/:id",
-            routing::get(get).route_layer(has_any_permission(vec![READ_PERMISSION, "org_admin"])),
-        )
+async fn get_permissions(
+    State(state): State<ServerState>,
+    auth: Authed,
+    Path(id): Path<RoleId>,
+) -> Result<impl IntoResponse, Error> {
+    // Make sure the role exists and is visible to the user.
+    Role::get(&state.db, &auth, &id).await?;
+
+    let permissions = members::get_role_permissions(&state.db, &auth.organization_id, &id).await?;
+
+    Ok(Json(RolePermissions { permissions }))
+}
+
+async fn update_permissions(
+    State(state): State<ServerState>,
+    auth: Authed,
+    Path(id): Path<RoleId>,
+    FormOrJson(payload): FormOrJson<RolePermissions>,
+) -> Result<impl IntoResponse, Error> {
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
+    members::replace_role_permissions(&mut *tx, &auth.organization_id, &id, &payload.permissions)
+        .await?;
+    tx.commit().await.change_context(Error::Db)?;
+
+    Ok(StatusCode::OK)
+}
+
+fn routes() -> ApiRouter {
+    let read_perms = &[READ_PERMISSION, "org_admin"];
+    let write_perms = &[WRITE_PERMISSION, OWNER_PERMISSION, "org_admin"];
+    let create_perms = &[CREATE_PERMISSION, "org_admin"];
+
+    let model = model_routes::<
+        RoleId,
+        Role,
+        Role,
+        queries::ListQueryFilters,
+        RoleCreatePayload,
+        RoleCreateResult,
+        RoleUpdatePayload,
+        RolePatchPayload,
+    >(
+        "role",
+        "/roles",
+        "/roles/:id",
+        read_perms,
+        write_perms,
+        create_perms,
+    );
+
+    ApiRouter::new()
+        .route(model.list, list)
+        .route(model.get, get)
+        .route(model.create, create)
+        .route(model.update, update)
+        .route(model.patch, patch)
+        .route(model.delete, delete)
         .route(
-            "/roles",
-            routing::post(create)
-                .route_layer(has_any_permission(vec![CREATE_PERMISSION, "org_admin"])),
+            ApiRoute::new(
+                Method::POST,
+                "/roles/bulk",
+                "role",
+                "Create, update and delete roles in one transaction",
+            )
+            .permissions(&[CREATE_PERMISSION, WRITE_PERMISSION, "org_admin"])
+            .request::<BulkRequest<RoleId, RoleCreatePayload, RoleUpdatePayload>>()
+            .response::<BulkResponse<RoleId>>(),
+            bulk_operations,
         )
         .route(
-            "/roles/:id",
-            routing::put(update).route_layer(has_any_permission(vec![
-                WRITE_PERMISSION,
-                OWNER_PERMISSION,
-                "org_admin",
-            ])),
+            ApiRoute::new(
+                Method::GET,
+                "/roles/:id/permissions",
+                "role",
+                "List the permissions granted by a role",
+            )
+            .permissions(read_perms)
+            .path_param::<RoleId>()
+            .response::<RolePermissions>(),
+            get_permissions,
         )
         .route(
-            "/roles/:id",
-            routing::delete(delete)
-                .route_layer(has_any_permission(vec![CREATE_PERMISSION, "org_admin"])),
+            ApiRoute::new(
+                Method::PUT,
+                "/roles/:id/permissions",
+                "role",
+                "Replace the permissions granted by a role",
+            )
+            .permissions(&[ORG_ADMIN_PERMISSION])
+            .path_param::<RoleId>()
+            .request::<RolePermissions>(),
+            update_permissions,
         )
 }
 
+pub fn create_routes() -> axum::Router<ServerState> {
+    routes().into_router()
+}
+
+pub fn api_docs() -> Vec<ApiRoute> {
+    routes().into_docs()
+}
+
 #[cfg(test)]
 mod test {
     use filigree::testing::ResponseExt;
@@ -290,8 +431,89 @@ mod test {
     async fn list_paginated(_pool: sqlx::PgPool) {}
 
     #[sqlx::test]
-    #[ignore = "todo"]
-    async fn list_filters(_pool: sqlx::PgPool) {}
+    async fn list_filters(pool: sqlx::PgPool) {
+        let (
+            _app,
+            BootstrappedData {
+                organization,
+                admin_user,
+                ..
+            },
+        ) = start_app(pool.clone()).await;
+
+        let added_objects = setup_test_objects(&pool, organization.id, 3).await;
+
+        let fetch_indexes = |query: Vec<(&'static str, &'static str)>| {
+            let client = &admin_user.client;
+            let added_objects = &added_objects;
+            async move {
+                let results = client
+                    .get("roles")
+                    .query(&query)
+                    .send()
+                    .await
+                    .unwrap()
+                    .log_error()
+                    .await
+                    .unwrap()
+                    .json::<Vec<serde_json::Value>>()
+                    .await
+                    .unwrap();
+
+                // Skip the roles that were created when bootstrapping the organization.
+                let mut indexes = results
+                    .iter()
+                    .filter_map(|o| {
+                        added_objects
+                            .iter()
+                            .position(|(_, added)| o["id"] == added.id.to_string())
+                    })
+                    .collect::<Vec<_>>();
+                indexes.sort();
+                indexes
+            }
+        };
+
+        assert_eq!(
+            fetch_indexes(vec![("name_contains", "object 1")]).await,
+            vec![1],
+            "name_contains"
+        );
+        assert_eq!(
+            fetch_indexes(vec![("name_contains", "TEST OBJECT")]).await,
+            Vec::<usize>::new(),
+            "name_contains is case sensitive"
+        );
+        assert_eq!(
+            fetch_indexes(vec![("name_icontains", "TEST OBJECT")]).await,
+            vec![0, 1, 2],
+            "name_icontains"
+        );
+        assert_eq!(
+            fetch_indexes(vec![("description_contains", "object 2")]).await,
+            vec![2],
+            "description_contains"
+        );
+        assert_eq!(
+            fetch_indexes(vec![("description_icontains", "OBJECT 2")]).await,
+            vec![2],
+            "description_icontains"
+        );
+        assert_eq!(
+            fetch_indexes(vec![("description_is_null", "true")]).await,
+            vec![0, 1],
+            "description_is_null"
+        );
+        assert_eq!(
+            fetch_indexes(vec![
+                ("description_is_null", "false"),
+                ("name_icontains", "test")
+            ])
+            .await,
+            vec![2],
+            "combined filters"
+        );
+    }
 
     #[sqlx::test]
     async fn get_object(pool: sqlx::PgPool) {
@@ -538,4 +760,198 @@ mod test {
             .unwrap();
         assert_eq!(response.status(), reqwest::StatusCode::OK);
     }
+
+    #[sqlx::test]
+    async fn update_role_permissions(pool: sqlx::PgPool) {
+        let (
+            _app,
+            BootstrappedData {
+                admin_user,
+                user,
+                admin_role,
+                user_role,
+                ..
+            },
+        ) = start_app(pool.clone()).await;
+
+        admin_user
+            .client
+            .put(&format!("roles/{user_role}/permissions"))
+            .json(&serde_json::json!({ "permissions": ["Post::read", "Comment::read"] }))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap();
+
+        let result: serde_json::Value = admin_user
+            .client
+            .get(&format!("roles/{user_role}/permissions"))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(
+            result["permissions"],
+            serde_json::json!(["Comment::read", "Post::read"])
+        );
+
+        let response = admin_user
+            .client
+            .put(&format!("roles/{user_role}/permissions"))
+            .json(&serde_json::json!({ "permissions": ["Post::fly"] }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+        // Only admins can change permissions
+        let response = user
+            .client
+            .put(&format!("roles/{user_role}/permissions"))
+            .json(&serde_json::json!({ "permissions": ["org_admin"] }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+
+        // Removing org_admin from the only admin role would leave the org without an admin
+        let response = admin_user
+            .client
+            .put(&format!("roles/{admin_role}/permissions"))
+            .json(&serde_json::json!({ "permissions": [] }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::CONFLICT);
+    }
+
+    #[sqlx::test]
+    async fn delete_admin_role(pool: sqlx::PgPool) {
+        let (
+            _app,
+            BootstrappedData {
+                admin_user,
+                admin_role,
+                ..
+            },
+        ) = start_app(pool.clone()).await;
+
+        // Deleting the only admin role would leave the org without an admin
+        let response = admin_user
+            .client
+            .delete(&format!("roles/{admin_role}"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::CONFLICT);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"]["kind"], "last_admin");
+
+        let result: serde_json::Value = admin_user
+            .client
+            .get(&format!("roles/{admin_role}/permissions"))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(result["permissions"], serde_json::json!(["org_admin"]));
+    }
+
+    #[sqlx::test]
+    async fn bulk_delete_admin_role(pool: sqlx::PgPool) {
+        let (
+            _app,
+            BootstrappedData {
+                organization,
+                admin_user,
+                admin_role,
+                ..
+            },
+        ) = start_app(pool.clone()).await;
+
+        let added_objects = setup_test_objects(&pool, organization.id, 1).await;
+
+        // Only the delete that would remove the last admin fails
+        let response = admin_user
+            .client
+            .post("roles/bulk")
+            .json(&serde_json::json!({
+                "atomic": false,
+                "operations": [
+                    { "op": "delete", "id": added_objects[0].1.id },
+                    { "op": "delete", "id": admin_role },
+                ]
+            }))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json::<serde_json::Value>()
+            .await
+            .unwrap();
+
+        assert_eq!(response["committed"], true);
+        assert_eq!(response["results"][0]["status"], "ok");
+        assert_eq!(response["results"][1]["status"], "error");
+        assert_eq!(response["results"][1]["kind"], "last_admin");
+
+        let response = admin_user
+            .client
+            .get(&format!("roles/{}", added_objects[0].1.id))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+        // An atomic request rolls back entirely
+        let response = admin_user
+            .client
+            .post("roles/bulk")
+            .json(&serde_json::json!({
+                "operations": [
+                    { "op": "delete", "id": admin_role },
+                ]
+            }))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json::<serde_json::Value>()
+            .await
+            .unwrap();
+
+        assert_eq!(response["committed"], false);
+        assert_eq!(response["results"][0]["status"], "error");
+        assert_eq!(response["results"][0]["kind"], "last_admin");
+
+        let result: serde_json::Value = admin_user
+            .client
+            .get(&format!("roles/{admin_role}/permissions"))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(result["permissions"], serde_json::json!(["org_admin"]));
+    }
 }
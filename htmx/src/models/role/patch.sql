@@ -0,0 +1,11 @@
+UPDATE
+  public.roles
+SET
+  name = COALESCE($1, name),
+  description = CASE WHEN $2 THEN $3
+    ELSE description
+  END,
+  updated_at = NOW()
+WHERE
+  id = $4
+  AND organization_id = $5
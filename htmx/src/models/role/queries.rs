@@ -7,14 +7,26 @@ use filigree::{
     errors::OrderByError,
     sql::{BindingOperator, FilterBuilder, ValuesBuilder},
 };
+use schemars::JsonSchema;
 use serde::Deserialize;
 use sqlx::{
-    postgres::PgRow, query_file, query_file_as, query_file_scalar, PgConnection, PgExecutor,
+    postgres::PgRow, query_file, query_file_as, query_file_scalar, PgConnection, PgExecutor, PgPool,
 };
 use tracing::{event, instrument, Level};
 
 use super::{types::*, RoleId};
-use crate::{auth::AuthInfo, models::organization::OrganizationId, Error};
+use crate::{
+    auth::{AuthInfo, Authed},
+    models::{
+        bulk::{BulkDeleted, BulkModel},
+        export::{self, ExportFormat},
+        filters::ExtraFilters,
+        organization::OrganizationId,
+        pagination::{self, ListTotal},
+    },
+    server::ServerState,
+    Error,
+};
 
 #[derive(Debug, Default)]
 enum OrderByField {
@@ -66,7 +78,7 @@ fn parse_order_by(field: &str) -> Result<(bool, OrderByField), OrderByError> {
     Ok((descending, value))
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Deserialize, Debug, Default, JsonSchema)]
 pub struct ListQueryFilters {
     pub page: Option<u32>,
     pub per_page: Option<u32>,
@@ -74,20 +86,66 @@ pub struct ListQueryFilters {
     pub order_by: Option<String>,
     #[serde(default)]
     pub id: Vec<RoleId>,
+    #[serde(default)]
+    pub name: Vec<String>,
+    #[serde(default)]
+    pub description: Vec<String>,
     pub updated_at_lte: Option<chrono::DateTime<chrono::Utc>>,
     pub updated_at_gte: Option<chrono::DateTime<chrono::Utc>>,
     pub created_at_lte: Option<chrono::DateTime<chrono::Utc>>,
     pub created_at_gte: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only return objects where `name` contains this string
+    pub name_contains: Option<String>,
+    /// Only return objects where `name` contains this string, ignoring case
+    pub name_icontains: Option<String>,
+    /// Only return objects where `description` contains this string
+    pub description_contains: Option<String>,
+    /// Only return objects where `description` contains this string, ignoring case
+    pub description_icontains: Option<String>,
+    /// Only return objects where `description` is (or is not) null
+    pub description_is_null: Option<bool>,
+    /// Wrap the results in an envelope that includes the total number of matching objects
+    pub include_total: Option<bool>,
 }
 
 impl ListQueryFilters {
     fn build_where_clause(&self) -> String {
-        let mut bindings = FilterBuilder::new(4);
+        let mut filters = ExtraFilters::new(4);
+
+        if self.name_contains.is_some() {
+            filters.contains("name", false);
+        }
+
+        if self.name_icontains.is_some() {
+            filters.contains("name", true);
+        }
+
+        if self.description_contains.is_some() {
+            filters.contains("description", false);
+        }
+
+        if self.description_icontains.is_some() {
+            filters.contains("description", true);
+        }
+
+        if let Some(description_is_null) = self.description_is_null {
+            filters.is_null("description", description_is_null);
+        }
+
+        let mut bindings = FilterBuilder::new(filters.next_binding());
 
         if !self.id.is_empty() {
             bindings.add_vec("id", &self.id);
         }
 
+        if !self.name.is_empty() {
+            bindings.add_vec("name", &self.name);
+        }
+
+        if !self.description.is_empty() {
+            bindings.add_vec("description", &self.description);
+        }
+
         if self.updated_at_lte.is_some() {
             bindings.add_option("updated_at", &self.updated_at_lte, BindingOperator::Lte);
         }
@@ -104,17 +162,48 @@ impl ListQueryFilters {
             bindings.add_option("created_at", &self.created_at_gte, BindingOperator::Gte);
         }
 
-        let query = bindings.to_string();
+        let mut query = bindings.to_string();
+        filters.append_to(&mut query);
         event!(Level::DEBUG, %query);
         query
     }
 
     fn bind_to_query<'a, T>(&'a self, mut query: QueryAs<'a, T>) -> QueryAs<'a, T> {
+        if self.name_contains.is_some() {
+            event!(Level::DEBUG, name_contains = ?self.name_contains);
+            query = query.bind(&self.name_contains);
+        }
+
+        if self.name_icontains.is_some() {
+            event!(Level::DEBUG, name_icontains = ?self.name_icontains);
+            query = query.bind(&self.name_icontains);
+        }
+
+        if self.description_contains.is_some() {
+            event!(Level::DEBUG, description_contains = ?self.description_contains);
+            query = query.bind(&self.description_contains);
+        }
+
+        if self.description_icontains.is_some() {
+            event!(Level::DEBUG, description_icontains = ?self.description_icontains);
+            query = query.bind(&self.description_icontains);
+        }
+
         if !self.id.is_empty() {
             event!(Level::DEBUG, id = ?self.id);
             query = query.bind(&self.id);
         }
 
+        if !self.name.is_empty() {
+            event!(Level::DEBUG, name = ?self.name);
+            query = query.bind(&self.name);
+        }
+
+        if !self.description.is_empty() {
+            event!(Level::DEBUG, description = ?self.description);
+            query = query.bind(&self.description);
+        }
+
         if self.updated_at_lte.is_some() {
             event!(Level::DEBUG, updated_at_lte = ?self.updated_at_lte);
             query = query.bind(&self.updated_at_lte);
@@ -180,6 +269,88 @@ impl Role {
         Self::list_internal(q, db, auth, filters).await
     }
 
+    /// Count the objects matching the filters, ignoring pagination. Very large tables are
+    /// estimated using the query planner instead of being counted exactly.
+    #[instrument(skip(db))]
+    pub async fn count(
+        db: &mut PgConnection,
+        auth: &AuthInfo,
+        filters: &ListQueryFilters,
+    ) -> Result<ListTotal, error_stack::Report<Error>> {
+        auth.require_permission(super::READ_PERMISSION)?;
+
+        let estimated = pagination::use_estimate(&mut *db, "roles").await?;
+        let q = include_str!("count.sql")
+            .replace("__insertion_point_filters", &filters.build_where_clause());
+
+        let count = if estimated {
+            let q = format!("EXPLAIN (FORMAT JSON) {q}");
+            let query = Self::bind_count_query(
+                sqlx::query_as::<_, (pagination::ExplainPlan,)>(&q),
+                auth,
+                filters,
+            );
+            let (plan,) = query.fetch_one(&mut *db).await.change_context(Error::Db)?;
+            pagination::plan_rows(&plan)
+        } else {
+            let q = format!("SELECT COUNT(*) FROM ({q}) matching");
+            let query = Self::bind_count_query(sqlx::query_as::<_, (i64,)>(&q), auth, filters);
+            let (count,) = query.fetch_one(&mut *db).await.change_context(Error::Db)?;
+            count
+        };
+
+        Ok(ListTotal { count, estimated })
+    }
+
+    fn bind_count_query<'a, T>(
+        mut query: QueryAs<'a, T>,
+        auth: &'a AuthInfo,
+        filters: &'a ListQueryFilters,
+    ) -> QueryAs<'a, T> {
+        // $2 and $3 are the LIMIT and OFFSET in the list queries. The count query doesn't use them,
+        // but they are still bound so that the filters use the same binding numbers.
+        query = query.bind(&auth.organization_id).bind(0i32).bind(0i32);
+
+        filters.bind_to_query(query)
+    }
+
+    /// Stream all the Role objects matching the filters as CSV or NDJSON. Unlike the list
+    /// functions, this does not limit the number of rows returned.
+    pub fn export(
+        db: &PgPool,
+        auth: Authed,
+        filters: ListQueryFilters,
+        format: ExportFormat,
+    ) -> Result<axum::response::Response, error_stack::Report<Error>> {
+        auth.require_permission(super::READ_PERMISSION)?;
+
+        let (descending, order_by_field) =
+            parse_order_by(filters.order_by.as_deref().unwrap_or("name"))
+                .change_context(Error::Filter)?;
+        let order_direction = if descending { "DESC" } else { "ASC" };
+
+        let q = include_str!("list.sql")
+            .replace(
+                "__insertion_point_order_by",
+                &format!("{} {}", order_by_field.as_str(), order_direction),
+            )
+            .replace("__insertion_point_filters", &filters.build_where_clause());
+        let (limit, offset) = export::bounds(filters.page, filters.per_page);
+
+        let response = export::stream(db, "roles", format, move |mut conn, writer| async move {
+            let mut query = sqlx::query_as::<_, RoleListResult>(q.as_str())
+                .bind(&auth.organization_id)
+                .bind(limit)
+                .bind(offset);
+
+            query = filters.bind_to_query(query);
+
+            writer.write_rows(query.fetch(&mut *conn)).await
+        });
+
+        Ok(response)
+    }
+
     async fn list_internal<T>(
         query_template: &str,
         db: impl PgExecutor<'_>,
@@ -292,6 +463,34 @@ impl Role {
         Ok(true)
     }
 
+    /// Update only the fields present in a JSON Merge Patch.
+    #[instrument(skip(db))]
+    pub async fn patch(
+        db: &mut PgConnection,
+        auth: &AuthInfo,
+        id: &RoleId,
+        payload: RolePatchPayload,
+    ) -> Result<bool, error_stack::Report<Error>> {
+        auth.require_permission(super::WRITE_PERMISSION)?;
+
+        let (description_set, description) =
+            crate::models::merge_patch::nullable_binding(&payload.description);
+
+        let result = query_file!(
+            "src/models/role/patch.sql",
+            payload.name.as_ref() as _,
+            description_set,
+            description as _,
+            id.as_uuid(),
+            auth.organization_id.as_uuid()
+        )
+        .execute(&mut *db)
+        .await
+        .change_context(Error::Db)?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
     #[instrument(skip(db))]
     pub async fn delete(
         db: impl PgExecutor<'_>,
@@ -341,3 +540,82 @@ impl Role {
         }
     }
 }
+
+#[async_trait::async_trait]
+impl BulkModel for Role {
+    type Id = RoleId;
+    type CreatePayload = RoleCreatePayload;
+    type UpdatePayload = RoleUpdatePayload;
+
+    fn bulk_create_id(payload: &RoleCreatePayload) -> RoleId {
+        RoleId::new()
+    }
+
+    async fn bulk_insert(
+        db: &mut PgConnection,
+        auth: &AuthInfo,
+        objects: Vec<(RoleId, RoleCreatePayload)>,
+    ) -> Result<Vec<RoleId>, error_stack::Report<Error>> {
+        auth.require_permission(super::CREATE_PERMISSION)?;
+
+        let q = include_str!("bulk_insert.sql");
+        let bindings = ValuesBuilder {
+            first_parameter: 1,
+            num_values: objects.len(),
+            num_columns: 4,
+        };
+        let q = q.replace("__insertion_point_insert_values", &bindings.to_string());
+
+        let mut query = sqlx::query_scalar::<_, RoleId>(q.as_str());
+        for (id, payload) in &objects {
+            query = query
+                .bind(id)
+                .bind(&auth.organization_id)
+                .bind(&payload.name)
+                .bind(&payload.description);
+        }
+
+        let inserted = query.fetch_all(&mut *db).await.change_context(Error::Db)?;
+
+        Ok(inserted)
+    }
+
+    async fn bulk_update(
+        db: &mut PgConnection,
+        auth: &AuthInfo,
+        id: &RoleId,
+        payload: RoleUpdatePayload,
+    ) -> Result<bool, error_stack::Report<Error>> {
+        Self::update(db, auth, id, payload).await
+    }
+
+    async fn bulk_delete(
+        state: &ServerState,
+        db: &mut PgConnection,
+        auth: &Authed,
+        ids: &[RoleId],
+    ) -> Result<BulkDeleted<RoleId>, error_stack::Report<Error>> {
+        auth.require_permission(super::CREATE_PERMISSION)?;
+
+        let uuids = ids
+            .iter()
+            .map(|id| id.as_uuid().clone())
+            .collect::<Vec<_>>();
+        let deleted = query_file_scalar!(
+            "src/models/role/bulk_delete.sql",
+            &uuids,
+            auth.organization_id.as_uuid()
+        )
+        .fetch_all(&mut *db)
+        .await
+        .change_context(Error::Db)?;
+
+        // Deleting a role removes its permissions and user assignments along with it.
+        crate::users::members::ensure_org_has_admin(&mut *db, &auth.organization_id).await?;
+
+        Ok(BulkDeleted {
+            ids: deleted,
+            files: Vec::new(),
+        })
+    }
+}
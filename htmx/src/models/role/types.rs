@@ -109,3 +109,14 @@ impl Default for RoleCreatePayloadAndUpdatePayload {
         }
     }
 }
+
+/// A JSON Merge Patch for a Role. Fields that are omitted from the patch are left unchanged.
+#[derive(Deserialize, Debug, Clone, schemars::JsonSchema)]
+pub struct RolePatchPayload {
+    #[serde(default, deserialize_with = "crate::models::merge_patch::required")]
+    #[schemars(with = "String")]
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "crate::models::merge_patch::nullable")]
+    #[schemars(with = "Option<String>")]
+    pub description: Option<Option<String>>,
+}
@@ -2,10 +2,9 @@
 use std::{borrow::Cow, str::FromStr};
 
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    extract::{OriginalUri, Path, State},
+    http::{HeaderMap, Method, StatusCode},
     response::IntoResponse,
-    routing,
 };
 use axum_extra::extract::Query;
 use axum_jsonschema::Json;
@@ -21,8 +20,14 @@ use super::{
     WRITE_PERMISSION,
 };
 use crate::{
-    auth::{has_any_permission, Authed},
-    server::ServerState,
+    auth::{permissions::ORG_ADMIN_PERMISSION, Authed},
+    models::{export::ExportFormat, merge_patch::MergePatch, pagination},
+    server::{
+        etag::{etag_header, IfMatch},
+        openapi::{model_routes, ApiRoute, ApiRouter},
+        ServerState,
+    },
+    users::members::{self, UserRoles},
     Error,
 };
 
@@ -33,17 +38,36 @@ async fn get(
 ) -> Result<impl IntoResponse, Error> {
     let object = User::get(&state.db, &auth, &id).await?;
 
-    Ok(Json(object))
+    Ok((etag_header(&object.updated_at), Json(object)))
 }
 
 async fn list(
     State(state): State<ServerState>,
     auth: Authed,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
     Query(qs): Query<queries::ListQueryFilters>,
 ) -> Result<impl IntoResponse, Error> {
+    if let Some(format) = ExportFormat::from_headers(&headers) {
+        return Ok(User::export(&state.db, auth, qs, format)?);
+    }
+
     let results = User::list(&state.db, &auth, &qs).await?;
 
-    Ok(Json(results))
+    let total = if qs.include_total.unwrap_or(false) {
+        let mut conn = state.db.acquire().await.change_context(Error::Db)?;
+        Some(User::count(&mut conn, &auth, &qs).await?)
+    } else {
+        None
+    };
+
+    Ok(pagination::list_response(
+        &uri,
+        qs.page,
+        qs.per_page,
+        results,
+        total,
+    ))
 }
 
 async fn create(
@@ -62,10 +86,14 @@ async fn update(
     State(state): State<ServerState>,
     auth: Authed,
     Path(id): Path<UserId>,
+    if_match: IfMatch,
     FormOrJson(payload): FormOrJson<UserUpdatePayload>,
 ) -> Result<impl IntoResponse, Error> {
     let mut tx = state.db.begin().await.change_context(Error::Db)?;
 
+    if_match
+        .lock_and_check(&mut *tx, "users", id.as_uuid(), &auth.organization_id)
+        .await?;
     let result = User::update(&mut *tx, &auth, &id, payload).await?;
 
     tx.commit().await.change_context(Error::Db)?;
@@ -77,6 +105,29 @@ async fn update(
     }
 }
 
+async fn patch(
+    State(state): State<ServerState>,
+    auth: Authed,
+    Path(id): Path<UserId>,
+    if_match: IfMatch,
+    MergePatch(payload): MergePatch<UserPatchPayload>,
+) -> Result<impl IntoResponse, Error> {
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
+
+    if_match
+        .lock_and_check(&mut *tx, "users", id.as_uuid(), &auth.organization_id)
+        .await?;
+    let result = User::patch(&mut *tx, &auth, &id, payload).await?;
+
+    tx.commit().await.change_context(Error::Db)?;
+
+    if result {
+        Ok(StatusCode::OK)
+    } else {
+        Ok(StatusCode::NOT_FOUND)
+    }
+}
+
 async fn delete(
     State(state): State<ServerState>,
     auth: Authed,
@@ -90,36 +141,101 @@ async fn delete(
         return Ok(StatusCode::NOT_FOUND);
     }
 
+    members::ensure_org_has_admin(&mut *tx, &auth.organization_id).await?;
+
     tx.commit().await.change_context(Error::Db)?;
 
     Ok(StatusCode::OK)
 }
 
-pub fn create_routes() -> axum::Router<ServerState> {
-    axum::Router::new()
-        .route(
-            "/users",
-            routing::get(list).route_layer(has_any_permission(vec![READ_PERMISSION, "org_admin"])),
-        )
-        .route(
-            "/users/:id",
-            routing::get(get).route_layer(has_any_permission(vec![READ_PERMISSION, "org_admin"])),
-        )
+async fn get_roles(
+    State(state): State<ServerState>,
+    auth: Authed,
+    Path(id): Path<UserId>,
+) -> Result<impl IntoResponse, Error> {
+    let member = members::get_member(&state.db, &auth.organization_id, &id).await?;
+
+    Ok(Json(UserRoles {
+        role_ids: member.role_ids,
+    }))
+}
+
+async fn update_roles(
+    State(state): State<ServerState>,
+    auth: Authed,
+    Path(id): Path<UserId>,
+    FormOrJson(payload): FormOrJson<UserRoles>,
+) -> Result<impl IntoResponse, Error> {
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
+    members::replace_user_roles(&mut *tx, &auth.organization_id, &id, &payload.role_ids).await?;
+    tx.commit().await.change_context(Error::Db)?;
+
+    Ok(StatusCode::OK)
+}
+
+fn routes() -> ApiRouter {
+    let read_perms = &[READ_PERMISSION, "org_admin"];
+    let write_perms = &[WRITE_PERMISSION, OWNER_PERMISSION, "org_admin"];
+    let create_perms = &[CREATE_PERMISSION, "org_admin"];
+
+    let model = model_routes::<
+        UserId,
+        User,
+        User,
+        queries::ListQueryFilters,
+        UserCreatePayload,
+        UserCreateResult,
+        UserUpdatePayload,
+        UserPatchPayload,
+    >(
+        "user",
+        "/users",
+        "/users/:id",
+        read_perms,
+        write_perms,
+        create_perms,
+    );
+
+    ApiRouter::new()
+        .route(model.list, list)
+        .route(model.get, get)
+        .route(model.update, update)
+        .route(model.patch, patch)
+        .route(model.delete, delete)
         .route(
-            "/users/:id",
-            routing::put(update).route_layer(has_any_permission(vec![
-                WRITE_PERMISSION,
-                OWNER_PERMISSION,
-                "org_admin",
-            ])),
+            ApiRoute::new(
+                Method::GET,
+                "/users/:id/roles",
+                "user",
+                "List the roles assigned to a user",
+            )
+            .permissions(read_perms)
+            .path_param::<UserId>()
+            .response::<UserRoles>(),
+            get_roles,
         )
         .route(
-            "/users/:id",
-            routing::delete(delete)
-                .route_layer(has_any_permission(vec![CREATE_PERMISSION, "org_admin"])),
+            ApiRoute::new(
+                Method::PUT,
+                "/users/:id/roles",
+                "user",
+                "Replace the roles assigned to a user",
+            )
+            .permissions(&[ORG_ADMIN_PERMISSION])
+            .path_param::<UserId>()
+            .request::<UserRoles>(),
+            update_roles,
         )
 }
 
+pub fn create_routes() -> axum::Router<ServerState> {
+    routes().into_router()
+}
+
+pub fn api_docs() -> Vec<ApiRoute> {
+    routes().into_docs()
+}
+
 #[cfg(test)]
 mod test {
     use filigree::testing::ResponseExt;
@@ -290,8 +406,100 @@ mod test {
     async fn list_paginated(_pool: sqlx::PgPool) {}
 
     #[sqlx::test]
-    #[ignore = "todo"]
-    async fn list_filters(_pool: sqlx::PgPool) {}
+    async fn list_filters(pool: sqlx::PgPool) {
+        let (
+            _app,
+            BootstrappedData {
+                organization,
+                admin_user,
+                ..
+            },
+        ) = start_app(pool.clone()).await;
+
+        let added_objects = setup_test_objects(&pool, organization.id, 3).await;
+
+        let fetch_indexes = |query: Vec<(&'static str, &'static str)>| {
+            let client = &admin_user.client;
+            let added_objects = &added_objects;
+            async move {
+                let results = client
+                    .get("users")
+                    .query(&query)
+                    .send()
+                    .await
+                    .unwrap()
+                    .log_error()
+                    .await
+                    .unwrap()
+                    .json::<Vec<serde_json::Value>>()
+                    .await
+                    .unwrap();
+
+                // Skip the users that were created when bootstrapping the organization.
+                let mut indexes = results
+                    .iter()
+                    .filter_map(|o| {
+                        added_objects
+                            .iter()
+                            .position(|(_, added)| o["id"] == added.id.to_string())
+                    })
+                    .collect::<Vec<_>>();
+                indexes.sort();
+                indexes
+            }
+        };
+
+        assert_eq!(
+            fetch_indexes(vec![("name_contains", "object 1")]).await,
+            vec![1],
+            "name_contains"
+        );
+        assert_eq!(
+            fetch_indexes(vec![("name_contains", "TEST OBJECT")]).await,
+            Vec::<usize>::new(),
+            "name_contains is case sensitive"
+        );
+        assert_eq!(
+            fetch_indexes(vec![("name_icontains", "TEST OBJECT")]).await,
+            vec![0, 1, 2],
+            "name_icontains"
+        );
+        assert_eq!(
+            fetch_indexes(vec![("email_contains", "object 2")]).await,
+            vec![2],
+            "email_contains"
+        );
+        assert_eq!(
+            fetch_indexes(vec![("email_icontains", "OBJECT 2")]).await,
+            vec![2],
+            "email_icontains"
+        );
+        assert_eq!(
+            fetch_indexes(vec![("avatar_url_contains", "object 2")]).await,
+            vec![2],
+            "avatar_url_contains"
+        );
+        assert_eq!(
+            fetch_indexes(vec![("avatar_url_icontains", "TEST OBJECT")]).await,
+            vec![2],
+            "avatar_url_icontains"
+        );
+        assert_eq!(
+            fetch_indexes(vec![("email_is_null", "true")]).await,
+            vec![0, 1],
+            "email_is_null"
+        );
+        assert_eq!(
+            fetch_indexes(vec![("avatar_url_is_null", "false")]).await,
+            vec![2],
+            "avatar_url_is_null"
+        );
+        assert_eq!(
+            fetch_indexes(vec![("email_is_null", "false"), ("name_icontains", "test")]).await,
+            vec![2],
+            "combined filters"
+        );
+    }
 
     #[sqlx::test]
     async fn get_object(pool: sqlx::PgPool) {
@@ -482,4 +690,91 @@ mod test {
             .unwrap();
         assert_eq!(response.status(), reqwest::StatusCode::OK);
     }
+
+    #[sqlx::test]
+    async fn update_user_roles(pool: sqlx::PgPool) {
+        let (
+            _app,
+            BootstrappedData {
+                admin_user,
+                user,
+                admin_role,
+                user_role,
+                ..
+            },
+        ) = start_app(pool.clone()).await;
+
+        admin_user
+            .client
+            .put(&format!("users/{}/roles", user.user_id))
+            .json(&serde_json::json!({ "role_ids": [admin_role, user_role] }))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap();
+
+        let result: serde_json::Value = user
+            .client
+            .get(&format!("users/{}/roles", user.user_id))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(result["role_ids"].as_array().unwrap().len(), 2);
+
+        // With two admins, removing admin from one of them is fine.
+        admin_user
+            .client
+            .put(&format!("users/{}/roles", admin_user.user_id))
+            .json(&serde_json::json!({ "role_ids": [user_role] }))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap();
+
+        // But the last one can not be removed.
+        let response = user
+            .client
+            .put(&format!("users/{}/roles", user.user_id))
+            .json(&serde_json::json!({ "role_ids": [user_role] }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::CONFLICT);
+    }
+
+    #[sqlx::test]
+    async fn delete_last_admin(pool: sqlx::PgPool) {
+        let (_app, BootstrappedData { admin_user, .. }) = start_app(pool.clone()).await;
+
+        // Deleting the only admin would leave the org without one
+        let response = admin_user
+            .client
+            .delete(&format!("users/{}", admin_user.user_id))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::CONFLICT);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"]["kind"], "last_admin");
+
+        admin_user
+            .client
+            .get(&format!("users/{}", admin_user.user_id))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap();
+    }
 }
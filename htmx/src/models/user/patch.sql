@@ -0,0 +1,14 @@
+UPDATE
+  public.users
+SET
+  name = COALESCE($1, name),
+  email = CASE WHEN $2 THEN $3
+    ELSE email
+  END,
+  avatar_url = CASE WHEN $4 THEN $5
+    ELSE avatar_url
+  END,
+  updated_at = NOW()
+WHERE
+  id = $6
+  AND organization_id = $7
@@ -121,3 +121,17 @@ impl Default for UserCreatePayloadAndUpdatePayload {
         }
     }
 }
+
+/// A JSON Merge Patch for a User. Fields that are omitted from the patch are left unchanged.
+#[derive(Deserialize, Debug, Clone, schemars::JsonSchema)]
+pub struct UserPatchPayload {
+    #[serde(default, deserialize_with = "crate::models::merge_patch::required")]
+    #[schemars(with = "String")]
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "crate::models::merge_patch::nullable")]
+    #[schemars(with = "Option<String>")]
+    pub email: Option<Option<String>>,
+    #[serde(default, deserialize_with = "crate::models::merge_patch::nullable")]
+    #[schemars(with = "Option<String>")]
+    pub avatar_url: Option<Option<String>>,
+}
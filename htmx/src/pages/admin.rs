@@ -0,0 +1,403 @@
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    routing,
+};
+use axum_extra::extract::Form;
+use error_stack::ResultExt;
+use maud::{html, Markup};
+use schemars::JsonSchema;
+
+use crate::{
+    auth::{
+        has_any_permission,
+        permissions::{ORG_ADMIN_PERMISSION, PERMISSIONS},
+        Authed,
+    },
+    models::{role::RoleId, user::UserId},
+    pages::{auth::WebAuthed, error::HtmlError, layout::root_layout_page},
+    server::ServerState,
+    users::members::{self, OrganizationMember, RoleWithPermissions},
+    Error,
+};
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, JsonSchema)]
+pub struct ToggleRolePayload {
+    pub role_id: RoleId,
+    pub enabled: bool,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, JsonSchema)]
+pub struct ToggleActivePayload {
+    pub active: bool,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, JsonSchema)]
+pub struct TogglePermissionPayload {
+    pub permission: String,
+    pub enabled: bool,
+}
+
+fn admin_nav() -> Markup {
+    html! {
+        div.tabs.tabs-bordered {
+            a.tab href="/admin/users" { "Users" }
+            a.tab href="/admin/roles" { "Roles" }
+        }
+    }
+}
+
+/// A toggle button that posts the opposite of its current state.
+fn toggle_button(label: &str, on: bool) -> Markup {
+    html! {
+        button.btn.btn-xs.btn-primary[on].btn-outline[!on]
+            type="submit"
+            aria-pressed=(if on { "true" } else { "false" })
+        { (label) }
+    }
+}
+
+fn member_row_fragment(
+    member: &OrganizationMember,
+    roles: &[RoleWithPermissions],
+    is_self: bool,
+) -> Markup {
+    html! {
+        tr.opacity-50[!member.active] id={ "member-" (member.user_id) } {
+            td { (member.name) }
+            td { (member.email.as_deref().unwrap_or_default()) }
+            td.flex.flex-wrap.gap-1 {
+                @for role in roles {
+                    @let has_role = member.role_ids.contains(&role.id);
+                    form
+                        hx-post={ "/admin/_action/users/" (member.user_id) "/roles" }
+                        hx-target="closest tr"
+                        hx-swap="outerHTML"
+                    {
+                        input type="hidden" name="role_id" value=(role.id);
+                        input type="hidden" name="enabled" value=(if has_role { "false" } else { "true" });
+                        (toggle_button(&role.name, has_role))
+                    }
+                }
+            }
+            td {
+                @if is_self {
+                    span.opacity-70 { "You" }
+                } @else {
+                    form
+                        hx-post={ "/admin/_action/users/" (member.user_id) "/active" }
+                        hx-target="closest tr"
+                        hx-swap="outerHTML"
+                    {
+                        input type="hidden" name="active" value=(if member.active { "false" } else { "true" });
+                        button.btn.btn-xs type="submit" {
+                            @if member.active { "Deactivate" } @else { "Reactivate" }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn role_permission_fragment(role_id: &RoleId, key: &str, label: &str, enabled: bool) -> Markup {
+    html! {
+        form
+            hx-post={ "/admin/_action/roles/" (role_id) "/permissions" }
+            hx-swap="outerHTML"
+        {
+            input type="hidden" name="permission" value=(key);
+            input type="hidden" name="enabled" value=(if enabled { "false" } else { "true" });
+            (toggle_button(label, enabled))
+        }
+    }
+}
+
+fn role_fragment(role: &RoleWithPermissions) -> Markup {
+    let has = |key: &str| role.permissions.iter().any(|p| p == key);
+    html! {
+        div.card.bg-base-100.shadow {
+            div.card-body {
+                h2.card-title { (role.name) }
+                @if let Some(description) = &role.description {
+                    p { (description) }
+                }
+                div.flex.flex-wrap.gap-1 {
+                    (role_permission_fragment(
+                        &role.id,
+                        ORG_ADMIN_PERMISSION,
+                        "Organization Admin",
+                        has(ORG_ADMIN_PERMISSION),
+                    ))
+                    @for permission in PERMISSIONS {
+                        div title=(permission.description) {
+                            (role_permission_fragment(&role.id, permission.key, permission.name, has(permission.key)))
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn users_page(
+    State(state): State<ServerState>,
+    auth: WebAuthed,
+) -> Result<impl IntoResponse, HtmlError> {
+    auth.require_permission(ORG_ADMIN_PERMISSION)?;
+
+    let members = members::list_members(&state.db, &auth.organization_id).await?;
+    let roles = members::list_roles_with_permissions(&state.db, &auth.organization_id).await?;
+    let invites = members::list_pending_invites(&state.db, &auth.organization_id).await?;
+
+    let body = html! {
+        div.flex.flex-col.gap-6.max-w-4xl.mx-auto.my-8 {
+            h1.text-2xl.font-bold { "Users" }
+            (admin_nav())
+            table.table {
+                thead { tr { th { "Name" } th { "Email" } th { "Roles" } th {} } }
+                tbody {
+                    @for member in &members {
+                        (member_row_fragment(member, &roles, member.user_id == auth.user_id))
+                    }
+                }
+            }
+            h2.text-xl.font-bold { "Pending Invites" }
+            @if invites.is_empty() {
+                p.opacity-70 { "No pending invites" }
+            } @else {
+                table.table {
+                    thead { tr { th { "Email" } th { "Name" } th { "Sent" } th { "Expires" } } }
+                    tbody {
+                        @for invite in &invites {
+                            tr {
+                                td { (invite.email) }
+                                td { (invite.name.as_deref().unwrap_or_default()) }
+                                td { (invite.invite_sent_at.format("%Y-%m-%d")) }
+                                td { (invite.token_expires_at.format("%Y-%m-%d")) }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    };
+
+    Ok(root_layout_page(Some(&auth), "Users", body))
+}
+
+async fn roles_page(
+    State(state): State<ServerState>,
+    auth: WebAuthed,
+) -> Result<impl IntoResponse, HtmlError> {
+    auth.require_permission(ORG_ADMIN_PERMISSION)?;
+
+    let roles = members::list_roles_with_permissions(&state.db, &auth.organization_id).await?;
+
+    let body = html! {
+        div.flex.flex-col.gap-6.max-w-4xl.mx-auto.my-8 {
+            h1.text-2xl.font-bold { "Roles" }
+            (admin_nav())
+            @for role in &roles {
+                (role_fragment(role))
+            }
+        }
+    };
+
+    Ok(root_layout_page(Some(&auth), "Roles", body))
+}
+
+async fn toggle_role_action(
+    State(state): State<ServerState>,
+    auth: Authed,
+    Path(user_id): Path<UserId>,
+    Form(form): Form<ToggleRolePayload>,
+) -> Result<impl IntoResponse, HtmlError> {
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
+    members::set_user_role(
+        &mut *tx,
+        &auth.organization_id,
+        &user_id,
+        &form.role_id,
+        form.enabled,
+    )
+    .await?;
+    members::ensure_org_has_admin(&mut *tx, &auth.organization_id).await?;
+    tx.commit().await.change_context(Error::Db)?;
+
+    let member = members::get_member(&state.db, &auth.organization_id, &user_id).await?;
+    let roles = members::list_roles_with_permissions(&state.db, &auth.organization_id).await?;
+
+    Ok(member_row_fragment(
+        &member,
+        &roles,
+        member.user_id == auth.user_id,
+    ))
+}
+
+async fn toggle_active_action(
+    State(state): State<ServerState>,
+    auth: Authed,
+    Path(user_id): Path<UserId>,
+    Form(form): Form<ToggleActivePayload>,
+) -> Result<impl IntoResponse, HtmlError> {
+    if user_id == auth.user_id {
+        // Don't let admins lock themselves out.
+        return Err(Error::MissingPermission(ORG_ADMIN_PERMISSION).into());
+    }
+
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
+    members::set_member_active(&mut *tx, &auth.organization_id, &user_id, form.active).await?;
+    members::ensure_org_has_admin(&mut *tx, &auth.organization_id).await?;
+    tx.commit().await.change_context(Error::Db)?;
+
+    let member = members::get_member(&state.db, &auth.organization_id, &user_id).await?;
+    let roles = members::list_roles_with_permissions(&state.db, &auth.organization_id).await?;
+
+    Ok(member_row_fragment(&member, &roles, false))
+}
+
+async fn toggle_permission_action(
+    State(state): State<ServerState>,
+    auth: Authed,
+    Path(role_id): Path<RoleId>,
+    Form(form): Form<TogglePermissionPayload>,
+) -> Result<impl IntoResponse, HtmlError> {
+    let label = if form.permission == ORG_ADMIN_PERMISSION {
+        "Organization Admin"
+    } else {
+        PERMISSIONS
+            .iter()
+            .find(|p| p.key == form.permission)
+            .map(|p| p.name)
+            .ok_or(Error::NotFound("Permission"))?
+    };
+
+    let roles = members::list_roles_with_permissions(&state.db, &auth.organization_id).await?;
+    if !roles.iter().any(|r| r.id == role_id) {
+        return Err(Error::NotFound("Role").into());
+    }
+
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
+    members::set_role_permission(
+        &mut *tx,
+        &auth.organization_id,
+        &role_id,
+        &form.permission,
+        form.enabled,
+    )
+    .await?;
+    members::ensure_org_has_admin(&mut *tx, &auth.organization_id).await?;
+    tx.commit().await.change_context(Error::Db)?;
+
+    Ok(role_permission_fragment(
+        &role_id,
+        &form.permission,
+        label,
+        form.enabled,
+    ))
+}
+
+pub fn create_routes() -> axum::Router<ServerState> {
+    axum::Router::new()
+        .route("/admin/users", routing::get(users_page))
+        .route("/admin/roles", routing::get(roles_page))
+        .route(
+            "/admin/_action/users/:id/roles",
+            routing::post(toggle_role_action)
+                .route_layer(has_any_permission(vec![ORG_ADMIN_PERMISSION])),
+        )
+        .route(
+            "/admin/_action/users/:id/active",
+            routing::post(toggle_active_action)
+                .route_layer(has_any_permission(vec![ORG_ADMIN_PERMISSION])),
+        )
+        .route(
+            "/admin/_action/roles/:id/permissions",
+            routing::post(toggle_permission_action)
+                .route_layer(has_any_permission(vec![ORG_ADMIN_PERMISSION])),
+        )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tests::{start_app, BootstrappedData};
+
+    #[sqlx::test]
+    async fn admin_pages(db: sqlx::PgPool) {
+        let (
+            app,
+            BootstrappedData {
+                admin_user,
+                user,
+                user_role,
+                ..
+            },
+        ) = start_app(db.clone()).await;
+
+        let client = reqwest::Client::new();
+        let admin_bearer = format!("Bearer {}", admin_user.api_key);
+
+        let page = client
+            .get(format!("{}/admin/users", app.base_url))
+            .header("Authorization", &admin_bearer)
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert!(page.contains(&user.email));
+
+        let response = client
+            .get(format!("{}/admin/roles", app.base_url))
+            .header("Authorization", format!("Bearer {}", user.api_key))
+            .send()
+            .await
+            .unwrap();
+        assert!(!response.status().is_success());
+
+        client
+            .post(format!(
+                "{}/admin/_action/roles/{}/permissions",
+                app.base_url, user_role
+            ))
+            .header("Authorization", &admin_bearer)
+            .form(&[("permission", "Post::owner"), ("enabled", "false")])
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+
+        let permissions = crate::users::members::get_role_permissions(
+            &db,
+            &admin_user.organization_id,
+            &user_role,
+        )
+        .await
+        .unwrap();
+        assert!(!permissions.iter().any(|p| p == "Post::owner"));
+
+        client
+            .post(format!(
+                "{}/admin/_action/users/{}/active",
+                app.base_url, user.user_id
+            ))
+            .header("Authorization", &admin_bearer)
+            .form(&[("active", "false")])
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+
+        let member =
+            crate::users::members::get_member(&db, &admin_user.organization_id, &user.user_id)
+                .await
+                .unwrap();
+        assert!(!member.active);
+    }
+}
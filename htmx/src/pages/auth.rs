@@ -4,7 +4,7 @@ use axum::{
     extract::{FromRequestParts, Request},
     response::{IntoResponse, Redirect, Response},
 };
-use axum_htmx::HxLocation;
+use axum_htmx::{HxLocation, HxRedirect};
 use filigree::{auth::AuthInfo as _, errors::HttpError};
 use futures::future::BoxFuture;
 use http::{request::Parts, StatusCode, Uri};
@@ -60,6 +60,42 @@ fn redirect_body(to: Uri) -> Response {
     (HxLocation::from_uri(to), Redirect::to(&t)).into_response()
 }
 
+/// Redirect after a successful form submission. htmx requests get an `HX-Redirect` header so that
+/// the whole page reloads, and plain form posts get a normal redirect.
+pub fn form_redirect(hx_request: bool, to: &str) -> Response {
+    if hx_request {
+        match to.parse::<Uri>() {
+            Ok(uri) => HxRedirect(uri).into_response(),
+            Err(_) => HxRedirect(Uri::from_static("/")).into_response(),
+        }
+    } else {
+        Redirect::to(to).into_response()
+    }
+}
+
+/// Return a redirect target that is safe to send the user to, falling back to the home page.
+/// Only local paths are allowed, to avoid redirecting to other sites.
+pub fn safe_redirect_path(redirect_to: Option<&str>) -> &str {
+    match redirect_to {
+        Some(path) if is_local_path(path) => path,
+        _ => "/",
+    }
+}
+
+fn is_local_path(path: &str) -> bool {
+    // Browsers treat `\` like `/`, so `/\evil.com` would be a link to another site.
+    if !path.starts_with('/')
+        || path.contains("//")
+        || path.contains('\\')
+        || path.chars().any(|c| c.is_control())
+    {
+        return false;
+    }
+
+    path.parse::<Uri>()
+        .is_ok_and(|uri| uri.scheme().is_none() && uri.authority().is_none())
+}
+
 pub fn make_login_link(redirect_to: Option<&Uri>) -> Uri {
     if let Some(r) = redirect_to {
         let redirect_to = r
@@ -139,3 +175,28 @@ where
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::safe_redirect_path;
+
+    #[test]
+    fn redirect_paths() {
+        assert_eq!(safe_redirect_path(None), "/");
+        assert_eq!(safe_redirect_path(Some("/posts")), "/posts");
+        assert_eq!(safe_redirect_path(Some("/posts?page=2")), "/posts?page=2");
+
+        for path in [
+            "",
+            "posts",
+            "https://evil.com",
+            "//evil.com",
+            "/\\evil.com",
+            "/\\/evil.com",
+            "/posts\nLocation: https://evil.com",
+            "/\tevil.com",
+        ] {
+            assert_eq!(safe_redirect_path(Some(path)), "/", "{path:?}");
+        }
+    }
+}
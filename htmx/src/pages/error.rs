@@ -1,28 +1,90 @@
 use axum::response::{IntoResponse, Response};
 use filigree::errors::HttpError;
 use http::StatusCode;
+use maud::{html, Markup};
 
 use crate::Error;
 
-pub struct HtmlError(pub Error);
+pub enum HtmlError {
+    /// An error that should be shown as a full error page.
+    Page(Error),
+    /// A validation error. The markup is the form re-rendered with its errors shown inline, and
+    /// is returned with a 422 status so that htmx swaps it in place of the submitted form.
+    Form(Markup),
+}
 
 impl From<Error> for HtmlError {
     fn from(value: Error) -> Self {
-        HtmlError(value)
+        HtmlError::Page(value)
     }
 }
 
 impl From<error_stack::Report<Error>> for HtmlError {
     fn from(value: error_stack::Report<Error>) -> Self {
-        HtmlError(Error::WrapReport(value))
+        HtmlError::Page(Error::WrapReport(value))
     }
 }
 
 impl IntoResponse for HtmlError {
     fn into_response(self) -> Response {
-        match self.0.status_code() {
-            StatusCode::NOT_FOUND => super::not_found::not_found_page(),
-            _ => super::generic_error::generic_error_page(&self.0),
+        match self {
+            HtmlError::Page(e) => match e.status_code() {
+                StatusCode::NOT_FOUND => super::not_found::not_found_page(),
+                _ => super::generic_error::generic_error_page(&e),
+            },
+            HtmlError::Form(body) => (StatusCode::UNPROCESSABLE_ENTITY, body).into_response(),
+        }
+    }
+}
+
+/// Validation errors for a form, to be rendered next to the relevant inputs.
+#[derive(Debug, Default)]
+pub struct FormErrors {
+    /// An error that applies to the form as a whole
+    pub message: Option<String>,
+    /// Errors for individual fields, keyed by the input name
+    pub fields: Vec<(&'static str, String)>,
+}
+
+impl FormErrors {
+    /// Create a set of errors with just a message for the whole form.
+    pub fn with_message(message: impl Into<String>) -> Self {
+        FormErrors {
+            message: Some(message.into()),
+            fields: Vec::new(),
+        }
+    }
+
+    pub fn add_field(&mut self, field: &'static str, message: impl Into<String>) {
+        self.fields.push((field, message.into()));
+    }
+
+    pub fn field(&self, field: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(name, _)| *name == field)
+            .map(|(_, message)| message.as_str())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.message.is_none() && self.fields.is_empty()
+    }
+
+    /// Render the form-level error message, if there is one.
+    pub fn message_alert(&self) -> Markup {
+        html! {
+            @if let Some(message) = &self.message {
+                div.alert.alert-error role="alert" { (message) }
+            }
+        }
+    }
+
+    /// Render the error for a single field, if there is one.
+    pub fn field_error(&self, field: &str) -> Markup {
+        html! {
+            @if let Some(message) = self.field(field) {
+                div.label { span.label-text-alt.text-error { (message) } }
+            }
         }
     }
 }
@@ -1,16 +1,18 @@
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
+    extract::{Host, State},
     response::IntoResponse,
     routing,
 };
 use filigree::extract::FormOrJson;
-use maud::html;
+use maud::{html, Markup};
 use schemars::JsonSchema;
 
 use crate::{
-    auth::{has_any_permission, Authed},
-    pages::{error::HtmlError, layout::root_layout_page},
+    auth::password_management::send_password_reset_email,
+    pages::{
+        error::{FormErrors, HtmlError},
+        layout::root_layout_page,
+    },
     server::ServerState,
 };
 
@@ -19,15 +21,52 @@ pub struct ForgotPayload {
     email: String,
 }
 
+fn forgot_form_fragment(email: &str, errors: &FormErrors) -> Markup {
+    html! {
+        form.flex.flex-col.gap-2 method="post" action="/forgot" hx-post="/forgot" hx-swap="outerHTML" {
+            (errors.message_alert())
+            label.form-control {
+                div.label { span.label-text { "Email" } }
+                input.input.input-bordered type="email" name="email" value=(email) required autocomplete="email";
+                (errors.field_error("email"))
+            }
+            button.btn.btn-primary type="submit" { "Send reset link" }
+            a.link href="/login" { "Back to login" }
+        }
+    }
+}
+
 async fn forgot_form(
     State(state): State<ServerState>,
+    Host(host): Host,
     FormOrJson(payload): FormOrJson<ForgotPayload>,
 ) -> Result<impl IntoResponse, HtmlError> {
-    Ok(html! {})
+    if payload.email.trim().is_empty() {
+        let mut errors = FormErrors::default();
+        errors.add_field("email", "Email is required");
+        return Err(HtmlError::Form(forgot_form_fragment(&payload.email, &errors)));
+    }
+
+    send_password_reset_email(&state, host, payload.email).await?;
+
+    Ok(html! {
+        div.alert.alert-success role="status" {
+            "If an account exists for that email, we've sent a link to reset your password."
+        }
+    })
 }
 
-async fn forgot_page(State(state): State<ServerState>) -> impl IntoResponse {
-    root_layout_page(None, "Forgot", html! { h1 { "Forgot" } })
+async fn forgot_page() -> impl IntoResponse {
+    root_layout_page(
+        None,
+        "Forgot Password",
+        html! {
+            div.flex.flex-col.gap-8.max-w-sm.mx-auto.mt-16 {
+                h1.text-2xl.font-bold { "Forgot your password?" }
+                (forgot_form_fragment("", &FormErrors::default()))
+            }
+        },
+    )
 }
 
 pub fn create_routes() -> axum::Router<ServerState> {
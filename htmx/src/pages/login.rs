@@ -1,20 +1,31 @@
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
-    response::IntoResponse,
+    extract::{Host, Query, State},
+    response::{IntoResponse, Response},
     routing,
 };
+use axum_htmx::HxRequest;
+use error_stack::ResultExt;
 use filigree::{
-    auth::{password::login_with_password, EmailAndPassword},
+    auth::{
+        password::login_with_password, passwordless_email_login::perform_passwordless_login,
+        EmailAndPassword,
+    },
     extract::FormOrJson,
 };
-use maud::html;
+use maud::{html, Markup};
 use schemars::JsonSchema;
+use tower_cookies::Cookies;
+use uuid::Uuid;
 
 use crate::{
-    auth::{has_any_permission, Authed},
-    pages::{error::HtmlError, layout::root_layout_page},
+    auth::passwordless_login::{accept_new_user_invite, send_passwordless_login_email},
+    pages::{
+        auth::{form_redirect, safe_redirect_path},
+        error::{FormErrors, HtmlError},
+        layout::root_layout_page,
+    },
     server::ServerState,
+    Error,
 };
 
 #[derive(serde::Deserialize, Debug)]
@@ -22,20 +33,305 @@ struct RedirectTo {
     redirect_to: Option<String>,
 }
 
+/// The query string for the login page. The token fields are set when the user arrives from a
+/// passwordless login email.
+#[derive(serde::Deserialize, Debug)]
+struct LoginPageQuery {
+    redirect_to: Option<String>,
+    email: Option<String>,
+    token: Option<Uuid>,
+    #[serde(default)]
+    invite: bool,
+}
+
+#[derive(serde::Deserialize, Debug, JsonSchema)]
+pub struct EmailLinkPayload {
+    email: String,
+}
+
+/// Build a form action URL that carries the redirect target along with it.
+fn action_url(path: &str, redirect_to: Option<&str>) -> String {
+    match redirect_to {
+        Some(r) => format!(
+            "{path}?redirect_to={}",
+            url::form_urlencoded::byte_serialize(r.as_bytes()).collect::<String>()
+        ),
+        None => path.to_string(),
+    }
+}
+
+fn password_login_form(redirect_to: Option<&str>, email: &str, errors: &FormErrors) -> Markup {
+    html! {
+        form.flex.flex-col.gap-2
+            method="post"
+            action=(action_url("/login", redirect_to))
+            hx-post=(action_url("/login", redirect_to))
+            hx-swap="outerHTML"
+        {
+            (errors.message_alert())
+            label.form-control {
+                div.label { span.label-text { "Email" } }
+                input.input.input-bordered type="email" name="email" value=(email) required autocomplete="email";
+                (errors.field_error("email"))
+            }
+            label.form-control {
+                div.label { span.label-text { "Password" } }
+                input.input.input-bordered type="password" name="password" required autocomplete="current-password";
+                (errors.field_error("password"))
+            }
+            button.btn.btn-primary type="submit" { "Log in" }
+            a.link href="/forgot" { "Forgot your password?" }
+        }
+    }
+}
+
+fn email_link_form(redirect_to: Option<&str>, email: &str, errors: &FormErrors) -> Markup {
+    html! {
+        form.flex.flex-col.gap-2
+            method="post"
+            action=(action_url("/login/email", redirect_to))
+            hx-post=(action_url("/login/email", redirect_to))
+            hx-swap="outerHTML"
+        {
+            (errors.message_alert())
+            label.form-control {
+                div.label { span.label-text { "Email" } }
+                input.input.input-bordered type="email" name="email" value=(email) required autocomplete="email";
+                (errors.field_error("email"))
+            }
+            button.btn type="submit" { "Email me a login link" }
+        }
+    }
+}
+
+fn login_page_body(redirect_to: Option<&str>, errors: &FormErrors) -> Markup {
+    html! {
+        div.flex.flex-col.gap-8.max-w-sm.mx-auto.mt-16 {
+            h1.text-2xl.font-bold { "Login" }
+            (password_login_form(redirect_to, "", errors))
+            div.divider { "or" }
+            (email_link_form(redirect_to, "", &FormErrors::default()))
+        }
+    }
+}
+
 async fn login_form(
     State(state): State<ServerState>,
+    HxRequest(hx_request): HxRequest,
+    cookies: Cookies,
     Query(query): Query<RedirectTo>,
     FormOrJson(payload): FormOrJson<EmailAndPassword>,
-) -> impl IntoResponse {
-    html! {}
+) -> Result<Response, HtmlError> {
+    let redirect_to = query.redirect_to.as_deref();
+    let email = payload.email.clone();
+
+    let mut errors = FormErrors::default();
+    if email.trim().is_empty() {
+        errors.add_field("email", "Email is required");
+    }
+    if payload.password.is_empty() {
+        errors.add_field("password", "Password is required");
+    }
+    if !errors.is_empty() {
+        return Err(HtmlError::Form(password_login_form(
+            redirect_to,
+            &email,
+            &errors,
+        )));
+    }
+
+    let result = login_with_password(&state.session_backend, &cookies, payload).await;
+    if let Err(e) = result {
+        if e.current_context().is_unauthenticated() {
+            let errors = FormErrors::with_message("Incorrect email or password");
+            return Err(HtmlError::Form(password_login_form(
+                redirect_to,
+                &email,
+                &errors,
+            )));
+        }
+
+        return Err(e.change_context(Error::AuthSubsystem).into());
+    }
+
+    Ok(form_redirect(hx_request, safe_redirect_path(redirect_to)))
 }
 
-async fn login_page(State(state): State<ServerState>) -> impl IntoResponse {
-    root_layout_page(None, "Login", html! { h1 { "Login" } })
+async fn email_link_form_submit(
+    State(state): State<ServerState>,
+    Host(host): Host,
+    Query(query): Query<RedirectTo>,
+    FormOrJson(payload): FormOrJson<EmailLinkPayload>,
+) -> Result<impl IntoResponse, HtmlError> {
+    let redirect_to = query.redirect_to.as_deref();
+    if payload.email.trim().is_empty() {
+        let mut errors = FormErrors::default();
+        errors.add_field("email", "Email is required");
+        return Err(HtmlError::Form(email_link_form(
+            redirect_to,
+            &payload.email,
+            &errors,
+        )));
+    }
+
+    send_passwordless_login_email(&state, host, payload.email, query.redirect_to.clone()).await?;
+
+    Ok(html! {
+        div.alert.alert-success role="status" {
+            "Check your email for a link to log in."
+        }
+    })
+}
+
+/// Log in using the token from a passwordless login email.
+async fn login_with_email_token(
+    state: &ServerState,
+    cookies: &Cookies,
+    host: &str,
+    email: String,
+    token: Uuid,
+    invite: bool,
+) -> Result<(), error_stack::Report<Error>> {
+    if state.host_is_allowed(host).is_err() {
+        // Bail due to some kind of hijinks
+        return Err(error_stack::Report::new(Error::InvalidHostHeader));
+    }
+
+    if invite {
+        if !state.filigree.new_user_flags.allow_public_signup {
+            return Err(error_stack::Report::new(Error::Login));
+        }
+
+        accept_new_user_invite(state, cookies, email, token).await
+    } else {
+        perform_passwordless_login(&state.filigree, cookies, email, token)
+            .await
+            .change_context(Error::Login)
+    }
+}
+
+async fn login_page(
+    State(state): State<ServerState>,
+    HxRequest(hx_request): HxRequest,
+    Host(host): Host,
+    cookies: Cookies,
+    Query(query): Query<LoginPageQuery>,
+) -> Result<Response, HtmlError> {
+    let redirect_to = query.redirect_to.as_deref();
+
+    let errors = match (query.email, query.token) {
+        (Some(email), Some(token)) => {
+            match login_with_email_token(&state, &cookies, &host, email, token, query.invite).await
+            {
+                Ok(()) => return Ok(form_redirect(hx_request, safe_redirect_path(redirect_to))),
+                Err(e) => match e.current_context() {
+                    Error::Login => {
+                        FormErrors::with_message("That login link is invalid or has expired.")
+                    }
+                    _ => return Err(e.into()),
+                },
+            }
+        }
+        _ => FormErrors::default(),
+    };
+
+    Ok(root_layout_page(None, "Login", login_page_body(redirect_to, &errors)).into_response())
 }
 
 pub fn create_routes() -> axum::Router<ServerState> {
     axum::Router::new()
         .route("/login", routing::get(login_page))
         .route("/login", routing::post(login_form))
+        .route("/login/email", routing::post(email_link_form_submit))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tests::{start_app, BootstrappedData};
+
+    fn web_client() -> reqwest::Client {
+        reqwest::ClientBuilder::new()
+            .cookie_store(true)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap()
+    }
+
+    #[sqlx::test]
+    #[cfg_attr(not(feature = "test_password"), ignore = "slow password test")]
+    async fn password_login(db: sqlx::PgPool) {
+        let (app, BootstrappedData { user, .. }) = start_app(db).await;
+        let client = web_client();
+
+        let response = client
+            .post(format!("{}/login", app.base_url))
+            .form(&[("email", user.email.as_str()), ("password", "wrong")])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+        let body = response.text().await.unwrap();
+        assert!(body.contains("Incorrect email or password"));
+
+        let response = client
+            .post(format!("{}/login?redirect_to=%2Freports", app.base_url))
+            .header("HX-Request", "true")
+            .form(&[
+                ("email", user.email.as_str()),
+                ("password", user.password.as_str()),
+            ])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(response.headers()["hx-redirect"], "/reports");
+
+        let response = client
+            .get(format!("{}/api/self", app.base_url))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+    }
+
+    #[sqlx::test]
+    async fn email_link_login(db: sqlx::PgPool) {
+        let (app, BootstrappedData { user, .. }) = start_app(db).await;
+        let client = web_client();
+
+        client
+            .post(format!("{}/login/email?redirect_to=%2Freports", app.base_url))
+            .form(&[("email", user.email.as_str())])
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+
+        let email = app.sent_emails.lock().unwrap().pop().unwrap();
+        let token = crate::auth::tests::extract_token_from_email(&email);
+
+        let response = client
+            .get(format!("{}/login", app.base_url))
+            .query(&[
+                ("token", token),
+                ("email", user.email.as_str()),
+                ("redirect_to", "/reports"),
+            ])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::SEE_OTHER);
+        assert_eq!(response.headers()["location"], "/reports");
+
+        // The token can only be used once
+        let response = client
+            .get(format!("{}/login", app.base_url))
+            .query(&[("token", token), ("email", user.email.as_str())])
+            .send()
+            .await
+            .unwrap();
+        let body = response.text().await.unwrap();
+        assert!(body.contains("invalid or has expired"));
+    }
 }
@@ -17,6 +17,7 @@ use crate::{
     Error,
 };
 
+mod admin;
 mod auth;
 mod error;
 mod forgot;
@@ -25,6 +26,7 @@ pub mod layout;
 mod login;
 mod logout;
 pub mod not_found;
+mod posts;
 mod reports;
 mod reset;
 
@@ -49,18 +51,28 @@ async fn home_page(
     State(state): State<ServerState>,
     auth: Option<WebAuthed>,
 ) -> Result<impl IntoResponse, HtmlError> {
-    let body = html! {};
+    let body = html! {
+        nav.flex.gap-4.max-w-2xl.mx-auto.my-8 {
+            a.link href="/posts" { "Posts" }
+            a.link href="/reports" { "Reports" }
+            @if auth.is_none() {
+                a.link href="/login" { "Log in" }
+            }
+        }
+    };
 
-    Ok(root_layout_page(auth.as_ref(), "title", body))
+    Ok(root_layout_page(auth.as_ref(), "Home", body))
 }
 
 pub fn create_routes() -> axum::Router<ServerState> {
     axum::Router::new()
         .route("/", routing::get(home_page))
         .route("/_action/count", routing::post(count_action))
+        .merge(admin::create_routes())
         .merge(login::create_routes())
         .merge(logout::create_routes())
         .merge(forgot::create_routes())
         .merge(reset::create_routes())
+        .merge(posts::create_routes())
         .merge(reports::create_routes())
 }
@@ -0,0 +1,188 @@
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    routing,
+};
+use axum_extra::extract::Query;
+use error_stack::ResultExt;
+use maud::{html, Markup};
+
+use crate::{
+    auth::AuthInfo,
+    models::{
+        post::{Post, PostId, PostPopulatedListResult},
+        post_image::{PostImage, PostImageId},
+    },
+    pages::{auth::WebAuthed, error::HtmlError, layout::root_layout_page},
+    server::ServerState,
+    Error,
+};
+
+pub mod _id;
+
+const FEED_PAGE_SIZE: u32 = 20;
+
+#[derive(serde::Deserialize, Debug)]
+struct FeedQuery {
+    #[serde(default)]
+    page: u32,
+}
+
+/// A thumbnail of a post image, linking to the full size image.
+pub fn post_image_thumbnail(post_id: &PostId, image: &PostImage) -> Markup {
+    let url = format!("/posts/{post_id}/images/{}", image.id);
+    let alt = image
+        .alt_text
+        .as_deref()
+        .or(image.file_original_name.as_deref())
+        .unwrap_or("Post image");
+
+    html! {
+        figure.flex.flex-col.gap-1.w-24 {
+            a href=(url) target="_blank" {
+                img.h-24.w-24.object-cover.rounded src=(url) alt=(alt) loading="lazy";
+            }
+            @if let Some(caption) = &image.caption {
+                figcaption.text-xs.opacity-70 { (caption) }
+            }
+        }
+    }
+}
+
+/// Thumbnails for a post's images, linking to the full size image.
+pub fn post_images_fragment(post_id: &PostId, images: &[PostImage]) -> Markup {
+    html! {
+        @if !images.is_empty() {
+            div.flex.flex-wrap.gap-2 {
+                @for image in images {
+                    (post_image_thumbnail(post_id, image))
+                }
+            }
+        }
+    }
+}
+
+fn feed_item(post: &PostPopulatedListResult, images: &[PostImage]) -> Markup {
+    html! {
+        article.card.bg-base-100.shadow {
+            div.card-body {
+                h2.card-title {
+                    a.link.link-hover href={ "/posts/" (post.id) } { (post.subject) }
+                }
+                p.whitespace-pre-wrap { (post.body) }
+                (post_images_fragment(&post.id, images))
+                div.text-sm.opacity-70 {
+                    (post.created_at.format("%Y-%m-%d %H:%M"))
+                    " · "
+                    (post.comment_ids.len())
+                    @if post.comment_ids.len() == 1 { " comment" } @else { " comments" }
+                    @if post.poll_id.is_some() { " · Poll" }
+                }
+            }
+        }
+    }
+}
+
+/// One page of the feed. If there may be more posts, the last element loads the next page when
+/// it scrolls into view.
+async fn feed_page_fragment(
+    state: &ServerState,
+    auth: &AuthInfo,
+    page: u32,
+) -> Result<Markup, error_stack::Report<Error>> {
+    let filters = crate::models::post::queries::ListQueryFilters {
+        page: Some(page),
+        per_page: Some(FEED_PAGE_SIZE),
+        order_by: Some("-created_at".to_string()),
+        ..Default::default()
+    };
+    let posts = Post::list_populated(&state.db, auth, &filters).await?;
+
+    let post_ids = posts.iter().map(|p| p.id.clone()).collect::<Vec<_>>();
+    let images = if posts.is_empty() {
+        Vec::new()
+    } else {
+        PostImage::list_for_posts(&state.db, auth, &post_ids).await?
+    };
+
+    let has_more = posts.len() == FEED_PAGE_SIZE as usize;
+
+    Ok(html! {
+        @for post in &posts {
+            @let post_images = images
+                .iter()
+                .filter(|i| i.post_id == post.id)
+                .cloned()
+                .collect::<Vec<_>>();
+            (feed_item(post, &post_images))
+        }
+        @if has_more {
+            div.loading.loading-dots.mx-auto
+                hx-get={ "/posts/_feed?page=" (page + 1) }
+                hx-trigger="revealed"
+                hx-swap="outerHTML" {}
+        }
+    })
+}
+
+async fn feed_fragment(
+    State(state): State<ServerState>,
+    auth: WebAuthed,
+    Query(query): Query<FeedQuery>,
+) -> Result<impl IntoResponse, HtmlError> {
+    let body = feed_page_fragment(&state, &auth, query.page).await?;
+    Ok(body)
+}
+
+async fn posts_page(
+    State(state): State<ServerState>,
+    auth: WebAuthed,
+) -> Result<impl IntoResponse, HtmlError> {
+    let feed = feed_page_fragment(&state, &auth, 0).await?;
+
+    let body = html! {
+        div.flex.flex-col.gap-4.max-w-2xl.mx-auto.my-8 {
+            h1.text-2xl.font-bold { "Posts" }
+            div#feed.flex.flex-col.gap-4 { (feed) }
+        }
+    };
+
+    Ok(root_layout_page(Some(&auth), "Posts", body))
+}
+
+/// Stream an image from storage, after checking that the user can see the post.
+async fn post_image(
+    State(state): State<ServerState>,
+    auth: WebAuthed,
+    Path((post_id, image_id)): Path<(PostId, PostImageId)>,
+) -> Result<impl IntoResponse, HtmlError> {
+    let image = PostImage::get(&state.db, &auth, &image_id).await?;
+    if image.post_id != post_id {
+        return Err(Error::NotFound("Post image").into());
+    }
+
+    let data = crate::models::post_image::storage::get_storage(&state)
+        .get(&image.file_storage_key)
+        .await
+        .change_context(Error::Storage)?;
+
+    let content_type = crate::storage_urls::content_type(
+        image.file_original_name.as_deref().unwrap_or_default(),
+    );
+
+    Ok((
+        crate::storage_urls::uploaded_file_headers(
+            content_type,
+            "private, max-age=3600".to_string(),
+        ),
+        axum::body::Body::from_stream(data.into_stream()),
+    ))
+}
+
+pub fn create_routes() -> axum::Router<ServerState> {
+    axum::Router::new()
+        .route("/posts", routing::get(posts_page))
+        .route("/posts/_feed", routing::get(feed_fragment))
+        .route("/posts/:id/images/:image_id", routing::get(post_image))
+        .merge(_id::create_routes())
+}
@@ -0,0 +1,518 @@
+use axum::{
+    extract::{DefaultBodyLimit, Multipart, Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing,
+};
+use axum_extra::extract::Form;
+use error_stack::ResultExt;
+use filigree::errors::HttpError;
+use maud::{html, Markup};
+use schemars::JsonSchema;
+
+use crate::{
+    auth::{has_any_permission, Authed},
+    models::{
+        comment::{Comment, CommentCreatePayload},
+        poll::Poll,
+        post::{Post, PostId, CREATE_PERMISSION},
+        post_image::PostImage,
+        reaction::{Reaction, ReactionCreatePayload},
+    },
+    pages::{
+        auth::WebAuthed,
+        error::{FormErrors, HtmlError},
+        layout::root_layout_page,
+    },
+    server::ServerState,
+    upload_policy::UploadPolicyError,
+    Error,
+};
+
+/// The reactions that can be added from the post page
+const REACTION_TYPES: &[&str] = &["👍", "❤️", "🎉", "😂"];
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, JsonSchema)]
+pub struct CommentFormPayload {
+    pub body: String,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, JsonSchema)]
+pub struct ReactionFormPayload {
+    #[serde(rename = "type")]
+    pub typ: String,
+}
+
+fn poll_fragment(poll: &Poll) -> Markup {
+    html! {
+        div.card.bg-base-200 {
+            div.card-body {
+                h2.card-title { (poll.question) }
+                @match &poll.answers {
+                    serde_json::Value::Array(answers) => {
+                        ul.list-disc.list-inside {
+                            @for answer in answers {
+                                li {
+                                    @match answer {
+                                        serde_json::Value::String(s) => { (s) }
+                                        other => { (other.to_string()) }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    serde_json::Value::Object(answers) => {
+                        ul.list-disc.list-inside {
+                            @for (answer, votes) in answers {
+                                li { (answer) ": " (votes.to_string()) }
+                            }
+                        }
+                    }
+                    other => { p { (other.to_string()) } }
+                }
+            }
+        }
+    }
+}
+
+/// The reaction buttons and counts. Each button adds a reaction and swaps in the updated section.
+pub fn reactions_fragment(post_id: &PostId, reactions: &[Reaction]) -> Markup {
+    html! {
+        div#reactions.flex.gap-2 {
+            @for typ in REACTION_TYPES {
+                @let count = reactions.iter().filter(|r| r.typ == *typ).count();
+                form
+                    hx-post={ "/posts/" (post_id) "/_action/reaction" }
+                    hx-target="#reactions"
+                    hx-swap="outerHTML"
+                {
+                    input type="hidden" name="type" value=(typ);
+                    button.btn.btn-sm type="submit" { (typ) " " (count) }
+                }
+            }
+        }
+    }
+}
+
+/// The comment list and the form to add a new comment.
+pub fn comments_fragment(
+    post_id: &PostId,
+    comments: &[Comment],
+    body: &str,
+    errors: &FormErrors,
+) -> Markup {
+    html! {
+        section#comments.flex.flex-col.gap-4 {
+            h2.text-xl.font-bold { "Comments" }
+            @if comments.is_empty() {
+                p.opacity-70 { "No comments yet" }
+            }
+            ul.flex.flex-col.gap-2 {
+                @for comment in comments {
+                    li {
+                        p.whitespace-pre-wrap { (comment.body) }
+                        div.text-sm.opacity-70 { (comment.created_at.format("%Y-%m-%d %H:%M")) }
+                    }
+                }
+            }
+            form.flex.flex-col.gap-2
+                hx-post={ "/posts/" (post_id) "/_action/comment" }
+                hx-target="#comments"
+                hx-swap="outerHTML"
+            {
+                (errors.message_alert())
+                textarea.textarea.textarea-bordered name="body" placeholder="Add a comment" { (body) }
+                (errors.field_error("body"))
+                button.btn.btn-primary.self-end type="submit" { "Comment" }
+            }
+        }
+    }
+}
+
+/// The form to upload images to a post. New images are added to the `#new-images` list.
+pub fn image_upload_form(post_id: &PostId, errors: &FormErrors) -> Markup {
+    html! {
+        form#image-upload.flex.flex-col.gap-2
+            hx-post={ "/posts/" (post_id) "/_action/images" }
+            hx-encoding="multipart/form-data"
+            hx-target="#new-images"
+            hx-swap="beforeend"
+        {
+            (errors.message_alert())
+            input.file-input.file-input-bordered
+                type="file"
+                name="file"
+                accept="image/*"
+                multiple
+                required;
+            input.input.input-bordered
+                type="text"
+                name="alt_text"
+                placeholder="Describe the image for people who can't see it";
+            input.input.input-bordered type="text" name="caption" placeholder="Caption";
+            button.btn.btn-primary.self-end type="submit" { "Upload" }
+        }
+    }
+}
+
+/// The images that were just uploaded, to be appended to the post's images.
+fn new_images_fragment(post_id: &PostId, images: &[PostImage]) -> Markup {
+    html! {
+        @for image in images {
+            (super::post_image_thumbnail(post_id, image))
+        }
+    }
+}
+
+async fn images_action(
+    State(state): State<ServerState>,
+    auth: Authed,
+    Path(id): Path<PostId>,
+    multipart: Multipart,
+) -> Result<Response, HtmlError> {
+    Post::get(&state.db, &auth, &id).await?;
+
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
+    let result = crate::models::post_image::storage::upload_multipart(
+        &state, &auth, &mut *tx, id, multipart,
+    )
+    .await;
+
+    let images = match result {
+        Ok(images) => images,
+        Err(report) => {
+            let message = report
+                .frames()
+                .find_map(|f| f.downcast_ref::<UploadPolicyError>())
+                .map(|e| e.to_string())
+                .unwrap_or_else(|| report.current_context().to_string());
+            let error = Error::WrapReport(report);
+            if !error.status_code().is_client_error() {
+                return Err(error.into());
+            }
+
+            // Show problems with the files, such as one being too large, on the form instead of
+            // adding them to the list of images.
+            let form = image_upload_form(&id, &FormErrors::with_message(message));
+            return Ok((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                [("HX-Retarget", "#image-upload"), ("HX-Reswap", "outerHTML")],
+                form,
+            )
+                .into_response());
+        }
+    };
+    crate::server::etag::touch(&mut *tx, "posts", id.as_uuid(), &auth.organization_id).await?;
+    tx.commit().await.change_context(Error::Db)?;
+
+    for image in &images {
+        crate::models::post_image::renditions::enqueue(&state, image).await;
+    }
+
+    Ok(new_images_fragment(&id, &images).into_response())
+}
+
+async fn comment_action(
+    State(state): State<ServerState>,
+    auth: Authed,
+    Path(id): Path<PostId>,
+    Form(form): Form<CommentFormPayload>,
+) -> Result<impl IntoResponse, HtmlError> {
+    if form.body.trim().is_empty() {
+        let comments = Post::get_child_comments_for_parent(&state.db, &auth, &id).await?;
+        let mut errors = FormErrors::default();
+        errors.add_field("body", "Comment can not be empty");
+        return Err(HtmlError::Form(comments_fragment(
+            &id, &comments, &form.body, &errors,
+        )));
+    }
+
+    // Make sure that the post exists and is visible to this user.
+    Post::get(&state.db, &auth, &id).await?;
+
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
+    let payload = CommentCreatePayload {
+        id: None,
+        body: form.body,
+        post_id: id.clone(),
+    };
+    Comment::create(&mut *tx, &auth, payload).await?;
+    crate::server::etag::touch(&mut *tx, "posts", id.as_uuid(), &auth.organization_id).await?;
+    tx.commit().await.change_context(Error::Db)?;
+
+    let comments = Post::get_child_comments_for_parent(&state.db, &auth, &id).await?;
+
+    Ok(comments_fragment(&id, &comments, "", &FormErrors::default()))
+}
+
+async fn reaction_action(
+    State(state): State<ServerState>,
+    auth: Authed,
+    Path(id): Path<PostId>,
+    Form(form): Form<ReactionFormPayload>,
+) -> Result<impl IntoResponse, HtmlError> {
+    if !REACTION_TYPES.contains(&form.typ.as_str()) {
+        return Err(Error::NotFound("Reaction type").into());
+    }
+
+    Post::get(&state.db, &auth, &id).await?;
+
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
+    let payload = ReactionCreatePayload {
+        id: None,
+        typ: form.typ,
+        post_id: id.clone(),
+    };
+    Reaction::create(&mut *tx, &auth, payload).await?;
+    crate::server::etag::touch(&mut *tx, "posts", id.as_uuid(), &auth.organization_id).await?;
+    tx.commit().await.change_context(Error::Db)?;
+
+    let reactions = Post::get_child_reactions_for_parent(&state.db, &auth, &id).await?;
+
+    Ok(reactions_fragment(&id, &reactions))
+}
+
+async fn post_page(
+    State(state): State<ServerState>,
+    auth: WebAuthed,
+    Path(id): Path<PostId>,
+) -> Result<impl IntoResponse, HtmlError> {
+    let post = Post::get_populated(&state.db, &auth, &id).await?;
+    let comments = Post::get_child_comments_for_parent(&state.db, &auth, &id).await?;
+
+    let body = html! {
+        div.flex.flex-col.gap-6.max-w-2xl.mx-auto.my-8 {
+            a.link href="/posts" { "← All posts" }
+            article.flex.flex-col.gap-4 {
+                h1.text-2xl.font-bold { (post.subject) }
+                div.text-sm.opacity-70 { (post.created_at.format("%Y-%m-%d %H:%M")) }
+                p.whitespace-pre-wrap { (post.body) }
+                (super::post_images_fragment(&post.id, &post.images))
+                div#new-images.flex.flex-wrap.gap-2 {}
+                (image_upload_form(&post.id, &FormErrors::default()))
+                @if let Some(poll) = &post.poll {
+                    (poll_fragment(poll))
+                }
+                (reactions_fragment(&post.id, &post.reactions))
+            }
+            (comments_fragment(&post.id, &comments, "", &FormErrors::default()))
+        }
+    };
+
+    Ok(root_layout_page(Some(&auth), &post.subject, body))
+}
+
+pub fn create_routes() -> axum::Router<ServerState> {
+    axum::Router::new()
+        .route("/posts/:id", routing::get(post_page))
+        .route("/posts/:id/_action/comment", routing::post(comment_action))
+        .route("/posts/:id/_action/reaction", routing::post(reaction_action))
+        .route(
+            "/posts/:id/_action/images",
+            // The upload policy limits the size of each file instead.
+            routing::post(images_action)
+                .layer(DefaultBodyLimit::disable())
+                .route_layer(has_any_permission(vec![CREATE_PERMISSION, "org_admin"])),
+        )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        models::post::{Post, PostId},
+        tests::{
+            multipart_body, start_app, BootstrappedData, MultipartPart, MULTIPART_BOUNDARY,
+        },
+    };
+
+    #[sqlx::test]
+    async fn post_pages(db: sqlx::PgPool) {
+        let (
+            app,
+            BootstrappedData {
+                organization, user, ..
+            },
+        ) = start_app(db.clone()).await;
+
+        let mut tx = db.begin().await.unwrap();
+        let post = Post::create_raw(
+            &mut *tx,
+            &PostId::new(),
+            &organization.id,
+            crate::models::post::testing::make_create_payload(0),
+        )
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+
+        let client = reqwest::Client::new();
+        let bearer = format!("Bearer {}", user.api_key);
+
+        let feed = client
+            .get(format!("{}/posts", app.base_url))
+            .header("Authorization", &bearer)
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert!(feed.contains(&post.subject));
+
+        let fragment = client
+            .post(format!("{}/posts/{}/_action/comment", app.base_url, post.id))
+            .header("Authorization", &bearer)
+            .form(&[("body", "A new comment")])
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert!(fragment.contains("A new comment"));
+
+        let response = client
+            .post(format!("{}/posts/{}/_action/comment", app.base_url, post.id))
+            .header("Authorization", &bearer)
+            .form(&[("body", "  ")])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+
+        let page = client
+            .get(format!("{}/posts/{}", app.base_url, post.id))
+            .header("Authorization", &bearer)
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert!(page.contains("A new comment"));
+    }
+
+    #[sqlx::test]
+    async fn upload_images_action(db: sqlx::PgPool) {
+        let (
+            app,
+            BootstrappedData {
+                organization,
+                user,
+                no_roles_user,
+                ..
+            },
+        ) = start_app(db.clone()).await;
+
+        let mut tx = db.begin().await.unwrap();
+        let post = Post::create_raw(
+            &mut *tx,
+            &PostId::new(),
+            &organization.id,
+            crate::models::post::testing::make_create_payload(0),
+        )
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+
+        let mut image = Vec::new();
+        ::image::DynamicImage::ImageRgb8(::image::RgbImage::new(32, 32))
+            .write_to(
+                &mut std::io::Cursor::new(&mut image),
+                ::image::ImageFormat::Png,
+            )
+            .unwrap();
+
+        let client = reqwest::Client::new();
+        let bearer = format!("Bearer {}", user.api_key);
+        let content_type = format!("multipart/form-data; boundary={MULTIPART_BOUNDARY}");
+        let url = format!("{}/posts/{}/_action/images", app.base_url, post.id);
+
+        let body = multipart_body(&[
+            MultipartPart {
+                name: "file",
+                filename: Some("photo.png"),
+                data: &image,
+            },
+            MultipartPart {
+                name: "alt_text",
+                filename: None,
+                data: b"A black square",
+            },
+            MultipartPart {
+                name: "caption",
+                filename: None,
+                data: b"Night sky",
+            },
+        ]);
+        let fragment = client
+            .post(&url)
+            .header("Authorization", &bearer)
+            .header("Content-Type", &content_type)
+            .body(body)
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert!(fragment.contains(r#"alt="A black square""#));
+        assert!(fragment.contains("Night sky"));
+
+        let page = client
+            .get(format!("{}/posts/{}", app.base_url, post.id))
+            .header("Authorization", &bearer)
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert!(page.contains("Night sky"));
+
+        // A file that the upload policy rejects shows an error on the form.
+        let body = multipart_body(&[MultipartPart {
+            name: "file",
+            filename: Some("notes.txt"),
+            data: b"This is not an image",
+        }]);
+        let response = client
+            .post(&url)
+            .header("Authorization", &bearer)
+            .header("Content-Type", &content_type)
+            .body(body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(response.headers()["HX-Retarget"], "#image-upload");
+        let form = response.text().await.unwrap();
+        assert!(form.contains(r#"id="image-upload""#));
+
+        // Uploading requires permission to create posts, not just to read them.
+        let body = multipart_body(&[MultipartPart {
+            name: "file",
+            filename: Some("photo.png"),
+            data: &image,
+        }]);
+        let response = client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", no_roles_user.api_key))
+            .header("Content-Type", &content_type)
+            .body(body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+    }
+}
@@ -12,8 +12,10 @@ use schemars::JsonSchema;
 
 use crate::{
     auth::{has_any_permission, Authed},
+    models::report::{Report, ReportId},
     pages::{auth::WebAuthed, error::HtmlError, layout::root_layout_page},
     server::ServerState,
+    users::favorites::{self, FavoriteObjectType},
     Error,
 };
 
@@ -24,17 +26,43 @@ pub struct FavoriteActionPayload {
     pub new_state: bool,
 }
 
-fn favorite_action_fragment() -> Markup {
-    html! {}
+/// The favorite toggle button for a report. Submitting it swaps in the button for the new state.
+pub fn favorite_action_fragment(id: &ReportId, favorited: bool) -> Markup {
+    html! {
+        form.favorite-toggle
+            hx-post={ "/reports/_action/favorite/" (id) }
+            hx-swap="outerHTML"
+        {
+            input type="hidden" name="new_state" value=(if favorited { "false" } else { "true" });
+            button type="submit"
+                aria-pressed=(if favorited { "true" } else { "false" })
+                title=(if favorited { "Remove from favorites" } else { "Add to favorites" })
+            {
+                @if favorited { "★" } @else { "☆" }
+            }
+        }
+    }
 }
 
 async fn favorite_action(
     State(state): State<ServerState>,
     auth: Authed,
-    Path(id): Path<String>,
-    form: Form<FavoriteActionPayload>,
+    Path(id): Path<ReportId>,
+    Form(form): Form<FavoriteActionPayload>,
 ) -> Result<impl IntoResponse, Error> {
-    let body = favorite_action_fragment();
+    // Make sure that the report exists and is visible to this user before touching favorites.
+    Report::get(&state.db, &auth, &id).await?;
+
+    favorites::set_favorite(
+        &state.db,
+        &auth,
+        FavoriteObjectType::Report,
+        id.as_uuid(),
+        form.new_state,
+    )
+    .await?;
+
+    let body = favorite_action_fragment(&id, form.new_state);
 
     Ok(body)
 }
@@ -1,33 +1,118 @@
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
+    extract::{Query, State},
     response::IntoResponse,
     routing,
 };
 use filigree::extract::FormOrJson;
-use maud::html;
+use maud::{html, Markup};
 use schemars::JsonSchema;
+use uuid::Uuid;
 
 use crate::{
-    auth::{has_any_permission, Authed},
-    pages::{error::HtmlError, layout::root_layout_page},
+    auth::password_management::reset_password_with_token,
+    pages::{
+        error::{FormErrors, HtmlError},
+        layout::root_layout_page,
+    },
     server::ServerState,
 };
 
 #[derive(serde::Deserialize, Debug, JsonSchema)]
 pub struct ResetPayload {
     email: String,
+    token: Uuid,
+    password: String,
+    confirm: String,
+}
+
+/// The query string from the link in a password reset email
+#[derive(serde::Deserialize, Debug)]
+struct ResetQuery {
+    email: Option<String>,
+    token: Option<Uuid>,
+}
+
+fn reset_form_fragment(email: &str, token: &Uuid, errors: &FormErrors) -> Markup {
+    html! {
+        form.flex.flex-col.gap-2 method="post" action="/reset" hx-post="/reset" hx-swap="outerHTML" {
+            (errors.message_alert())
+            input type="hidden" name="email" value=(email);
+            input type="hidden" name="token" value=(token);
+            label.form-control {
+                div.label { span.label-text { "New password" } }
+                input.input.input-bordered type="password" name="password" required autocomplete="new-password";
+                (errors.field_error("password"))
+            }
+            label.form-control {
+                div.label { span.label-text { "Confirm password" } }
+                input.input.input-bordered type="password" name="confirm" required autocomplete="new-password";
+                (errors.field_error("confirm"))
+            }
+            button.btn.btn-primary type="submit" { "Set password" }
+        }
+    }
 }
 
 async fn reset_form(
     State(state): State<ServerState>,
-    payload: FormOrJson<ResetPayload>,
+    FormOrJson(payload): FormOrJson<ResetPayload>,
 ) -> Result<impl IntoResponse, HtmlError> {
-    Ok(html! {})
+    let mut errors = FormErrors::default();
+    if payload.password.is_empty() {
+        errors.add_field("password", "Password is required");
+    } else if payload.password != payload.confirm {
+        errors.add_field("confirm", "Passwords do not match");
+    }
+
+    if !errors.is_empty() {
+        return Err(HtmlError::Form(reset_form_fragment(
+            &payload.email,
+            &payload.token,
+            &errors,
+        )));
+    }
+
+    let updated =
+        reset_password_with_token(&state, &payload.email, payload.token, payload.password).await?;
+
+    if !updated {
+        return Ok(html! {
+            div.alert.alert-error role="alert" {
+                "This reset link is invalid or has expired. "
+                a.link href="/forgot" { "Request a new one" }
+            }
+        });
+    }
+
+    Ok(html! {
+        div.alert.alert-success role="status" {
+            "Your password has been changed. "
+            a.link href="/login" { "Log in" }
+        }
+    })
 }
 
-async fn reset_page(State(state): State<ServerState>) -> impl IntoResponse {
-    root_layout_page(None, "Reset", html! { h1 { "Reset" } })
+async fn reset_page(Query(query): Query<ResetQuery>) -> impl IntoResponse {
+    let body = match (query.email, query.token) {
+        (Some(email), Some(token)) => reset_form_fragment(&email, &token, &FormErrors::default()),
+        _ => html! {
+            p {
+                "This page must be opened from the link in a password reset email. "
+                a.link href="/forgot" { "Request a reset link" }
+            }
+        },
+    };
+
+    root_layout_page(
+        None,
+        "Reset Password",
+        html! {
+            div.flex.flex-col.gap-8.max-w-sm.mx-auto.mt-16 {
+                h1.text-2xl.font-bold { "Reset your password" }
+                (body)
+            }
+        },
+    )
 }
 
 pub fn create_routes() -> axum::Router<ServerState> {
@@ -35,3 +120,72 @@ pub fn create_routes() -> axum::Router<ServerState> {
         .route("/reset", routing::get(reset_page))
         .route("/reset", routing::post(reset_form))
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        auth::tests::extract_token_from_email,
+        tests::{start_app, BootstrappedData},
+    };
+
+    #[sqlx::test]
+    #[cfg_attr(not(feature = "test_password"), ignore = "slow password test")]
+    async fn forgot_and_reset_password(db: sqlx::PgPool) {
+        let (app, BootstrappedData { user, .. }) = start_app(db).await;
+        let client = reqwest::Client::new();
+
+        client
+            .post(format!("{}/forgot", app.base_url))
+            .form(&[("email", user.email.as_str())])
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+
+        let email = app.sent_emails.lock().unwrap().pop().unwrap();
+        assert!(email.text.contains("/reset?token="));
+        let token = extract_token_from_email(&email).to_string();
+
+        let response = client
+            .post(format!("{}/reset", app.base_url))
+            .form(&[
+                ("email", user.email.as_str()),
+                ("token", token.as_str()),
+                ("password", "a_new_password"),
+                ("confirm", "not-the-same"),
+            ])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(response.text().await.unwrap().contains("Passwords do not match"));
+
+        let body = client
+            .post(format!("{}/reset", app.base_url))
+            .form(&[
+                ("email", user.email.as_str()),
+                ("token", token.as_str()),
+                ("password", "a_new_password"),
+                ("confirm", "a_new_password"),
+            ])
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert!(body.contains("Your password has been changed"));
+
+        app.client
+            .post("auth/login")
+            .json(&serde_json::json!({ "email": user.email, "password": "a_new_password" }))
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+}
@@ -0,0 +1,173 @@
+//! Optimistic concurrency control using `ETag` and `If-Match` headers.
+//!
+//! The ETag for an object is derived from its `updated_at` column. Update endpoints lock the row,
+//! compare its current ETag against the `If-Match` header, and return 412 Precondition Failed if
+//! someone else modified it in the meantime.
+
+use std::convert::Infallible;
+
+use async_trait::async_trait;
+use axum::{
+    extract::FromRequestParts,
+    http::{header, request::Parts, HeaderValue},
+};
+use chrono::{DateTime, Utc};
+use error_stack::{Report, ResultExt};
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::{models::organization::OrganizationId, Error};
+
+/// Generate the ETag header value for an object with the given `updated_at` time.
+pub fn etag_for(updated_at: &DateTime<Utc>) -> String {
+    format!("\"{}\"", updated_at.timestamp_micros())
+}
+
+/// Generate an `ETag` header for an object with the given `updated_at` time.
+pub fn etag_header(updated_at: &DateTime<Utc>) -> [(header::HeaderName, HeaderValue); 1] {
+    let value = HeaderValue::from_str(&etag_for(updated_at)).expect("ETag is a valid header");
+    [(header::ETAG, value)]
+}
+
+/// The parsed `If-Match` header. When the header is absent, any version of the object matches.
+#[derive(Debug, Default)]
+pub struct IfMatch(Option<Vec<String>>);
+
+impl IfMatch {
+    pub fn from_header(value: Option<&HeaderValue>) -> Self {
+        let tags = value.and_then(|v| v.to_str().ok()).map(|v| {
+            v.split(',')
+                .map(|tag| tag.trim().to_string())
+                .filter(|tag| !tag.is_empty())
+                .collect::<Vec<_>>()
+        });
+
+        Self(tags)
+    }
+
+    /// Return true if the object's current `updated_at` satisfies the header.
+    pub fn matches(&self, updated_at: &DateTime<Utc>) -> bool {
+        let Some(tags) = &self.0 else {
+            return true;
+        };
+
+        let current = etag_for(updated_at);
+        // Weak tags never match, since If-Match uses the strong comparison function.
+        tags.iter().any(|tag| tag == "*" || *tag == current)
+    }
+
+    /// Lock the object's row for the rest of the transaction and make sure that it hasn't changed
+    /// since the version named in the header. This does nothing if the header was not sent, or
+    /// if the object doesn't exist, so that the update itself can return the proper 404.
+    pub async fn lock_and_check(
+        &self,
+        tx: &mut PgConnection,
+        table: &'static str,
+        id: &Uuid,
+        organization_id: &OrganizationId,
+    ) -> Result<(), Report<Error>> {
+        if self.0.is_none() {
+            return Ok(());
+        }
+
+        let q = format!(
+            "SELECT updated_at FROM public.{table} WHERE id = $1 AND organization_id = $2 FOR UPDATE"
+        );
+        let updated_at = sqlx::query_scalar::<_, DateTime<Utc>>(&q)
+            .bind(id)
+            .bind(organization_id.as_uuid())
+            .fetch_optional(&mut *tx)
+            .await
+            .change_context(Error::Db)?;
+
+        self.check_locked(updated_at)
+    }
+
+    fn check_locked(&self, updated_at: Option<DateTime<Utc>>) -> Result<(), Report<Error>> {
+        match updated_at {
+            Some(updated_at) if !self.matches(&updated_at) => {
+                Err(Report::new(Error::PreconditionFailed))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Like [IfMatch::lock_and_check], for models such as polls that have at most one row per
+    /// parent and are addressed by the parent's ID.
+    pub async fn lock_and_check_by_parent(
+        &self,
+        tx: &mut PgConnection,
+        table: &'static str,
+        parent_column: &'static str,
+        parent_id: &Uuid,
+        organization_id: &OrganizationId,
+    ) -> Result<(), Report<Error>> {
+        if self.0.is_none() {
+            return Ok(());
+        }
+
+        let q = format!(
+            "SELECT updated_at FROM public.{table} WHERE {parent_column} = $1 AND organization_id = $2 FOR UPDATE"
+        );
+        let updated_at = sqlx::query_scalar::<_, DateTime<Utc>>(&q)
+            .bind(parent_id)
+            .bind(organization_id.as_uuid())
+            .fetch_optional(&mut *tx)
+            .await
+            .change_context(Error::Db)?;
+
+        self.check_locked(updated_at)
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for IfMatch {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self::from_header(parts.headers.get(header::IF_MATCH)))
+    }
+}
+
+/// Mark a parent object as modified after one of its children changes, so that the parent's
+/// ETag covers the whole object.
+pub async fn touch(
+    tx: &mut PgConnection,
+    table: &'static str,
+    id: &Uuid,
+    organization_id: &OrganizationId,
+) -> Result<(), Report<Error>> {
+    let q = format!(
+        "UPDATE public.{table} SET updated_at = now() WHERE id = $1 AND organization_id = $2"
+    );
+    sqlx::query(&q)
+        .bind(id)
+        .bind(organization_id.as_uuid())
+        .execute(&mut *tx)
+        .await
+        .change_context(Error::Db)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn if_match() {
+        let now = Utc::now();
+        let etag = etag_for(&now);
+
+        assert!(IfMatch::from_header(None).matches(&now));
+        assert!(IfMatch::from_header(Some(&HeaderValue::from_static("*"))).matches(&now));
+        assert!(IfMatch::from_header(Some(
+            &HeaderValue::from_str(&format!("\"1\", {etag}")).unwrap()
+        ))
+        .matches(&now));
+        assert!(!IfMatch::from_header(Some(&HeaderValue::from_static("\"1\""))).matches(&now));
+        assert!(
+            !IfMatch::from_header(Some(&HeaderValue::from_str(&format!("W/{etag}")).unwrap()))
+                .matches(&now)
+        );
+    }
+}
@@ -0,0 +1,447 @@
+//! Support for the `Idempotency-Key` header on create endpoints.
+//!
+//! The first response to a POST with an `Idempotency-Key` is stored for 24 hours, keyed by
+//! organization, user, and key, and replayed when the client retries the request. A retry that
+//! arrives while the first request is still running gets a 409 Conflict. If the first request is
+//! cancelled, for example because the client disconnected, the key is released so that a retry
+//! can run. Expired keys are removed by the `purge_idempotency_keys` job.
+
+use std::time::Duration;
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, HeaderName, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use error_stack::ResultExt;
+use sqlx::PgPool;
+use tracing::{event, Level};
+
+use super::ServerState;
+use crate::{
+    auth::{AuthInfo, Authed},
+    models::{organization::OrganizationId, user::UserId},
+    Error,
+};
+
+pub const IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+/// Set on responses which were replayed from an earlier request.
+pub const IDEMPOTENT_REPLAYED: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+const MAX_KEY_LENGTH: usize = 255;
+
+/// How long after the request timeout an unfinished claim is treated as abandoned. This covers the
+/// time to store the response, and claims left behind if the server stopped in the middle of a
+/// request.
+const STALE_CLAIM_MARGIN: Duration = Duration::from_secs(30);
+
+/// Middleware which applies the `Idempotency-Key` header to POST requests. Requests without the
+/// header are passed through unchanged.
+pub async fn handle_idempotency_key(
+    State(state): State<ServerState>,
+    auth: Option<Authed>,
+    request: Request,
+    next: Next,
+) -> Result<Response, Error> {
+    if request.method() != Method::POST {
+        return Ok(next.run(request).await);
+    }
+
+    let Some(key) = request.headers().get(&IDEMPOTENCY_KEY) else {
+        return Ok(next.run(request).await);
+    };
+
+    // Unauthenticated requests will be rejected by the handler, so there's nothing to store.
+    let Some(auth) = auth else {
+        return Ok(next.run(request).await);
+    };
+
+    let key = key
+        .to_str()
+        .ok()
+        .map(str::trim)
+        .filter(|k| !k.is_empty() && k.len() <= MAX_KEY_LENGTH)
+        .ok_or(Error::InvalidIdempotencyKey)?
+        .to_string();
+    let path = request.uri().path().to_string();
+
+    // Requests are cut off at the request timeout, so a claim older than that is from a request
+    // that is no longer running.
+    let stale_after = state.request_timeout + STALE_CLAIM_MARGIN;
+    if !claim_key(&state.db, &auth, &key, &path, stale_after).await? {
+        return replay(&state.db, &auth, &key, &path).await;
+    }
+
+    let mut guard = ClaimGuard {
+        db: state.db.clone(),
+        organization_id: auth.organization_id,
+        user_id: auth.user_id,
+        key: Some(key.clone()),
+    };
+
+    let response = next.run(request).await;
+    let result = store_response(&state.db, &auth, &key, response).await;
+    if result.is_ok() {
+        // The response was stored, or the key was already released.
+        guard.key = None;
+    }
+
+    result
+}
+
+/// Releases a claimed key if the request doesn't finish, such as when the client disconnects and
+/// the request's future is dropped.
+struct ClaimGuard {
+    db: PgPool,
+    organization_id: OrganizationId,
+    user_id: UserId,
+    /// The claimed key, or `None` once the request has finished with it.
+    key: Option<String>,
+}
+
+impl Drop for ClaimGuard {
+    fn drop(&mut self) {
+        let Some(key) = self.key.take() else {
+            return;
+        };
+
+        let db = self.db.clone();
+        let organization_id = self.organization_id;
+        let user_id = self.user_id;
+        tokio::spawn(async move {
+            if let Err(e) = release_key(&db, &organization_id, &user_id, &key).await {
+                event!(Level::ERROR, error = ?e, "Failed to release idempotency key");
+            }
+        });
+    }
+}
+
+/// Try to claim the key for this request. This fails if the key is already in use, unless the
+/// earlier use has expired or its request seems to have died without finishing.
+async fn claim_key(
+    db: &PgPool,
+    auth: &AuthInfo,
+    key: &str,
+    path: &str,
+    stale_after: Duration,
+) -> Result<bool, error_stack::Report<Error>> {
+    let claimed = sqlx::query_scalar!(
+        r##"INSERT INTO idempotency_keys (organization_id, user_id, key, path, expires_at)
+        VALUES ($1, $2, $3, $4, now() + interval '24 hours')
+        ON CONFLICT (organization_id, user_id, key) DO UPDATE
+        SET path = EXCLUDED.path,
+            status_code = NULL,
+            content_type = NULL,
+            response_body = NULL,
+            created_at = now(),
+            expires_at = EXCLUDED.expires_at
+        WHERE idempotency_keys.expires_at < now()
+            OR (idempotency_keys.status_code IS NULL
+                AND idempotency_keys.created_at < now() - make_interval(secs => $5))
+        RETURNING true AS "claimed!""##,
+        auth.organization_id.as_uuid(),
+        auth.user_id.as_uuid(),
+        key,
+        path,
+        stale_after.as_secs_f64()
+    )
+    .fetch_optional(db)
+    .await
+    .change_context(Error::Db)?;
+
+    Ok(claimed.is_some())
+}
+
+async fn replay(db: &PgPool, auth: &AuthInfo, key: &str, path: &str) -> Result<Response, Error> {
+    let row = sqlx::query!(
+        "SELECT path, status_code, content_type, response_body
+        FROM idempotency_keys
+        WHERE organization_id = $1 AND user_id = $2 AND key = $3",
+        auth.organization_id.as_uuid(),
+        auth.user_id.as_uuid(),
+        key
+    )
+    .fetch_optional(db)
+    .await
+    .change_context(Error::Db)?;
+
+    // If the row is gone, the first request failed and released the key just now, so the client
+    // can try again.
+    let Some(row) = row else {
+        return Err(Error::IdempotencyKeyInProgress);
+    };
+
+    if row.path != path {
+        return Err(Error::IdempotencyKeyReused);
+    }
+
+    let (Some(status_code), Some(body)) = (row.status_code, row.response_body) else {
+        return Err(Error::IdempotencyKeyInProgress);
+    };
+
+    let mut response = Body::from(body).into_response();
+    *response.status_mut() = StatusCode::from_u16(status_code as u16).unwrap_or(StatusCode::OK);
+
+    let headers = response.headers_mut();
+    headers.insert(IDEMPOTENT_REPLAYED, HeaderValue::from_static("true"));
+    if let Some(content_type) = row
+        .content_type
+        .and_then(|c| HeaderValue::from_str(&c).ok())
+    {
+        headers.insert(header::CONTENT_TYPE, content_type);
+    }
+
+    Ok(response)
+}
+
+async fn store_response(
+    db: &PgPool,
+    auth: &AuthInfo,
+    key: &str,
+    response: Response,
+) -> Result<Response, Error> {
+    let (parts, body) = response.into_parts();
+
+    // Server errors are not stored, so that the client can retry after the problem is fixed.
+    if parts.status.is_server_error() {
+        release_key(db, &auth.organization_id, &auth.user_id, key).await?;
+        return Ok(Response::from_parts(parts, body));
+    }
+
+    let Ok(body) = axum::body::to_bytes(body, usize::MAX).await else {
+        release_key(db, &auth.organization_id, &auth.user_id, key).await?;
+        return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+    };
+
+    let content_type = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|c| c.to_str().ok());
+
+    sqlx::query!(
+        "UPDATE idempotency_keys
+        SET status_code = $4, content_type = $5, response_body = $6
+        WHERE organization_id = $1 AND user_id = $2 AND key = $3",
+        auth.organization_id.as_uuid(),
+        auth.user_id.as_uuid(),
+        key,
+        parts.status.as_u16() as i16,
+        content_type,
+        body.as_ref()
+    )
+    .execute(db)
+    .await
+    .change_context(Error::Db)?;
+
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+async fn release_key(
+    db: &PgPool,
+    organization_id: &OrganizationId,
+    user_id: &UserId,
+    key: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "DELETE FROM idempotency_keys
+        WHERE organization_id = $1 AND user_id = $2 AND key = $3 AND status_code IS NULL",
+        organization_id.as_uuid(),
+        user_id.as_uuid(),
+        key
+    )
+    .execute(db)
+    .await
+    .change_context(Error::Db)?;
+
+    Ok(())
+}
+
+/// Remove idempotency keys that have expired, returning how many were removed.
+pub async fn purge_expired(db: &PgPool) -> Result<u64, error_stack::Report<Error>> {
+    let result = sqlx::query!("DELETE FROM idempotency_keys WHERE expires_at < now()")
+        .execute(db)
+        .await
+        .change_context(Error::Db)?;
+
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod test {
+    use filigree::testing::ResponseExt;
+
+    use crate::tests::{start_app, BootstrappedData};
+
+    #[sqlx::test]
+    async fn replay_create(pool: sqlx::PgPool) {
+        let (
+            _app,
+            BootstrappedData {
+                admin_user, user, ..
+            },
+        ) = start_app(pool.clone()).await;
+
+        let payload = crate::models::post::testing::make_create_payload(1);
+
+        let create = |key: &'static str| {
+            admin_user
+                .client
+                .post("posts")
+                .header("Idempotency-Key", key)
+                .json(&payload)
+                .send()
+        };
+
+        let first = create("abc").await.unwrap().log_error().await.unwrap();
+        assert_eq!(first.status(), reqwest::StatusCode::CREATED);
+        assert!(first.headers().get("idempotent-replayed").is_none());
+        let first = first.json::<serde_json::Value>().await.unwrap();
+
+        let second = create("abc").await.unwrap().log_error().await.unwrap();
+        assert_eq!(second.status(), reqwest::StatusCode::CREATED);
+        assert_eq!(second.headers()["idempotent-replayed"], "true");
+        let second = second.json::<serde_json::Value>().await.unwrap();
+        assert_eq!(first, second, "retry should replay the first response");
+
+        let third = create("def")
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json::<serde_json::Value>()
+            .await
+            .unwrap();
+        assert_ne!(first["id"], third["id"], "a new key creates a new object");
+
+        let count = sqlx::query_scalar!(r##"SELECT COUNT(*) AS "count!" FROM posts"##)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 2);
+
+        // Keys are scoped to the user
+        let other_user = user
+            .client
+            .post("posts")
+            .header("Idempotency-Key", "abc")
+            .json(&payload)
+            .send()
+            .await
+            .unwrap();
+        assert!(other_user.headers().get("idempotent-replayed").is_none());
+
+        // Reusing a key on another endpoint fails
+        let response = admin_user
+            .client
+            .post(&format!("posts/{}/comments", first["id"].as_str().unwrap()))
+            .header("Idempotency-Key", "abc")
+            .json(&crate::models::comment::testing::make_create_payload(1))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[sqlx::test]
+    async fn in_progress_conflict(pool: sqlx::PgPool) {
+        let (
+            _app,
+            BootstrappedData {
+                organization,
+                admin_user,
+                ..
+            },
+        ) = start_app(pool.clone()).await;
+
+        // Simulate a request that is still running.
+        sqlx::query!(
+            "INSERT INTO idempotency_keys (organization_id, user_id, key, path, expires_at)
+            VALUES ($1, $2, 'abc', '/posts', now() + interval '24 hours')",
+            organization.id.as_uuid(),
+            admin_user.user_id.as_uuid(),
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let response = admin_user
+            .client
+            .post("posts")
+            .header("Idempotency-Key", "abc")
+            .json(&crate::models::post::testing::make_create_payload(1))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::CONFLICT);
+
+        // A claim older than the request timeout belongs to a request that is no longer running.
+        sqlx::query!("UPDATE idempotency_keys SET created_at = now() - interval '2 minutes'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        admin_user
+            .client
+            .post("posts")
+            .header("Idempotency-Key", "abc")
+            .json(&crate::models::post::testing::make_create_payload(1))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap();
+
+        sqlx::query!("UPDATE idempotency_keys SET expires_at = now() - interval '1 second'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(super::purge_expired(&pool).await.unwrap(), 1);
+    }
+
+    #[sqlx::test]
+    async fn cancelled_request_releases_key(pool: sqlx::PgPool) {
+        let (
+            _app,
+            BootstrappedData {
+                organization,
+                admin_user,
+                ..
+            },
+        ) = start_app(pool.clone()).await;
+
+        sqlx::query!(
+            "INSERT INTO idempotency_keys (organization_id, user_id, key, path, expires_at)
+            VALUES ($1, $2, 'abc', '/posts', now() + interval '24 hours')",
+            organization.id.as_uuid(),
+            admin_user.user_id.as_uuid(),
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        // Dropping the guard is what happens when the request's future is dropped.
+        drop(super::ClaimGuard {
+            db: pool.clone(),
+            organization_id: organization.id,
+            user_id: admin_user.user_id,
+            key: Some("abc".to_string()),
+        });
+
+        let mut remaining = 1;
+        for _ in 0..50 {
+            remaining =
+                sqlx::query_scalar!(r##"SELECT COUNT(*) AS "count!" FROM idempotency_keys"##)
+                    .fetch_one(&pool)
+                    .await
+                    .unwrap();
+            if remaining == 0 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        assert_eq!(remaining, 0, "the key should be released");
+    }
+}
@@ -1,10 +1,41 @@
-use axum::{routing, Router};
+use axum::{http::Method, Router};
 
-use super::ServerState;
-use crate::auth::permissions::list_permissions;
+use super::{
+    health,
+    openapi::{ApiRoute, ApiRouter},
+    ServerState,
+};
+use crate::auth::permissions::{list_permissions, PermissionInfo};
+
+fn routes() -> ApiRouter {
+    ApiRouter::new()
+        .route(
+            ApiRoute::new(
+                Method::GET,
+                "/healthz",
+                "meta",
+                "Check if the server is running",
+            ),
+            health::healthz,
+        )
+        .route(
+            ApiRoute::new(
+                Method::GET,
+                "/meta/permissions",
+                "meta",
+                "List the available permissions",
+            )
+            .response::<Vec<PermissionInfo>>(),
+            list_permissions,
+        )
+}
 
 pub fn create_routes() -> Router<ServerState> {
-    Router::new().route("/permissions", routing::get(list_permissions))
+    routes().into_router()
+}
+
+pub fn api_docs() -> Vec<ApiRoute> {
+    routes().into_docs()
 }
 
 #[cfg(test)]
@@ -5,7 +5,7 @@ use std::{
     time::Duration,
 };
 
-use axum::{extract::FromRef, handler::Handler, routing::get, Router};
+use axum::{extract::FromRef, handler::Handler, Router};
 use error_stack::{Report, ResultExt};
 use filigree::{
     auth::{
@@ -30,8 +30,11 @@ use tracing::{event, Level, Span};
 
 use crate::{error::Error, storage};
 
+pub mod etag;
 mod health;
+pub mod idempotency;
 mod meta;
+pub mod openapi;
 #[cfg(test)]
 mod tests;
 
@@ -51,6 +54,10 @@ pub struct ServerStateInner {
     pub queue: effectum::Queue,
     /// Object storage providers
     pub storage: storage::AppStorage,
+    /// The renditions to generate for uploaded images
+    pub image_renditions: Vec<crate::models::post_image::renditions::RenditionSpec>,
+    /// How long a request can run before it is cancelled
+    pub request_timeout: std::time::Duration,
 }
 
 impl ServerStateInner {
@@ -271,6 +278,8 @@ pub struct Config {
     pub init_recurring_jobs: bool,
 
     pub storage: storage::AppStorageConfig,
+    /// The renditions to generate for uploaded images
+    pub image_renditions: Vec<crate::models::post_image::renditions::RenditionSpec>,
 }
 
 /// Create the server and return it, ready to run.
@@ -324,6 +333,8 @@ pub async fn create_server(config: Config) -> Result<Server, Report<Error>> {
         secrets: config.secrets,
         queue,
         storage: storage::AppStorage::new(config.storage).change_context(Error::ServerStart)?,
+        image_renditions: config.image_renditions,
+        request_timeout: config.request_timeout,
     }));
 
     let queue_workers = crate::jobs::init(&state, config.init_recurring_jobs)
@@ -345,13 +356,19 @@ pub async fn create_server(config: Config) -> Result<Server, Report<Error>> {
     };
 
     let api_routes: Router<ServerState> = Router::new()
-        .route("/healthz", get(health::healthz))
-        .nest("/meta", meta::create_routes())
+        .merge(meta::create_routes())
+        .merge(openapi::create_routes())
         .merge(filigree::auth::endpoints::create_routes())
         .merge(filigree::auth::oauth::create_routes())
-        .merge(crate::models::create_routes())
+        .merge(
+            crate::models::create_routes().route_layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                idempotency::handle_idempotency_key,
+            )),
+        )
         .merge(crate::users::users::create_routes())
         .merge(crate::auth::create_routes())
+        .merge(crate::storage_urls::create_routes())
         // Return not found here so we don't run the other non-API fallbacks
         .fallback(|| async { Error::NotFound("Route") });
 
@@ -0,0 +1,643 @@
+//! Generation of the OpenAPI document for the API.
+//!
+//! Axum routers can't be introspected, so each module that creates API routes builds them with an
+//! [ApiRouter], which registers each handler along with the [ApiRoute] describing it. The method,
+//! path and permissions of a route are only written once, and the request, response, path and
+//! query schemas come from the same `JsonSchema` types that the handlers use.
+
+use std::{borrow::Cow, sync::OnceLock};
+
+use axum::{
+    extract::State,
+    handler::Handler,
+    http::Method,
+    response::{Html, IntoResponse},
+    routing::{self, MethodFilter, MethodRouter},
+    Json, Router,
+};
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    schema::{ObjectValidation, Schema, SchemaObject, SubschemaValidation},
+    JsonSchema,
+};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use super::ServerState;
+use crate::{auth::has_any_permission, models::pagination::ListResponse, Error};
+
+/// Generates the schema for a type, using a reference to the shared component where possible.
+pub type SchemaFn = fn(&mut SchemaGenerator) -> Schema;
+
+fn subschema<T: JsonSchema>(gen: &mut SchemaGenerator) -> Schema {
+    gen.subschema_for::<T>()
+}
+
+fn inline_schema<T: JsonSchema>(gen: &mut SchemaGenerator) -> Schema {
+    T::json_schema(gen)
+}
+
+/// A list of `T`, or the [ListResponse] envelope when `include_total` is set.
+fn list_schema<T: JsonSchema>(gen: &mut SchemaGenerator) -> Schema {
+    Schema::Object(SchemaObject {
+        subschemas: Some(Box::new(SubschemaValidation {
+            one_of: Some(vec![
+                gen.subschema_for::<Vec<T>>(),
+                gen.subschema_for::<ListResponse<T>>(),
+            ]),
+            ..Default::default()
+        })),
+        ..Default::default()
+    })
+}
+
+/// The body returned by the API when a request fails.
+#[derive(Serialize, JsonSchema)]
+pub struct ErrorResponse {
+    pub error: ErrorResponseData,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct ErrorResponseData {
+    /// A machine-readable identifier for the type of error
+    pub kind: String,
+    /// A description of the error
+    pub message: String,
+    /// Additional information about the error, if any
+    pub details: Option<Value>,
+}
+
+/// The description of a single API route
+pub struct ApiRoute {
+    method: Method,
+    path: &'static str,
+    tag: &'static str,
+    summary: Cow<'static, str>,
+    permissions: Vec<&'static str>,
+    path_params: Vec<SchemaFn>,
+    query: Option<SchemaFn>,
+    request: Option<SchemaFn>,
+    response: Option<SchemaFn>,
+    status: u16,
+    idempotency_key: bool,
+    exports: bool,
+}
+
+impl ApiRoute {
+    /// Describe a route. `path` uses the Axum syntax for path parameters, i.e. `/posts/:id`.
+    pub fn new(
+        method: Method,
+        path: &'static str,
+        tag: &'static str,
+        summary: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        Self {
+            method,
+            path,
+            tag,
+            summary: summary.into(),
+            permissions: Vec::new(),
+            path_params: Vec::new(),
+            query: None,
+            request: None,
+            response: None,
+            status: 200,
+            idempotency_key: false,
+            exports: false,
+        }
+    }
+
+    /// The permissions that [ApiRouter] checks with a `has_any_permission` layer. Any one of them
+    /// grants access.
+    pub fn permissions(mut self, permissions: &[&'static str]) -> Self {
+        self.permissions = permissions.to_vec();
+        self
+    }
+
+    /// Add the type of the next path parameter, in the order they appear in the path.
+    pub fn path_param<T: JsonSchema>(mut self) -> Self {
+        self.path_params.push(subschema::<T>);
+        self
+    }
+
+    /// The query string type. Each of its fields becomes a query parameter.
+    pub fn query<T: JsonSchema>(mut self) -> Self {
+        self.query = Some(inline_schema::<T>);
+        self
+    }
+
+    pub fn request<T: JsonSchema>(mut self) -> Self {
+        self.request = Some(subschema::<T>);
+        self
+    }
+
+    pub fn response<T: JsonSchema>(mut self) -> Self {
+        self.response = Some(subschema::<T>);
+        self
+    }
+
+    /// The response of a list route that supports `include_total`, which is either an array of
+    /// `T` or a [ListResponse] envelope.
+    pub fn list_response<T: JsonSchema>(mut self) -> Self {
+        self.response = Some(list_schema::<T>);
+        self
+    }
+
+    /// Document the CSV and NDJSON responses that the route returns when the `Accept` header asks
+    /// for them. See [export](crate::models::export).
+    pub fn exports(mut self) -> Self {
+        self.exports = true;
+        self
+    }
+
+    /// Set the status code for a successful response. The default is 200.
+    pub fn status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Document the optional `Idempotency-Key` header, if this is a POST route. See
+    /// [idempotency](super::idempotency).
+    pub fn idempotency_key(mut self) -> Self {
+        self.idempotency_key = self.method == Method::POST;
+        self
+    }
+
+    fn openapi_path(&self) -> String {
+        self.path
+            .split('/')
+            .map(|segment| match segment.strip_prefix(':') {
+                Some(name) => Cow::Owned(format!("{{{name}}}")),
+                None => Cow::Borrowed(segment),
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    fn path_param_names(&self) -> impl Iterator<Item = &str> {
+        self.path
+            .split('/')
+            .filter_map(|segment| segment.strip_prefix(':'))
+    }
+
+    fn operation(&self, gen: &mut SchemaGenerator) -> Value {
+        let mut parameters = self
+            .path_param_names()
+            .zip(self.path_params.iter())
+            .map(|(name, schema)| {
+                json!({
+                    "name": name,
+                    "in": "path",
+                    "required": true,
+                    "schema": schema(gen),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if let Some(query) = self.query {
+            if let Some(object) = query(gen).into_object().object {
+                let ObjectValidation {
+                    properties,
+                    required,
+                    ..
+                } = *object;
+                for (name, field_schema) in properties {
+                    let is_array = matches!(
+                        &field_schema,
+                        Schema::Object(SchemaObject { array: Some(_), .. })
+                    );
+                    let mut param = json!({
+                        "name": name,
+                        "in": "query",
+                        "required": required.contains(&name),
+                        "schema": field_schema,
+                    });
+                    if is_array {
+                        param["style"] = json!("form");
+                        param["explode"] = json!(true);
+                    }
+                    parameters.push(param);
+                }
+            }
+        }
+
+        if self.idempotency_key {
+            parameters.push(json!({
+                "name": "Idempotency-Key",
+                "in": "header",
+                "required": false,
+                "description": "Retrying a request with the same key within 24 hours returns the original response instead of creating another object.",
+                "schema": { "type": "string", "maxLength": 255 },
+            }));
+        }
+
+        let mut success = match self.response {
+            Some(response) => json!({
+                "description": "Success",
+                "content": { "application/json": { "schema": response(gen) } }
+            }),
+            None => json!({ "description": "Success" }),
+        };
+
+        if self.exports {
+            success["content"]["text/csv"] = json!({ "schema": { "type": "string" } });
+            success["content"]["application/x-ndjson"] = json!({
+                "schema": { "type": "string", "description": "One JSON object per line" }
+            });
+        }
+
+        let error = json!({
+            "description": "Error",
+            "content": {
+                "application/json": {
+                    "schema": gen.subschema_for::<ErrorResponse>()
+                }
+            }
+        });
+
+        let mut operation = json!({
+            "tags": [self.tag],
+            "summary": self.summary,
+            "parameters": parameters,
+            "responses": {
+                (self.status.to_string()): success,
+                "default": error,
+            },
+        });
+
+        if let Some(request) = self.request {
+            let schema = request(gen);
+            let content = if self.method == Method::PATCH {
+                json!({
+                    crate::models::merge_patch::MERGE_PATCH_CONTENT_TYPE: { "schema": schema },
+                })
+            } else {
+                json!({
+                    "application/json": { "schema": schema },
+                    "application/x-www-form-urlencoded": { "schema": schema },
+                })
+            };
+            operation["requestBody"] = json!({
+                "required": true,
+                "content": content,
+            });
+        }
+
+        if !self.permissions.is_empty() {
+            operation["description"] = json!(format!(
+                "Requires one of these permissions: {}",
+                self.permissions.join(", ")
+            ));
+            operation["x-required-permissions"] = json!(self.permissions);
+        }
+
+        operation
+    }
+}
+
+/// A router that keeps the documentation for each of its routes.
+pub struct ApiRouter {
+    router: Router<ServerState>,
+    docs: Vec<ApiRoute>,
+}
+
+impl Default for ApiRouter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ApiRouter {
+    pub fn new() -> Self {
+        Self {
+            router: Router::new(),
+            docs: Vec::new(),
+        }
+    }
+
+    /// Add a handler at the method and path of `route`. If the route has permissions, they are
+    /// checked with a `has_any_permission` layer.
+    pub fn route<H, T>(self, route: ApiRoute, handler: H) -> Self
+    where
+        H: Handler<T, ServerState>,
+        T: 'static,
+    {
+        self.route_with(route, handler, |r| r)
+    }
+
+    /// Like [ApiRouter::route], but `layers` can add other layers to the handler, such as a body
+    /// size limit. They run inside the permissions check.
+    pub fn route_with<H, T>(
+        mut self,
+        route: ApiRoute,
+        handler: H,
+        layers: impl FnOnce(MethodRouter<ServerState>) -> MethodRouter<ServerState>,
+    ) -> Self
+    where
+        H: Handler<T, ServerState>,
+        T: 'static,
+    {
+        let filter = MethodFilter::try_from(route.method.clone())
+            .unwrap_or_else(|_| panic!("Unsupported method {} for {}", route.method, route.path));
+
+        let mut method_router = layers(routing::on(filter, handler));
+        if !route.permissions.is_empty() {
+            method_router =
+                method_router.route_layer(has_any_permission(route.permissions.clone()));
+        }
+
+        self.router = self.router.route(route.path, method_router);
+        self.docs.push(route);
+        self
+    }
+
+    pub fn merge(mut self, other: ApiRouter) -> Self {
+        self.router = self.router.merge(other.router);
+        self.docs.extend(other.docs);
+        self
+    }
+
+    pub fn into_router(self) -> Router<ServerState> {
+        self.router
+    }
+
+    pub fn into_docs(self) -> Vec<ApiRoute> {
+        self.docs
+    }
+}
+
+/// The standard routes for a model, to be registered with an [ApiRouter] along with their
+/// handlers.
+pub struct ModelRoutes {
+    pub list: ApiRoute,
+    pub get: ApiRoute,
+    pub create: ApiRoute,
+    pub update: ApiRoute,
+    pub patch: ApiRoute,
+    pub delete: ApiRoute,
+}
+
+/// Describe the standard list, get, create, update, and delete routes for a model.
+#[allow(clippy::too_many_arguments)]
+pub fn model_routes<
+    Id,
+    Item,
+    ListItem,
+    Filters,
+    CreatePayload,
+    CreateResult,
+    UpdatePayload,
+    PatchPayload,
+>(
+    tag: &'static str,
+    list_path: &'static str,
+    item_path: &'static str,
+    read_permissions: &[&'static str],
+    write_permissions: &[&'static str],
+    create_permissions: &[&'static str],
+) -> ModelRoutes
+where
+    Id: JsonSchema,
+    Item: JsonSchema,
+    ListItem: JsonSchema,
+    Filters: JsonSchema,
+    CreatePayload: JsonSchema,
+    CreateResult: JsonSchema,
+    UpdatePayload: JsonSchema,
+    PatchPayload: JsonSchema,
+{
+    ModelRoutes {
+        list: ApiRoute::new(Method::GET, list_path, tag, format!("List {tag} objects"))
+            .permissions(read_permissions)
+            .query::<Filters>()
+            .list_response::<ListItem>()
+            .exports(),
+        get: ApiRoute::new(Method::GET, item_path, tag, format!("Get a {tag}"))
+            .permissions(read_permissions)
+            .path_param::<Id>()
+            .response::<Item>(),
+        create: ApiRoute::new(Method::POST, list_path, tag, format!("Create a {tag}"))
+            .permissions(create_permissions)
+            .request::<CreatePayload>()
+            .response::<CreateResult>()
+            .status(201),
+        update: ApiRoute::new(Method::PUT, item_path, tag, format!("Update a {tag}"))
+            .permissions(write_permissions)
+            .path_param::<Id>()
+            .request::<UpdatePayload>(),
+        patch: ApiRoute::new(
+            Method::PATCH,
+            item_path,
+            tag,
+            format!("Partially update a {tag}"),
+        )
+        .permissions(write_permissions)
+        .path_param::<Id>()
+        .request::<PatchPayload>(),
+        delete: ApiRoute::new(Method::DELETE, item_path, tag, format!("Delete a {tag}"))
+            .permissions(create_permissions)
+            .path_param::<Id>(),
+    }
+}
+
+/// Describe the routes for a child model that is accessed through its parent, such as
+/// `/posts/:id/comments/:child_id`.
+#[allow(clippy::too_many_arguments)]
+pub fn child_model_routes<
+    ParentId,
+    Id,
+    Item,
+    Filters,
+    CreatePayload,
+    CreateResult,
+    UpdatePayload,
+    PatchPayload,
+>(
+    tag: &'static str,
+    list_path: &'static str,
+    item_path: &'static str,
+    read_permissions: &[&'static str],
+    write_permissions: &[&'static str],
+    create_permissions: &[&'static str],
+) -> ModelRoutes
+where
+    ParentId: JsonSchema,
+    Id: JsonSchema,
+    Item: JsonSchema,
+    Filters: JsonSchema,
+    CreatePayload: JsonSchema,
+    CreateResult: JsonSchema,
+    UpdatePayload: JsonSchema,
+    PatchPayload: JsonSchema,
+{
+    ModelRoutes {
+        list: ApiRoute::new(Method::GET, list_path, tag, format!("List {tag} objects"))
+            .permissions(read_permissions)
+            .path_param::<ParentId>()
+            .query::<Filters>()
+            .response::<Vec<Item>>(),
+        create: ApiRoute::new(Method::POST, list_path, tag, format!("Create a {tag}"))
+            .permissions(create_permissions)
+            .path_param::<ParentId>()
+            .request::<CreatePayload>()
+            .response::<CreateResult>(),
+        get: ApiRoute::new(Method::GET, item_path, tag, format!("Get a {tag}"))
+            .permissions(read_permissions)
+            .path_param::<ParentId>()
+            .path_param::<Id>()
+            .response::<Item>(),
+        update: ApiRoute::new(Method::PUT, item_path, tag, format!("Update a {tag}"))
+            .permissions(write_permissions)
+            .path_param::<ParentId>()
+            .path_param::<Id>()
+            .request::<UpdatePayload>(),
+        patch: ApiRoute::new(
+            Method::PATCH,
+            item_path,
+            tag,
+            format!("Partially update a {tag}"),
+        )
+        .permissions(write_permissions)
+        .path_param::<ParentId>()
+        .path_param::<Id>()
+        .request::<PatchPayload>(),
+        delete: ApiRoute::new(Method::DELETE, item_path, tag, format!("Delete a {tag}"))
+            .permissions(create_permissions)
+            .path_param::<ParentId>()
+            .path_param::<Id>(),
+    }
+}
+
+fn all_routes() -> Vec<ApiRoute> {
+    let mut routes = Vec::new();
+    routes.extend(super::meta::api_docs());
+    routes.extend(crate::models::api_docs());
+    routes.extend(crate::users::users::api_docs());
+    routes.extend(crate::auth::api_docs());
+    routes
+}
+
+/// Build the OpenAPI document for all the routes under `/api`.
+pub fn build_document() -> Value {
+    let mut settings = SchemaSettings::draft2019_09();
+    settings.definitions_path = "#/components/schemas/".to_string();
+    let mut gen = settings.into_generator();
+
+    let mut paths = Map::new();
+    for route in all_routes() {
+        let operation = route.operation(&mut gen);
+        let path_item = paths
+            .entry(format!("/api{}", route.openapi_path()))
+            .or_insert_with(|| json!({}));
+        path_item[route.method.as_str().to_lowercase()] = operation;
+    }
+
+    let schemas = gen
+        .take_definitions()
+        .into_iter()
+        .map(|(name, schema)| (name, serde_json::to_value(schema).unwrap_or_default()))
+        .collect::<Map<_, _>>();
+
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": "Filigree Htmx Test App",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths,
+        "components": {
+            "schemas": schemas,
+            "securitySchemes": {
+                "api_key": { "type": "http", "scheme": "bearer" },
+            },
+        },
+        "security": [{ "api_key": [] }],
+    })
+}
+
+async fn openapi_json() -> impl IntoResponse {
+    static DOCUMENT: OnceLock<Value> = OnceLock::new();
+    Json(DOCUMENT.get_or_init(build_document).clone())
+}
+
+async fn docs_viewer(State(state): State<ServerState>) -> Result<impl IntoResponse, Error> {
+    if state.production {
+        return Err(Error::NotFound("Route"));
+    }
+
+    Ok(Html(
+        r##"<!doctype html>
+<html>
+  <head>
+    <title>API Reference</title>
+    <meta charset="utf-8" />
+    <meta name="viewport" content="width=device-width, initial-scale=1" />
+  </head>
+  <body>
+    <script id="api-reference" data-url="/api/openapi.json"></script>
+    <script src="/vendor/scalar-api-reference.js"></script>
+  </body>
+</html>"##,
+    ))
+}
+
+pub fn create_routes() -> Router<ServerState> {
+    Router::new()
+        .route("/openapi.json", routing::get(openapi_json))
+        .route("/docs", routing::get(docs_viewer))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::tests::{start_app, BootstrappedData};
+
+    #[test]
+    fn document_includes_routes() {
+        let doc = super::build_document();
+
+        let get_post = &doc["paths"]["/api/posts/{id}"]["get"];
+        assert_eq!(get_post["parameters"][0]["name"], "id");
+        assert!(get_post["x-required-permissions"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!("Post::read")));
+
+        let list_posts = &doc["paths"]["/api/posts"]["get"];
+        assert!(list_posts["parameters"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|p| p["name"] == "per_page" && p["in"] == "query"));
+        let list_content = &list_posts["responses"]["200"]["content"];
+        assert_eq!(
+            list_content["application/json"]["schema"]["oneOf"]
+                .as_array()
+                .unwrap()
+                .len(),
+            2
+        );
+        assert!(list_content["text/csv"].is_object());
+        assert!(list_content["application/x-ndjson"].is_object());
+
+        assert!(doc["components"]["schemas"]["ErrorResponse"].is_object());
+        assert!(doc["components"]["schemas"]["PostCreatePayloadAndUpdatePayload"].is_object());
+    }
+
+    #[sqlx::test]
+    async fn serve_openapi_json(db: sqlx::PgPool) {
+        let (_app, BootstrappedData { user, .. }) = start_app(db).await;
+
+        let doc: serde_json::Value = user
+            .client
+            .get("openapi.json")
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(doc["openapi"], "3.1.0");
+    }
+}
@@ -9,10 +9,28 @@ use filigree::{
 };
 use url::Url;
 
+use crate::{
+    storage_keys::StorageKeyTemplates, storage_quota::QuotaLimits, storage_urls::UrlSigner,
+    upload_policy::UploadPolicy,
+};
+
 pub struct AppStorage {
     pub image_hosting: Storage,
     pub image_uploads: Storage,
     pub pdfs: Storage,
+    pub image_hosting_policy: UploadPolicy,
+    pub image_uploads_policy: UploadPolicy,
+    pub pdfs_policy: UploadPolicy,
+    /// Store files in content-addressed blobs shared by identical uploads
+    pub image_hosting_deduplicate: bool,
+    pub image_uploads_deduplicate: bool,
+    pub pdfs_deduplicate: bool,
+    /// Templates for the object keys of each file model
+    pub key_templates: StorageKeyTemplates,
+    /// The default storage quota for each organization
+    pub quota: QuotaLimits,
+    /// Signs URLs for objects in buckets that have no public URL
+    pub url_signer: UrlSigner,
     pub config_cdn: StorageConfig,
     pub config_disk: StorageConfig,
 }
@@ -29,22 +47,54 @@ impl AppStorage {
             pdfs: Storage::new(&config.pdfs.config, config.pdfs.bucket)
                 .attach_printable("Unable to create storage for pdfs")?
                 .with_public_url(config.pdfs.public_url),
+            image_hosting_policy: config.image_hosting.policy,
+            image_uploads_policy: config.image_uploads.policy,
+            pdfs_policy: config.pdfs.policy,
+            image_hosting_deduplicate: config.image_hosting.deduplicate,
+            image_uploads_deduplicate: config.image_uploads.deduplicate,
+            pdfs_deduplicate: config.pdfs.deduplicate,
+            key_templates: config.key_templates,
+            quota: config.quota,
+            url_signer: config.url_signer,
             config_cdn: config.config_cdn,
             config_disk: config.config_disk,
         })
     }
+
+    /// Look up a bucket by the name that the app uses for it, such as `image_uploads`.
+    pub fn bucket(&self, name: &str) -> Option<&Storage> {
+        match name {
+            "image_hosting" => Some(&self.image_hosting),
+            "image_uploads" => Some(&self.image_uploads),
+            "pdfs" => Some(&self.pdfs),
+            _ => None,
+        }
+    }
+
+    /// Create a signed, expiring URL that serves an object through the app. The `filename`
+    /// determines the content type of the response.
+    pub fn signed_url(&self, bucket: &str, key: &str, filename: Option<&str>) -> String {
+        self.url_signer.sign(bucket, key, filename)
+    }
 }
 
 pub struct AppStorageConfigEntry {
     pub config: StorageConfig,
     pub bucket: String,
     pub public_url: Option<Url>,
+    /// Limits on the files that can be uploaded to this bucket
+    pub policy: UploadPolicy,
+    /// Store files in content-addressed blobs, so that identical files are only stored once
+    pub deduplicate: bool,
 }
 
 pub struct AppStorageConfig {
     pub image_hosting: AppStorageConfigEntry,
     pub image_uploads: AppStorageConfigEntry,
     pub pdfs: AppStorageConfigEntry,
+    pub key_templates: StorageKeyTemplates,
+    pub quota: QuotaLimits,
+    pub url_signer: UrlSigner,
     pub config_cdn: StorageConfig,
     pub config_disk: StorageConfig,
 }
@@ -84,6 +134,15 @@ impl AppStorageConfig {
                     Url::parse("https://cdn.example.com/").expect("URL from template was invalid"),
                 ));
 
+        let image_hosting_policy = UploadPolicy::images().merge_env("STORAGE_IMAGE_HOSTING_")?;
+
+        let image_hosting_deduplicate =
+            parse_option::<bool>(std::env::var("STORAGE_IMAGE_HOSTING_DEDUPLICATE").ok())
+                .map_err(|_| {
+                    StorageError::Configuration("Invalid STORAGE_IMAGE_HOSTING_DEDUPLICATE")
+                })?
+                .unwrap_or(false);
+
         let mut bucket_config_image_uploads = config_disk.clone();
         bucket_config_image_uploads.merge_env("STORAGE_IMAGE_UPLOADS_")?;
 
@@ -95,6 +154,15 @@ impl AppStorageConfig {
                 StorageError::Configuration("Invalid URL in STORAGE_IMAGE_UPLOADS_PUBLIC_URL")
             })?;
 
+        let image_uploads_policy = UploadPolicy::images().merge_env("STORAGE_IMAGE_UPLOADS_")?;
+
+        let image_uploads_deduplicate =
+            parse_option::<bool>(std::env::var("STORAGE_IMAGE_UPLOADS_DEDUPLICATE").ok())
+                .map_err(|_| {
+                    StorageError::Configuration("Invalid STORAGE_IMAGE_UPLOADS_DEDUPLICATE")
+                })?
+                .unwrap_or(false);
+
         let mut bucket_config_pdfs = config_disk.clone();
         bucket_config_pdfs.merge_env("STORAGE_PDFS_")?;
 
@@ -106,22 +174,44 @@ impl AppStorageConfig {
                 StorageError::Configuration("Invalid URL in STORAGE_PDFS_PUBLIC_URL")
             })?;
 
+        let pdfs_policy = UploadPolicy::pdfs().merge_env("STORAGE_PDFS_")?;
+
+        let pdfs_deduplicate = parse_option::<bool>(std::env::var("STORAGE_PDFS_DEDUPLICATE").ok())
+            .map_err(|_| StorageError::Configuration("Invalid STORAGE_PDFS_DEDUPLICATE"))?
+            .unwrap_or(false);
+
+        let key_templates = StorageKeyTemplates::from_env()
+            .map_err(|_| StorageError::Configuration("Invalid STORAGE_KEY_TEMPLATE setting"))?;
+
+        let quota = QuotaLimits::from_env()?;
+
+        let url_signer = UrlSigner::from_env()?;
+
         Ok(AppStorageConfig {
             image_hosting: AppStorageConfigEntry {
                 config: bucket_config_image_hosting,
                 bucket: image_hosting_bucket,
                 public_url: image_hosting_public_url,
+                policy: image_hosting_policy,
+                deduplicate: image_hosting_deduplicate,
             },
             image_uploads: AppStorageConfigEntry {
                 config: bucket_config_image_uploads,
                 bucket: image_uploads_bucket,
                 public_url: image_uploads_public_url,
+                policy: image_uploads_policy,
+                deduplicate: image_uploads_deduplicate,
             },
             pdfs: AppStorageConfigEntry {
                 config: bucket_config_pdfs,
                 bucket: pdfs_bucket,
                 public_url: pdfs_public_url,
+                policy: pdfs_policy,
+                deduplicate: pdfs_deduplicate,
             },
+            key_templates,
+            quota,
+            url_signer,
             config_cdn,
             config_disk,
         })
@@ -136,17 +226,26 @@ impl AppStorageConfig {
                 public_url: Some(
                     Url::parse("https://cdn.example.com/").expect("URL from template was invalid"),
                 ),
+                policy: UploadPolicy::images(),
+                deduplicate: false,
             },
             image_uploads: AppStorageConfigEntry {
                 config: StorageConfig::Memory,
                 bucket: "fl-test-image-uploads".to_string(),
                 public_url: None,
+                policy: UploadPolicy::images(),
+                deduplicate: false,
             },
             pdfs: AppStorageConfigEntry {
                 config: StorageConfig::Memory,
                 bucket: "fl-test-pdfs".to_string(),
                 public_url: None,
+                policy: UploadPolicy::pdfs(),
+                deduplicate: false,
             },
+            key_templates: StorageKeyTemplates::default(),
+            quota: QuotaLimits::default(),
+            url_signer: UrlSigner::random(std::time::Duration::from_secs(60 * 60)),
             config_cdn: StorageConfig::Memory,
             config_disk: StorageConfig::Memory,
         }
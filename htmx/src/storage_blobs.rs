@@ -0,0 +1,181 @@
+//! Content-addressed storage of uploaded files.
+//!
+//! When deduplication is enabled for a bucket, a file is stored under a key derived from its
+//! hash, so identical files uploaded by the same organization share a single object. Each
+//! object has a row in `storage_blobs` that counts the rows referring to it, and the object is
+//! only deleted once the last of those references is released.
+
+use std::fmt::Display;
+
+use error_stack::{Report, ResultExt};
+use sqlx::{PgConnection, PgExecutor};
+
+use crate::{models::organization::OrganizationId, Error};
+
+/// A stored blob
+#[derive(Debug, Clone)]
+pub struct Blob {
+    pub storage_key: String,
+    pub size: i64,
+}
+
+/// The result of adding a reference to a blob
+#[derive(Debug, Clone)]
+pub struct AcquiredBlob {
+    pub storage_key: String,
+    /// True if this is the first reference, and so the object still has to be written
+    pub created: bool,
+}
+
+/// Format a hash as lowercase hex.
+pub fn encode_hash(hash: &[u8]) -> String {
+    hash.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Parse a hex-encoded hash, returning `None` if it is not valid hex.
+pub fn parse_hash(hash: &str) -> Option<Vec<u8>> {
+    if hash.is_empty() || hash.len() % 2 != 0 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    (0..hash.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hash[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// The storage key for a blob: `{organization_id}/{model}/blobs/{hash}`
+pub fn blob_key(organization_id: impl Display, model: &str, hash: &[u8]) -> String {
+    format!("{organization_id}/{model}/blobs/{}", encode_hash(hash))
+}
+
+/// Look up the blob with the given hash.
+pub async fn find(
+    db: impl PgExecutor<'_>,
+    organization_id: &OrganizationId,
+    bucket: &str,
+    hash: &[u8],
+) -> Result<Option<Blob>, Report<Error>> {
+    sqlx::query_as!(
+        Blob,
+        "SELECT storage_key, size FROM public.storage_blobs
+        WHERE organization_id = $1 AND bucket = $2 AND hash = $3",
+        organization_id.as_uuid(),
+        bucket,
+        hash
+    )
+    .fetch_optional(db)
+    .await
+    .change_context(Error::Db)
+}
+
+/// Add a reference to the blob with the given hash, creating the blob if it does not exist yet.
+///
+/// A new blob's row is locked until the transaction commits, so a concurrent upload of the same
+/// file waits here and then finds the object already written.
+pub async fn acquire(
+    tx: &mut PgConnection,
+    organization_id: &OrganizationId,
+    bucket: &str,
+    hash: &[u8],
+    storage_key: &str,
+    size: i64,
+) -> Result<AcquiredBlob, Report<Error>> {
+    sqlx::query_as!(
+        AcquiredBlob,
+        r#"INSERT INTO public.storage_blobs
+            (organization_id, bucket, hash, storage_key, size, ref_count)
+        VALUES ($1, $2, $3, $4, $5, 1)
+        ON CONFLICT (organization_id, bucket, hash) DO UPDATE
+        SET ref_count = storage_blobs.ref_count + 1, updated_at = now()
+        RETURNING storage_key, ref_count = 1 AS "created!""#,
+        organization_id.as_uuid(),
+        bucket,
+        hash,
+        storage_key,
+        size
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .change_context(Error::Db)
+}
+
+/// Add a reference to an existing blob. Returns `None` if there is no blob with this hash.
+pub async fn acquire_existing(
+    tx: &mut PgConnection,
+    organization_id: &OrganizationId,
+    bucket: &str,
+    hash: &[u8],
+) -> Result<Option<Blob>, Report<Error>> {
+    sqlx::query_as!(
+        Blob,
+        "UPDATE public.storage_blobs
+        SET ref_count = ref_count + 1, updated_at = now()
+        WHERE organization_id = $1 AND bucket = $2 AND hash = $3
+        RETURNING storage_key, size",
+        organization_id.as_uuid(),
+        bucket,
+        hash
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .change_context(Error::Db)
+}
+
+/// Release a reference to the object at `storage_key`, and return true if nothing refers to it
+/// anymore so that it can be deleted. Objects that are not blobs only ever have one reference.
+pub async fn release(
+    tx: &mut PgConnection,
+    organization_id: &OrganizationId,
+    bucket: &str,
+    storage_key: &str,
+) -> Result<bool, Report<Error>> {
+    let ref_count = sqlx::query_scalar!(
+        "UPDATE public.storage_blobs
+        SET ref_count = ref_count - 1, updated_at = now()
+        WHERE organization_id = $1 AND bucket = $2 AND storage_key = $3
+        RETURNING ref_count",
+        organization_id.as_uuid(),
+        bucket,
+        storage_key
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .change_context(Error::Db)?;
+
+    match ref_count {
+        None => Ok(true),
+        Some(count) if count <= 0 => {
+            sqlx::query!(
+                "DELETE FROM public.storage_blobs
+                WHERE organization_id = $1 AND bucket = $2 AND storage_key = $3",
+                organization_id.as_uuid(),
+                bucket,
+                storage_key
+            )
+            .execute(&mut *tx)
+            .await
+            .change_context(Error::Db)?;
+            Ok(true)
+        }
+        Some(_) => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hash_encoding() {
+        let hash = [0x00, 0x1f, 0xa0, 0xff];
+        assert_eq!(encode_hash(&hash), "001fa0ff");
+        assert_eq!(parse_hash("001fa0ff"), Some(hash.to_vec()));
+        assert_eq!(parse_hash("001FA0FF"), Some(hash.to_vec()));
+        assert_eq!(parse_hash("001"), None);
+        assert_eq!(parse_hash("zz"), None);
+        assert_eq!(parse_hash("+f"), None);
+        assert_eq!(parse_hash("é"), None);
+        assert_eq!(parse_hash(""), None);
+    }
+}
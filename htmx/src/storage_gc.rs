@@ -0,0 +1,283 @@
+//! Garbage collection of objects in storage that are no longer referenced by the database.
+//!
+//! Storage and the database can drift apart: an upload can be written before its transaction
+//! fails, deleting a file can fail after the row is already gone, and cascading deletes never
+//! touch storage at all. Collection lists every object in each bucket and deletes the ones that
+//! no row refers to. Objects newer than the grace period are always kept, so that uploads whose
+//! transactions have not committed yet are left alone.
+
+use std::collections::HashSet;
+
+use error_stack::{Report, ResultExt};
+use filigree::storage::Storage;
+use futures::TryStreamExt;
+use object_store::path::Path;
+use serde::Serialize;
+use sqlx::PgPool;
+use tracing::{event, Level};
+
+use crate::{storage::AppStorage, Error};
+
+/// The default time to wait before an unreferenced object is considered an orphan
+pub const DEFAULT_GRACE_PERIOD_HOURS: i64 = 24;
+
+#[derive(Debug, Clone)]
+pub struct GcOptions {
+    /// Objects modified more recently than this are never deleted
+    pub grace_period: chrono::Duration,
+    /// Report the orphaned objects without deleting them
+    pub dry_run: bool,
+}
+
+impl Default for GcOptions {
+    fn default() -> Self {
+        Self {
+            grace_period: chrono::Duration::hours(DEFAULT_GRACE_PERIOD_HOURS),
+            dry_run: false,
+        }
+    }
+}
+
+/// The result of collecting garbage in a bucket
+#[derive(Debug, Default, Serialize)]
+pub struct BucketGcReport {
+    pub bucket: &'static str,
+    /// The number of objects in the bucket
+    pub scanned: usize,
+    /// The keys of the unreferenced objects older than the grace period
+    pub orphaned: Vec<String>,
+    /// The total size of the orphaned objects
+    pub orphaned_bytes: u64,
+    /// The number of orphaned objects that were deleted
+    pub deleted: usize,
+    /// The number of orphaned objects that could not be deleted
+    pub failed: usize,
+}
+
+/// Find the orphaned objects in every bucket and, unless this is a dry run, delete them.
+pub async fn collect_garbage(
+    db: &PgPool,
+    storage: &AppStorage,
+    options: &GcOptions,
+) -> Result<Vec<BucketGcReport>, Report<Error>> {
+    // Read the time before listing the references, so that an object can't be written and
+    // referenced after the references are read while still looking older than the cutoff.
+    let cutoff = chrono::Utc::now() - options.grace_period;
+    if !options.dry_run {
+        prune_blobs(db, cutoff).await?;
+    }
+    let references = referenced_keys(db).await?;
+
+    let buckets = [
+        ("image_uploads", &storage.image_uploads),
+        ("image_hosting", &storage.image_hosting),
+        ("pdfs", &storage.pdfs),
+    ];
+
+    let mut reports = Vec::with_capacity(buckets.len());
+    for (bucket, bucket_storage) in buckets {
+        let report =
+            collect_bucket(bucket, bucket_storage, &references, cutoff, options.dry_run).await?;
+        event!(
+            Level::INFO,
+            bucket,
+            scanned = report.scanned,
+            orphaned = report.orphaned.len(),
+            orphaned_bytes = report.orphaned_bytes,
+            deleted = report.deleted,
+            failed = report.failed,
+            dry_run = options.dry_run,
+            "Collected storage garbage"
+        );
+        reports.push(report);
+    }
+
+    Ok(reports)
+}
+
+/// Return the `(bucket, path)` pairs of every object that the database refers to. The keys are
+/// converted to the paths that the store lists them under, which encodes characters such as `%`.
+async fn referenced_keys(db: &PgPool) -> Result<HashSet<(String, Path)>, Report<Error>> {
+    let rows = sqlx::query!(
+        r#"SELECT file_storage_bucket AS "bucket!", file_storage_key AS "key!"
+        FROM public.post_images
+        UNION ALL
+        SELECT 'image_hosting' AS "bucket!", r.value->>'file_storage_key' AS "key!"
+        FROM public.post_images
+        CROSS JOIN LATERAL jsonb_array_elements(renditions) r
+        UNION ALL
+        SELECT 'image_uploads' AS "bucket!", unnest(chunk_keys) AS "key!"
+        FROM public.post_image_uploads"#
+    )
+    .fetch_all(db)
+    .await
+    .change_context(Error::Db)?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.bucket, Path::from(row.key)))
+        .collect())
+}
+
+/// Delete the `storage_blobs` rows that no file refers to. These are left behind when rows are
+/// removed without releasing their blobs, such as when a post is deleted by a cascade. Once the
+/// row is gone the object is unreferenced and is collected with the rest.
+async fn prune_blobs(
+    db: &PgPool,
+    cutoff: chrono::DateTime<chrono::Utc>,
+) -> Result<(), Report<Error>> {
+    let pruned = sqlx::query!(
+        "DELETE FROM public.storage_blobs b
+        WHERE b.updated_at < $1
+            AND NOT EXISTS (
+                SELECT 1 FROM public.post_images p
+                WHERE p.file_storage_bucket = b.bucket AND p.file_storage_key = b.storage_key
+            )",
+        cutoff
+    )
+    .execute(db)
+    .await
+    .change_context(Error::Db)?
+    .rows_affected();
+
+    if pruned > 0 {
+        event!(Level::INFO, pruned, "Pruned unreferenced storage blobs");
+    }
+
+    Ok(())
+}
+
+async fn collect_bucket(
+    bucket: &'static str,
+    storage: &Storage,
+    references: &HashSet<(String, Path)>,
+    cutoff: chrono::DateTime<chrono::Utc>,
+    dry_run: bool,
+) -> Result<BucketGcReport, Report<Error>> {
+    let mut report = BucketGcReport {
+        bucket,
+        ..Default::default()
+    };
+
+    // Finish listing before deleting anything, since not every provider handles deletes in the
+    // middle of a listing.
+    let mut objects = storage.list(None);
+    while let Some(object) = objects.try_next().await.change_context(Error::Storage)? {
+        report.scanned += 1;
+        if object.last_modified > cutoff {
+            continue;
+        }
+
+        if references.contains(&(bucket.to_string(), object.location.clone())) {
+            continue;
+        }
+
+        report.orphaned_bytes += object.size as u64;
+        report.orphaned.push(object.location.to_string());
+    }
+
+    if dry_run {
+        return Ok(report);
+    }
+
+    for key in &report.orphaned {
+        match storage.delete(key).await {
+            Ok(_) => report.deleted += 1,
+            Err(e) => {
+                event!(Level::WARN, bucket, key, error = ?e, "Failed to delete orphaned object");
+                report.failed += 1;
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::{
+        models::{
+            post::{Post, PostId},
+            post_image::{PostImage, PostImageId, PostImageUpdatePayload},
+        },
+        storage::AppStorageConfig,
+        tests::{start_app, BootstrappedData},
+    };
+
+    #[sqlx::test]
+    async fn collect_orphans(pool: sqlx::PgPool) {
+        let (_app, BootstrappedData { organization, .. }) = start_app(pool.clone()).await;
+        let storage = AppStorage::new(AppStorageConfig::new_in_memory()).unwrap();
+
+        let mut tx = pool.begin().await.unwrap();
+        let post_id = PostId::new();
+        Post::create_raw(
+            &mut *tx,
+            &post_id,
+            &organization.id,
+            crate::models::post::testing::make_create_payload(0),
+        )
+        .await
+        .unwrap();
+        // Keys with `%` or non-ASCII characters are listed in their encoded form, and must still
+        // match their references.
+        let referenced = ["referenced.png", "caf%C3%A9.png", "café.png"];
+        for key in referenced {
+            PostImage::upsert_with_parent_post(
+                &mut *tx,
+                &organization.id,
+                &post_id,
+                &PostImageUpdatePayload {
+                    id: Some(PostImageId::new()),
+                    post_id,
+                    file_storage_key: key.to_string(),
+                    file_storage_bucket: "image_uploads".to_string(),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        }
+        tx.commit().await.unwrap();
+
+        for key in referenced.into_iter().chain(["orphan.png"]) {
+            storage
+                .image_uploads
+                .put(key, Bytes::from_static(b"data"))
+                .await
+                .unwrap();
+        }
+
+        // Nothing is old enough to collect with the default grace period.
+        let reports = collect_garbage(&pool, &storage, &GcOptions::default())
+            .await
+            .unwrap();
+        assert!(reports.iter().all(|r| r.orphaned.is_empty()));
+
+        let dry_run = GcOptions {
+            grace_period: chrono::Duration::zero(),
+            dry_run: true,
+        };
+        let reports = collect_garbage(&pool, &storage, &dry_run).await.unwrap();
+        assert_eq!(reports[0].bucket, "image_uploads");
+        assert_eq!(reports[0].scanned, 4);
+        assert_eq!(reports[0].orphaned, vec!["orphan.png".to_string()]);
+        assert_eq!(reports[0].orphaned_bytes, 4);
+        assert_eq!(reports[0].deleted, 0);
+        storage.image_uploads.get("orphan.png").await.unwrap();
+
+        let options = GcOptions {
+            dry_run: false,
+            ..dry_run
+        };
+        let reports = collect_garbage(&pool, &storage, &options).await.unwrap();
+        assert_eq!(reports[0].deleted, 1);
+        assert!(storage.image_uploads.get("orphan.png").await.is_err());
+        for key in referenced {
+            storage.image_uploads.get(key).await.unwrap();
+        }
+    }
+}
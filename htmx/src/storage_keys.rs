@@ -0,0 +1,215 @@
+//! Construction of object storage keys for uploaded files.
+//!
+//! Every key starts with the organization ID and the file model, so that all of an organization's
+//! files can be found by prefix, and the rest comes from a [KeyTemplate]. Filenames come from the
+//! client, so they are sanitized before they become part of a key.
+
+use std::{fmt::Display, str::FromStr};
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+/// Characters that are escaped in filenames. Everything except ASCII letters, digits, `-`, `_`
+/// and `.`.
+const FILENAME_ESCAPE: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'_').remove(b'.');
+
+/// The maximum length of a sanitized filename, in bytes
+const MAX_FILENAME_LEN: usize = 128;
+
+/// The longest extension that is kept intact when a filename is truncated
+const MAX_EXTENSION_LEN: usize = 16;
+
+/// Turn a client-supplied filename into one that is safe to use in a storage key.
+///
+/// Any directory components are removed, runs of whitespace become `-`, control characters are
+/// dropped, and other characters outside of `[A-Za-z0-9._-]` are percent-encoded. Leading dots are
+/// removed so that the name can't be `..` or a hidden file, and long names are truncated while
+/// keeping the extension.
+pub fn sanitize_filename(filename: &str) -> String {
+    let name = filename
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .trim_start_matches('.');
+
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, ext))
+            if !stem.is_empty()
+                && !ext.is_empty()
+                && ext.len() <= MAX_EXTENSION_LEN
+                && ext.chars().all(|c| c.is_ascii_alphanumeric()) =>
+        {
+            (stem, Some(ext.to_ascii_lowercase()))
+        }
+        _ => (name, None),
+    };
+
+    let max_stem_len = MAX_FILENAME_LEN - extension.as_ref().map(|e| e.len() + 1).unwrap_or(0);
+    let mut output = String::with_capacity(stem.len());
+    let mut last_was_space = false;
+    for c in stem.chars() {
+        if c.is_control() {
+            continue;
+        }
+
+        let is_space = c.is_whitespace();
+        if is_space && last_was_space {
+            continue;
+        }
+        last_was_space = is_space;
+
+        let mut buf = [0; 4];
+        let piece = if is_space {
+            "-".to_string()
+        } else {
+            utf8_percent_encode(c.encode_utf8(&mut buf), FILENAME_ESCAPE).to_string()
+        };
+
+        // Only cut between characters, so that an escape sequence is never split.
+        if output.len() + piece.len() > max_stem_len {
+            break;
+        }
+        output.push_str(&piece);
+    }
+
+    if output.is_empty() {
+        output.push_str("file");
+    }
+
+    if let Some(extension) = extension {
+        output.push('.');
+        output.push_str(&extension);
+    }
+
+    output
+}
+
+/// A template for the part of a storage key that follows the organization and model prefix.
+///
+/// The template can contain the placeholders `{id}` and `{filename}`. `{id}` is required, so that
+/// each key is unique.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyTemplate(String);
+
+impl KeyTemplate {
+    /// Build a storage key for a file.
+    pub fn render(
+        &self,
+        organization_id: impl Display,
+        model: &str,
+        id: impl Display,
+        filename: &str,
+    ) -> String {
+        let path = self
+            .0
+            .replace("{id}", &id.to_string())
+            .replace("{filename}", &sanitize_filename(filename));
+        format!("{organization_id}/{model}/{path}")
+    }
+}
+
+impl Default for KeyTemplate {
+    fn default() -> Self {
+        Self("{id}-{filename}".to_string())
+    }
+}
+
+impl FromStr for KeyTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let template = s.trim();
+
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("Unclosed placeholder in key template {template}"))?;
+            let placeholder = &rest[start..start + end + 1];
+            if placeholder != "{id}" && placeholder != "{filename}" {
+                return Err(format!("Unknown placeholder {placeholder} in key template"));
+            }
+            rest = &rest[start + end + 1..];
+        }
+
+        if !template.contains("{id}") {
+            return Err("Key templates must contain {id}".to_string());
+        }
+
+        if template.starts_with('/')
+            || template
+                .split('/')
+                .any(|segment| segment.is_empty() || segment == "." || segment == "..")
+        {
+            return Err(format!("Invalid path in key template {template}"));
+        }
+
+        Ok(Self(template.to_string()))
+    }
+}
+
+/// The key templates for each file model
+#[derive(Debug, Clone, Default)]
+pub struct StorageKeyTemplates {
+    pub post_image: KeyTemplate,
+}
+
+impl StorageKeyTemplates {
+    /// Read the templates from the `STORAGE_KEY_TEMPLATE_<MODEL>` environment variables, using the
+    /// default template for any that are not set.
+    pub fn from_env() -> Result<Self, String> {
+        let template = |var: &str| -> Result<KeyTemplate, String> {
+            std::env::var(var)
+                .ok()
+                .map(|t| t.parse())
+                .transpose()
+                .map(|t| t.unwrap_or_default())
+        };
+
+        Ok(Self {
+            post_image: template("STORAGE_KEY_TEMPLATE_POST_IMAGE")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sanitize() {
+        assert_eq!(sanitize_filename("photo.JPG"), "photo.jpg");
+        assert_eq!(sanitize_filename("../../etc/passwd"), "passwd");
+        assert_eq!(sanitize_filename("C:\\Users\\me\\my  photo.png"), "my-photo.png");
+        assert_eq!(sanitize_filename("..hidden"), "hidden");
+        assert_eq!(sanitize_filename(".."), "file");
+        assert_eq!(sanitize_filename(""), "file");
+        assert_eq!(sanitize_filename("a\u{0}b?c.png"), "ab%3Fc.png");
+        assert_eq!(sanitize_filename("café.png"), "caf%C3%A9.png");
+
+        let long = format!("{}.jpeg", "é".repeat(100));
+        let sanitized = sanitize_filename(&long);
+        assert!(sanitized.len() <= MAX_FILENAME_LEN);
+        assert!(sanitized.ends_with("%C3%A9.jpeg"));
+    }
+
+    #[test]
+    fn template() {
+        let template = KeyTemplate::default();
+        assert_eq!(
+            template.render("org1", "post_image", "img1", "My Photo.png"),
+            "org1/post_image/img1-My-Photo.png"
+        );
+
+        let template = "{id}/original/{filename}".parse::<KeyTemplate>().unwrap();
+        assert_eq!(
+            template.render("org1", "post_image", "img1", "../x.png"),
+            "org1/post_image/img1/original/x.png"
+        );
+
+        assert!("{filename}".parse::<KeyTemplate>().is_err());
+        assert!("{id}/{name}".parse::<KeyTemplate>().is_err());
+        assert!("{id}/../{filename}".parse::<KeyTemplate>().is_err());
+        assert!("/{id}".parse::<KeyTemplate>().is_err());
+    }
+}
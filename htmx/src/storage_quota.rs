@@ -0,0 +1,362 @@
+//! Per-organization storage quotas.
+//!
+//! Usage is the total size of every file an organization has stored, counted from the database
+//! rather than by listing buckets. Deduplicated files are only counted once. Each organization
+//! has a soft limit, which sends a warning email to its administrators, and a hard limit, past
+//! which uploads are rejected. The defaults come from the `STORAGE_QUOTA_SOFT_LIMIT` and
+//! `STORAGE_QUOTA_HARD_LIMIT` environment variables, and can be overridden for an organization
+//! in the `storage_quotas` table.
+
+use error_stack::{Report, ResultExt};
+use filigree::{config::parse_option, storage::StorageError};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgExecutor};
+use tracing::{event, Level};
+
+use crate::{models::organization::OrganizationId, server::ServerState, Error};
+
+/// How long to wait before warning an organization about its soft limit again
+const WARNING_INTERVAL_DAYS: i32 = 7;
+
+/// Storage limits, in bytes. `None` means that there is no limit.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema,
+)]
+pub struct QuotaLimits {
+    /// Usage above this limit sends a warning to the organization's administrators
+    pub soft_limit: Option<i64>,
+    /// Uploads that would take usage above this limit are rejected
+    pub hard_limit: Option<i64>,
+}
+
+impl QuotaLimits {
+    /// Read the default limits from the `STORAGE_QUOTA_SOFT_LIMIT` and `STORAGE_QUOTA_HARD_LIMIT`
+    /// environment variables.
+    pub fn from_env() -> Result<Self, StorageError> {
+        let limit = |var: &str| {
+            parse_option::<i64>(std::env::var(var).ok())
+                .map(|limit| limit.filter(|l| *l > 0))
+                .map_err(|_| StorageError::Configuration("Invalid STORAGE_QUOTA setting"))
+        };
+
+        Ok(Self {
+            soft_limit: limit("STORAGE_QUOTA_SOFT_LIMIT")?,
+            hard_limit: limit("STORAGE_QUOTA_HARD_LIMIT")?,
+        })
+    }
+}
+
+/// The storage used by one model in one bucket
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct StorageUsageItem {
+    pub bucket: String,
+    pub model: String,
+    /// The number of stored objects
+    pub objects: i64,
+    /// The total size of the objects, in bytes
+    pub bytes: i64,
+}
+
+/// An organization's storage usage and limits
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct StorageUsage {
+    /// The total size of all stored objects, in bytes
+    pub total_bytes: i64,
+    #[serde(flatten)]
+    pub limits: QuotaLimits,
+    pub items: Vec<StorageUsageItem>,
+}
+
+/// Get the limits for an organization, falling back to `defaults` for any that it doesn't override.
+pub async fn get_limits(
+    db: impl PgExecutor<'_>,
+    defaults: &QuotaLimits,
+    organization_id: &OrganizationId,
+) -> Result<QuotaLimits, Report<Error>> {
+    let row = sqlx::query!(
+        "SELECT soft_limit, hard_limit FROM public.storage_quotas WHERE organization_id = $1",
+        organization_id.as_uuid()
+    )
+    .fetch_optional(db)
+    .await
+    .change_context(Error::Db)?;
+
+    Ok(QuotaLimits {
+        soft_limit: row
+            .as_ref()
+            .and_then(|r| r.soft_limit)
+            .or(defaults.soft_limit),
+        hard_limit: row
+            .as_ref()
+            .and_then(|r| r.hard_limit)
+            .or(defaults.hard_limit),
+    })
+}
+
+/// Override the limits for an organization. `None` uses the default limit.
+pub async fn set_limits(
+    db: impl PgExecutor<'_>,
+    organization_id: &OrganizationId,
+    limits: &QuotaLimits,
+) -> Result<(), Report<Error>> {
+    sqlx::query!(
+        "INSERT INTO public.storage_quotas (organization_id, soft_limit, hard_limit)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (organization_id) DO UPDATE
+        SET soft_limit = EXCLUDED.soft_limit,
+            hard_limit = EXCLUDED.hard_limit,
+            updated_at = now()",
+        organization_id.as_uuid(),
+        limits.soft_limit,
+        limits.hard_limit
+    )
+    .execute(db)
+    .await
+    .change_context(Error::Db)?;
+
+    Ok(())
+}
+
+/// Calculate an organization's storage usage, broken down by bucket and model.
+pub async fn get_usage(
+    db: impl PgExecutor<'_>,
+    organization_id: &OrganizationId,
+) -> Result<Vec<StorageUsageItem>, Report<Error>> {
+    // UNION removes the duplicate rows of files that share a deduplicated object.
+    sqlx::query_as!(
+        StorageUsageItem,
+        r#"SELECT bucket AS "bucket!", model AS "model!",
+            COUNT(*) AS "objects!", COALESCE(SUM(size), 0)::bigint AS "bytes!"
+        FROM (
+            SELECT file_storage_bucket AS bucket, 'post_image' AS model,
+                file_storage_key AS key, COALESCE(file_size, 0) AS size
+            FROM public.post_images
+            WHERE organization_id = $1
+            UNION
+            SELECT 'image_hosting', 'post_image', r.value->>'file_storage_key',
+                COALESCE((r.value->>'file_size')::bigint, 0)
+            FROM public.post_images
+            CROSS JOIN LATERAL jsonb_array_elements(renditions) r
+            WHERE organization_id = $1
+            UNION
+            SELECT 'image_uploads', 'post_image_upload', id::text, upload_offset
+            FROM public.post_image_uploads
+            WHERE organization_id = $1 AND completed_at IS NULL
+        ) files
+        GROUP BY bucket, model
+        ORDER BY bucket, model"#,
+        organization_id.as_uuid()
+    )
+    .fetch_all(db)
+    .await
+    .change_context(Error::Db)
+}
+
+/// Get an organization's usage along with its limits.
+pub async fn get_usage_report(
+    db: &sqlx::PgPool,
+    defaults: &QuotaLimits,
+    organization_id: &OrganizationId,
+) -> Result<StorageUsage, Report<Error>> {
+    let limits = get_limits(db, defaults, organization_id).await?;
+    let items = get_usage(db, organization_id).await?;
+
+    Ok(StorageUsage {
+        total_bytes: items.iter().map(|i| i.bytes).sum(),
+        limits,
+        items,
+    })
+}
+
+async fn total_usage(
+    db: impl PgExecutor<'_>,
+    organization_id: &OrganizationId,
+) -> Result<i64, Report<Error>> {
+    let usage = get_usage(db, organization_id).await?;
+    Ok(usage.iter().map(|i| i.bytes).sum())
+}
+
+/// Return how many more bytes the organization can store, or `None` if it has no hard limit.
+///
+/// This is only a snapshot, used to stop an upload early once it can't possibly fit.
+/// [check] is the authoritative check.
+pub async fn remaining(
+    db: &mut PgConnection,
+    defaults: &QuotaLimits,
+    organization_id: &OrganizationId,
+) -> Result<Option<i64>, Report<Error>> {
+    let limits = get_limits(&mut *db, defaults, organization_id).await?;
+    let Some(hard_limit) = limits.hard_limit else {
+        return Ok(None);
+    };
+
+    let usage = total_usage(&mut *db, organization_id).await?;
+    Ok(Some((hard_limit - usage).max(0)))
+}
+
+/// The result of a successful quota check
+#[derive(Debug, Clone, Copy)]
+pub struct QuotaCheck {
+    /// True if the new usage is over the soft limit
+    pub over_soft_limit: bool,
+}
+
+/// Check that the organization can store `new_bytes` more bytes.
+///
+/// This takes a lock that is held until the transaction ends, so that concurrent uploads can't
+/// each pass the check and together go over the limit. Call it as late as possible before
+/// committing.
+pub async fn check(
+    tx: &mut PgConnection,
+    defaults: &QuotaLimits,
+    organization_id: &OrganizationId,
+    new_bytes: i64,
+) -> Result<QuotaCheck, Report<Error>> {
+    let limits = get_limits(&mut *tx, defaults, organization_id).await?;
+    if limits.soft_limit.is_none() && limits.hard_limit.is_none() {
+        return Ok(QuotaCheck {
+            over_soft_limit: false,
+        });
+    }
+
+    sqlx::query!(
+        "SELECT pg_advisory_xact_lock(hashtextextended('storage_quota:' || $1::text, 0))",
+        organization_id.as_uuid()
+    )
+    .execute(&mut *tx)
+    .await
+    .change_context(Error::Db)?;
+
+    let usage = total_usage(&mut *tx, organization_id).await? + new_bytes;
+    if limits.hard_limit.is_some_and(|limit| usage > limit) {
+        return Err(Report::new(Error::StorageQuotaExceeded));
+    }
+
+    Ok(QuotaCheck {
+        over_soft_limit: limits.soft_limit.is_some_and(|limit| usage > limit),
+    })
+}
+
+/// Warn the organization's administrators if its usage is over the soft limit. Each
+/// organization is warned at most once a week.
+pub async fn send_soft_limit_warning(
+    state: &ServerState,
+    organization_id: &OrganizationId,
+) -> Result<(), Report<Error>> {
+    let usage = get_usage_report(&state.db, &state.storage.quota, organization_id).await?;
+    let Some(soft_limit) = usage.limits.soft_limit else {
+        return Ok(());
+    };
+
+    if usage.total_bytes <= soft_limit {
+        return Ok(());
+    }
+
+    let mut tx = state.db.begin().await.change_context(Error::Db)?;
+    let claimed = sqlx::query_scalar!(
+        r#"INSERT INTO public.storage_quotas (organization_id, soft_limit_warned_at)
+        VALUES ($1, now())
+        ON CONFLICT (organization_id) DO UPDATE
+        SET soft_limit_warned_at = now()
+        WHERE storage_quotas.soft_limit_warned_at IS NULL
+            OR storage_quotas.soft_limit_warned_at < now() - make_interval(days => $2)
+        RETURNING true AS "claimed!""#,
+        organization_id.as_uuid(),
+        WARNING_INTERVAL_DAYS
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .change_context(Error::Db)?;
+
+    if claimed.is_none() {
+        return Ok(());
+    }
+
+    let organization_name = sqlx::query_scalar!(
+        "SELECT name FROM public.organizations WHERE id = $1",
+        organization_id.as_uuid()
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .change_context(Error::Db)?;
+
+    // The organization's owner, and anyone with the org_admin permission directly or through
+    // a role.
+    let recipients = sqlx::query!(
+        r#"SELECT u.email AS "email!", u.name
+        FROM public.users u
+        WHERE u.email IS NOT NULL
+            AND (
+                u.id = (SELECT owner FROM public.organizations WHERE id = $1)
+                OR EXISTS (
+                    SELECT 1 FROM public.permissions p
+                    WHERE p.organization_id = $1
+                        AND p.permission = 'org_admin'
+                        AND (
+                            p.actor_id = u.id
+                            OR p.actor_id IN (
+                                SELECT role_id FROM public.user_roles ur
+                                WHERE ur.organization_id = $1 AND ur.user_id = u.id
+                            )
+                        )
+                )
+            )"#,
+        organization_id.as_uuid()
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .change_context(Error::Db)?;
+
+    for recipient in recipients {
+        let template = crate::emails::StorageQuotaWarningTemplate {
+            user_name: Some(recipient.name),
+            organization_name: organization_name.clone(),
+            usage: format_bytes(usage.total_bytes),
+            soft_limit: format_bytes(soft_limit),
+            hard_limit: usage.limits.hard_limit.map(format_bytes),
+        };
+
+        if let Err(e) = state
+            .filigree
+            .email
+            .send_template(recipient.email.clone(), template)
+            .await
+        {
+            event!(Level::ERROR, email = recipient.email, error = ?e, "Failed to send storage quota warning");
+        }
+    }
+
+    tx.commit().await.change_context(Error::Db)?;
+
+    Ok(())
+}
+
+/// Format a size in bytes for people to read, e.g. "1.5 GB".
+pub fn format_bytes(bytes: i64) -> String {
+    const UNITS: &[&str] = &["bytes", "KB", "MB", "GB", "TB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} bytes")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn format() {
+        assert_eq!(format_bytes(100), "100 bytes");
+        assert_eq!(format_bytes(1536), "1.5 KB");
+        assert_eq!(format_bytes(5 * 1024 * 1024 * 1024), "5.0 GB");
+    }
+}
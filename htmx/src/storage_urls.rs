@@ -0,0 +1,301 @@
+//! Signed, expiring URLs for stored objects.
+//!
+//! Buckets without a public URL can't be linked to directly, so the app serves their objects
+//! itself at `/api/storage/{bucket}/{key}`. The URL carries an expiration time and an HMAC-SHA256
+//! signature over the bucket, key, filename and expiration time. The signature takes the place of
+//! authentication, so the URL works anywhere that a browser fetches a file, such as an `<img>` tag.
+//!
+//! The signing key comes from `STORAGE_URL_SIGNING_KEY`, and every instance of the app must use
+//! the same one. URLs are valid for `STORAGE_SIGNED_URL_EXPIRY` seconds, or an hour by default.
+
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, State},
+    http::header,
+    response::IntoResponse,
+    routing,
+};
+use axum_extra::extract::Query;
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use error_stack::ResultExt;
+use filigree::{config::parse_option, storage::StorageError};
+use hmac::{Hmac, Mac};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use sha2::Sha256;
+use tracing::{event, Level};
+
+use crate::{server::ServerState, Error};
+
+/// The path that signed URLs are served from
+pub const SIGNED_URL_PATH: &str = "/api/storage";
+
+/// How long a signed URL is valid for if `STORAGE_SIGNED_URL_EXPIRY` is not set
+const DEFAULT_EXPIRY: Duration = Duration::from_secs(60 * 60);
+
+/// Characters that are escaped in each segment of an object key. Keys of uploaded files contain
+/// percent-encoded filenames, so `%` has to be escaped too.
+const KEY_SEGMENT_ESCAPE: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'_').remove(b'.');
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Creates and verifies signed URLs
+pub struct UrlSigner {
+    key: Vec<u8>,
+    /// How long a new URL is valid for
+    pub expiry: Duration,
+}
+
+impl UrlSigner {
+    pub fn new(key: impl Into<Vec<u8>>, expiry: Duration) -> Self {
+        Self {
+            key: key.into(),
+            expiry,
+        }
+    }
+
+    /// Create a signer with a random key. Its URLs stop working when the process exits, and other
+    /// instances of the app don't accept them.
+    pub fn random(expiry: Duration) -> Self {
+        let key = [uuid::Uuid::new_v4(), uuid::Uuid::new_v4()]
+            .iter()
+            .flat_map(|u| u.into_bytes())
+            .collect::<Vec<_>>();
+        Self::new(key, expiry)
+    }
+
+    /// Read the signing key from `STORAGE_URL_SIGNING_KEY` and the expiry time, in seconds, from
+    /// `STORAGE_SIGNED_URL_EXPIRY`. A random key is used if none is set.
+    pub fn from_env() -> Result<Self, StorageError> {
+        let expiry = parse_option::<u64>(std::env::var("STORAGE_SIGNED_URL_EXPIRY").ok())
+            .map_err(|_| StorageError::Configuration("Invalid STORAGE_SIGNED_URL_EXPIRY"))?
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_EXPIRY);
+
+        match std::env::var("STORAGE_URL_SIGNING_KEY") {
+            Ok(key) if !key.is_empty() => Ok(Self::new(key, expiry)),
+            _ => {
+                event!(
+                    Level::WARN,
+                    "STORAGE_URL_SIGNING_KEY is not set, so signed URLs will stop working when the server restarts"
+                );
+                Ok(Self::random(expiry))
+            }
+        }
+    }
+
+    fn mac(&self, bucket: &str, key: &str, filename: Option<&str>, expires: i64) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.key).expect("HMAC accepts keys of any length");
+
+        // Prefix each field with its length so that the boundaries between them can't be moved.
+        for field in [bucket, key, filename.unwrap_or_default()] {
+            mac.update(&(field.len() as u64).to_be_bytes());
+            mac.update(field.as_bytes());
+        }
+        mac.update(&expires.to_be_bytes());
+        mac
+    }
+
+    /// Create a URL for an object that is valid for [UrlSigner::expiry]. The `filename` sets the
+    /// content type of the response.
+    pub fn sign(&self, bucket: &str, key: &str, filename: Option<&str>) -> String {
+        let expires = chrono::Utc::now().timestamp() + self.expiry.as_secs() as i64;
+        self.sign_with_expiration(bucket, key, filename, expires)
+    }
+
+    fn sign_with_expiration(
+        &self,
+        bucket: &str,
+        key: &str,
+        filename: Option<&str>,
+        expires: i64,
+    ) -> String {
+        let signature = self
+            .mac(bucket, key, filename, expires)
+            .finalize()
+            .into_bytes();
+
+        let path = key
+            .split('/')
+            .map(|segment| utf8_percent_encode(segment, KEY_SEGMENT_ESCAPE).to_string())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let mut query = url::form_urlencoded::Serializer::new(String::new());
+        query.append_pair("expires", &expires.to_string());
+        if let Some(filename) = filename {
+            query.append_pair("filename", filename);
+        }
+        query.append_pair("signature", &BASE64_URL_SAFE_NO_PAD.encode(signature));
+
+        format!("{SIGNED_URL_PATH}/{bucket}/{path}?{}", query.finish())
+    }
+
+    /// Check that a URL's signature is valid and that it has not expired.
+    pub fn verify(
+        &self,
+        bucket: &str,
+        key: &str,
+        filename: Option<&str>,
+        expires: i64,
+        signature: &str,
+    ) -> bool {
+        if expires < chrono::Utc::now().timestamp() {
+            return false;
+        }
+
+        let Ok(signature) = BASE64_URL_SAFE_NO_PAD.decode(signature) else {
+            return false;
+        };
+
+        self.mac(bucket, key, filename, expires)
+            .verify_slice(&signature)
+            .is_ok()
+    }
+}
+
+/// Guess the content type of a file from the extension of its name.
+pub fn content_type(filename: &str) -> &'static str {
+    let extension = filename
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "svg" => "image/svg+xml",
+        "pdf" => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Headers for a response that serves an uploaded file inline from the app's origin.
+pub fn uploaded_file_headers(
+    content_type: &str,
+    cache_control: String,
+) -> [(header::HeaderName, String); 4] {
+    [
+        (header::CONTENT_TYPE, content_type.to_string()),
+        (header::CACHE_CONTROL, cache_control),
+        (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+        // Uploaded files come from users, so don't let an SVG run scripts on the app's origin.
+        (header::CONTENT_SECURITY_POLICY, "sandbox".to_string()),
+    ]
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct SignedUrlQuery {
+    expires: i64,
+    signature: String,
+    filename: Option<String>,
+}
+
+/// Stream an object from storage, if the URL's signature is valid.
+async fn get_object(
+    State(state): State<ServerState>,
+    Path((bucket, key)): Path<(String, String)>,
+    Query(qs): Query<SignedUrlQuery>,
+) -> Result<impl IntoResponse, Error> {
+    let valid = state.storage.url_signer.verify(
+        &bucket,
+        &key,
+        qs.filename.as_deref(),
+        qs.expires,
+        &qs.signature,
+    );
+    if !valid {
+        return Err(Error::InvalidSignedUrl);
+    }
+
+    let storage = state
+        .storage
+        .bucket(&bucket)
+        .ok_or(Error::NotFound("Bucket"))?;
+    let data = storage.get(&key).await.change_context(Error::Storage)?;
+
+    let content_type = content_type(qs.filename.as_deref().unwrap_or(&key));
+    let max_age = (qs.expires - chrono::Utc::now().timestamp()).max(0);
+
+    Ok((
+        uploaded_file_headers(content_type, format!("private, max-age={max_age}")),
+        axum::body::Body::from_stream(data.into_stream()),
+    ))
+}
+
+pub fn create_routes() -> axum::Router<ServerState> {
+    axum::Router::new().route("/storage/:bucket/*key", routing::get(get_object))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse_url(url: &str) -> (&str, SignedUrlQuery) {
+        let (path, query) = url.split_once('?').unwrap();
+        let qs = parse_query(query);
+        (path, qs)
+    }
+
+    fn parse_query(query: &str) -> SignedUrlQuery {
+        let params = url::form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .collect::<std::collections::HashMap<_, _>>();
+        SignedUrlQuery {
+            expires: params["expires"].parse().unwrap(),
+            signature: params["signature"].clone(),
+            filename: params.get("filename").cloned(),
+        }
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let signer = UrlSigner::new("the-key", DEFAULT_EXPIRY);
+        let key = "org/post_image/a%20b.png";
+        let url = signer.sign("image_uploads", key, Some("a b.png"));
+        let (path, qs) = parse_url(&url);
+        assert_eq!(
+            path,
+            "/api/storage/image_uploads/org/post_image/a%2520b.png"
+        );
+        assert_eq!(qs.filename.as_deref(), Some("a b.png"));
+
+        let verify = |bucket: &str, key: &str, filename: Option<&str>, expires: i64| {
+            signer.verify(bucket, key, filename, expires, &qs.signature)
+        };
+        assert!(verify("image_uploads", key, Some("a b.png"), qs.expires));
+        assert!(!verify("pdfs", key, Some("a b.png"), qs.expires));
+        assert!(!verify(
+            "image_uploads",
+            "org/post_image/b.png",
+            Some("a b.png"),
+            qs.expires
+        ));
+        assert!(!verify("image_uploads", key, Some("a b.svg"), qs.expires));
+        assert!(!verify("image_uploads", key, None, qs.expires));
+        assert!(!verify(
+            "image_uploads",
+            key,
+            Some("a b.png"),
+            qs.expires + 1
+        ));
+
+        let other_signer = UrlSigner::new("another-key", DEFAULT_EXPIRY);
+        assert!(!other_signer.verify(
+            "image_uploads",
+            key,
+            Some("a b.png"),
+            qs.expires,
+            &qs.signature
+        ));
+
+        let expires = chrono::Utc::now().timestamp() - 1;
+        let url = signer.sign_with_expiration("image_uploads", key, None, expires);
+        let (_, qs) = parse_url(&url);
+        assert!(!signer.verify("image_uploads", key, None, qs.expires, &qs.signature));
+    }
+}
@@ -51,16 +51,52 @@ pub struct BootstrappedData {
 
 pub struct TestAppOptions {
     pub obfuscate_errors: Option<bool>,
+    /// Override the in-memory storage configuration
+    pub storage: Option<crate::storage::AppStorageConfig>,
 }
 
 impl Default for TestAppOptions {
     fn default() -> Self {
         Self {
             obfuscate_errors: Some(false),
+            storage: None,
         }
     }
 }
 
+/// A part of a `multipart/form-data` body. Parts with a filename are files.
+pub struct MultipartPart<'a> {
+    pub name: &'a str,
+    pub filename: Option<&'a str>,
+    pub data: &'a [u8],
+}
+
+/// The boundary used by [multipart_body]
+pub const MULTIPART_BOUNDARY: &str = "test-multipart-boundary";
+
+/// Build a `multipart/form-data` body, to be sent with a `Content-Type` of
+/// `multipart/form-data; boundary={MULTIPART_BOUNDARY}`.
+pub fn multipart_body(parts: &[MultipartPart]) -> Vec<u8> {
+    let mut body = Vec::new();
+    for part in parts {
+        body.extend_from_slice(format!("--{MULTIPART_BOUNDARY}\r\n").as_bytes());
+        let disposition = match part.filename {
+            Some(filename) => format!(
+                "Content-Disposition: form-data; name=\"{}\"; filename=\"{filename}\"\r\n\
+                Content-Type: application/octet-stream\r\n",
+                part.name
+            ),
+            None => format!("Content-Disposition: form-data; name=\"{}\"\r\n", part.name),
+        };
+        body.extend_from_slice(disposition.as_bytes());
+        body.extend_from_slice(b"\r\n");
+        body.extend_from_slice(part.data);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{MULTIPART_BOUNDARY}--\r\n").as_bytes());
+    body
+}
+
 pub async fn start_app(pg_pool: PgPool) -> (TestApp, BootstrappedData) {
     start_app_with_options(pg_pool, TestAppOptions::default()).await
 }
@@ -125,7 +161,10 @@ pub async fn start_app_with_options(
         ),
         queue_path,
         init_recurring_jobs: false,
-        storage: crate::storage::AppStorageConfig::new_in_memory(),
+        storage: options
+            .storage
+            .unwrap_or_else(crate::storage::AppStorageConfig::new_in_memory),
+        image_renditions: crate::models::post_image::renditions::default_renditions(),
     };
 
     let server = crate::server::create_server(config)
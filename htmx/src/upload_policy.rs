@@ -0,0 +1,338 @@
+//! Validation of uploaded files against the policy for their storage bucket.
+//!
+//! Each bucket has an [UploadPolicy] that limits the size of a file, the types of files that can
+//! be stored in it, and how many files can belong to a single parent object. The file type comes
+//! from the file's magic bytes, not from its name, and the checks run while the upload is
+//! streaming so that a bad file is rejected as soon as possible.
+
+use axum::http::StatusCode;
+use filigree::{
+    config::parse_option,
+    errors::{ForceObfuscate, HttpError},
+    storage::StorageError,
+    uploads::{UploadInspector, UploadInspectorError},
+};
+
+/// The number of bytes needed to recognize any of the types in [sniff_content_type]
+const SNIFF_LEN: usize = 16;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UploadPolicy {
+    /// The maximum size of a file, in bytes
+    pub max_size: Option<usize>,
+    /// The MIME types that may be uploaded. If empty, any type is allowed.
+    pub allowed_types: Vec<String>,
+    /// The maximum number of files attached to a single parent object
+    pub max_per_parent: Option<i64>,
+}
+
+impl UploadPolicy {
+    /// The default policy for buckets that store images
+    pub fn images() -> Self {
+        Self {
+            max_size: Some(20 * 1024 * 1024),
+            allowed_types: ["image/jpeg", "image/png", "image/gif", "image/webp"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            max_per_parent: Some(20),
+        }
+    }
+
+    /// The default policy for buckets that store PDFs
+    pub fn pdfs() -> Self {
+        Self {
+            max_size: Some(50 * 1024 * 1024),
+            allowed_types: vec!["application/pdf".to_string()],
+            max_per_parent: None,
+        }
+    }
+
+    /// Override the policy with the `{prefix}MAX_SIZE`, `{prefix}ALLOWED_TYPES` and
+    /// `{prefix}MAX_PER_PARENT` environment variables, when they are set. `ALLOWED_TYPES` is a
+    /// comma-separated list, where `*` allows any type.
+    pub fn merge_env(mut self, prefix: &str) -> Result<Self, StorageError> {
+        let var = |name: &str| std::env::var(format!("{prefix}{name}")).ok();
+
+        if let Some(max_size) = parse_option::<usize>(var("MAX_SIZE"))
+            .map_err(|_| StorageError::Configuration("Invalid upload policy MAX_SIZE"))?
+        {
+            self.max_size = Some(max_size).filter(|s| *s > 0);
+        }
+
+        if let Some(max_per_parent) = parse_option::<i64>(var("MAX_PER_PARENT"))
+            .map_err(|_| StorageError::Configuration("Invalid upload policy MAX_PER_PARENT"))?
+        {
+            self.max_per_parent = Some(max_per_parent).filter(|m| *m > 0);
+        }
+
+        if let Some(allowed_types) = var("ALLOWED_TYPES") {
+            self.allowed_types = allowed_types
+                .split(',')
+                .map(|t| t.trim().to_ascii_lowercase())
+                .filter(|t| !t.is_empty() && t != "*")
+                .collect();
+        }
+
+        Ok(self)
+    }
+
+    /// Check if a sniffed content type may be uploaded.
+    pub fn allows_type(&self, content_type: Option<&str>) -> bool {
+        if self.allowed_types.is_empty() {
+            return true;
+        }
+
+        content_type
+            .map(|t| self.allowed_types.iter().any(|allowed| allowed == t))
+            .unwrap_or(false)
+    }
+
+    /// Return an error if a parent that already has `existing` files can not accept another one.
+    pub fn check_count(&self, existing: i64) -> Result<(), UploadPolicyError> {
+        match self.max_per_parent {
+            Some(max) if existing >= max => Err(UploadPolicyError::TooManyFiles(max)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check a complete file against the policy.
+    pub fn check(&self, data: &[u8]) -> Result<Option<&'static str>, UploadPolicyError> {
+        let mut inspector = UploadPolicyInspector::new(self, None);
+        inspector.inspect(data)?;
+        inspector.finish()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum UploadPolicyError {
+    #[error("File is larger than the maximum of {0} bytes")]
+    TooLarge(usize),
+    #[error("Files of type {} are not allowed", .0.as_deref().unwrap_or("unknown"))]
+    UnsupportedType(Option<String>),
+    #[error("No more than {0} files may be attached")]
+    TooManyFiles(i64),
+    #[error("The organization's storage quota has been exceeded")]
+    QuotaExceeded,
+    #[error(transparent)]
+    Inspector(#[from] UploadInspectorError),
+}
+
+impl HttpError for UploadPolicyError {
+    type Detail = String;
+
+    fn error_kind(&self) -> &'static str {
+        match self {
+            Self::TooLarge(_) => "file_too_large",
+            Self::UnsupportedType(_) => "unsupported_file_type",
+            Self::TooManyFiles(_) => "too_many_files",
+            Self::QuotaExceeded => crate::error::ErrorKind::StorageQuotaExceeded.as_str(),
+            Self::Inspector(e) => e.error_kind(),
+        }
+    }
+
+    fn obfuscate(&self) -> Option<ForceObfuscate> {
+        match self {
+            Self::Inspector(e) => e.obfuscate(),
+            _ => None,
+        }
+    }
+
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::UnsupportedType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Self::TooManyFiles(_) => StatusCode::CONFLICT,
+            Self::QuotaExceeded => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::Inspector(e) => e.status_code(),
+        }
+    }
+
+    fn error_detail(&self) -> String {
+        self.to_string()
+    }
+}
+
+/// An [UploadInspector] that enforces the size and type limits of an [UploadPolicy] as the file
+/// streams in.
+pub struct UploadPolicyInspector<'a> {
+    policy: &'a UploadPolicy,
+    max_size: Option<usize>,
+    quota: Option<i64>,
+    size: usize,
+    prefix: Vec<u8>,
+    content_type: Option<Option<&'static str>>,
+}
+
+impl<'a> UploadPolicyInspector<'a> {
+    /// Create an inspector for the policy. `limit` further restricts the maximum size, if given.
+    pub fn new(policy: &'a UploadPolicy, limit: Option<usize>) -> Self {
+        let max_size = match (policy.max_size, limit) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+
+        Self {
+            policy,
+            max_size,
+            quota: None,
+            size: 0,
+            prefix: Vec::with_capacity(SNIFF_LEN),
+            content_type: None,
+        }
+    }
+
+    /// Reject the file once it is larger than the organization's remaining storage quota.
+    pub fn with_quota(mut self, remaining: Option<i64>) -> Self {
+        self.quota = remaining;
+        self
+    }
+
+    fn check_type(&mut self) -> Result<Option<&'static str>, UploadPolicyError> {
+        let content_type = sniff_content_type(&self.prefix);
+        if !self.policy.allows_type(content_type) {
+            return Err(UploadPolicyError::UnsupportedType(
+                content_type.map(String::from),
+            ));
+        }
+
+        self.content_type = Some(content_type);
+        Ok(content_type)
+    }
+
+    /// Finish inspecting the file, and return its content type if it was recognized. This
+    /// checks the type of files too short to have been checked while streaming.
+    pub fn finish(mut self) -> Result<Option<&'static str>, UploadPolicyError> {
+        match self.content_type {
+            Some(content_type) => Ok(content_type),
+            None => self.check_type(),
+        }
+    }
+}
+
+impl<'a> UploadInspector<UploadPolicyError> for UploadPolicyInspector<'a> {
+    fn inspect(&mut self, data: &[u8]) -> Result<(), UploadPolicyError> {
+        self.size += data.len();
+        if let Some(max_size) = self.max_size {
+            if self.size > max_size {
+                return Err(UploadPolicyError::TooLarge(max_size));
+            }
+        }
+
+        if let Some(quota) = self.quota {
+            if self.size as i64 > quota {
+                return Err(UploadPolicyError::QuotaExceeded);
+            }
+        }
+
+        if self.content_type.is_none() {
+            let needed = SNIFF_LEN - self.prefix.len();
+            self.prefix
+                .extend_from_slice(&data[..needed.min(data.len())]);
+            if self.prefix.len() == SNIFF_LEN {
+                self.check_type()?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Determine the MIME type of a file from its first bytes.
+pub fn sniff_content_type(data: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\xFF\xD8\xFF", "image/jpeg"),
+        (b"\x89PNG\r\n\x1A\n", "image/png"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"BM", "image/bmp"),
+        (b"II*\0", "image/tiff"),
+        (b"MM\0*", "image/tiff"),
+        (b"%PDF-", "application/pdf"),
+    ];
+
+    if data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+
+    if data.len() >= 12 && &data[4..8] == b"ftyp" {
+        return match &data[8..12] {
+            b"avif" | b"avis" => Some("image/avif"),
+            b"heic" | b"heix" | b"mif1" => Some("image/heic"),
+            _ => None,
+        };
+    }
+
+    SIGNATURES
+        .iter()
+        .find(|(signature, _)| data.starts_with(signature))
+        .map(|(_, content_type)| *content_type)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1A\n\0\0\0\rIHDR";
+
+    #[test]
+    fn sniff() {
+        assert_eq!(sniff_content_type(PNG), Some("image/png"));
+        assert_eq!(
+            sniff_content_type(b"RIFF\0\0\0\0WEBPVP8 "),
+            Some("image/webp")
+        );
+        assert_eq!(sniff_content_type(b"%PDF-1.7"), Some("application/pdf"));
+        assert_eq!(sniff_content_type(b"<html></html>"), None);
+    }
+
+    #[test]
+    fn type_checked_while_streaming() {
+        let policy = UploadPolicy::images();
+
+        let mut inspector = UploadPolicyInspector::new(&policy, None);
+        inspector.inspect(&PNG[..4]).unwrap();
+        inspector.inspect(&PNG[4..]).unwrap();
+        assert_eq!(inspector.finish().unwrap(), Some("image/png"));
+
+        let mut inspector = UploadPolicyInspector::new(&policy, None);
+        let err = inspector.inspect(b"%PDF-1.7 and more bytes").unwrap_err();
+        assert_eq!(err.status_code(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        // Files too short to be checked while streaming are checked at the end.
+        let mut inspector = UploadPolicyInspector::new(&policy, None);
+        inspector.inspect(b"GIF").unwrap();
+        let err = inspector.finish().unwrap_err();
+        assert_eq!(err.status_code(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[test]
+    fn size_limit() {
+        let policy = UploadPolicy {
+            max_size: Some(20),
+            ..Default::default()
+        };
+
+        let mut inspector = UploadPolicyInspector::new(&policy, None);
+        inspector.inspect(&[0; 15]).unwrap();
+        let err = inspector.inspect(&[0; 15]).unwrap_err();
+        assert_eq!(err.status_code(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let mut inspector = UploadPolicyInspector::new(&policy, Some(10));
+        let err = inspector.inspect(&[0; 15]).unwrap_err();
+        assert!(matches!(err, UploadPolicyError::TooLarge(10)));
+
+        let mut inspector = UploadPolicyInspector::new(&policy, None).with_quota(Some(12));
+        inspector.inspect(&[0; 12]).unwrap();
+        let err = inspector.inspect(&[0; 1]).unwrap_err();
+        assert!(matches!(err, UploadPolicyError::QuotaExceeded));
+    }
+
+    #[test]
+    fn count_limit() {
+        let policy = UploadPolicy::images();
+        assert!(policy.check_count(19).is_ok());
+        let err = policy.check_count(20).unwrap_err();
+        assert_eq!(err.status_code(), StatusCode::CONFLICT);
+    }
+}
@@ -0,0 +1,399 @@
+//! Per-user favorites for reports and posts
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use axum_extra::extract::Query;
+use axum_jsonschema::Json;
+use error_stack::{Report, ResultExt};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+use crate::{
+    auth::{AuthInfo, Authed},
+    models::{
+        organization::OrganizationId,
+        post::{Post, PostId},
+        report::{Report as ReportModel, ReportId},
+    },
+    server::ServerState,
+    Error,
+};
+
+/// The types of objects that can be favorited
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FavoriteObjectType {
+    Report,
+    Post,
+}
+
+impl FavoriteObjectType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Report => "report",
+            Self::Post => "post",
+        }
+    }
+
+    fn from_db(s: &str) -> Option<Self> {
+        match s {
+            "report" => Some(Self::Report),
+            "post" => Some(Self::Post),
+            _ => None,
+        }
+    }
+
+    /// Format a database UUID as the prefixed object ID for this type.
+    fn format_id(&self, id: Uuid) -> String {
+        match self {
+            Self::Report => ReportId::from_uuid(id).to_string(),
+            Self::Post => PostId::from_uuid(id).to_string(),
+        }
+    }
+}
+
+/// A single favorited object
+#[derive(Serialize, Debug, JsonSchema)]
+pub struct Favorite {
+    pub object_type: FavoriteObjectType,
+    pub object_id: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Build the WHERE clause fragment used by the list queries to filter on favorites.
+/// `user_binding` is the query parameter that holds the current user's ID, and the organization
+/// ID is always expected at `$1`.
+pub fn filter_clause(favorited: bool, user_binding: usize) -> String {
+    format!(
+        "{not}EXISTS (SELECT 1 FROM public.favorites fav WHERE fav.organization_id = $1 AND fav.user_id = ${user_binding} AND fav.object_id = tb.id)",
+        not = if favorited { "" } else { "NOT " },
+    )
+}
+
+/// Return if the current user has favorited the given object.
+pub async fn is_favorite(
+    db: impl PgExecutor<'_>,
+    auth: &AuthInfo,
+    object_id: &Uuid,
+) -> Result<bool, Report<Error>> {
+    let found = sqlx::query_scalar!(
+        "SELECT true FROM public.favorites
+        WHERE organization_id = $1 AND user_id = $2 AND object_id = $3",
+        auth.organization_id.as_uuid(),
+        auth.user_id.as_uuid(),
+        object_id
+    )
+    .fetch_optional(db)
+    .await
+    .change_context(Error::Db)?
+    .is_some();
+
+    Ok(found)
+}
+
+/// Add or remove an object from the current user's favorites. This does not check that the
+/// object exists or that the user can read it; callers should do that first.
+pub async fn set_favorite(
+    db: impl PgExecutor<'_>,
+    auth: &AuthInfo,
+    object_type: FavoriteObjectType,
+    object_id: &Uuid,
+    favorited: bool,
+) -> Result<(), Report<Error>> {
+    if favorited {
+        sqlx::query!(
+            "INSERT INTO public.favorites (organization_id, user_id, object_type, object_id)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT DO NOTHING",
+            auth.organization_id.as_uuid(),
+            auth.user_id.as_uuid(),
+            object_type.as_str(),
+            object_id
+        )
+        .execute(db)
+        .await
+        .change_context(Error::Db)?;
+    } else {
+        sqlx::query!(
+            "DELETE FROM public.favorites
+            WHERE organization_id = $1 AND user_id = $2 AND object_id = $3",
+            auth.organization_id.as_uuid(),
+            auth.user_id.as_uuid(),
+            object_id
+        )
+        .execute(db)
+        .await
+        .change_context(Error::Db)?;
+    }
+
+    Ok(())
+}
+
+/// Remove an object from every user's favorites, for use when the object is deleted.
+pub async fn remove_object(
+    db: impl PgExecutor<'_>,
+    organization_id: &OrganizationId,
+    object_id: &Uuid,
+) -> Result<(), Report<Error>> {
+    sqlx::query!(
+        "DELETE FROM public.favorites WHERE organization_id = $1 AND object_id = $2",
+        organization_id.as_uuid(),
+        object_id
+    )
+    .execute(db)
+    .await
+    .change_context(Error::Db)?;
+
+    Ok(())
+}
+
+/// List the current user's favorites, most recent first.
+pub async fn list_favorites(
+    db: impl PgExecutor<'_>,
+    auth: &AuthInfo,
+    object_type: Option<FavoriteObjectType>,
+) -> Result<Vec<Favorite>, Report<Error>> {
+    let rows = sqlx::query!(
+        "SELECT object_type, object_id, created_at
+        FROM public.favorites
+        WHERE organization_id = $1 AND user_id = $2
+            AND ($3::text IS NULL OR object_type = $3)
+        ORDER BY created_at DESC",
+        auth.organization_id.as_uuid(),
+        auth.user_id.as_uuid(),
+        object_type.map(|t| t.as_str())
+    )
+    .fetch_all(db)
+    .await
+    .change_context(Error::Db)?;
+
+    let favorites = rows
+        .into_iter()
+        .filter_map(|row| {
+            let object_type = FavoriteObjectType::from_db(&row.object_type)?;
+            Some(Favorite {
+                object_type,
+                object_id: object_type.format_id(row.object_id),
+                created_at: row.created_at,
+            })
+        })
+        .collect();
+
+    Ok(favorites)
+}
+
+#[derive(Deserialize, Debug, JsonSchema)]
+pub struct ListFavoritesQuery {
+    pub object_type: Option<FavoriteObjectType>,
+}
+
+pub async fn list_favorites_endpoint(
+    State(state): State<ServerState>,
+    auth: Authed,
+    Query(qs): Query<ListFavoritesQuery>,
+) -> Result<impl IntoResponse, Error> {
+    let favorites = list_favorites(&state.db, &auth, qs.object_type).await?;
+    Ok(Json(favorites))
+}
+
+/// Make sure the object exists and is readable by the user, and return its UUID.
+async fn check_favorite_target(
+    state: &ServerState,
+    auth: &AuthInfo,
+    object_type: FavoriteObjectType,
+    id: &str,
+) -> Result<Uuid, Report<Error>> {
+    let id = match object_type {
+        FavoriteObjectType::Report => {
+            let id = id
+                .parse::<ReportId>()
+                .change_context(Error::NotFound("Report"))?;
+            *ReportModel::get(&state.db, auth, &id).await?.id.as_uuid()
+        }
+        FavoriteObjectType::Post => {
+            let id = id
+                .parse::<PostId>()
+                .change_context(Error::NotFound("Post"))?;
+            *Post::get(&state.db, auth, &id).await?.id.as_uuid()
+        }
+    };
+
+    Ok(id)
+}
+
+pub async fn add_favorite_endpoint(
+    State(state): State<ServerState>,
+    auth: Authed,
+    Path((object_type, id)): Path<(FavoriteObjectType, String)>,
+) -> Result<impl IntoResponse, Error> {
+    let object_id = check_favorite_target(&state, &auth, object_type, &id).await?;
+    set_favorite(&state.db, &auth, object_type, &object_id, true).await?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn remove_favorite_endpoint(
+    State(state): State<ServerState>,
+    auth: Authed,
+    Path((object_type, id)): Path<(FavoriteObjectType, String)>,
+) -> Result<impl IntoResponse, Error> {
+    let object_id = check_favorite_target(&state, &auth, object_type, &id).await?;
+    set_favorite(&state.db, &auth, object_type, &object_id, false).await?;
+    Ok(StatusCode::OK)
+}
+
+#[cfg(test)]
+mod test {
+    use filigree::testing::ResponseExt;
+
+    use crate::{
+        models::{post::Post, report::Report},
+        tests::{start_app, BootstrappedData},
+    };
+
+    #[sqlx::test]
+    async fn favorite_posts_and_reports(db: sqlx::PgPool) {
+        let (
+            _app,
+            BootstrappedData {
+                organization,
+                user,
+                admin_user,
+                ..
+            },
+        ) = start_app(db.clone()).await;
+
+        let mut tx = db.begin().await.unwrap();
+        let mut posts = Vec::new();
+        for i in 0..3 {
+            let id = crate::models::post::PostId::new();
+            let post = Post::create_raw(
+                &mut *tx,
+                &id,
+                &organization.id,
+                crate::models::post::testing::make_create_payload(i),
+            )
+            .await
+            .unwrap();
+            posts.push(post);
+        }
+
+        let report_id = crate::models::report::ReportId::new();
+        let report = Report::create_raw(
+            &mut *tx,
+            &report_id,
+            &organization.id,
+            crate::models::report::testing::make_create_payload(0),
+        )
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+
+        user.client
+            .put(&format!("self/favorites/post/{}", posts[1].id))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap();
+
+        user.client
+            .put(&format!("self/favorites/report/{}", report.id))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap();
+
+        let favorites: Vec<serde_json::Value> = user
+            .client
+            .get("self/favorites")
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(favorites.len(), 2);
+
+        let favorited_posts: Vec<serde_json::Value> = user
+            .client
+            .get("posts")
+            .query(&[("favorited", "true")])
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(favorited_posts.len(), 1);
+        assert_eq!(favorited_posts[0]["id"], posts[1].id.to_string());
+
+        let other_posts: Vec<serde_json::Value> = user
+            .client
+            .get("posts")
+            .query(&[("favorited", "false")])
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(other_posts.len(), 2);
+
+        // Favorites are per-user
+        let admin_favorites: Vec<serde_json::Value> = admin_user
+            .client
+            .get("reports")
+            .query(&[("favorited", "true")])
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert!(admin_favorites.is_empty());
+
+        user.client
+            .delete(&format!("self/favorites/post/{}", posts[1].id))
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap();
+
+        let favorites: Vec<serde_json::Value> = user
+            .client
+            .get("self/favorites")
+            .query(&[("object_type", "post")])
+            .send()
+            .await
+            .unwrap()
+            .log_error()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert!(favorites.is_empty());
+    }
+}
@@ -0,0 +1,421 @@
+//! Management of organization members, their roles, and the permissions granted to each role.
+
+use error_stack::{Report, ResultExt};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgExecutor};
+use uuid::Uuid;
+
+use crate::{
+    auth::permissions::{is_known_permission, ORG_ADMIN_PERMISSION},
+    models::{organization::OrganizationId, role::RoleId, user::UserId},
+    Error,
+};
+
+/// The full set of permissions granted to a role
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct RolePermissions {
+    pub permissions: Vec<String>,
+}
+
+/// The full set of roles assigned to a user
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct UserRoles {
+    pub role_ids: Vec<RoleId>,
+}
+
+/// A user in the organization, along with the roles assigned to them
+#[derive(Debug, Clone)]
+pub struct OrganizationMember {
+    pub user_id: UserId,
+    pub name: String,
+    pub email: Option<String>,
+    pub active: bool,
+    pub role_ids: Vec<RoleId>,
+}
+
+/// A role in the organization and the permissions granted to it
+#[derive(Debug, Clone)]
+pub struct RoleWithPermissions {
+    pub id: RoleId,
+    pub name: String,
+    pub description: Option<String>,
+    pub permissions: Vec<String>,
+}
+
+/// An invitation to join the organization which has not been accepted yet
+#[derive(Debug, Clone)]
+pub struct PendingInvite {
+    pub email: String,
+    pub name: Option<String>,
+    pub invite_sent_at: chrono::DateTime<chrono::Utc>,
+    pub token_expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+pub async fn list_members(
+    db: impl PgExecutor<'_>,
+    organization_id: &OrganizationId,
+) -> Result<Vec<OrganizationMember>, Report<Error>> {
+    sqlx::query_as!(
+        OrganizationMember,
+        r##"SELECT
+            u.id AS "user_id: UserId",
+            u.name,
+            u.email,
+            om.active,
+            COALESCE(
+                ARRAY_AGG(ur.role_id) FILTER (WHERE ur.role_id IS NOT NULL),
+                ARRAY[]::uuid[]
+            ) AS "role_ids!: Vec<RoleId>"
+        FROM organization_members om
+        JOIN users u ON u.id = om.user_id
+        LEFT JOIN user_roles ur
+            ON ur.organization_id = om.organization_id AND ur.user_id = om.user_id
+        WHERE om.organization_id = $1
+        GROUP BY u.id, om.active
+        ORDER BY u.name"##,
+        organization_id.as_uuid()
+    )
+    .fetch_all(db)
+    .await
+    .change_context(Error::Db)
+}
+
+pub async fn get_member(
+    db: impl PgExecutor<'_>,
+    organization_id: &OrganizationId,
+    user_id: &UserId,
+) -> Result<OrganizationMember, Report<Error>> {
+    sqlx::query_as!(
+        OrganizationMember,
+        r##"SELECT
+            u.id AS "user_id: UserId",
+            u.name,
+            u.email,
+            om.active,
+            COALESCE(
+                ARRAY_AGG(ur.role_id) FILTER (WHERE ur.role_id IS NOT NULL),
+                ARRAY[]::uuid[]
+            ) AS "role_ids!: Vec<RoleId>"
+        FROM organization_members om
+        JOIN users u ON u.id = om.user_id
+        LEFT JOIN user_roles ur
+            ON ur.organization_id = om.organization_id AND ur.user_id = om.user_id
+        WHERE om.organization_id = $1 AND om.user_id = $2
+        GROUP BY u.id, om.active"##,
+        organization_id.as_uuid(),
+        user_id.as_uuid()
+    )
+    .fetch_optional(db)
+    .await
+    .change_context(Error::Db)?
+    .ok_or(Error::NotFound("User"))
+    .map_err(Report::new)
+}
+
+pub async fn list_roles_with_permissions(
+    db: impl PgExecutor<'_>,
+    organization_id: &OrganizationId,
+) -> Result<Vec<RoleWithPermissions>, Report<Error>> {
+    sqlx::query_as!(
+        RoleWithPermissions,
+        r##"SELECT
+            r.id AS "id: RoleId",
+            r.name,
+            r.description,
+            COALESCE(
+                ARRAY_AGG(p.permission) FILTER (WHERE p.permission IS NOT NULL),
+                ARRAY[]::text[]
+            ) AS "permissions!"
+        FROM roles r
+        LEFT JOIN permissions p
+            ON p.organization_id = r.organization_id AND p.actor_id = r.id
+        WHERE r.organization_id = $1
+        GROUP BY r.id
+        ORDER BY r.name"##,
+        organization_id.as_uuid()
+    )
+    .fetch_all(db)
+    .await
+    .change_context(Error::Db)
+}
+
+pub async fn get_role_permissions(
+    db: impl PgExecutor<'_>,
+    organization_id: &OrganizationId,
+    role_id: &RoleId,
+) -> Result<Vec<String>, Report<Error>> {
+    sqlx::query_scalar!(
+        "SELECT permission FROM permissions
+        WHERE organization_id = $1 AND actor_id = $2
+        ORDER BY permission",
+        organization_id.as_uuid(),
+        role_id.as_uuid()
+    )
+    .fetch_all(db)
+    .await
+    .change_context(Error::Db)
+}
+
+/// Grant or revoke a single permission for a role. Nothing happens if the role is not part of
+/// the organization.
+pub async fn set_role_permission(
+    db: impl PgExecutor<'_>,
+    organization_id: &OrganizationId,
+    role_id: &RoleId,
+    permission: &str,
+    enabled: bool,
+) -> Result<(), Report<Error>> {
+    if enabled {
+        sqlx::query!(
+            "INSERT INTO permissions (organization_id, actor_id, permission)
+            SELECT $1, id, $3 FROM roles WHERE organization_id = $1 AND id = $2
+            ON CONFLICT DO NOTHING",
+            organization_id.as_uuid(),
+            role_id.as_uuid(),
+            permission
+        )
+        .execute(db)
+        .await
+    } else {
+        sqlx::query!(
+            "DELETE FROM permissions
+            WHERE organization_id = $1 AND actor_id = $2 AND permission = $3",
+            organization_id.as_uuid(),
+            role_id.as_uuid(),
+            permission
+        )
+        .execute(db)
+        .await
+    }
+    .change_context(Error::Db)?;
+
+    Ok(())
+}
+
+/// Add or remove a role from a member of the organization. Nothing happens if the user or role
+/// is not part of the organization.
+pub async fn set_user_role(
+    db: impl PgExecutor<'_>,
+    organization_id: &OrganizationId,
+    user_id: &UserId,
+    role_id: &RoleId,
+    enabled: bool,
+) -> Result<(), Report<Error>> {
+    if enabled {
+        sqlx::query!(
+            "INSERT INTO user_roles (organization_id, user_id, role_id)
+            SELECT $1, $2, $3
+            WHERE EXISTS (SELECT 1 FROM roles WHERE organization_id = $1 AND id = $3)
+                AND EXISTS (
+                    SELECT 1 FROM organization_members WHERE organization_id = $1 AND user_id = $2
+                )
+            ON CONFLICT DO NOTHING",
+            organization_id.as_uuid(),
+            user_id.as_uuid(),
+            role_id.as_uuid()
+        )
+        .execute(db)
+        .await
+    } else {
+        sqlx::query!(
+            "DELETE FROM user_roles WHERE organization_id = $1 AND user_id = $2 AND role_id = $3",
+            organization_id.as_uuid(),
+            user_id.as_uuid(),
+            role_id.as_uuid()
+        )
+        .execute(db)
+        .await
+    }
+    .change_context(Error::Db)?;
+
+    Ok(())
+}
+
+/// Activate or deactivate a member of the organization. Inactive members can not log in.
+pub async fn set_member_active(
+    db: impl PgExecutor<'_>,
+    organization_id: &OrganizationId,
+    user_id: &UserId,
+    active: bool,
+) -> Result<bool, Report<Error>> {
+    let result = sqlx::query!(
+        "UPDATE organization_members SET active = $3
+        WHERE organization_id = $1 AND user_id = $2",
+        organization_id.as_uuid(),
+        user_id.as_uuid(),
+        active
+    )
+    .execute(db)
+    .await
+    .change_context(Error::Db)?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn list_pending_invites(
+    db: impl PgExecutor<'_>,
+    organization_id: &OrganizationId,
+) -> Result<Vec<PendingInvite>, Report<Error>> {
+    sqlx::query_as!(
+        PendingInvite,
+        "SELECT email, name, invite_sent_at, token_expires_at
+        FROM user_invites
+        WHERE organization_id = $1
+        ORDER BY invite_sent_at DESC",
+        organization_id.as_uuid()
+    )
+    .fetch_all(db)
+    .await
+    .change_context(Error::Db)
+}
+
+/// Return an error if the organization has no active members with the `org_admin` permission,
+/// either directly or through one of their roles. This should be called in the same transaction
+/// as any change that could remove an admin, so that the change can be rolled back.
+pub async fn ensure_org_has_admin(
+    tx: &mut PgConnection,
+    organization_id: &OrganizationId,
+) -> Result<(), Report<Error>> {
+    // Lock the organization so that concurrent transactions that each remove a different admin
+    // are checked one at a time. Otherwise both could see the other's admin and leave none.
+    sqlx::query!(
+        "SELECT 1 AS locked FROM organizations WHERE id = $1 FOR UPDATE",
+        organization_id.as_uuid()
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .change_context(Error::Db)?;
+
+    let admins = sqlx::query_scalar!(
+        r##"SELECT COUNT(*) AS "count!"
+        FROM organization_members om
+        WHERE om.organization_id = $1
+            AND om.active
+            AND EXISTS (
+                SELECT 1 FROM permissions p
+                WHERE p.organization_id = $1
+                    AND p.permission = $2
+                    AND (
+                        p.actor_id = om.user_id
+                        OR p.actor_id IN (
+                            SELECT role_id FROM user_roles ur
+                            WHERE ur.organization_id = $1 AND ur.user_id = om.user_id
+                        )
+                    )
+            )"##,
+        organization_id.as_uuid(),
+        ORG_ADMIN_PERMISSION
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .change_context(Error::Db)?;
+
+    if admins == 0 {
+        return Err(Report::new(Error::LastAdmin));
+    }
+
+    Ok(())
+}
+
+/// Replace all the permissions for a role.
+pub async fn replace_role_permissions(
+    tx: &mut PgConnection,
+    organization_id: &OrganizationId,
+    role_id: &RoleId,
+    permissions: &[String],
+) -> Result<(), Report<Error>> {
+    if let Some(unknown) = permissions.iter().find(|p| !is_known_permission(p)) {
+        return Err(Report::new(Error::UnknownPermission)).attach_printable(unknown.to_string());
+    }
+
+    let role_exists = sqlx::query_scalar!(
+        "SELECT true FROM roles WHERE organization_id = $1 AND id = $2",
+        organization_id.as_uuid(),
+        role_id.as_uuid()
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .change_context(Error::Db)?
+    .is_some();
+
+    if !role_exists {
+        return Err(Report::new(Error::NotFound("Role")));
+    }
+
+    sqlx::query!(
+        "DELETE FROM permissions WHERE organization_id = $1 AND actor_id = $2",
+        organization_id.as_uuid(),
+        role_id.as_uuid()
+    )
+    .execute(&mut *tx)
+    .await
+    .change_context(Error::Db)?;
+
+    sqlx::query!(
+        "INSERT INTO permissions (organization_id, actor_id, permission)
+        SELECT $1, $2, UNNEST($3::text[])
+        ON CONFLICT DO NOTHING",
+        organization_id.as_uuid(),
+        role_id.as_uuid(),
+        permissions
+    )
+    .execute(&mut *tx)
+    .await
+    .change_context(Error::Db)?;
+
+    ensure_org_has_admin(&mut *tx, organization_id).await
+}
+
+/// Replace all the roles for a member of the organization.
+pub async fn replace_user_roles(
+    tx: &mut PgConnection,
+    organization_id: &OrganizationId,
+    user_id: &UserId,
+    role_ids: &[RoleId],
+) -> Result<(), Report<Error>> {
+    // Make sure the user is in this organization
+    get_member(&mut *tx, organization_id, user_id).await?;
+
+    let mut role_uuids = role_ids
+        .iter()
+        .map(|id| *id.as_uuid())
+        .collect::<Vec<Uuid>>();
+    role_uuids.sort_unstable();
+    role_uuids.dedup();
+
+    let found_roles = sqlx::query_scalar!(
+        r##"SELECT COUNT(*) AS "count!" FROM roles WHERE organization_id = $1 AND id = ANY($2)"##,
+        organization_id.as_uuid(),
+        &role_uuids
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .change_context(Error::Db)?;
+
+    if found_roles != role_uuids.len() as i64 {
+        return Err(Report::new(Error::NotFound("Role")));
+    }
+
+    sqlx::query!(
+        "DELETE FROM user_roles WHERE organization_id = $1 AND user_id = $2",
+        organization_id.as_uuid(),
+        user_id.as_uuid()
+    )
+    .execute(&mut *tx)
+    .await
+    .change_context(Error::Db)?;
+
+    sqlx::query!(
+        "INSERT INTO user_roles (organization_id, user_id, role_id)
+        SELECT $1, $2, UNNEST($3::uuid[])",
+        organization_id.as_uuid(),
+        user_id.as_uuid(),
+        &role_uuids
+    )
+    .execute(&mut *tx)
+    .await
+    .change_context(Error::Db)?;
+
+    ensure_org_has_admin(&mut *tx, organization_id).await
+}
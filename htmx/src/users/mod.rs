@@ -1,3 +1,5 @@
+pub mod favorites;
+pub mod members;
 pub mod organization;
 pub mod users;
 
@@ -1,5 +1,9 @@
 use async_trait::async_trait;
-use axum::{extract::State, http::StatusCode, response::IntoResponse, routing};
+use axum::{
+    extract::State,
+    http::{Method, StatusCode},
+    response::IntoResponse,
+};
 use axum_jsonschema::Json;
 use error_stack::{Report, ResultExt};
 use filigree::{
@@ -19,9 +23,12 @@ use crate::{
     auth::Authed,
     models::{
         organization::OrganizationId,
-        user::{User, UserCreatePayload, UserId},
+        user::{User, UserCreatePayload, UserId, UserUpdatePayload},
+    },
+    server::{
+        openapi::{ApiRoute, ApiRouter},
+        ServerState,
     },
-    server::ServerState,
     Error,
 };
 
@@ -199,7 +206,7 @@ async fn get_current_user_endpoint(
 async fn update_current_user_endpoint(
     State(state): State<ServerState>,
     authed: Authed,
-    FormOrJson(body): FormOrJson<crate::models::user::UserUpdatePayload>,
+    FormOrJson(body): FormOrJson<UserUpdatePayload>,
 ) -> Result<impl IntoResponse, Error> {
     // TODO Need a query specifically for updating self
     let mut tx = state.db.begin().await.change_context(Error::Db)?;
@@ -214,10 +221,64 @@ async fn update_current_user_endpoint(
     Ok(status)
 }
 
+fn routes() -> ApiRouter {
+    use super::favorites::{
+        add_favorite_endpoint, list_favorites_endpoint, remove_favorite_endpoint, Favorite,
+        FavoriteObjectType, ListFavoritesQuery,
+    };
+
+    ApiRouter::new()
+        .route(
+            ApiRoute::new(Method::GET, "/self", "self", "Get the current user")
+                .response::<SelfUser>(),
+            get_current_user_endpoint,
+        )
+        .route(
+            ApiRoute::new(Method::PUT, "/self", "self", "Update the current user")
+                .request::<UserUpdatePayload>(),
+            update_current_user_endpoint,
+        )
+        .route(
+            ApiRoute::new(
+                Method::GET,
+                "/self/favorites",
+                "self",
+                "List the current user's favorites",
+            )
+            .query::<ListFavoritesQuery>()
+            .response::<Vec<Favorite>>(),
+            list_favorites_endpoint,
+        )
+        .route(
+            ApiRoute::new(
+                Method::PUT,
+                "/self/favorites/:object_type/:id",
+                "self",
+                "Add a favorite",
+            )
+            .path_param::<FavoriteObjectType>()
+            .path_param::<String>(),
+            add_favorite_endpoint,
+        )
+        .route(
+            ApiRoute::new(
+                Method::DELETE,
+                "/self/favorites/:object_type/:id",
+                "self",
+                "Remove a favorite",
+            )
+            .path_param::<FavoriteObjectType>()
+            .path_param::<String>(),
+            remove_favorite_endpoint,
+        )
+}
+
 pub fn create_routes() -> axum::Router<ServerState> {
-    axum::Router::new()
-        .route("/self", routing::get(get_current_user_endpoint))
-        .route("/self", routing::put(update_current_user_endpoint))
+    routes().into_router()
+}
+
+pub fn api_docs() -> Vec<ApiRoute> {
+    routes().into_docs()
 }
 
 #[cfg(test)]
@@ -24,3 +24,12 @@ Alpine.start();
 if (process.env.LIVE_RELOAD === "true") {
 	startLiveReload();
 }
+
+// Forms that fail validation are returned with a 422 status and should still replace the
+// submitted form so that the errors are shown.
+document.body.addEventListener("htmx:beforeSwap", (evt) => {
+	if (evt.detail.xhr.status === 422) {
+		evt.detail.shouldSwap = true;
+		evt.detail.isError = false;
+	}
+});
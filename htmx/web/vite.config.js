@@ -6,6 +6,10 @@ const env = production ? "production" : "development";
 
 const enableLiveReload = !production || process.env.LIVE_RELOAD === "true";
 
+const apiReferenceSource =
+	"node_modules/@scalar/api-reference/dist/browser/standalone.js";
+const apiReferencePath = "/vendor/scalar-api-reference.js";
+
 export default defineConfig({
 	build: {
 		outDir: "build",
@@ -36,6 +40,21 @@ export default defineConfig({
 		"process.env.LIVE_RELOAD": enableLiveReload ? `'true'` : `'false'`,
 	},
 	plugins: [
+		{
+			// Serve the API docs viewer from the web build instead of a CDN.
+			name: "vendor-api-reference",
+			configureServer(server) {
+				server.middlewares.use(apiReferencePath, (req, res) => {
+					res.setHeader("Content-Type", "text/javascript");
+					fs.createReadStream(apiReferenceSource).pipe(res);
+				});
+			},
+			writeBundle(options) {
+				const outDir = options.dir ?? "build";
+				fs.mkdirSync(`${outDir}/vendor`, { recursive: true });
+				fs.copyFileSync(apiReferenceSource, `${outDir}${apiReferencePath}`);
+			},
+		},
 		{
 			name: "modify-manifest-keys",
 			apply: "build",